@@ -56,15 +56,18 @@
 //! [`propose_shielding`]: crate::data_api::wallet::propose_shielding
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fmt::Debug,
     hash::Hash,
     io,
     num::{NonZeroU32, TryFromIntError},
+    ops::Range,
 };
 
 use incrementalmerkletree::{frontier::Frontier, Retention};
+use iso_currency::Currency;
 use nonempty::NonEmpty;
+use rust_decimal::Decimal;
 use secrecy::SecretVec;
 use shardtree::{error::ShardTreeError, store::ShardStore, ShardTree};
 use zip32::fingerprint::SeedFingerprint;
@@ -81,14 +84,15 @@ use crate::{
     },
     proto::service::TreeState,
     wallet::{Note, NoteId, ReceivedNote, Recipient, WalletTransparentOutput, WalletTx},
-    ShieldedProtocol,
+    zip321::TransactionRequest,
+    PoolType, ShieldedProtocol,
 };
 use zcash_primitives::{
     block::BlockHash,
     consensus::BlockHeight,
     memo::{Memo, MemoBytes},
     transaction::{
-        components::amount::{BalanceError, NonNegativeAmount},
+        components::amount::{Amount, BalanceError, NonNegativeAmount},
         Transaction, TxId,
     },
 };
@@ -105,6 +109,7 @@ use zcash_primitives::consensus::NetworkUpgrade;
 pub mod chain;
 pub mod error;
 pub mod scanning;
+pub mod snapshot;
 pub mod wallet;
 
 /// The height of subtree roots in the Sapling note commitment tree.
@@ -120,6 +125,15 @@ pub const SAPLING_SHARD_HEIGHT: u8 = sapling::NOTE_COMMITMENT_TREE_DEPTH / 2;
 #[cfg(feature = "orchard")]
 pub const ORCHARD_SHARD_HEIGHT: u8 = { orchard::NOTE_COMMITMENT_TREE_DEPTH as u8 } / 2;
 
+/// The default number of consecutive unused external transparent addresses that a wallet
+/// derives beyond the last such address that is known to have received funds.
+///
+/// This is the gap limit recommended by [BIP 44].
+///
+/// [BIP 44]: https://github.com/bitcoin/bips/blob/master/bip-0044.mediawiki#address-gap-limit
+#[cfg(feature = "transparent-inputs")]
+pub const DEFAULT_TRANSPARENT_GAP_LIMIT: u32 = 20;
+
 /// An enumeration of constraints that can be applied when querying for nullifiers for notes
 /// belonging to the wallet.
 pub enum NullifierQuery {
@@ -133,6 +147,7 @@ pub struct Balance {
     spendable_value: NonNegativeAmount,
     change_pending_confirmation: NonNegativeAmount,
     value_pending_spendability: NonNegativeAmount,
+    locked_value: NonNegativeAmount,
 }
 
 impl Balance {
@@ -141,6 +156,7 @@ impl Balance {
         spendable_value: NonNegativeAmount::ZERO,
         change_pending_confirmation: NonNegativeAmount::ZERO,
         value_pending_spendability: NonNegativeAmount::ZERO,
+        locked_value: NonNegativeAmount::ZERO,
     };
 
     fn check_total_adding(
@@ -150,6 +166,7 @@ impl Balance {
         (self.spendable_value
             + self.change_pending_confirmation
             + self.value_pending_spendability
+            + self.locked_value
             + value)
             .ok_or(BalanceError::Overflow)
     }
@@ -201,9 +218,26 @@ impl Balance {
         Ok(())
     }
 
+    /// Returns the value in the account of received notes that have been locked against
+    /// spending with [`WalletWrite::set_note_spend_policy`]. This value is not included in any
+    /// of the other components of the balance, irrespective of confirmation depth.
+    pub fn locked_value(&self) -> NonNegativeAmount {
+        self.locked_value
+    }
+
+    /// Adds the specified value to the locked total, checking for overflow.
+    pub fn add_locked_value(&mut self, value: NonNegativeAmount) -> Result<(), BalanceError> {
+        self.check_total_adding(value)?;
+        self.locked_value = (self.locked_value + value).unwrap();
+        Ok(())
+    }
+
     /// Returns the total value of funds represented by this [`Balance`].
     pub fn total(&self) -> NonNegativeAmount {
-        (self.spendable_value + self.change_pending_confirmation + self.value_pending_spendability)
+        (self.spendable_value
+            + self.change_pending_confirmation
+            + self.value_pending_spendability
+            + self.locked_value)
             .expect("Balance cannot overflow MAX_MONEY")
     }
 }
@@ -226,6 +260,13 @@ pub struct AccountBalance {
     /// zero-conf transaction to perform that shielding, and the resulting shielded notes will be
     /// subject to normal confirmation rules.
     unshielded: NonNegativeAmount,
+
+    /// The value of all unspent transparent outputs belonging to the account that have been
+    /// locked against spending. This value is not included in `unshielded`.
+    unshielded_locked: NonNegativeAmount,
+
+    /// Whether this balance reflects only the value received by the account.
+    received_only: bool,
 }
 
 impl AccountBalance {
@@ -234,10 +275,15 @@ impl AccountBalance {
         sapling_balance: Balance::ZERO,
         orchard_balance: Balance::ZERO,
         unshielded: NonNegativeAmount::ZERO,
+        unshielded_locked: NonNegativeAmount::ZERO,
+        received_only: false,
     };
 
     fn check_total(&self) -> Result<NonNegativeAmount, BalanceError> {
-        (self.sapling_balance.total() + self.orchard_balance.total() + self.unshielded)
+        (self.sapling_balance.total()
+            + self.orchard_balance.total()
+            + self.unshielded
+            + self.unshielded_locked)
             .ok_or(BalanceError::Overflow)
     }
 
@@ -275,7 +321,8 @@ impl AccountBalance {
         Ok(result)
     }
 
-    /// Returns the total value of unspent transparent transaction outputs belonging to the wallet.
+    /// Returns the total value of unspent transparent transaction outputs belonging to the wallet,
+    /// excluding those that have been locked against spending.
     pub fn unshielded(&self) -> NonNegativeAmount {
         self.unshielded
     }
@@ -288,9 +335,29 @@ impl AccountBalance {
         Ok(())
     }
 
+    /// Returns the total value of unspent transparent transaction outputs belonging to the wallet
+    /// that have been locked against spending.
+    pub fn unshielded_locked(&self) -> NonNegativeAmount {
+        self.unshielded_locked
+    }
+
+    /// Adds the specified value to the locked unshielded total, checking for overflow of
+    /// the total account balance.
+    pub fn add_unshielded_locked_value(
+        &mut self,
+        value: NonNegativeAmount,
+    ) -> Result<(), BalanceError> {
+        self.unshielded_locked = (self.unshielded_locked + value).ok_or(BalanceError::Overflow)?;
+        self.check_total()?;
+        Ok(())
+    }
+
     /// Returns the total value of funds belonging to the account.
     pub fn total(&self) -> NonNegativeAmount {
-        (self.sapling_balance.total() + self.orchard_balance.total() + self.unshielded)
+        (self.sapling_balance.total()
+            + self.orchard_balance.total()
+            + self.unshielded
+            + self.unshielded_locked)
             .expect("Account balance cannot overflow MAX_MONEY")
     }
 
@@ -316,6 +383,183 @@ impl AccountBalance {
             + self.orchard_balance.value_pending_spendability)
             .expect("Account balance cannot overflow MAX_MONEY")
     }
+
+    /// Returns the total value of shielded (Sapling and Orchard) funds that have been locked
+    /// against spending.
+    pub fn locked_value(&self) -> NonNegativeAmount {
+        (self.sapling_balance.locked_value + self.orchard_balance.locked_value)
+            .expect("Account balance cannot overflow MAX_MONEY")
+    }
+
+    /// Returns `true` if this balance reflects only the value received by the account.
+    ///
+    /// This is the case for accounts for which only an incoming viewing key is available.
+    /// Such accounts cannot detect when their notes are spent, so the balance may include
+    /// value that has since been spent, and none of it is reported as spendable.
+    pub fn is_received_only(&self) -> bool {
+        self.received_only
+    }
+
+    /// Marks this balance as reflecting only the value received by the account. See
+    /// [`Self::is_received_only`].
+    pub fn mark_received_only(&mut self) {
+        self.received_only = true;
+    }
+}
+
+/// The value held by an account in a single value pool, classified by whether and when it can be
+/// spent.
+///
+/// This is returned per pool by [`WalletRead::get_account_pool_balances`]. Each unspent output
+/// contributes to exactly one of the components of the balance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolBalance {
+    spendable_value: NonNegativeAmount,
+    change_pending_confirmation: NonNegativeAmount,
+    value_pending_confirmation: NonNegativeAmount,
+    unconfirmed_value: NonNegativeAmount,
+    locked_value: NonNegativeAmount,
+}
+
+impl PoolBalance {
+    /// The [`PoolBalance`] value having zero values for all its fields.
+    pub const ZERO: Self = Self {
+        spendable_value: NonNegativeAmount::ZERO,
+        change_pending_confirmation: NonNegativeAmount::ZERO,
+        value_pending_confirmation: NonNegativeAmount::ZERO,
+        unconfirmed_value: NonNegativeAmount::ZERO,
+        locked_value: NonNegativeAmount::ZERO,
+    };
+
+    fn add_checked(
+        &self,
+        field: NonNegativeAmount,
+        value: NonNegativeAmount,
+    ) -> Result<NonNegativeAmount, BalanceError> {
+        (self.total() + value).ok_or(BalanceError::Overflow)?;
+        (field + value).ok_or(BalanceError::Overflow)
+    }
+
+    /// Returns the value of outputs that are confirmed to the required confirmation depth and
+    /// may currently be spent.
+    pub fn spendable_value(&self) -> NonNegativeAmount {
+        self.spendable_value
+    }
+
+    /// Adds the specified value to the spendable total, checking for overflow.
+    pub fn add_spendable_value(&mut self, value: NonNegativeAmount) -> Result<(), BalanceError> {
+        self.spendable_value = self.add_checked(self.spendable_value, value)?;
+        Ok(())
+    }
+
+    /// Returns the value of change outputs that do not yet have sufficient confirmations to be
+    /// spendable, including change in transactions that have not yet been mined.
+    pub fn change_pending_confirmation(&self) -> NonNegativeAmount {
+        self.change_pending_confirmation
+    }
+
+    /// Adds the specified value to the pending change total, checking for overflow.
+    pub fn add_pending_change_value(
+        &mut self,
+        value: NonNegativeAmount,
+    ) -> Result<(), BalanceError> {
+        self.change_pending_confirmation =
+            self.add_checked(self.change_pending_confirmation, value)?;
+        Ok(())
+    }
+
+    /// Returns the value of received outputs that have been mined but are not yet spendable,
+    /// either because they do not have sufficient confirmations or because additional scanning
+    /// is required before witnesses for them can be constructed.
+    pub fn value_pending_confirmation(&self) -> NonNegativeAmount {
+        self.value_pending_confirmation
+    }
+
+    /// Adds the specified value to the pending confirmation total, checking for overflow.
+    pub fn add_pending_confirmation_value(
+        &mut self,
+        value: NonNegativeAmount,
+    ) -> Result<(), BalanceError> {
+        self.value_pending_confirmation =
+            self.add_checked(self.value_pending_confirmation, value)?;
+        Ok(())
+    }
+
+    /// Returns the value of received outputs, other than change, in transactions that have not
+    /// yet been mined.
+    pub fn unconfirmed_value(&self) -> NonNegativeAmount {
+        self.unconfirmed_value
+    }
+
+    /// Adds the specified value to the unconfirmed total, checking for overflow.
+    pub fn add_unconfirmed_value(&mut self, value: NonNegativeAmount) -> Result<(), BalanceError> {
+        self.unconfirmed_value = self.add_checked(self.unconfirmed_value, value)?;
+        Ok(())
+    }
+
+    /// Returns the value of outputs that have been locked against spending. This value is not
+    /// included in any of the other components of the balance.
+    pub fn locked_value(&self) -> NonNegativeAmount {
+        self.locked_value
+    }
+
+    /// Adds the specified value to the locked total, checking for overflow.
+    pub fn add_locked_value(&mut self, value: NonNegativeAmount) -> Result<(), BalanceError> {
+        self.locked_value = self.add_checked(self.locked_value, value)?;
+        Ok(())
+    }
+
+    /// Returns the total value of funds represented by this [`PoolBalance`].
+    pub fn total(&self) -> NonNegativeAmount {
+        (self.spendable_value
+            + self.change_pending_confirmation
+            + self.value_pending_confirmation
+            + self.unconfirmed_value
+            + self.locked_value)
+            .expect("Balance cannot overflow MAX_MONEY")
+    }
+}
+
+/// The balances held by a single account in each of the value pools, as returned by
+/// [`WalletRead::get_account_pool_balances`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccountPoolBalances {
+    sapling: PoolBalance,
+    orchard: PoolBalance,
+    transparent: PoolBalance,
+}
+
+impl AccountPoolBalances {
+    /// The [`AccountPoolBalances`] value having zero balances in every pool.
+    pub const ZERO: Self = Self {
+        sapling: PoolBalance::ZERO,
+        orchard: PoolBalance::ZERO,
+        transparent: PoolBalance::ZERO,
+    };
+
+    /// Returns the balance held by the account in the given pool.
+    pub fn pool(&self, pool: PoolType) -> &PoolBalance {
+        match pool {
+            PoolType::Transparent => &self.transparent,
+            PoolType::Shielded(ShieldedProtocol::Sapling) => &self.sapling,
+            PoolType::Shielded(ShieldedProtocol::Orchard) => &self.orchard,
+        }
+    }
+
+    /// Returns a mutable reference to the balance held by the account in the given pool.
+    pub fn pool_mut(&mut self, pool: PoolType) -> &mut PoolBalance {
+        match pool {
+            PoolType::Transparent => &mut self.transparent,
+            PoolType::Shielded(ShieldedProtocol::Sapling) => &mut self.sapling,
+            PoolType::Shielded(ShieldedProtocol::Orchard) => &mut self.orchard,
+        }
+    }
+
+    /// Returns the total value of funds belonging to the account across all pools.
+    pub fn total(&self) -> NonNegativeAmount {
+        (self.sapling.total() + self.orchard.total() + self.transparent.total())
+            .expect("Account balance cannot overflow MAX_MONEY")
+    }
 }
 
 /// The kinds of accounts supported by `zcash_client_backend`.
@@ -329,6 +573,15 @@ pub enum AccountSource {
 
     /// An account imported from a viewing key.
     Imported,
+
+    /// An account that tracks a single, externally generated transparent address, such as that
+    /// of a paper wallet.
+    ///
+    /// Such an account has no shielded viewing keys, so it cannot receive shielded funds or
+    /// generate addresses. The wallet detects the UTXOs received at its address, and these can
+    /// be swept into a shielded account of the wallet once the spending key for the address is
+    /// available at signing time.
+    StandaloneTransparent,
 }
 
 /// A set of capabilities that a client account must provide.
@@ -346,12 +599,12 @@ pub trait Account<AccountId: Copy> {
     /// they are unable to maintain an accurate balance.
     fn ufvk(&self) -> Option<&UnifiedFullViewingKey>;
 
-    /// Returns the UIVK that the wallet backend has stored for the account.
+    /// Returns the UIVK that the wallet backend has stored for the account, if any.
     ///
-    /// All accounts are required to have at least an incoming viewing key. This gives no
-    /// indication about whether an account can be used in a wallet context; for that, use
-    /// [`Account::ufvk`].
-    fn uivk(&self) -> UnifiedIncomingViewingKey;
+    /// All accounts other than [`AccountSource::StandaloneTransparent`] accounts are required
+    /// to have at least an incoming viewing key. This gives no indication about whether an
+    /// account can be used in a wallet context; for that, use [`Account::ufvk`].
+    fn uivk(&self) -> Option<UnifiedIncomingViewingKey>;
 }
 
 #[cfg(any(test, feature = "test-dependencies"))]
@@ -368,8 +621,8 @@ impl<A: Copy> Account<A> for (A, UnifiedFullViewingKey) {
         Some(&self.1)
     }
 
-    fn uivk(&self) -> UnifiedIncomingViewingKey {
-        self.1.to_unified_incoming_viewing_key()
+    fn uivk(&self) -> Option<UnifiedIncomingViewingKey> {
+        Some(self.1.to_unified_incoming_viewing_key())
     }
 }
 
@@ -387,11 +640,90 @@ impl<A: Copy> Account<A> for (A, UnifiedIncomingViewingKey) {
         None
     }
 
-    fn uivk(&self) -> UnifiedIncomingViewingKey {
-        self.1.clone()
+    fn uivk(&self) -> Option<UnifiedIncomingViewingKey> {
+        Some(self.1.clone())
     }
 }
 
+/// The details of an account, as returned by [`WalletRead::get_account_details`].
+#[derive(Clone, Debug)]
+pub struct AccountDetails<AccountId> {
+    account_id: AccountId,
+    source: AccountSource,
+    ufvk: Option<UnifiedFullViewingKey>,
+    uivk: Option<UnifiedIncomingViewingKey>,
+    birthday_height: BlockHeight,
+    name: Option<String>,
+}
+
+impl<AccountId: Copy> AccountDetails<AccountId> {
+    /// Constructs a new [`AccountDetails`] from its constituent parts.
+    pub fn from_parts(
+        account_id: AccountId,
+        source: AccountSource,
+        ufvk: Option<UnifiedFullViewingKey>,
+        uivk: Option<UnifiedIncomingViewingKey>,
+        birthday_height: BlockHeight,
+        name: Option<String>,
+    ) -> Self {
+        Self {
+            account_id,
+            source,
+            ufvk,
+            uivk,
+            birthday_height,
+            name,
+        }
+    }
+
+    /// Returns the unique identifier for the account.
+    pub fn account_id(&self) -> AccountId {
+        self.account_id
+    }
+
+    /// Returns whether the account is derived or imported, and the derivation parameters if
+    /// applicable.
+    pub fn source(&self) -> AccountSource {
+        self.source
+    }
+
+    /// Returns the UFVK that the wallet backend has stored for the account, if any.
+    pub fn ufvk(&self) -> Option<&UnifiedFullViewingKey> {
+        self.ufvk.as_ref()
+    }
+
+    /// Returns the UIVK that the wallet backend has stored for the account, if any.
+    ///
+    /// This is `None` only for [`AccountSource::StandaloneTransparent`] accounts.
+    pub fn uivk(&self) -> Option<&UnifiedIncomingViewingKey> {
+        self.uivk.as_ref()
+    }
+
+    /// Returns the birthday height of the account.
+    pub fn birthday_height(&self) -> BlockHeight {
+        self.birthday_height
+    }
+
+    /// Returns the human-readable name of the account, if one has been set with
+    /// [`WalletWrite::set_account_name`].
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+/// A value in the application-defined metadata of an account.
+///
+/// See [`WalletWrite::set_account_metadata`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccountMetadataValue {
+    /// A UTF-8 string.
+    Text(String),
+    /// A signed integer.
+    Integer(i64),
+    /// An arbitrary byte string.
+    Bytes(Vec<u8>),
+}
+
 /// A polymorphic ratio type, usually used for rational numbers.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Ratio<T> {
@@ -773,6 +1105,26 @@ pub trait WalletRead {
     /// to the wallet.
     fn get_account_birthday(&self, account: Self::AccountId) -> Result<BlockHeight, Self::Error>;
 
+    /// Returns the details of the given account, including its keys, birthday height and name,
+    /// or `Ok(None)` if the account is not known to the wallet.
+    fn get_account_details(
+        &self,
+        account: Self::AccountId,
+    ) -> Result<Option<AccountDetails<Self::AccountId>>, Self::Error>;
+
+    /// Returns the name that has been set for the given account with
+    /// [`WalletWrite::set_account_name`], if any, or an error if the account is not known to the
+    /// wallet.
+    fn get_account_name(&self, account: Self::AccountId) -> Result<Option<String>, Self::Error>;
+
+    /// Returns the application-defined metadata that has been stored for the given account with
+    /// [`WalletWrite::set_account_metadata`], or an error if the account is not known to the
+    /// wallet.
+    fn get_account_metadata(
+        &self,
+        account: Self::AccountId,
+    ) -> Result<BTreeMap<String, AccountMetadataValue>, Self::Error>;
+
     /// Returns the birthday height for the wallet.
     ///
     /// This returns the earliest birthday height among accounts maintained by this wallet,
@@ -786,6 +1138,18 @@ pub trait WalletRead {
         min_confirmations: u32,
     ) -> Result<Option<WalletSummary<Self::AccountId>>, Self::Error>;
 
+    /// Returns the balances of every account in the wallet, broken down by value pool, given the
+    /// specified minimum number of confirmations.
+    ///
+    /// The returned map contains an entry for each account in the wallet, including accounts
+    /// that hold no funds. All balances are computed against the same view of the wallet's
+    /// state, so that they are consistent with one another even if new blocks are being
+    /// written concurrently.
+    fn get_account_pool_balances(
+        &self,
+        min_confirmations: u32,
+    ) -> Result<HashMap<Self::AccountId, AccountPoolBalances>, Self::Error>;
+
     /// Returns the height of the chain as known to the wallet as of the most recent call to
     /// [`WalletWrite::update_chain_tip`].
     ///
@@ -818,7 +1182,8 @@ pub trait WalletRead {
     ///
     /// If the wallet is fully synced, this will be equivalent to `block_fully_scanned`;
     /// otherwise the maximal scanned height is likely to be greater than the fully scanned height
-    /// due to the fact that out-of-order scanning can leave gaps.
+    /// due to the fact that out-of-order scanning can leave gaps. Blocks that were only scanned
+    /// for spends, via [`WalletWrite::put_spend_scanned_blocks`], are not considered scanned.
     fn block_max_scanned(&self) -> Result<Option<BlockMetadata>, Self::Error>;
 
     /// Returns a vector of suggested scan ranges based upon the current wallet state.
@@ -852,6 +1217,19 @@ pub trait WalletRead {
     /// Returns the minimum block height corresponding to an unspent note in the wallet.
     fn get_min_unspent_height(&self) -> Result<Option<BlockHeight>, Self::Error>;
 
+    /// Returns the height to which [`WalletWrite::truncate_to_height`] would rewind the wallet
+    /// if it were called with `max_height`.
+    ///
+    /// This is the greatest height at or below `max_height` to which the wallet can be safely
+    /// truncated. It is `max_height` itself if no blocks above `max_height` have been scanned;
+    /// otherwise it is the greatest such height for which the wallet retains a note commitment
+    /// tree checkpoint. Returns `None` if there is no such height, in which case
+    /// [`WalletWrite::truncate_to_height`] will return an error.
+    fn get_truncation_height(
+        &self,
+        max_height: BlockHeight,
+    ) -> Result<Option<BlockHeight>, Self::Error>;
+
     /// Returns the block height in which the specified transaction was mined, or `Ok(None)` if the
     /// transaction is not in the main chain.
     fn get_tx_height(&self, txid: TxId) -> Result<Option<BlockHeight>, Self::Error>;
@@ -891,8 +1269,9 @@ pub trait WalletRead {
     /// Returns the set of all transparent receivers associated with the given account.
     ///
     /// The set contains all transparent receivers that are known to have been derived
-    /// under this account. Wallets should scan the chain for UTXOs sent to these
-    /// receivers.
+    /// under this account, including the ephemeral addresses returned by
+    /// [`WalletRead::get_ephemeral_address_usage`]. Wallets should scan the chain for UTXOs
+    /// sent to these receivers.
     #[cfg(feature = "transparent-inputs")]
     fn get_transparent_receivers(
         &self,
@@ -903,6 +1282,9 @@ pub trait WalletRead {
 
     /// Returns a mapping from transparent receiver to not-yet-shielded UTXO balance,
     /// for each address associated with a nonzero balance.
+    ///
+    /// UTXOs that have been locked against spending with [`WalletWrite::set_utxo_spend_policy`]
+    /// are not included.
     #[cfg(feature = "transparent-inputs")]
     fn get_transparent_balances(
         &self,
@@ -911,6 +1293,102 @@ pub trait WalletRead {
     ) -> Result<HashMap<TransparentAddress, NonNegativeAmount>, Self::Error> {
         Ok(HashMap::new())
     }
+
+    /// Returns the usage of each external transparent address that the wallet has derived for
+    /// the given account under its gap limit, in order of address index.
+    ///
+    /// The wallet derives every address up to the gap limit beyond the last address known to
+    /// have received funds, and includes these in [`WalletRead::get_transparent_receivers`].
+    /// When recovering an account from its seed, a wallet should look up the UTXOs received by
+    /// each unused address and add them with [`WalletWrite::put_received_transparent_utxo`],
+    /// repeating until no further funds are found: each newly used address advances the window
+    /// of derived addresses.
+    #[cfg(feature = "transparent-inputs")]
+    fn get_transparent_address_usage(
+        &self,
+        _account: Self::AccountId,
+    ) -> Result<Vec<TransparentAddressUsage>, Self::Error> {
+        Ok(vec![])
+    }
+
+    /// Returns the usage of each ephemeral transparent address that the wallet has derived for
+    /// the given account under its gap limit, in order of address index.
+    ///
+    /// Ephemeral addresses are derived under the [ZIP 320] ephemeral scope
+    /// `m/44'/<coin_type>'/<account>'/2`, and each is used once, to fund a payment to a TEX
+    /// address. They are tracked separately from external addresses, with a gap limit window of
+    /// their own. When recovering an account from its seed, a wallet should look up the
+    /// transactions that paid each unused ephemeral address and record the earliest with
+    /// [`WalletWrite::mark_transparent_address_used`], repeating until no further transactions
+    /// are found.
+    ///
+    /// [ZIP 320]: https://zips.z.cash/zip-0320
+    #[cfg(feature = "transparent-inputs")]
+    fn get_ephemeral_address_usage(
+        &self,
+        _account: Self::AccountId,
+    ) -> Result<Vec<TransparentAddressUsage>, Self::Error> {
+        Ok(vec![])
+    }
+
+    /// Returns the stored exchange rate observation for the given currency that was fetched
+    /// closest in time to `time`, or `Ok(None)` if no observation was fetched within
+    /// `max_distance` of `time`.
+    ///
+    /// An observation fetched exactly `max_distance` away from `time` is considered close
+    /// enough. If two observations are equally close, the earlier one is returned.
+    fn get_exchange_rate_nearest(
+        &self,
+        currency: Currency,
+        time: time::OffsetDateTime,
+        max_distance: time::Duration,
+    ) -> Result<Option<ExchangeRateObservation>, Self::Error>;
+
+    /// Returns the draft payments that have been saved for the given account, in the order
+    /// in which they were saved.
+    fn get_drafts(
+        &self,
+        account: Self::AccountId,
+    ) -> Result<Vec<(DraftId, DraftPayment)>, Self::Error>;
+
+    /// Returns the draft payment with the given identifier, along with the account for which
+    /// it was saved, or `Ok(None)` if no such draft exists.
+    fn get_draft(
+        &self,
+        draft_id: DraftId,
+    ) -> Result<Option<(Self::AccountId, DraftPayment)>, Self::Error>;
+
+    /// Returns a page of summaries of the transactions that affected the given account and
+    /// match `filter`.
+    ///
+    /// Transactions are ordered with unmined transactions first, followed by mined
+    /// transactions in descending order of mined height and then of index within the block.
+    /// At most `limit` summaries are returned, starting at position `offset`.
+    ///
+    /// This ordering is total and does not depend on the state of the chain, so the position
+    /// of a mined transaction changes only when transactions are added ahead of it. To page
+    /// through the history without skipping or duplicating rows while new blocks arrive,
+    /// restrict `filter` to mined heights up to the chain tip observed when fetching the first
+    /// page.
+    fn get_transactions(
+        &self,
+        account: Self::AccountId,
+        filter: &TransactionFilter,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<TransactionSummary>, Self::Error>;
+
+    /// Returns the memos on outputs sent or received by the given account that match `query`,
+    /// along with the identifiers of the outputs they were found on.
+    ///
+    /// Results are ordered by the height at which the containing transaction was mined, with
+    /// unmined transactions last. A memo that the account both sent and received, such as a
+    /// memo on a payment to itself, is returned once.
+    fn find_memos(
+        &self,
+        account: Self::AccountId,
+        query: &MemoQuery,
+    ) -> Result<Vec<(NoteId, MemoBytes)>, Self::Error>;
 }
 
 /// The relevance of a seed to a given wallet.
@@ -928,6 +1406,370 @@ pub enum SeedRelevance<A: Copy> {
     NoAccounts,
 }
 
+/// The usage of an external or ephemeral transparent address that the wallet has derived for
+/// an account under its gap limit.
+///
+/// This is the type returned by [`WalletRead::get_transparent_address_usage`] and
+/// [`WalletRead::get_ephemeral_address_usage`].
+#[cfg(feature = "transparent-inputs")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransparentAddressUsage {
+    address: TransparentAddress,
+    metadata: TransparentAddressMetadata,
+    reserved: bool,
+    first_use_height: Option<BlockHeight>,
+}
+
+#[cfg(feature = "transparent-inputs")]
+impl TransparentAddressUsage {
+    /// Constructs a new [`TransparentAddressUsage`] from its constituent parts.
+    pub fn from_parts(
+        address: TransparentAddress,
+        metadata: TransparentAddressMetadata,
+        reserved: bool,
+        first_use_height: Option<BlockHeight>,
+    ) -> Self {
+        Self {
+            address,
+            metadata,
+            reserved,
+            first_use_height,
+        }
+    }
+
+    /// Returns the address.
+    pub fn address(&self) -> &TransparentAddress {
+        &self.address
+    }
+
+    /// Returns the derivation metadata for the address.
+    pub fn metadata(&self) -> &TransparentAddressMetadata {
+        &self.metadata
+    }
+
+    /// Returns whether the address has been handed out by
+    /// [`WalletWrite::reserve_next_transparent_addresses`] or
+    /// [`WalletWrite::reserve_next_ephemeral_addresses`].
+    pub fn is_reserved(&self) -> bool {
+        self.reserved
+    }
+
+    /// Returns the height of the earliest block in which the address is known to have
+    /// received funds, or `None` if the address is not known to have been used.
+    pub fn first_use_height(&self) -> Option<BlockHeight> {
+        self.first_use_height
+    }
+}
+
+/// An observation of the price of ZEC in a fiat currency, as fetched from an exchange rate
+/// source at a particular time.
+///
+/// This is the type stored by [`WalletWrite::insert_exchange_rate`], and retrieved by
+/// [`WalletRead::get_exchange_rate_nearest`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExchangeRateObservation {
+    currency: Currency,
+    rate: Decimal,
+    fetched_at: time::OffsetDateTime,
+}
+
+impl ExchangeRateObservation {
+    /// Constructs a new observation from its constituent parts.
+    ///
+    /// `rate` is the value of 1 ZEC in units of `currency`.
+    pub fn from_parts(currency: Currency, rate: Decimal, fetched_at: time::OffsetDateTime) -> Self {
+        Self {
+            currency,
+            rate,
+            fetched_at,
+        }
+    }
+
+    /// Returns the currency in which the price of ZEC was observed.
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    /// Returns the value of 1 ZEC in units of [`Self::currency`].
+    pub fn rate(&self) -> Decimal {
+        self.rate
+    }
+
+    /// Returns the time at which the rate was fetched.
+    pub fn fetched_at(&self) -> time::OffsetDateTime {
+        self.fetched_at
+    }
+}
+
+/// A unique identifier for a draft payment saved in the wallet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DraftId(u64);
+
+impl DraftId {
+    /// Constructs a draft identifier from its underlying integer representation.
+    pub fn from_u64(id: u64) -> Self {
+        DraftId(id)
+    }
+
+    /// Returns the underlying integer representation of this identifier.
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A payment that has been composed, but not yet confirmed by the user.
+///
+/// A draft records what the user intends to pay, not how the wallet would fund it. No
+/// proposal is stored alongside the draft; instead, [`wallet::promote_draft_to_proposal`]
+/// selects inputs against the wallet state at the time the draft is confirmed. Drafts have
+/// no effect on the wallet's balance.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DraftPayment {
+    request: TransactionRequest,
+    min_confirmations: NonZeroU32,
+    note: String,
+}
+
+impl DraftPayment {
+    /// Constructs a new draft payment.
+    ///
+    /// `min_confirmations` is the confirmation policy that will be used to select inputs
+    /// when the draft is promoted to a proposal, and `note` is free-form text for the user's
+    /// own reference.
+    pub fn new(request: TransactionRequest, min_confirmations: NonZeroU32, note: String) -> Self {
+        Self {
+            request,
+            min_confirmations,
+            note,
+        }
+    }
+
+    /// Returns the payment request that this draft will pay.
+    pub fn request(&self) -> &TransactionRequest {
+        &self.request
+    }
+
+    /// Returns the minimum number of confirmations required for notes spent by this draft.
+    pub fn min_confirmations(&self) -> NonZeroU32 {
+        self.min_confirmations
+    }
+
+    /// Returns the user's note for this draft.
+    pub fn note(&self) -> &str {
+        &self.note
+    }
+}
+
+/// The direction of value flow for an account in a transaction, as used by
+/// [`TransactionFilter`] and [`MemoQuery`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransactionDirection {
+    /// The transaction decreased the account's balance.
+    Sent,
+    /// The transaction increased the account's balance.
+    Received,
+}
+
+/// Criteria used to select transactions in [`WalletRead::get_transactions`].
+///
+/// The default filter matches every transaction involving the account.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransactionFilter {
+    direction: Option<TransactionDirection>,
+    mined_heights: Option<Range<BlockHeight>>,
+    block_times: Option<Range<u32>>,
+    min_value: Option<NonNegativeAmount>,
+}
+
+impl TransactionFilter {
+    /// Returns a filter that matches every transaction.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restricts the filter to transactions that moved value in the given direction.
+    pub fn with_direction(mut self, direction: TransactionDirection) -> Self {
+        self.direction = Some(direction);
+        self
+    }
+
+    /// Restricts the filter to transactions mined within the given range of heights.
+    ///
+    /// Unmined transactions never match a filter with a height range.
+    pub fn with_mined_heights(mut self, heights: Range<BlockHeight>) -> Self {
+        self.mined_heights = Some(heights);
+        self
+    }
+
+    /// Restricts the filter to transactions mined in blocks whose timestamps, in seconds
+    /// since the Unix epoch, lie within the given range.
+    ///
+    /// Unmined transactions never match a filter with a time range.
+    pub fn with_block_times(mut self, times: Range<u32>) -> Self {
+        self.block_times = Some(times);
+        self
+    }
+
+    /// Restricts the filter to transactions that changed the account's balance by at least
+    /// the given value, in either direction.
+    pub fn with_min_value(mut self, value: NonNegativeAmount) -> Self {
+        self.min_value = Some(value);
+        self
+    }
+
+    /// Returns the direction of value flow that transactions must have, if any.
+    pub fn direction(&self) -> Option<TransactionDirection> {
+        self.direction
+    }
+
+    /// Returns the range of heights within which transactions must have been mined, if any.
+    pub fn mined_heights(&self) -> Option<&Range<BlockHeight>> {
+        self.mined_heights.as_ref()
+    }
+
+    /// Returns the range of block timestamps within which transactions must have been mined,
+    /// if any.
+    pub fn block_times(&self) -> Option<&Range<u32>> {
+        self.block_times.as_ref()
+    }
+
+    /// Returns the minimum absolute change in the account's balance, if any.
+    pub fn min_value(&self) -> Option<NonNegativeAmount> {
+        self.min_value
+    }
+}
+
+/// The content that a memo must have in order to match a [`MemoQuery`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MemoPattern {
+    /// Matches text memos that contain the given string, ignoring case.
+    Text(String),
+    /// Matches memos whose bytes are exactly equal to the given memo, including memos
+    /// that are not valid text.
+    Bytes(MemoBytes),
+}
+
+/// Criteria used to search memos in [`WalletRead::find_memos`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoQuery {
+    pattern: MemoPattern,
+    direction: Option<TransactionDirection>,
+}
+
+impl MemoQuery {
+    /// Returns a query that matches text memos containing `text`, ignoring case.
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::new(MemoPattern::Text(text.into()))
+    }
+
+    /// Returns a query that matches memos with exactly the given bytes.
+    pub fn bytes(memo: MemoBytes) -> Self {
+        Self::new(MemoPattern::Bytes(memo))
+    }
+
+    /// Returns a query that matches memos against the given pattern.
+    pub fn new(pattern: MemoPattern) -> Self {
+        Self {
+            pattern,
+            direction: None,
+        }
+    }
+
+    /// Restricts the query to memos on outputs that the account sent
+    /// ([`TransactionDirection::Sent`]) or received ([`TransactionDirection::Received`]).
+    ///
+    /// By default, both sent and received memos are searched.
+    pub fn with_direction(mut self, direction: TransactionDirection) -> Self {
+        self.direction = Some(direction);
+        self
+    }
+
+    /// Returns the pattern that memos must match.
+    pub fn pattern(&self) -> &MemoPattern {
+        &self.pattern
+    }
+
+    /// Returns the direction of the outputs to be searched, if restricted.
+    pub fn direction(&self) -> Option<TransactionDirection> {
+        self.direction
+    }
+}
+
+/// A summary of the effects of a transaction on a single account, as returned by
+/// [`WalletRead::get_transactions`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactionSummary {
+    txid: TxId,
+    mined_height: Option<BlockHeight>,
+    fee_paid: Option<NonNegativeAmount>,
+    account_value_delta: Amount,
+    has_memo: bool,
+    transparent_output_count: usize,
+    sapling_output_count: usize,
+    orchard_output_count: usize,
+}
+
+impl TransactionSummary {
+    /// Constructs a new [`TransactionSummary`] from its constituent parts.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_parts(
+        txid: TxId,
+        mined_height: Option<BlockHeight>,
+        fee_paid: Option<NonNegativeAmount>,
+        account_value_delta: Amount,
+        has_memo: bool,
+        transparent_output_count: usize,
+        sapling_output_count: usize,
+        orchard_output_count: usize,
+    ) -> Self {
+        Self {
+            txid,
+            mined_height,
+            fee_paid,
+            account_value_delta,
+            has_memo,
+            transparent_output_count,
+            sapling_output_count,
+            orchard_output_count,
+        }
+    }
+
+    /// Returns the transaction ID.
+    pub fn txid(&self) -> TxId {
+        self.txid
+    }
+
+    /// Returns the height at which the transaction was mined, or `None` if it is unmined.
+    pub fn mined_height(&self) -> Option<BlockHeight> {
+        self.mined_height
+    }
+
+    /// Returns the fee paid by the transaction, if known.
+    pub fn fee_paid(&self) -> Option<NonNegativeAmount> {
+        self.fee_paid
+    }
+
+    /// Returns the net change in the account's balance caused by the transaction.
+    pub fn account_value_delta(&self) -> Amount {
+        self.account_value_delta
+    }
+
+    /// Returns whether any output of the transaction involving the account carries a memo.
+    pub fn has_memo(&self) -> bool {
+        self.has_memo
+    }
+
+    /// Returns the number of outputs in the given pool that were sent from or received by
+    /// the account, including change outputs.
+    pub fn output_count(&self, pool: PoolType) -> usize {
+        match pool {
+            PoolType::Transparent => self.transparent_output_count,
+            PoolType::Shielded(ShieldedProtocol::Sapling) => self.sapling_output_count,
+            PoolType::Shielded(ShieldedProtocol::Orchard) => self.orchard_output_count,
+        }
+    }
+}
+
 /// Metadata describing the sizes of the zcash note commitment trees as of a particular block.
 #[derive(Debug, Clone, Copy)]
 pub struct BlockMetadata {
@@ -1471,6 +2313,42 @@ pub trait WalletWrite: WalletRead {
         birthday: &AccountBirthday,
     ) -> Result<(Self::AccountId, UnifiedSpendingKey), Self::Error>;
 
+    /// Tells the wallet to track an account for which only a [`UnifiedIncomingViewingKey`]
+    /// is available.
+    ///
+    /// Such an account can detect funds sent to it, but because no nullifiers can be derived
+    /// for its notes, the wallet cannot detect when those funds are spent; its balance is
+    /// therefore reported as received-only, and it cannot be used as the source of a
+    /// transaction.
+    ///
+    /// The birthday semantics are the same as for [`WalletWrite::create_account`].
+    fn import_account_uivk(
+        &mut self,
+        uivk: &UnifiedIncomingViewingKey,
+        birthday: &AccountBirthday,
+    ) -> Result<Self::AccountId, Self::Error>;
+
+    /// Tells the wallet to track a single, externally generated transparent address, such as
+    /// that of a paper wallet, as a new [`AccountSource::StandaloneTransparent`] account.
+    ///
+    /// The wallet includes the address in [`WalletRead::get_transparent_receivers`] for the new
+    /// account, and records the UTXOs received by it that are added with
+    /// [`WalletWrite::put_received_transparent_utxo`]. These can be swept into a shielded
+    /// account with [`wallet::shield_standalone_transparent_funds`] once the spending key for
+    /// the address is available. The account has no shielded viewing keys, so it cannot be
+    /// used to generate addresses or as the source of any other transaction.
+    ///
+    /// `birthday_height` should be no later than the height of the first UTXO received by the
+    /// address; wallets should look up the UTXOs received at the address from that height.
+    ///
+    /// Returns an error if the address is already tracked by the wallet.
+    #[cfg(feature = "transparent-inputs")]
+    fn import_standalone_transparent_address(
+        &mut self,
+        address: &TransparentAddress,
+        birthday_height: BlockHeight,
+    ) -> Result<Self::AccountId, Self::Error>;
+
     /// Generates and persists the next available diversified address, given the current
     /// addresses known to the wallet.
     ///
@@ -1482,6 +2360,28 @@ pub trait WalletWrite: WalletRead {
         request: UnifiedAddressRequest,
     ) -> Result<Option<UnifiedAddress>, Self::Error>;
 
+    /// Sets the human-readable name of the given account, or removes it if `name` is `None`.
+    ///
+    /// Account names are unique within a wallet. Returns an error if another account already has
+    /// the given name, or if the account is not known to the wallet.
+    fn set_account_name(
+        &mut self,
+        account: Self::AccountId,
+        name: Option<&str>,
+    ) -> Result<(), Self::Error>;
+
+    /// Stores a value under the given key in the application-defined metadata of the given
+    /// account, replacing any existing value, or removes the key if `value` is `None`.
+    ///
+    /// Returns an error if the account is not known to the wallet. Removing a key that is not
+    /// present is not an error.
+    fn set_account_metadata(
+        &mut self,
+        account: Self::AccountId,
+        key: &str,
+        value: Option<AccountMetadataValue>,
+    ) -> Result<(), Self::Error>;
+
     /// Updates the wallet's view of the blockchain.
     ///
     /// This method is used to provide the wallet with information about the state of the
@@ -1505,12 +2405,79 @@ pub trait WalletWrite: WalletRead {
         blocks: Vec<ScannedBlock<Self::AccountId>>,
     ) -> Result<(), Self::Error>;
 
+    /// Updates the state of the wallet database by persisting the provided block information,
+    /// where the blocks were scanned only for spends of the wallet's previously-detected notes.
+    ///
+    /// This behaves like [`WalletWrite::put_blocks`], except that the heights of `blocks` must
+    /// not be recorded as having been fully scanned: notes received by the wallet in these
+    /// blocks have not been detected, and so the range must remain eligible to be returned by
+    /// [`WalletRead::suggest_scan_ranges`] until it has been scanned with
+    /// [`WalletWrite::put_blocks`].
+    ///
+    /// ### Arguments
+    /// - `from_state` must be the chain state for the block height prior to the first
+    ///   block in `blocks`.
+    /// - `blocks` must be sequential, in order of increasing block height.
+    fn put_spend_scanned_blocks(
+        &mut self,
+        from_state: &ChainState,
+        blocks: Vec<ScannedBlock<Self::AccountId>>,
+    ) -> Result<(), Self::Error>;
+
     /// Adds a transparent UTXO received by the wallet to the data store.
+    ///
+    /// If the UTXO was received by an external transparent address derived under the wallet's
+    /// gap limit, the address is recorded as used, as by
+    /// [`WalletWrite::mark_transparent_address_used`].
     fn put_received_transparent_utxo(
         &mut self,
         output: &WalletTransparentOutput,
     ) -> Result<Self::UtxoRef, Self::Error>;
 
+    /// Reserves the `n` unused external transparent addresses with the lowest address indices
+    /// that have not previously been reserved for the given account, and returns them.
+    ///
+    /// Reserved addresses are never returned again by this method, so each may be given out to
+    /// a different party. Only addresses within the gap limit beyond the last used address may
+    /// be reserved; if fewer than `n` such addresses are available, an error is returned and no
+    /// addresses are reserved.
+    #[cfg(feature = "transparent-inputs")]
+    fn reserve_next_transparent_addresses(
+        &mut self,
+        account: Self::AccountId,
+        n: u32,
+    ) -> Result<Vec<(TransparentAddress, TransparentAddressMetadata)>, Self::Error>;
+
+    /// Reserves the `n` unused ephemeral transparent addresses with the lowest address indices
+    /// that have not previously been reserved for the given account, and returns them.
+    ///
+    /// Ephemeral addresses are derived under the [ZIP 320] ephemeral scope, and are used to fund
+    /// payments to TEX addresses; see [`WalletRead::get_ephemeral_address_usage`]. As with
+    /// [`WalletWrite::reserve_next_transparent_addresses`], only addresses within the gap limit
+    /// beyond the last used ephemeral address may be reserved, so that they can be rediscovered
+    /// when the account is recovered from its seed.
+    ///
+    /// [ZIP 320]: https://zips.z.cash/zip-0320
+    #[cfg(feature = "transparent-inputs")]
+    fn reserve_next_ephemeral_addresses(
+        &mut self,
+        account: Self::AccountId,
+        n: u32,
+    ) -> Result<Vec<(TransparentAddress, TransparentAddressMetadata)>, Self::Error>;
+
+    /// Records that the given external or ephemeral transparent address was observed to have
+    /// received funds in the block at the given height, and derives any addresses that this
+    /// brings within the gap limit of its account.
+    ///
+    /// Returns an error if the address is not one that the wallet has derived under its gap
+    /// limit.
+    #[cfg(feature = "transparent-inputs")]
+    fn mark_transparent_address_used(
+        &mut self,
+        address: &TransparentAddress,
+        height: BlockHeight,
+    ) -> Result<(), Self::Error>;
+
     /// Caches a decrypted transaction in the persistent wallet store.
     fn store_decrypted_tx(
         &mut self,
@@ -1524,7 +2491,8 @@ pub trait WalletWrite: WalletRead {
         sent_tx: &SentTransaction<Self::AccountId>,
     ) -> Result<(), Self::Error>;
 
-    /// Truncates the wallet database to the specified height.
+    /// Truncates the wallet database to at most the specified height, and returns the height
+    /// to which the wallet was actually truncated.
     ///
     /// This method assumes that the state of the underlying data store is
     /// consistent up to a particular block height. Since it is possible that
@@ -1532,12 +2500,76 @@ pub trait WalletWrite: WalletRead {
     /// implemented in order to allow users of this API to "reset" the data store
     /// to correctly represent chainstate as of a specified block height.
     ///
-    /// After calling this method, the block at the given height will be the
+    /// The wallet can only be truncated to heights for which it retains enough note commitment
+    /// tree state to resume scanning. The height actually truncated to is the greatest such
+    /// height not exceeding `max_height`, as reported in advance by
+    /// [`WalletRead::get_truncation_height`]; it may be lower than `max_height`. If there is no
+    /// such height, an error is returned and the wallet is not modified.
+    ///
+    /// After calling this method, the block at the returned height will be the
     /// most recent block and all other operations will treat this block
-    /// as the chain tip for balance determination purposes.
+    /// as the chain tip for balance determination purposes. In particular:
+    /// - Metadata for scanned blocks above the returned height is removed, along with the note
+    ///   commitment tree data and nullifier information derived from those blocks. Ranges of
+    ///   the scan queue above the returned height are removed, and will be recreated by the next
+    ///   call to [`WalletWrite::update_chain_tip`].
+    /// - Transactions mined above the returned height are returned to the set of unmined
+    ///   transactions. They are not deleted; transactions created by the wallet may be mined
+    ///   again, or will expire.
+    /// - Notes received in those transactions are retained, along with their memos, but do not
+    ///   contribute to the wallet's balance until their transactions are mined again. Notes
+    ///   spent by those transactions remain marked as spent until the spending transactions
+    ///   expire.
+    /// - Transparent outputs received above the returned height are removed.
+    /// - Received and sent notes, transactions and UTXOs at or below the returned height are
+    ///   retained unchanged.
+    fn truncate_to_height(&mut self, max_height: BlockHeight) -> Result<BlockHeight, Self::Error>;
+
+    /// Stores an exchange rate observation, so that the fiat value of transactions can
+    /// later be displayed as of the time they were made.
+    ///
+    /// An existing observation for the same currency and fetch time is replaced.
+    fn insert_exchange_rate(
+        &mut self,
+        observation: &ExchangeRateObservation,
+    ) -> Result<(), Self::Error>;
+
+    /// Saves a draft payment for the given account, and returns the identifier that may be
+    /// used to retrieve it.
+    fn save_draft(
+        &mut self,
+        account: Self::AccountId,
+        draft: &DraftPayment,
+    ) -> Result<DraftId, Self::Error>;
+
+    /// Deletes the draft payment with the given identifier. Deleting a draft that does not
+    /// exist is not an error.
+    fn delete_draft(&mut self, draft_id: DraftId) -> Result<(), Self::Error>;
+
+    /// Sets whether the received note with the given identifier is locked against spending.
     ///
-    /// There may be restrictions on heights to which it is possible to truncate.
-    fn truncate_to_height(&mut self, block_height: BlockHeight) -> Result<(), Self::Error>;
+    /// A locked note is never returned by [`InputSource::select_spendable_notes`] or
+    /// [`InputSource::get_spendable_note`], and so will not be spent by any transaction
+    /// proposal. Its value remains part of the account's balance, and is reported by
+    /// [`Balance::locked_value`].
+    ///
+    /// Returns an error if the note does not belong to the wallet.
+    fn set_note_spend_policy(&mut self, note_id: NoteId, locked: bool) -> Result<(), Self::Error>;
+
+    /// Sets whether the transparent UTXO at the given outpoint is locked against spending.
+    ///
+    /// A locked UTXO is never returned by [`InputSource::get_unspent_transparent_output`] or
+    /// [`InputSource::get_unspent_transparent_outputs`], and so will not be shielded or spent.
+    /// Its value remains part of the account's balance, and is reported by
+    /// [`AccountBalance::unshielded_locked`].
+    ///
+    /// Returns an error if the UTXO does not belong to the wallet.
+    #[cfg(feature = "transparent-inputs")]
+    fn set_utxo_spend_policy(
+        &mut self,
+        outpoint: &OutPoint,
+        locked: bool,
+    ) -> Result<(), Self::Error>;
 }
 
 /// This trait describes a capability for manipulating wallet note commitment trees.
@@ -1614,21 +2646,29 @@ pub trait WalletCommitmentTrees {
 #[cfg(feature = "test-dependencies")]
 pub mod testing {
     use incrementalmerkletree::Address;
+    use iso_currency::Currency;
     use secrecy::{ExposeSecret, SecretVec};
     use shardtree::{error::ShardTreeError, store::memory::MemoryShardStore, ShardTree};
-    use std::{collections::HashMap, convert::Infallible, num::NonZeroU32};
+    use std::{
+        collections::{BTreeMap, HashMap},
+        convert::Infallible,
+        num::NonZeroU32,
+    };
     use zip32::fingerprint::SeedFingerprint;
 
     use zcash_primitives::{
         block::BlockHash,
         consensus::{BlockHeight, Network},
-        memo::Memo,
+        memo::{Memo, MemoBytes},
         transaction::{components::amount::NonNegativeAmount, Transaction, TxId},
     };
 
     use crate::{
         address::UnifiedAddress,
-        keys::{UnifiedAddressRequest, UnifiedFullViewingKey, UnifiedSpendingKey},
+        keys::{
+            UnifiedAddressRequest, UnifiedFullViewingKey, UnifiedIncomingViewingKey,
+            UnifiedSpendingKey,
+        },
         wallet::{Note, NoteId, ReceivedNote, WalletTransparentOutput},
         ShieldedProtocol,
     };
@@ -1636,13 +2676,18 @@ pub mod testing {
     use super::{
         chain::{ChainState, CommitmentTreeRoot},
         scanning::ScanRange,
-        AccountBirthday, BlockMetadata, DecryptedTransaction, InputSource, NullifierQuery,
-        ScannedBlock, SeedRelevance, SentTransaction, SpendableNotes, WalletCommitmentTrees,
-        WalletRead, WalletSummary, WalletWrite, SAPLING_SHARD_HEIGHT,
+        AccountBirthday, AccountDetails, AccountMetadataValue, AccountPoolBalances, BlockMetadata,
+        DecryptedTransaction, DraftId, DraftPayment, ExchangeRateObservation, InputSource,
+        MemoQuery, NullifierQuery, ScannedBlock, SeedRelevance, SentTransaction, SpendableNotes,
+        TransactionFilter, TransactionSummary, WalletCommitmentTrees, WalletRead, WalletSummary,
+        WalletWrite, SAPLING_SHARD_HEIGHT,
     };
 
     #[cfg(feature = "transparent-inputs")]
-    use {crate::wallet::TransparentAddressMetadata, zcash_primitives::legacy::TransparentAddress};
+    use {
+        crate::wallet::TransparentAddressMetadata,
+        zcash_primitives::{legacy::TransparentAddress, transaction::components::OutPoint},
+    };
 
     #[cfg(feature = "orchard")]
     use super::ORCHARD_SHARD_HEIGHT;
@@ -1761,6 +2806,27 @@ pub mod testing {
             Err(())
         }
 
+        fn get_account_details(
+            &self,
+            _account: Self::AccountId,
+        ) -> Result<Option<AccountDetails<Self::AccountId>>, Self::Error> {
+            Ok(None)
+        }
+
+        fn get_account_name(
+            &self,
+            _account: Self::AccountId,
+        ) -> Result<Option<String>, Self::Error> {
+            Err(())
+        }
+
+        fn get_account_metadata(
+            &self,
+            _account: Self::AccountId,
+        ) -> Result<BTreeMap<String, AccountMetadataValue>, Self::Error> {
+            Err(())
+        }
+
         fn get_wallet_birthday(&self) -> Result<Option<BlockHeight>, Self::Error> {
             Ok(None)
         }
@@ -1772,6 +2838,13 @@ pub mod testing {
             Ok(None)
         }
 
+        fn get_account_pool_balances(
+            &self,
+            _min_confirmations: u32,
+        ) -> Result<HashMap<Self::AccountId, AccountPoolBalances>, Self::Error> {
+            Ok(HashMap::new())
+        }
+
         fn chain_height(&self) -> Result<Option<BlockHeight>, Self::Error> {
             Ok(None)
         }
@@ -1817,6 +2890,13 @@ pub mod testing {
             Ok(None)
         }
 
+        fn get_truncation_height(
+            &self,
+            _max_height: BlockHeight,
+        ) -> Result<Option<BlockHeight>, Self::Error> {
+            Ok(None)
+        }
+
         fn get_tx_height(&self, _txid: TxId) -> Result<Option<BlockHeight>, Self::Error> {
             Ok(None)
         }
@@ -1867,6 +2947,47 @@ pub mod testing {
         ) -> Result<HashMap<TransparentAddress, NonNegativeAmount>, Self::Error> {
             Ok(HashMap::new())
         }
+
+        fn get_exchange_rate_nearest(
+            &self,
+            _currency: Currency,
+            _time: time::OffsetDateTime,
+            _max_distance: time::Duration,
+        ) -> Result<Option<ExchangeRateObservation>, Self::Error> {
+            Ok(None)
+        }
+
+        fn get_drafts(
+            &self,
+            _account: Self::AccountId,
+        ) -> Result<Vec<(DraftId, DraftPayment)>, Self::Error> {
+            Ok(vec![])
+        }
+
+        fn get_draft(
+            &self,
+            _draft_id: DraftId,
+        ) -> Result<Option<(Self::AccountId, DraftPayment)>, Self::Error> {
+            Ok(None)
+        }
+
+        fn get_transactions(
+            &self,
+            _account: Self::AccountId,
+            _filter: &TransactionFilter,
+            _offset: u32,
+            _limit: u32,
+        ) -> Result<Vec<TransactionSummary>, Self::Error> {
+            Ok(vec![])
+        }
+
+        fn find_memos(
+            &self,
+            _account: Self::AccountId,
+            _query: &MemoQuery,
+        ) -> Result<Vec<(NoteId, MemoBytes)>, Self::Error> {
+            Ok(vec![])
+        }
     }
 
     impl WalletWrite for MockWalletDb {
@@ -1884,6 +3005,23 @@ pub mod testing {
                 .map_err(|_| ())
         }
 
+        fn import_account_uivk(
+            &mut self,
+            _uivk: &UnifiedIncomingViewingKey,
+            _birthday: &AccountBirthday,
+        ) -> Result<Self::AccountId, Self::Error> {
+            Ok(0)
+        }
+
+        #[cfg(feature = "transparent-inputs")]
+        fn import_standalone_transparent_address(
+            &mut self,
+            _address: &TransparentAddress,
+            _birthday_height: BlockHeight,
+        ) -> Result<Self::AccountId, Self::Error> {
+            Ok(0)
+        }
+
         fn get_next_available_address(
             &mut self,
             _account: Self::AccountId,
@@ -1892,6 +3030,23 @@ pub mod testing {
             Ok(None)
         }
 
+        fn set_account_name(
+            &mut self,
+            _account: Self::AccountId,
+            _name: Option<&str>,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_account_metadata(
+            &mut self,
+            _account: Self::AccountId,
+            _key: &str,
+            _value: Option<AccountMetadataValue>,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
         #[allow(clippy::type_complexity)]
         fn put_blocks(
             &mut self,
@@ -1901,6 +3056,14 @@ pub mod testing {
             Ok(())
         }
 
+        fn put_spend_scanned_blocks(
+            &mut self,
+            _from_state: &ChainState,
+            _blocks: Vec<ScannedBlock<Self::AccountId>>,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
         fn update_chain_tip(&mut self, _tip_height: BlockHeight) -> Result<(), Self::Error> {
             Ok(())
         }
@@ -1919,7 +3082,46 @@ pub mod testing {
             Ok(())
         }
 
-        fn truncate_to_height(&mut self, _block_height: BlockHeight) -> Result<(), Self::Error> {
+        fn truncate_to_height(
+            &mut self,
+            max_height: BlockHeight,
+        ) -> Result<BlockHeight, Self::Error> {
+            Ok(max_height)
+        }
+
+        fn insert_exchange_rate(
+            &mut self,
+            _observation: &ExchangeRateObservation,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn save_draft(
+            &mut self,
+            _account: Self::AccountId,
+            _draft: &DraftPayment,
+        ) -> Result<DraftId, Self::Error> {
+            Ok(DraftId::from_u64(0))
+        }
+
+        fn delete_draft(&mut self, _draft_id: DraftId) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_note_spend_policy(
+            &mut self,
+            _note_id: NoteId,
+            _locked: bool,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        #[cfg(feature = "transparent-inputs")]
+        fn set_utxo_spend_policy(
+            &mut self,
+            _outpoint: &OutPoint,
+            _locked: bool,
+        ) -> Result<(), Self::Error> {
             Ok(())
         }
 
@@ -1930,6 +3132,33 @@ pub mod testing {
         ) -> Result<Self::UtxoRef, Self::Error> {
             Ok(0)
         }
+
+        #[cfg(feature = "transparent-inputs")]
+        fn reserve_next_transparent_addresses(
+            &mut self,
+            _account: Self::AccountId,
+            _n: u32,
+        ) -> Result<Vec<(TransparentAddress, TransparentAddressMetadata)>, Self::Error> {
+            Ok(vec![])
+        }
+
+        #[cfg(feature = "transparent-inputs")]
+        fn reserve_next_ephemeral_addresses(
+            &mut self,
+            _account: Self::AccountId,
+            _n: u32,
+        ) -> Result<Vec<(TransparentAddress, TransparentAddressMetadata)>, Self::Error> {
+            Ok(vec![])
+        }
+
+        #[cfg(feature = "transparent-inputs")]
+        fn mark_transparent_address_used(
+            &mut self,
+            _address: &TransparentAddress,
+            _height: BlockHeight,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
     }
 
     impl WalletCommitmentTrees for MockWalletDb {
@@ -19,6 +19,7 @@
 //!             error::Error,
 //!             scan_cached_blocks,
 //!             testing as chain_testing,
+//!             truncate_to_chain_fork,
 //!         },
 //!         scanning::ScanPriority,
 //!         testing,
@@ -92,8 +93,17 @@
 //!                     // size of recent CompactBlocks, etc.
 //!                     let rewind_height = err.at_height().saturating_sub(10);
 //!
-//!                     // Rewind to the chosen height.
-//!                     wallet_db.truncate_to_height(rewind_height).map_err(Error::Wallet)?;
+//!                     // Rewind to the chosen height. The wallet may rewind further than
+//!                     // requested, and reports the height to which it was rewound along with
+//!                     // the wallet transactions that are no longer mined.
+//!                     let reorg_info = truncate_to_chain_fork(&mut wallet_db, rewind_height)
+//!                         .map_err(Error::Wallet)?;
+//!                     let rewind_height = reorg_info.fork_height();
+//!
+//!                     // Notify the user of any payments that are no longer confirmed.
+//!                     for txid in reorg_info.unmined_wallet_txids() {
+//!                         unimplemented!("notify_unconfirmed(txid)");
+//!                     }
 //!
 //!                     // Delete cached blocks from rewind_height onwards.
 //!                     //
@@ -151,19 +161,28 @@
 //! # }
 //! ```
 
-use std::ops::Range;
+use std::{
+    collections::BTreeMap,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 use incrementalmerkletree::frontier::Frontier;
 use subtle::ConditionallySelectable;
 use zcash_primitives::{
     block::BlockHash,
     consensus::{self, BlockHeight},
+    transaction::TxId,
+    zip32::Scope,
 };
 
 use crate::{
-    data_api::{NullifierQuery, WalletWrite},
+    data_api::{Account, NullifierQuery, TransactionFilter, WalletWrite},
     proto::compact_formats::CompactBlock,
-    scanning::{scan_block_with_runners, BatchRunners, Nullifiers, ScanningKeys},
+    scanning::{scan_block_with_runners, BatchRunners, Nullifiers, ScanParallelism, ScanningKeys},
 };
 
 #[cfg(feature = "sync")]
@@ -456,6 +475,19 @@ impl ScanSummary {
         }
     }
 
+    /// Extends this summary with the results of scanning the blocks immediately following
+    /// its range.
+    fn merge(&mut self, next: &ScanSummary) {
+        self.scanned_range.end = next.scanned_range.end;
+        self.spent_sapling_note_count += next.spent_sapling_note_count;
+        self.received_sapling_note_count += next.received_sapling_note_count;
+        #[cfg(feature = "orchard")]
+        {
+            self.spent_orchard_note_count += next.spent_orchard_note_count;
+            self.received_orchard_note_count += next.received_orchard_note_count;
+        }
+    }
+
     /// Returns the range of blocks successfully scanned.
     pub fn scanned_range(&self) -> Range<BlockHeight> {
         self.scanned_range.clone()
@@ -567,6 +599,82 @@ impl ChainState {
     }
 }
 
+/// Progress information reported to a [`ScanProgressObserver`] by
+/// [`scan_cached_blocks_with_progress`] after each batch of blocks has been scanned.
+#[derive(Clone, Debug)]
+pub struct BatchScanProgress {
+    batch_range: Range<BlockHeight>,
+    blocks_remaining: usize,
+    trial_decryptions: u64,
+    summary: ScanSummary,
+}
+
+impl BatchScanProgress {
+    /// Returns the range of blocks scanned in the batch that has just completed.
+    pub fn batch_range(&self) -> Range<BlockHeight> {
+        self.batch_range.clone()
+    }
+
+    /// Returns the number of blocks in the requested range that have not yet been scanned.
+    ///
+    /// This is computed from the requested `limit`; the block source may contain fewer
+    /// blocks than this.
+    pub fn blocks_remaining(&self) -> usize {
+        self.blocks_remaining
+    }
+
+    /// Returns the number of trial decryptions performed so far across all batches.
+    pub fn trial_decryptions(&self) -> u64 {
+        self.trial_decryptions
+    }
+
+    /// Returns a summary of the notes found so far across all batches.
+    pub fn summary(&self) -> &ScanSummary {
+        &self.summary
+    }
+}
+
+/// A receiver of progress updates from [`scan_cached_blocks_with_progress`].
+///
+/// This is implemented for any `FnMut(&BatchScanProgress)` closure.
+pub trait ScanProgressObserver {
+    /// Called after each batch of blocks has been scanned and its results have been committed
+    /// to the wallet.
+    fn on_batch_scanned(&mut self, progress: &BatchScanProgress);
+}
+
+impl<F: FnMut(&BatchScanProgress)> ScanProgressObserver for F {
+    fn on_batch_scanned(&mut self, progress: &BatchScanProgress) {
+        self(progress)
+    }
+}
+
+/// A handle that may be used to request that [`scan_cached_blocks_with_progress`] stop
+/// scanning.
+///
+/// Cancellation is cooperative: a scan that is in progress finishes and commits its current
+/// batch before returning. Clones of a handle share the same cancellation state, so a clone
+/// may be moved to another thread in order to cancel a scan from there.
+#[derive(Clone, Debug, Default)]
+pub struct ScanCancellation(Arc<AtomicBool>);
+
+impl ScanCancellation {
+    /// Constructs a new handle that has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests that any scan using this handle stop after its current batch.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Returns whether cancellation has been requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
 /// Scans at most `limit` blocks from the provided block source for in order to find transactions
 /// received by the accounts tracked in the provided wallet database.
 ///
@@ -574,6 +682,9 @@ impl ChainState {
 /// update their UI with scanning progress. Repeatedly calling this function with `from_height ==
 /// None` will process sequential ranges of blocks.
 ///
+/// See [`scan_cached_blocks_with_progress`] for a variant that reports progress and can be
+/// cancelled while scanning.
+///
 /// ## Panics
 ///
 /// This method will panic if `from_height != from_state.block_height() + 1`.
@@ -592,15 +703,216 @@ where
     BlockSourceT: BlockSource,
     DbT: WalletWrite,
     <DbT as WalletRead>::AccountId: ConditionallySelectable + Default + Send + 'static,
+{
+    scan_cached_blocks_with_progress(
+        params,
+        block_source,
+        data_db,
+        from_height,
+        from_state,
+        limit,
+        limit,
+        &ScanParallelism::default(),
+        &mut |_: &BatchScanProgress| {},
+        &ScanCancellation::new(),
+    )
+}
+
+/// Scans at most `limit` blocks from the provided block source in batches of at most
+/// `batch_size` blocks, reporting progress to `observer` after each batch.
+///
+/// Trial decryption of the scanned outputs is parallelized according to `parallelism`; see
+/// [`ScanParallelism`] for details.
+///
+/// The results of each batch are committed to the wallet before `observer` is notified, and
+/// `cancellation` is checked before each batch is started. If a scan is cancelled, the returned
+/// [`ScanSummary`] describes the work that was completed; scanning may be resumed by calling
+/// this function again, starting from the end of [`ScanSummary::scanned_range`].
+///
+/// Scanning in a single batch of `limit` blocks with the default [`ScanParallelism`] is
+/// equivalent to [`scan_cached_blocks`].
+///
+/// ## Panics
+///
+/// This method will panic if `from_height != from_state.block_height() + 1`, or if
+/// `batch_size == 0`.
+#[tracing::instrument(skip(
+    params,
+    block_source,
+    data_db,
+    from_state,
+    parallelism,
+    observer,
+    cancellation
+))]
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+pub fn scan_cached_blocks_with_progress<ParamsT, DbT, BlockSourceT, ObserverT>(
+    params: &ParamsT,
+    block_source: &BlockSourceT,
+    data_db: &mut DbT,
+    from_height: BlockHeight,
+    from_state: &ChainState,
+    limit: usize,
+    batch_size: usize,
+    parallelism: &ScanParallelism,
+    observer: &mut ObserverT,
+    cancellation: &ScanCancellation,
+) -> Result<ScanSummary, Error<DbT::Error, BlockSourceT::Error>>
+where
+    ParamsT: consensus::Parameters + Send + 'static,
+    BlockSourceT: BlockSource,
+    DbT: WalletWrite,
+    <DbT as WalletRead>::AccountId: ConditionallySelectable + Default + Send + 'static,
+    ObserverT: ScanProgressObserver + ?Sized,
 {
     assert_eq!(from_height, from_state.block_height + 1);
+    assert!(batch_size > 0);
 
     // Fetch the UnifiedFullViewingKeys we are tracking
     let account_ufvks = data_db
         .get_unified_full_viewing_keys()
         .map_err(Error::Wallet)?;
-    let scanning_keys = ScanningKeys::from_account_ufvks(account_ufvks);
-    let mut runners = BatchRunners::<_, (), ()>::for_keys(100, &scanning_keys);
+    // Accounts without a UFVK are scanned using their UIVK, which can only detect received
+    // notes. Standalone transparent accounts have neither, and are not scanned.
+    let mut account_uivks = vec![];
+    for account_id in data_db.get_account_ids().map_err(Error::Wallet)? {
+        if !account_ufvks.contains_key(&account_id) {
+            if let Some(uivk) = data_db
+                .get_account(account_id)
+                .map_err(Error::Wallet)?
+                .and_then(|account| account.uivk())
+            {
+                account_uivks.push((account_id, uivk));
+            }
+        }
+    }
+    let scanning_keys = ScanningKeys::from_account_viewing_keys(account_ufvks, account_uivks);
+
+    let mut progress = BatchScanProgress {
+        batch_range: from_height..from_height,
+        blocks_remaining: limit,
+        trial_decryptions: 0,
+        summary: ScanSummary::for_range(from_height..from_height),
+    };
+    let mut chain_state = from_state.clone();
+    while progress.blocks_remaining > 0 && !cancellation.is_cancelled() {
+        let batch_limit = std::cmp::min(batch_size, progress.blocks_remaining);
+        let (batch_summary, trial_decryptions, next_state) = scan_batch(
+            params,
+            block_source,
+            data_db,
+            &scanning_keys,
+            parallelism,
+            &chain_state,
+            batch_limit,
+            false,
+        )?;
+
+        let batch_len = usize::try_from(
+            u32::from(batch_summary.scanned_range.end)
+                - u32::from(batch_summary.scanned_range.start),
+        )
+        .expect("usize is at least 32 bits");
+        if batch_len == 0 {
+            // The block source has no more blocks.
+            break;
+        }
+
+        progress.batch_range = batch_summary.scanned_range.clone();
+        progress.blocks_remaining -= batch_len;
+        progress.trial_decryptions += trial_decryptions;
+        progress.summary.merge(&batch_summary);
+        chain_state = next_state;
+
+        observer.on_batch_scanned(&progress);
+
+        if batch_len < batch_limit {
+            break;
+        }
+    }
+
+    Ok(progress.summary)
+}
+
+/// Scans at most `limit` blocks from the provided block source for spends of the notes already
+/// known to the wallet, without attempting to detect notes received by the wallet.
+///
+/// This is a faster alternative to [`scan_cached_blocks`] for use when the wallet only needs to
+/// learn whether its existing notes have been spent, for example after a short period offline.
+/// The nullifiers revealed in each block are checked against the wallet's unspent Sapling and
+/// Orchard notes, and the note commitment trees and chain tip are updated as by
+/// [`scan_cached_blocks`], but no trial decryption is performed.
+///
+/// The scanned blocks are committed using [`WalletWrite::put_spend_scanned_blocks`], and so the
+/// range remains eligible for a full scan by [`scan_cached_blocks`]; notes received in these
+/// blocks will be detected at that time. The notes counted as received in the returned
+/// [`ScanSummary`] are always zero.
+///
+/// ## Panics
+///
+/// This method will panic if `from_height != from_state.block_height() + 1`.
+#[tracing::instrument(skip(params, block_source, data_db, from_state))]
+#[allow(clippy::type_complexity)]
+pub fn detect_spends_in_cached_blocks<ParamsT, DbT, BlockSourceT>(
+    params: &ParamsT,
+    block_source: &BlockSourceT,
+    data_db: &mut DbT,
+    from_height: BlockHeight,
+    from_state: &ChainState,
+    limit: usize,
+) -> Result<ScanSummary, Error<DbT::Error, BlockSourceT::Error>>
+where
+    ParamsT: consensus::Parameters + Send + 'static,
+    BlockSourceT: BlockSource,
+    DbT: WalletWrite,
+    <DbT as WalletRead>::AccountId: ConditionallySelectable + Default + Send + 'static,
+{
+    assert_eq!(from_height, from_state.block_height + 1);
+
+    let (summary, _, _) = scan_batch(
+        params,
+        block_source,
+        data_db,
+        &ScanningKeys::empty(),
+        &ScanParallelism::single_threaded(),
+        from_state,
+        limit,
+        true,
+    )?;
+
+    Ok(summary)
+}
+
+/// Scans at most `limit` blocks following `from_state` and commits the results to the wallet.
+///
+/// If `spends_only` is set, the blocks are committed as having been scanned only for spends.
+///
+/// Returns a summary of the scanned blocks, the number of trial decryptions performed, and the
+/// chain state as of the last scanned block.
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+fn scan_batch<ParamsT, DbT, BlockSourceT>(
+    params: &ParamsT,
+    block_source: &BlockSourceT,
+    data_db: &mut DbT,
+    scanning_keys: &ScanningKeys<
+        <DbT as WalletRead>::AccountId,
+        (<DbT as WalletRead>::AccountId, Scope),
+    >,
+    parallelism: &ScanParallelism,
+    from_state: &ChainState,
+    limit: usize,
+    spends_only: bool,
+) -> Result<(ScanSummary, u64, ChainState), Error<DbT::Error, BlockSourceT::Error>>
+where
+    ParamsT: consensus::Parameters + Send + 'static,
+    BlockSourceT: BlockSource,
+    DbT: WalletWrite,
+    <DbT as WalletRead>::AccountId: ConditionallySelectable + Default + Send + 'static,
+{
+    let from_height = from_state.block_height + 1;
+    let mut runners = BatchRunners::<_, (), ()>::for_keys(parallelism, scanning_keys);
 
     block_source.with_blocks::<_, DbT::Error>(Some(from_height), Some(limit), |block| {
         runners.add_block(params, block).map_err(|e| e.into())
@@ -628,15 +940,24 @@ where
 
     let mut scanned_blocks = vec![];
     let mut scan_summary = ScanSummary::for_range(from_height..from_height);
+    let mut trial_decryptions = 0u64;
     block_source.with_blocks::<_, DbT::Error>(
         Some(from_height),
         Some(limit),
         |block: CompactBlock| {
             scan_summary.scanned_range.end = block.height() + 1;
+            let sapling_outputs: usize = block.vtx.iter().map(|tx| tx.outputs.len()).sum();
+            trial_decryptions += (sapling_outputs * scanning_keys.sapling().len()) as u64;
+            #[cfg(feature = "orchard")]
+            {
+                let orchard_actions: usize = block.vtx.iter().map(|tx| tx.actions.len()).sum();
+                trial_decryptions += (orchard_actions * scanning_keys.orchard().len()) as u64;
+            }
+
             let scanned_block = scan_block_with_runners::<_, _, _, (), ()>(
                 params,
                 block,
-                &scanning_keys,
+                scanning_keys,
                 &nullifiers,
                 prior_block_metadata.as_ref(),
                 Some(&mut runners),
@@ -688,10 +1009,168 @@ where
         },
     )?;
 
-    data_db
-        .put_blocks(from_state, scanned_blocks)
-        .map_err(Error::Wallet)?;
-    Ok(scan_summary)
+    // Advance the chain state past the scanned blocks, so that a subsequent batch can be
+    // committed on top of this one.
+    let next_state = match scanned_blocks.last() {
+        Some(last) => {
+            let mut final_sapling_tree = from_state.final_sapling_tree.clone();
+            #[cfg(feature = "orchard")]
+            let mut final_orchard_tree = from_state.final_orchard_tree.clone();
+            for block in &scanned_blocks {
+                for (node, _) in block.sapling().commitments() {
+                    final_sapling_tree.append(*node);
+                }
+                #[cfg(feature = "orchard")]
+                for (node, _) in block.orchard().commitments() {
+                    final_orchard_tree.append(*node);
+                }
+            }
+            ChainState::new(
+                last.height(),
+                last.block_hash(),
+                final_sapling_tree,
+                #[cfg(feature = "orchard")]
+                final_orchard_tree,
+            )
+        }
+        None => from_state.clone(),
+    };
+
+    if spends_only {
+        data_db.put_spend_scanned_blocks(from_state, scanned_blocks)
+    } else {
+        data_db.put_blocks(from_state, scanned_blocks)
+    }
+    .map_err(Error::Wallet)?;
+    Ok((scan_summary, trial_decryptions, next_state))
+}
+
+/// Checks whether the given block connects to the wallet's view of the chain.
+///
+/// The hash of `block` is compared against the hash of the block that the wallet has stored at
+/// the same height, if any, and its previous block hash against the hash of the block that the
+/// wallet has stored at the preceding height, if any. If either comparison fails, the wallet's
+/// view of the chain has diverged from the chain that `block` belongs to, and this returns the
+/// lowest height at which the wallet's stored block is known to differ from that chain. The
+/// wallet must then be truncated below the returned height, for example by using
+/// [`truncate_to_chain_fork`].
+///
+/// If the returned height is the height of `block`, the block's parent is shared between the
+/// two chains and the chains forked at the height immediately below it. Otherwise, the fork
+/// point lies further back, and the caller should check earlier blocks to locate it.
+///
+/// Returns `Ok(None)` if no divergence was detected, including when the wallet has not stored
+/// blocks at the relevant heights.
+pub fn check_chain_continuity<DbT: WalletRead>(
+    wallet_db: &DbT,
+    block: &CompactBlock,
+) -> Result<Option<BlockHeight>, DbT::Error> {
+    let height = block.height();
+    if height > BlockHeight::from(0) {
+        if let Some(stored_prev_hash) = wallet_db.get_block_hash(height - 1)? {
+            if stored_prev_hash != block.prev_hash() {
+                return Ok(Some(height - 1));
+            }
+        }
+    }
+
+    Ok(match wallet_db.get_block_hash(height)? {
+        Some(stored_hash) if stored_hash != block.hash() => Some(height),
+        _ => None,
+    })
+}
+
+/// Information about the wallet transactions that were affected by rewinding the wallet past a
+/// chain reorganization.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReorgInfo {
+    fork_height: BlockHeight,
+    orphaned_txids: Vec<TxId>,
+    unmined_wallet_txids: Vec<TxId>,
+}
+
+impl ReorgInfo {
+    /// Returns the height to which the wallet was truncated. Blocks above this height are no
+    /// longer part of the wallet's view of the chain.
+    pub fn fork_height(&self) -> BlockHeight {
+        self.fork_height
+    }
+
+    /// Returns the IDs of the wallet transactions that had been mined in the discarded blocks,
+    /// in order of the heights at which they had been mined.
+    ///
+    /// These transactions are now treated as unmined: they will be marked as mined again if
+    /// they are found when rescanning the chain, or otherwise expire as usual.
+    pub fn orphaned_txids(&self) -> &[TxId] {
+        &self.orphaned_txids
+    }
+
+    /// Returns the subset of [`Self::orphaned_txids`] that decreased the balance of at least one
+    /// of the wallet's accounts, such as payments made by the wallet. Applications may wish to
+    /// notify the user that these transactions are no longer confirmed.
+    pub fn unmined_wallet_txids(&self) -> &[TxId] {
+        &self.unmined_wallet_txids
+    }
+}
+
+/// Truncates the wallet to at most `max_height`, as [`WalletWrite::truncate_to_height`] does,
+/// and returns information about the wallet transactions that were mined in the discarded
+/// blocks.
+///
+/// This should be used to rewind the wallet when a chain reorganization has been detected, for
+/// example by [`check_chain_continuity`] or a continuity error returned by
+/// [`scan_cached_blocks`].
+pub fn truncate_to_chain_fork<DbT: WalletWrite>(
+    wallet_db: &mut DbT,
+    max_height: BlockHeight,
+) -> Result<ReorgInfo, DbT::Error> {
+    const PAGE_SIZE: u32 = 100;
+
+    // The wallet may truncate to a height below `max_height`, so we collect the transactions
+    // mined above the height to which it will actually be truncated.
+    let truncation_height = wallet_db
+        .get_truncation_height(max_height)?
+        .unwrap_or(max_height);
+    let filter = TransactionFilter::new()
+        .with_mined_heights((truncation_height + 1)..BlockHeight::from_u32(u32::MAX));
+
+    // Maps each orphaned transaction to whether it decreased the balance of any account.
+    let mut orphaned = BTreeMap::new();
+    for account_id in wallet_db.get_account_ids()? {
+        let mut offset = 0;
+        loop {
+            let page = wallet_db.get_transactions(account_id, &filter, offset, PAGE_SIZE)?;
+            for tx in &page {
+                if let Some(mined_height) = tx.mined_height() {
+                    *orphaned.entry((mined_height, tx.txid())).or_insert(false) |=
+                        tx.account_value_delta().is_negative();
+                }
+            }
+            if page.len() < PAGE_SIZE as usize {
+                break;
+            }
+            offset += PAGE_SIZE;
+        }
+    }
+
+    let fork_height = wallet_db.truncate_to_height(max_height)?;
+
+    let mut orphaned_txids = vec![];
+    let mut unmined_wallet_txids = vec![];
+    for ((mined_height, txid), is_sent) in orphaned {
+        if mined_height > fork_height {
+            orphaned_txids.push(txid);
+            if is_sent {
+                unmined_wallet_txids.push(txid);
+            }
+        }
+    }
+
+    Ok(ReorgInfo {
+        fork_height,
+        orphaned_txids,
+        unmined_wallet_txids,
+    })
 }
 
 #[cfg(feature = "test-dependencies")]
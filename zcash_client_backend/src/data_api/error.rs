@@ -4,14 +4,17 @@ use std::error;
 use std::fmt::{self, Debug, Display};
 
 use shardtree::error::ShardTreeError;
+use zcash_primitives::consensus::BlockHeight;
 use zcash_primitives::transaction::components::amount::NonNegativeAmount;
 use zcash_primitives::transaction::{
     builder,
     components::{amount::BalanceError, transparent},
+    TxId,
 };
 
 use crate::address::UnifiedAddress;
 use crate::data_api::wallet::input_selection::InputSelectorError;
+use crate::data_api::DraftId;
 use crate::proposal::ProposalError;
 use crate::PoolType;
 
@@ -42,6 +45,10 @@ pub enum Error<DataSourceError, CommitmentTreeError, SelectionError, FeeError> {
     /// No account could be found corresponding to a provided spending key.
     KeyNotRecognized,
 
+    /// The account has only an incoming viewing key, which cannot be used to detect spends of
+    /// its notes, so the wallet cannot construct transactions that spend from it.
+    AccountCannotSpend,
+
     /// Zcash amount computation encountered an overflow or underflow.
     BalanceError(BalanceError),
 
@@ -51,6 +58,14 @@ pub enum Error<DataSourceError, CommitmentTreeError, SelectionError, FeeError> {
         required: NonNegativeAmount,
     },
 
+    /// Unable to create a new spend because the wallet's unlocked balance is not sufficient,
+    /// but it would be if the account's notes locked against spending were unlocked.
+    InsufficientUnlockedFunds {
+        available: NonNegativeAmount,
+        required: NonNegativeAmount,
+        locked: NonNegativeAmount,
+    },
+
     /// The wallet must first perform a scan of the blockchain before other
     /// operations can be performed.
     ScanRequired,
@@ -81,6 +96,18 @@ pub enum Error<DataSourceError, CommitmentTreeError, SelectionError, FeeError> {
     /// full viewing key for an account.
     NoteMismatch(NoteId),
 
+    /// No draft payment exists in the wallet with the given identifier.
+    DraftNotFound(DraftId),
+
+    /// An input of the proposal being executed is no longer spendable by the wallet, for
+    /// example because it was spent by another transaction after the proposal was created.
+    InputNotSpendable(TxId, PoolType, u32),
+
+    /// The anchor height of a proposal step is not available in the wallet's note commitment
+    /// tree, either because the checkpoint has been pruned or because the wallet's view of the
+    /// chain no longer includes that height. The proposal must be recreated.
+    AnchorNotAvailable(BlockHeight),
+
     #[cfg(feature = "transparent-inputs")]
     AddressNotRecognized(TransparentAddress),
 }
@@ -122,6 +149,10 @@ where
                     "Wallet does not contain an account corresponding to the provided spending key"
                 )
             }
+            Error::AccountCannotSpend => write!(
+                f,
+                "The account has only an incoming viewing key, and cannot be spent from."
+            ),
             Error::BalanceError(e) => write!(
                 f,
                 "The value lies outside the valid range of Zcash amounts: {:?}.",
@@ -133,6 +164,13 @@ where
                 u64::from(*available),
                 u64::from(*required)
             ),
+            Error::InsufficientUnlockedFunds { available, required, locked } => write!(
+                f,
+                "Insufficient unlocked balance (have {}, need {} including fee); {} is locked against spending",
+                u64::from(*available),
+                u64::from(*required),
+                u64::from(*locked)
+            ),
             Error::ScanRequired => write!(f, "Must scan blocks first"),
             Error::Builder(e) => write!(f, "An error occurred building the transaction: {}", e),
             Error::MemoForbidden => write!(f, "It is not possible to send a memo to a transparent address."),
@@ -144,6 +182,17 @@ where
             ),
             Error::NoSpendingKey(addr) => write!(f, "No spending key available for address: {}", addr),
             Error::NoteMismatch(n) => write!(f, "A note being spent ({:?}) does not correspond to either the internal or external full viewing key for the provided spending key.", n),
+            Error::DraftNotFound(id) => write!(f, "No draft payment exists with identifier {}.", id.as_u64()),
+            Error::InputNotSpendable(txid, pool, idx) => write!(
+                f,
+                "The {} input for txid {}, index {} is no longer spendable.",
+                pool, txid, idx
+            ),
+            Error::AnchorNotAvailable(height) => write!(
+                f,
+                "No note commitment tree checkpoint is available for anchor height {}.",
+                height
+            ),
 
             #[cfg(feature = "transparent-inputs")]
             Error::AddressNotRecognized(_) => {
@@ -0,0 +1,496 @@
+//! Export and import of wallet state snapshots.
+//!
+//! A wallet snapshot captures everything a wallet backend has learned from the chain for its
+//! accounts: the accounts' viewing keys and birthdays, the note commitment tree shards,
+//! frontiers and checkpoints, the decrypted notes along with their nullifiers and note
+//! commitment tree positions, transparent UTXOs, and the state of the scan queue. Restoring a
+//! snapshot into a fresh wallet allows it to resume scanning where the exported wallet left off,
+//! instead of rescanning from the accounts' birthday heights.
+//!
+//! Wallet backends built on this crate do not store spending keys, and so snapshots never
+//! contain spending keys; the caller must continue to supply them when creating transactions.
+//!
+//! The contents of a snapshot are described by the backend as a set of [`SnapshotTable`]s,
+//! each of which names its columns, so that a snapshot can be inspected without knowledge of
+//! the schema that produced it. The encoded form produced by [`export_wallet_snapshot`] carries
+//! an explicit format version and a BLAKE2b-256 digest of its contents; [`import_wallet_snapshot`]
+//! refuses to decode data that fails either check. Backends are additionally responsible for
+//! rejecting snapshots whose [`WalletSnapshot::schema`] does not match their own, so that a
+//! snapshot taken before a schema change cannot be silently restored afterwards.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use zcash_encoding::{CompactSize, Vector};
+use zcash_primitives::consensus::NetworkType;
+
+/// The magic bytes with which every encoded wallet snapshot begins.
+const SNAPSHOT_MAGIC: [u8; 8] = *b"ZWALSNAP";
+
+/// The version of the snapshot encoding produced by [`export_wallet_snapshot`].
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// The BLAKE2b personalization used for the snapshot integrity digest.
+const SNAPSHOT_DIGEST_PERSONALIZATION: &[u8; 16] = b"ZcashWalletSnap_";
+
+/// The length in bytes of the snapshot integrity digest.
+const SNAPSHOT_DIGEST_LEN: usize = 32;
+
+/// A single value stored in a [`SnapshotTable`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum SnapshotValue {
+    /// The absence of a value.
+    Null,
+    /// A signed integer.
+    Integer(i64),
+    /// A floating-point number.
+    Real(f64),
+    /// A UTF-8 string.
+    Text(String),
+    /// An arbitrary byte string.
+    Blob(Vec<u8>),
+}
+
+/// A named set of rows within a [`WalletSnapshot`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SnapshotTable {
+    name: String,
+    columns: Vec<String>,
+    rows: Vec<Vec<SnapshotValue>>,
+}
+
+impl SnapshotTable {
+    /// Constructs a new table from its name, column names and rows.
+    ///
+    /// Returns `None` if any row does not contain exactly one value per column.
+    pub fn from_parts(
+        name: String,
+        columns: Vec<String>,
+        rows: Vec<Vec<SnapshotValue>>,
+    ) -> Option<Self> {
+        if rows.iter().all(|row| row.len() == columns.len()) {
+            Some(SnapshotTable {
+                name,
+                columns,
+                rows,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the name of this table.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the names of the columns of this table.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// Returns the rows of this table, each of which has one value per column.
+    pub fn rows(&self) -> &[Vec<SnapshotValue>] {
+        &self.rows
+    }
+}
+
+/// The decoded contents of a wallet snapshot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WalletSnapshot {
+    network: NetworkType,
+    schema: Vec<String>,
+    tables: Vec<SnapshotTable>,
+}
+
+impl WalletSnapshot {
+    /// Constructs a new snapshot of a wallet for the given network.
+    ///
+    /// `schema` is an opaque description of the storage schema that the backend used to produce
+    /// the snapshot, such as the set of database migrations that had been applied.
+    pub fn from_parts(
+        network: NetworkType,
+        schema: Vec<String>,
+        tables: Vec<SnapshotTable>,
+    ) -> Self {
+        WalletSnapshot {
+            network,
+            schema,
+            tables,
+        }
+    }
+
+    /// Returns the network to which the snapshotted wallet belongs.
+    pub fn network(&self) -> NetworkType {
+        self.network
+    }
+
+    /// Returns the backend's description of the schema this snapshot was produced with.
+    pub fn schema(&self) -> &[String] {
+        &self.schema
+    }
+
+    /// Returns the tables of wallet data contained in this snapshot.
+    pub fn tables(&self) -> &[SnapshotTable] {
+        &self.tables
+    }
+
+    /// Consumes this snapshot, returning its tables.
+    pub fn into_tables(self) -> Vec<SnapshotTable> {
+        self.tables
+    }
+}
+
+/// A wallet backend that can produce and restore [`WalletSnapshot`]s.
+pub trait WalletSnapshotStore {
+    /// The type of errors that may be generated when producing or restoring a snapshot.
+    type Error;
+
+    /// Returns a snapshot of the wallet's current state.
+    ///
+    /// The snapshot must be taken against a single consistent view of the wallet, and must not
+    /// include any spending key material.
+    fn read_snapshot(&self) -> Result<WalletSnapshot, Self::Error>;
+
+    /// Populates the wallet from the given snapshot.
+    ///
+    /// Implementations must reject snapshots for a different network or a different schema,
+    /// and must reject restoring a snapshot into a wallet that already contains any data.
+    fn restore_snapshot(&mut self, snapshot: WalletSnapshot) -> Result<(), Self::Error>;
+}
+
+/// Errors that can occur when importing a wallet snapshot.
+#[derive(Debug)]
+pub enum SnapshotError<E> {
+    /// The data provided is not an encoded wallet snapshot.
+    NotASnapshot,
+    /// The snapshot was encoded with a format version that is not supported by this library.
+    UnsupportedVersion(u32),
+    /// The snapshot data does not match its integrity digest.
+    IntegrityCheckFailed,
+    /// The snapshot data passed its integrity check but could not be decoded.
+    Malformed(String),
+    /// The wallet backend failed to restore the snapshot.
+    Wallet(E),
+}
+
+impl<E: fmt::Display> fmt::Display for SnapshotError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::NotASnapshot => write!(f, "The data is not a wallet snapshot."),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "Wallet snapshot version {} is not supported; expected version {}.",
+                version, SNAPSHOT_VERSION
+            ),
+            SnapshotError::IntegrityCheckFailed => {
+                write!(f, "The wallet snapshot failed its integrity check.")
+            }
+            SnapshotError::Malformed(reason) => {
+                write!(f, "The wallet snapshot is malformed: {}", reason)
+            }
+            SnapshotError::Wallet(e) => write!(f, "Failed to restore wallet snapshot: {}", e),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for SnapshotError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::Wallet(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Produces an encoded snapshot of the state of the given wallet.
+///
+/// The result can be restored into a fresh wallet using [`import_wallet_snapshot`].
+pub fn export_wallet_snapshot<W: WalletSnapshotStore>(wallet: &W) -> Result<Vec<u8>, W::Error> {
+    Ok(encode_snapshot(&wallet.read_snapshot()?))
+}
+
+/// Decodes the given snapshot data and uses it to populate the given wallet, which must not
+/// yet contain any data.
+pub fn import_wallet_snapshot<W: WalletSnapshotStore>(
+    wallet: &mut W,
+    data: &[u8],
+) -> Result<(), SnapshotError<W::Error>> {
+    let snapshot = decode_snapshot(data)?;
+    wallet
+        .restore_snapshot(snapshot)
+        .map_err(SnapshotError::Wallet)
+}
+
+fn snapshot_digest(data: &[u8]) -> blake2b_simd::Hash {
+    blake2b_simd::Params::new()
+        .hash_length(SNAPSHOT_DIGEST_LEN)
+        .personal(SNAPSHOT_DIGEST_PERSONALIZATION)
+        .hash(data)
+}
+
+/// Encodes a snapshot as `magic || version || body || digest`, where the digest covers all of
+/// the preceding bytes.
+fn encode_snapshot(snapshot: &WalletSnapshot) -> Vec<u8> {
+    let mut data = SNAPSHOT_MAGIC.to_vec();
+    data.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+    write_body(&mut data, snapshot).expect("writing to a Vec cannot fail");
+    let digest = snapshot_digest(&data);
+    data.extend_from_slice(digest.as_bytes());
+    data
+}
+
+/// Decodes a snapshot produced by [`encode_snapshot`], checking its version and integrity.
+fn decode_snapshot<E>(data: &[u8]) -> Result<WalletSnapshot, SnapshotError<E>> {
+    if data.len() < SNAPSHOT_MAGIC.len() || data[..SNAPSHOT_MAGIC.len()] != SNAPSHOT_MAGIC {
+        return Err(SnapshotError::NotASnapshot);
+    }
+    let header_len = SNAPSHOT_MAGIC.len() + 4;
+    if data.len() < header_len + SNAPSHOT_DIGEST_LEN {
+        return Err(SnapshotError::IntegrityCheckFailed);
+    }
+
+    let version = u32::from_le_bytes(
+        data[SNAPSHOT_MAGIC.len()..header_len]
+            .try_into()
+            .expect("slice has length 4"),
+    );
+    if version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+
+    let (contents, digest) = data.split_at(data.len() - SNAPSHOT_DIGEST_LEN);
+    if snapshot_digest(contents).as_bytes() != digest {
+        return Err(SnapshotError::IntegrityCheckFailed);
+    }
+
+    let mut body = &contents[header_len..];
+    let snapshot = read_body(&mut body).map_err(|e| SnapshotError::Malformed(e.to_string()))?;
+    if body.is_empty() {
+        Ok(snapshot)
+    } else {
+        Err(SnapshotError::Malformed(
+            "Unexpected trailing data after snapshot contents.".to_owned(),
+        ))
+    }
+}
+
+fn write_str<W: Write>(mut writer: W, value: &str) -> io::Result<()> {
+    Vector::write(&mut writer, value.as_bytes(), |w, b| w.write_all(&[*b]))
+}
+
+fn read_str<R: Read>(mut reader: R) -> io::Result<String> {
+    let bytes = read_bytes(&mut reader)?;
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn read_bytes<R: Read>(mut reader: R) -> io::Result<Vec<u8>> {
+    let len = CompactSize::read_t::<_, usize>(&mut reader)?;
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn write_body<W: Write>(mut writer: W, snapshot: &WalletSnapshot) -> io::Result<()> {
+    let network_code: u8 = match snapshot.network {
+        NetworkType::Main => 0,
+        NetworkType::Test => 1,
+        NetworkType::Regtest => 2,
+    };
+    writer.write_all(&[network_code])?;
+    Vector::write(&mut writer, &snapshot.schema, |w, s| write_str(w, s))?;
+    Vector::write(&mut writer, &snapshot.tables, |w, table| {
+        write_str(&mut *w, &table.name)?;
+        Vector::write(&mut *w, &table.columns, |w, c| write_str(w, c))?;
+        Vector::write(&mut *w, &table.rows, |w, row| {
+            // The number of values per row is given by the number of columns.
+            for value in row {
+                write_value(&mut *w, value)?;
+            }
+            Ok(())
+        })
+    })
+}
+
+fn write_value<W: Write>(mut writer: W, value: &SnapshotValue) -> io::Result<()> {
+    match value {
+        SnapshotValue::Null => writer.write_all(&[0]),
+        SnapshotValue::Integer(i) => {
+            writer.write_all(&[1])?;
+            writer.write_all(&i.to_le_bytes())
+        }
+        SnapshotValue::Real(r) => {
+            writer.write_all(&[2])?;
+            writer.write_all(&r.to_le_bytes())
+        }
+        SnapshotValue::Text(s) => {
+            writer.write_all(&[3])?;
+            write_str(writer, s)
+        }
+        SnapshotValue::Blob(b) => {
+            writer.write_all(&[4])?;
+            Vector::write(writer, b, |w, b| w.write_all(&[*b]))
+        }
+    }
+}
+
+fn read_u8<R: Read>(mut reader: R) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_body<R: Read>(mut reader: R) -> io::Result<WalletSnapshot> {
+    let network = match read_u8(&mut reader)? {
+        0 => NetworkType::Main,
+        1 => NetworkType::Test,
+        2 => NetworkType::Regtest,
+        other => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown network code {}", other),
+            ))
+        }
+    };
+    let schema = Vector::read(&mut reader, |r| read_str(r))?;
+    let tables = Vector::read(&mut reader, |r| {
+        let name = read_str(&mut *r)?;
+        let columns = Vector::read(&mut *r, |r| read_str(r))?;
+        let rows = Vector::read(&mut *r, |r| {
+            (0..columns.len())
+                .map(|_| read_value(&mut *r))
+                .collect::<io::Result<Vec<_>>>()
+        })?;
+        Ok(SnapshotTable {
+            name,
+            columns,
+            rows,
+        })
+    })?;
+
+    Ok(WalletSnapshot {
+        network,
+        schema,
+        tables,
+    })
+}
+
+fn read_value<R: Read>(mut reader: R) -> io::Result<SnapshotValue> {
+    match read_u8(&mut reader)? {
+        0 => Ok(SnapshotValue::Null),
+        1 => {
+            let mut buf = [0; 8];
+            reader.read_exact(&mut buf)?;
+            Ok(SnapshotValue::Integer(i64::from_le_bytes(buf)))
+        }
+        2 => {
+            let mut buf = [0; 8];
+            reader.read_exact(&mut buf)?;
+            Ok(SnapshotValue::Real(f64::from_le_bytes(buf)))
+        }
+        3 => read_str(reader).map(SnapshotValue::Text),
+        4 => read_bytes(reader).map(SnapshotValue::Blob),
+        other => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unknown value type {}", other),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use zcash_primitives::consensus::NetworkType;
+
+    use super::{
+        decode_snapshot, encode_snapshot, SnapshotError, SnapshotTable, SnapshotValue,
+        WalletSnapshot, SNAPSHOT_MAGIC,
+    };
+
+    fn snapshot() -> WalletSnapshot {
+        WalletSnapshot::from_parts(
+            NetworkType::Test,
+            vec!["migration-a".to_owned(), "migration-b".to_owned()],
+            vec![
+                SnapshotTable::from_parts(
+                    "accounts".to_owned(),
+                    vec!["id".to_owned(), "ufvk".to_owned(), "birthday".to_owned()],
+                    vec![
+                        vec![
+                            SnapshotValue::Integer(1),
+                            SnapshotValue::Text("uviewtest1".to_owned()),
+                            SnapshotValue::Integer(-7),
+                        ],
+                        vec![
+                            SnapshotValue::Integer(2),
+                            SnapshotValue::Null,
+                            SnapshotValue::Real(1.5),
+                        ],
+                    ],
+                )
+                .unwrap(),
+                SnapshotTable::from_parts("empty".to_owned(), vec!["data".to_owned()], vec![])
+                    .unwrap(),
+                SnapshotTable::from_parts(
+                    "shards".to_owned(),
+                    vec!["data".to_owned()],
+                    vec![vec![SnapshotValue::Blob(vec![0, 1, 2, 255])]],
+                )
+                .unwrap(),
+            ],
+        )
+    }
+
+    #[test]
+    fn table_rows_must_match_columns() {
+        assert!(SnapshotTable::from_parts(
+            "t".to_owned(),
+            vec!["a".to_owned(), "b".to_owned()],
+            vec![vec![SnapshotValue::Null]],
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let snapshot = snapshot();
+        let encoded = encode_snapshot(&snapshot);
+        assert_eq!(decode_snapshot::<()>(&encoded).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn snapshot_rejects_invalid_data() {
+        let encoded = encode_snapshot(&snapshot());
+
+        assert_matches!(
+            decode_snapshot::<()>(b"not a snapshot"),
+            Err(SnapshotError::NotASnapshot)
+        );
+
+        let mut wrong_version = encoded.clone();
+        wrong_version[SNAPSHOT_MAGIC.len()] = 2;
+        assert_matches!(
+            decode_snapshot::<()>(&wrong_version),
+            Err(SnapshotError::UnsupportedVersion(2))
+        );
+
+        // Flipping any bit of the contents or of the digest must be detected.
+        for i in [
+            SNAPSHOT_MAGIC.len() + 4,
+            encoded.len() / 2,
+            encoded.len() - 1,
+        ] {
+            let mut corrupted = encoded.clone();
+            corrupted[i] ^= 0x01;
+            assert_matches!(
+                decode_snapshot::<()>(&corrupted),
+                Err(SnapshotError::IntegrityCheckFailed)
+            );
+        }
+
+        assert_matches!(
+            decode_snapshot::<()>(&encoded[..encoded.len() - 1]),
+            Err(SnapshotError::IntegrityCheckFailed)
+        );
+    }
+}
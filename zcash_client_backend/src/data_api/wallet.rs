@@ -39,17 +39,21 @@ use sapling::{
     note_encryption::{try_sapling_note_decryption, PreparedIncomingViewingKey},
     prover::{OutputProver, SpendProver},
 };
-use std::num::NonZeroU32;
+use shardtree::{
+    error::{QueryError, ShardTreeError},
+    store::ShardStore,
+};
+use std::{convert::Infallible, num::NonZeroU32};
 
 use super::InputSource;
 use crate::{
     address::Address,
     data_api::{
-        error::Error, Account, SentTransaction, SentTransactionOutput, WalletCommitmentTrees,
-        WalletRead, WalletWrite,
+        error::Error, Account, DraftId, SentTransaction, SentTransactionOutput,
+        WalletCommitmentTrees, WalletRead, WalletWrite,
     },
     decrypt_transaction,
-    fees::{self, DustOutputPolicy},
+    fees::{self, ChangeError, DustOutputPolicy, FeeEstimate, TransactionShape},
     keys::UnifiedSpendingKey,
     proposal::{self, Proposal, ProposalError},
     wallet::{Note, OvkPolicy, Recipient},
@@ -59,22 +63,28 @@ use crate::{
 use zcash_primitives::transaction::{
     builder::{BuildConfig, BuildResult, Builder},
     components::{amount::NonNegativeAmount, sapling::zip212_enforcement},
-    fees::{zip317::FeeError as Zip317FeeError, FeeRule, StandardFeeRule},
+    fees::{
+        zip317::{FeeError as Zip317FeeError, FeeRule as Zip317FeeRule},
+        FeeRule, StandardFeeRule,
+    },
     Transaction, TxId,
 };
 use zcash_protocol::{
     consensus::{self, BlockHeight, NetworkUpgrade},
     memo::MemoBytes,
+    value::MAX_MONEY,
 };
 use zip32::Scope;
 
 #[cfg(feature = "transparent-inputs")]
 use {
     input_selection::ShieldingSelector,
-    std::convert::Infallible,
     zcash_keys::encoding::AddressCodec,
-    zcash_primitives::legacy::TransparentAddress,
-    zcash_primitives::transaction::components::{OutPoint, TxOut},
+    zcash_primitives::legacy::{
+        keys::{AccountPrivKey, IncomingViewingKey},
+        TransparentAddress,
+    },
+    zcash_primitives::transaction::components::{amount::BalanceError, OutPoint, TxOut},
 };
 
 pub mod input_selection;
@@ -197,7 +207,7 @@ where
 ///
 /// let account = AccountId::from(0);
 /// let req = UnifiedAddressRequest::new(false, true, true);
-/// let usk = UnifiedSpendingKey::from_seed(&Network::TestNetwork, &[0; 32][..], account).unwrap();
+/// let usk = UnifiedSpendingKey::from_seed(&Network::TestNetwork, &[], &[0; 32][..], account).unwrap();
 /// let to = usk.to_unified_full_viewing_key().default_address(req).0.into();
 ///
 /// let mut db_read = testing::MockWalletDb {
@@ -400,6 +410,11 @@ where
 /// Select transaction inputs, compute fees, and construct a proposal for a transaction or series
 /// of transactions that can then be authorized and made ready for submission to the network with
 /// [`create_proposed_transactions`].
+///
+/// Returns [`Error::AccountCannotSpend`] if `spend_from_account` has only an incoming viewing
+/// key. If input selection fails for lack of funds, and the account's notes that have been locked
+/// against spending would have made up the shortfall, returns
+/// [`Error::InsufficientUnlockedFunds`] rather than [`Error::InsufficientFunds`].
 #[allow(clippy::too_many_arguments)]
 #[allow(clippy::type_complexity)]
 pub fn propose_transfer<DbT, ParamsT, InputsT, CommitmentTreeErrT>(
@@ -419,26 +434,165 @@ pub fn propose_transfer<DbT, ParamsT, InputsT, CommitmentTreeErrT>(
     >,
 >
 where
-    DbT: WalletRead + InputSource<Error = <DbT as WalletRead>::Error>,
+    DbT: InputSource,
+    DbT: WalletRead<
+        Error = <DbT as InputSource>::Error,
+        AccountId = <DbT as InputSource>::AccountId,
+    >,
     <DbT as InputSource>::NoteRef: Copy + Eq + Ord,
     ParamsT: consensus::Parameters + Clone,
     InputsT: InputSelector<InputSource = DbT>,
 {
+    if let Some(account) = wallet_db
+        .get_account(spend_from_account)
+        .map_err(Error::DataSource)?
+    {
+        if !can_spend_from(&account) {
+            return Err(Error::AccountCannotSpend);
+        }
+    }
+
     let (target_height, anchor_height) = wallet_db
         .get_target_and_anchor_heights(min_confirmations)
         .map_err(|e| Error::from(InputSelectorError::DataSource(e)))?
         .ok_or_else(|| Error::from(InputSelectorError::SyncRequired))?;
 
-    input_selector
-        .propose_transaction(
-            params,
-            wallet_db,
-            target_height,
-            anchor_height,
-            spend_from_account,
-            request,
-        )
-        .map_err(Error::from)
+    match input_selector.propose_transaction(
+        params,
+        wallet_db,
+        target_height,
+        anchor_height,
+        spend_from_account,
+        request,
+    ) {
+        Err(InputSelectorError::InsufficientFunds {
+            available,
+            required,
+        }) => {
+            // If the account's locked notes would have covered the shortfall, report them so
+            // that the caller can distinguish this from a genuine lack of funds.
+            let locked = wallet_db
+                .get_wallet_summary(u32::from(min_confirmations))
+                .map_err(Error::DataSource)?
+                .and_then(|summary| {
+                    summary
+                        .account_balances()
+                        .get(&spend_from_account)
+                        .map(|balance| balance.locked_value())
+                })
+                .unwrap_or(NonNegativeAmount::ZERO);
+
+            Err(insufficient_funds(available, required, locked))
+        }
+        result => result.map_err(Error::from),
+    }
+}
+
+/// Returns whether the wallet can construct transactions that spend from the given account.
+///
+/// An account with only an incoming viewing key cannot detect spends of its funds, so any
+/// inputs selected for it might already have been spent.
+fn can_spend_from<AccountId: Copy>(account: &impl Account<AccountId>) -> bool {
+    account.ufvk().is_some() || account.uivk().is_none()
+}
+
+/// Returns [`Error::AccountCannotSpend`] if any of the given addresses belongs to an account
+/// that has only an incoming viewing key.
+#[cfg(feature = "transparent-inputs")]
+fn check_transparent_sources_can_spend<'a, DbT, CE, SE, FE>(
+    wallet_db: &DbT,
+    addrs: impl IntoIterator<Item = &'a TransparentAddress>,
+) -> Result<(), Error<<DbT as WalletRead>::Error, CE, SE, FE>>
+where
+    DbT: WalletRead,
+{
+    let addrs = addrs.into_iter().collect::<Vec<_>>();
+    for account_id in wallet_db.get_account_ids().map_err(Error::DataSource)? {
+        if let Some(account) = wallet_db
+            .get_account(account_id)
+            .map_err(Error::DataSource)?
+        {
+            if !can_spend_from(&account) {
+                let receivers = wallet_db
+                    .get_transparent_receivers(account_id)
+                    .map_err(Error::DataSource)?;
+                if addrs.iter().any(|addr| receivers.contains_key(*addr)) {
+                    return Err(Error::AccountCannotSpend);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns [`Error::InsufficientUnlockedFunds`] if `locked` value would have made up the
+/// shortfall between `available` and `required`, or [`Error::InsufficientFunds`] otherwise.
+fn insufficient_funds<DE, CE, SE, FE>(
+    available: NonNegativeAmount,
+    required: NonNegativeAmount,
+    locked: NonNegativeAmount,
+) -> Error<DE, CE, SE, FE> {
+    if locked > NonNegativeAmount::ZERO
+        && (available + locked).map_or(true, |total| total >= required)
+    {
+        Error::InsufficientUnlockedFunds {
+            available,
+            required,
+            locked,
+        }
+    } else {
+        Error::InsufficientFunds {
+            available,
+            required,
+        }
+    }
+}
+
+/// Constructs a proposal for paying the draft payment with the given identifier, which may then
+/// be executed using [`create_proposed_transactions`].
+///
+/// Inputs are selected against the current state of the wallet, using the draft's confirmation
+/// policy; the draft is not modified. Callers will usually want to remove the draft with
+/// [`WalletWrite::delete_draft`] once the proposal has been executed.
+#[allow(clippy::type_complexity)]
+pub fn promote_draft_to_proposal<DbT, ParamsT, InputsT, CommitmentTreeErrT>(
+    wallet_db: &mut DbT,
+    params: &ParamsT,
+    input_selector: &InputsT,
+    draft_id: DraftId,
+) -> Result<
+    Proposal<InputsT::FeeRule, <DbT as InputSource>::NoteRef>,
+    Error<
+        <DbT as WalletRead>::Error,
+        CommitmentTreeErrT,
+        InputsT::Error,
+        <InputsT::FeeRule as FeeRule>::Error,
+    >,
+>
+where
+    DbT: InputSource,
+    DbT: WalletRead<
+        Error = <DbT as InputSource>::Error,
+        AccountId = <DbT as InputSource>::AccountId,
+    >,
+    <DbT as InputSource>::NoteRef: Copy + Eq + Ord,
+    ParamsT: consensus::Parameters + Clone,
+    InputsT: InputSelector<InputSource = DbT>,
+{
+    let (account, draft) = wallet_db
+        .get_draft(draft_id)
+        .map_err(Error::DataSource)?
+        .ok_or(Error::DraftNotFound(draft_id))?;
+
+    propose_transfer(
+        wallet_db,
+        params,
+        account,
+        input_selector,
+        draft.request().clone(),
+        draft.min_confirmations(),
+    )
 }
 
 /// Proposes making a payment to the specified address from the given account.
@@ -527,8 +681,136 @@ where
     )
 }
 
+/// Estimates the range of ZIP 317 fees that a transfer from the given account might require,
+/// without selecting inputs or constructing a [`Proposal`].
+///
+/// The estimate is for a transaction making `payment_count` payments to `payment_pool`, along
+/// with a single shielded change output. The minimum assumes that the payments are funded by a
+/// single note, in whichever of the pools holding the account's spendable notes yields the lower
+/// fee. The maximum assumes that every spendable note of the account must be spent, and so
+/// reflects the account's note fragmentation. Notes having a value less than the marginal fee are
+/// dust, and are never counted as inputs.
+///
+/// The estimate does not depend upon the account holding sufficient funds for the transfer, and
+/// if the account has no spendable notes, both bounds assume a single note in `payment_pool` (or
+/// the Sapling pool, for transparent payments).
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+pub fn estimate_transfer_fee<DbT, ParamsT, CommitmentTreeErrT>(
+    wallet_db: &DbT,
+    params: &ParamsT,
+    fee_rule: &Zip317FeeRule,
+    spend_from_account: <DbT as InputSource>::AccountId,
+    payment_pool: PoolType,
+    payment_count: usize,
+    min_confirmations: NonZeroU32,
+) -> Result<
+    FeeEstimate,
+    Error<
+        <DbT as WalletRead>::Error,
+        CommitmentTreeErrT,
+        ChangeError<Zip317FeeError, Infallible>,
+        Zip317FeeError,
+    >,
+>
+where
+    ParamsT: consensus::Parameters,
+    DbT: InputSource,
+    DbT: WalletRead<
+        Error = <DbT as InputSource>::Error,
+        AccountId = <DbT as InputSource>::AccountId,
+    >,
+{
+    let (target_height, anchor_height) = wallet_db
+        .get_target_and_anchor_heights(min_confirmations)
+        .map_err(Error::DataSource)?
+        .ok_or(Error::ScanRequired)?;
+
+    #[cfg(not(feature = "orchard"))]
+    let selectable_pools = &[ShieldedProtocol::Sapling];
+    #[cfg(feature = "orchard")]
+    let selectable_pools = &[ShieldedProtocol::Sapling, ShieldedProtocol::Orchard];
+
+    let notes = wallet_db
+        .select_spendable_notes(
+            spend_from_account,
+            NonNegativeAmount::const_from_u64(MAX_MONEY),
+            selectable_pools,
+            anchor_height,
+            &[],
+        )
+        .map_err(Error::DataSource)?;
+
+    let is_spendable = |value: NonNegativeAmount| value >= fee_rule.marginal_fee();
+    let mut sapling_spends = 0;
+    for note in notes.sapling() {
+        if is_spendable(note.note_value()?) {
+            sapling_spends += 1;
+        }
+    }
+    #[cfg(feature = "orchard")]
+    let mut orchard_spends = 0;
+    #[cfg(feature = "orchard")]
+    for note in notes.orchard() {
+        if is_spendable(note.note_value()?) {
+            orchard_spends += 1;
+        }
+    }
+    #[cfg(not(feature = "orchard"))]
+    let orchard_spends = 0;
+
+    // Computes the fee for the given numbers of Sapling and Orchard spends. As in the standard
+    // change strategies, change is sent to Orchard if the transaction involves Orchard at all.
+    let fee_for = |sapling_spends: usize, _orchard_spends: usize| {
+        let count = |pool| {
+            if payment_pool == pool {
+                payment_count
+            } else {
+                0
+            }
+        };
+        #[cfg(feature = "orchard")]
+        let orchard_outputs = count(PoolType::Shielded(ShieldedProtocol::Orchard));
+        #[cfg(feature = "orchard")]
+        let orchard_change = usize::from(_orchard_spends > 0 || orchard_outputs > 0);
+        #[cfg(not(feature = "orchard"))]
+        let orchard_change = 0;
+
+        let shape = TransactionShape::empty()
+            .with_transparent(0, count(PoolType::Transparent))
+            .with_sapling(
+                sapling_spends,
+                count(PoolType::Shielded(ShieldedProtocol::Sapling)) + 1 - orchard_change,
+            );
+        #[cfg(feature = "orchard")]
+        let shape = shape.with_orchard(_orchard_spends, orchard_outputs + orchard_change);
+
+        fees::estimate_fee(params, fee_rule, target_height, &shape).map_err(Error::NoteSelection)
+    };
+
+    let default_spends = match payment_pool {
+        PoolType::Shielded(ShieldedProtocol::Orchard) => (0, 1),
+        _ => (1, 0),
+    };
+    let minimum = match (sapling_spends, orchard_spends) {
+        (0, 0) => fee_for(default_spends.0, default_spends.1)?,
+        (0, _) => fee_for(0, 1)?,
+        (_, 0) => fee_for(1, 0)?,
+        (_, _) => std::cmp::min(fee_for(1, 0)?, fee_for(0, 1)?),
+    };
+    let maximum = std::cmp::max(minimum, fee_for(sapling_spends, orchard_spends)?);
+
+    Ok(FeeEstimate::from_parts(minimum, maximum).expect("The maximum is at least the minimum"))
+}
+
 /// Constructs a proposal to shield all of the funds belonging to the provided set of
 /// addresses.
+///
+/// If the unlocked funds at these addresses do not reach `shielding_threshold`, and the
+/// transparent outputs locked against spending by the accounts that own them would have made up
+/// the shortfall, returns [`Error::InsufficientUnlockedFunds`] rather than
+/// [`Error::InsufficientFunds`]. Returns [`Error::AccountCannotSpend`] if any of the addresses
+/// belongs to an account that has only an incoming viewing key.
 #[cfg(feature = "transparent-inputs")]
 #[allow(clippy::too_many_arguments)]
 #[allow(clippy::type_complexity)]
@@ -553,21 +835,49 @@ where
     DbT: WalletRead + InputSource<Error = <DbT as WalletRead>::Error>,
     InputsT: ShieldingSelector<InputSource = DbT>,
 {
+    check_transparent_sources_can_spend(wallet_db, from_addrs)?;
+
     let chain_tip_height = wallet_db
         .chain_height()
         .map_err(|e| Error::from(InputSelectorError::DataSource(e)))?
         .ok_or_else(|| Error::from(InputSelectorError::SyncRequired))?;
 
-    input_selector
-        .propose_shielding(
-            params,
-            wallet_db,
-            shielding_threshold,
-            from_addrs,
-            chain_tip_height + 1,
-            min_confirmations,
-        )
-        .map_err(Error::from)
+    match input_selector.propose_shielding(
+        params,
+        wallet_db,
+        shielding_threshold,
+        from_addrs,
+        chain_tip_height + 1,
+        min_confirmations,
+    ) {
+        Err(InputSelectorError::InsufficientFunds {
+            available,
+            required,
+        }) => {
+            // Locked UTXOs are not returned for shielding, so report them if they would have
+            // covered the shortfall.
+            let mut locked = NonNegativeAmount::ZERO;
+            if let Some(summary) = wallet_db
+                .get_wallet_summary(min_confirmations)
+                .map_err(Error::DataSource)?
+            {
+                for account_id in wallet_db.get_account_ids().map_err(Error::DataSource)? {
+                    let receivers = wallet_db
+                        .get_transparent_receivers(account_id)
+                        .map_err(Error::DataSource)?;
+                    if from_addrs.iter().any(|addr| receivers.contains_key(addr)) {
+                        if let Some(balance) = summary.account_balances().get(&account_id) {
+                            locked = (locked + balance.unshielded_locked())
+                                .ok_or(BalanceError::Overflow)?;
+                        }
+                    }
+                }
+            }
+
+            Err(insufficient_funds(available, required, locked))
+        }
+        result => result.map_err(Error::from),
+    }
 }
 
 /// Construct, prove, and sign a transaction or series of transactions using the inputs supplied by
@@ -581,6 +891,18 @@ where
 /// step is not supported, because the ultimate positions of those notes in the global note
 /// commitment tree cannot be known until the transaction that produces those notes is mined,
 /// and therefore the required spend proofs for such notes cannot be constructed.
+///
+/// The proposal need not have been created by this wallet instance; it may, for example, have
+/// been deserialized from its protobuf representation after being created by a watch-only
+/// wallet, in which case it should have been decoded with
+/// [`try_into_checked_standard_proposal`]. Before any transaction is built, the inputs of each
+/// step are checked against the current state of the wallet again; if an input has since been
+/// spent, or if the wallet's note commitment tree has no checkpoint at the anchor height of a
+/// step, an [`Error::InputNotSpendable`] or [`Error::AnchorNotAvailable`] error is returned
+/// respectively. If a transparent input belongs to an account that has only
+/// an incoming viewing key, [`Error::AccountCannotSpend`] is returned.
+///
+/// [`try_into_checked_standard_proposal`]: crate::proto::proposal::Proposal::try_into_checked_standard_proposal
 #[allow(clippy::too_many_arguments)]
 #[allow(clippy::type_complexity)]
 pub fn create_proposed_transactions<DbT, ParamsT, InputsErrT, FeeRuleT, N>(
@@ -601,10 +923,50 @@ pub fn create_proposed_transactions<DbT, ParamsT, InputsErrT, FeeRuleT, N>(
     >,
 >
 where
-    DbT: WalletWrite + WalletCommitmentTrees,
+    DbT: WalletWrite + WalletCommitmentTrees + InputSource<Error = <DbT as WalletRead>::Error>,
+    ParamsT: consensus::Parameters + Clone,
+    FeeRuleT: FeeRule,
+{
+    create_proposed_transactions_inner(
+        wallet_db,
+        params,
+        spend_prover,
+        output_prover,
+        usk,
+        #[cfg(feature = "transparent-inputs")]
+        &[],
+        ovk_policy,
+        proposal,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+fn create_proposed_transactions_inner<DbT, ParamsT, InputsErrT, FeeRuleT, N>(
+    wallet_db: &mut DbT,
+    params: &ParamsT,
+    spend_prover: &impl SpendProver,
+    output_prover: &impl OutputProver,
+    usk: &UnifiedSpendingKey,
+    #[cfg(feature = "transparent-inputs")] standalone_keys: &[AccountPrivKey],
+    ovk_policy: OvkPolicy,
+    proposal: &Proposal<FeeRuleT, N>,
+) -> Result<
+    NonEmpty<TxId>,
+    Error<
+        <DbT as WalletRead>::Error,
+        <DbT as WalletCommitmentTrees>::Error,
+        InputsErrT,
+        FeeRuleT::Error,
+    >,
+>
+where
+    DbT: WalletWrite + WalletCommitmentTrees + InputSource<Error = <DbT as WalletRead>::Error>,
     ParamsT: consensus::Parameters + Clone,
     FeeRuleT: FeeRule,
 {
+    check_proposal_inputs(wallet_db, proposal)?;
+
     let mut step_results = Vec::with_capacity(proposal.steps().len());
     for step in proposal.steps() {
         let step_result = create_proposed_transaction(
@@ -613,6 +975,8 @@ where
             spend_prover,
             output_prover,
             usk,
+            #[cfg(feature = "transparent-inputs")]
+            standalone_keys,
             ovk_policy.clone(),
             proposal.fee_rule(),
             proposal.min_target_height(),
@@ -631,6 +995,126 @@ where
     .expect("proposal.steps is NonEmpty"))
 }
 
+/// Checks that every input of the given proposal is still spendable by the wallet, and that
+/// the wallet's note commitment trees have a checkpoint at the anchor height of each step that
+/// spends shielded notes.
+#[allow(clippy::type_complexity)]
+fn check_proposal_inputs<DbT, InputsErrT, FeeRuleT, N>(
+    wallet_db: &mut DbT,
+    proposal: &Proposal<FeeRuleT, N>,
+) -> Result<
+    (),
+    Error<
+        <DbT as WalletRead>::Error,
+        <DbT as WalletCommitmentTrees>::Error,
+        InputsErrT,
+        FeeRuleT::Error,
+    >,
+>
+where
+    DbT: WalletRead + WalletCommitmentTrees + InputSource<Error = <DbT as WalletRead>::Error>,
+    FeeRuleT: FeeRule,
+{
+    for step in proposal.steps() {
+        #[cfg(feature = "transparent-inputs")]
+        check_transparent_sources_can_spend(
+            wallet_db,
+            step.transparent_inputs()
+                .iter()
+                .map(|utxo| utxo.recipient_address()),
+        )?;
+
+        #[cfg(feature = "transparent-inputs")]
+        for utxo in step.transparent_inputs() {
+            let outpoint = utxo.outpoint();
+            if wallet_db
+                .get_unspent_transparent_output(outpoint)
+                .map_err(Error::DataSource)?
+                .is_none()
+            {
+                return Err(Error::InputNotSpendable(
+                    TxId::from_bytes(*outpoint.hash()),
+                    PoolType::Transparent,
+                    outpoint.n(),
+                ));
+            }
+        }
+
+        if let Some(inputs) = step.shielded_inputs() {
+            let anchor_height = inputs.anchor_height();
+            for protocol in [ShieldedProtocol::Sapling, ShieldedProtocol::Orchard] {
+                if inputs
+                    .notes()
+                    .iter()
+                    .any(|n| n.note().protocol() == protocol)
+                    && !anchor_checkpoint_exists(wallet_db, protocol, anchor_height)
+                        .map_err(Error::CommitmentTree)?
+                {
+                    return Err(Error::AnchorNotAvailable(anchor_height));
+                }
+            }
+
+            for note in inputs.notes() {
+                let protocol = note.note().protocol();
+                let index = u32::from(note.output_index());
+                if wallet_db
+                    .get_spendable_note(note.txid(), protocol, index)
+                    .map_err(Error::DataSource)?
+                    .is_none()
+                {
+                    return Err(Error::InputNotSpendable(
+                        *note.txid(),
+                        PoolType::Shielded(protocol),
+                        index,
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns whether the wallet's note commitment tree for the given protocol has a checkpoint at
+/// the given anchor height.
+pub(crate) fn anchor_checkpoint_exists<DbT: WalletCommitmentTrees>(
+    wallet_db: &mut DbT,
+    protocol: ShieldedProtocol,
+    anchor_height: BlockHeight,
+) -> Result<bool, ShardTreeError<DbT::Error>> {
+    match protocol {
+        ShieldedProtocol::Sapling => wallet_db.with_sapling_tree_mut(|tree| {
+            tree.store()
+                .get_checkpoint(&anchor_height)
+                .map(|checkpoint| checkpoint.is_some())
+                .map_err(ShardTreeError::Storage)
+        }),
+        #[cfg(feature = "orchard")]
+        ShieldedProtocol::Orchard => wallet_db.with_orchard_tree_mut(|tree| {
+            tree.store()
+                .get_checkpoint(&anchor_height)
+                .map(|checkpoint| checkpoint.is_some())
+                .map_err(ShardTreeError::Storage)
+        }),
+        #[cfg(not(feature = "orchard"))]
+        ShieldedProtocol::Orchard => Ok(false),
+    }
+}
+
+/// Reports a pruned checkpoint at the anchor height of a proposal step as
+/// [`Error::AnchorNotAvailable`], rather than as a generic commitment tree error.
+fn anchor_error<DE, CE, SE, FE>(
+    e: ShardTreeError<CE>,
+    anchor_height: BlockHeight,
+) -> Error<DE, CE, SE, FE> {
+    match e {
+        ShardTreeError::Query(QueryError::CheckpointPruned) => {
+            Error::AnchorNotAvailable(anchor_height)
+        }
+        e => Error::CommitmentTree(e),
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 #[allow(clippy::type_complexity)]
 fn create_proposed_transaction<DbT, ParamsT, InputsErrT, FeeRuleT, N>(
@@ -639,6 +1123,7 @@ fn create_proposed_transaction<DbT, ParamsT, InputsErrT, FeeRuleT, N>(
     spend_prover: &impl SpendProver,
     output_prover: &impl OutputProver,
     usk: &UnifiedSpendingKey,
+    #[cfg(feature = "transparent-inputs")] standalone_keys: &[AccountPrivKey],
     ovk_policy: OvkPolicy,
     fee_rule: &FeeRuleT,
     min_target_height: BlockHeight,
@@ -682,10 +1167,21 @@ where
                         Ok(())
                     }
                 }
-                proposal::StepOutputIndex::Change(_) => {
-                    // Only shielded change is supported by zcash_client_backend, so multi-step
-                    // transactions cannot yet spend prior transactions' change outputs.
-                    Err(Error::ProposalNotSupported)
+                proposal::StepOutputIndex::Change(i) => {
+                    // Only ephemeral transparent change outputs, which fund payments to TEX
+                    // addresses, may be spent by later steps.
+                    let prior_change = step
+                        .0
+                        .balance()
+                        .proposed_change()
+                        .get(i)
+                        .ok_or(Error::Proposal(ProposalError::ReferenceError(*s_ref)))?;
+
+                    if prior_change.output_pool() == PoolType::Transparent {
+                        Ok(())
+                    } else {
+                        Err(Error::ProposalNotSupported)
+                    }
                 }
             },
         )?;
@@ -704,7 +1200,8 @@ where
                 |inputs| {
                     wallet_db.with_sapling_tree_mut::<_, _, Error<_, _, _, _>>(|sapling_tree| {
                         let anchor = sapling_tree
-                            .root_at_checkpoint_id(&inputs.anchor_height())?
+                            .root_at_checkpoint_id(&inputs.anchor_height())
+                            .map_err(|e| anchor_error(e, inputs.anchor_height()))?
                             .into();
 
                         let sapling_inputs = inputs
@@ -747,7 +1244,8 @@ where
                 |inputs| {
                     wallet_db.with_orchard_tree_mut::<_, _, Error<_, _, _, _>>(|orchard_tree| {
                         let anchor = orchard_tree
-                            .root_at_checkpoint_id(&inputs.anchor_height())?
+                            .root_at_checkpoint_id(&inputs.anchor_height())
+                            .map_err(|e| anchor_error(e, inputs.anchor_height()))?
                             .into();
 
                         let orchard_inputs = inputs
@@ -802,6 +1300,17 @@ where
         let known_addrs = wallet_db
             .get_transparent_receivers(account)
             .map_err(Error::DataSource)?;
+        let standalone_addrs = standalone_keys
+            .iter()
+            .filter(|key| !key.is_bip44())
+            .map(|key| {
+                let address = key
+                    .to_account_pubkey()
+                    .derive_ext_ivk_from_legacy_key()
+                    .default_legacy_address();
+                (address, key)
+            })
+            .collect::<Vec<_>>();
 
         let mut utxos_spent: Vec<OutPoint> = vec![];
         let mut add_transparent_input = |addr: &TransparentAddress,
@@ -816,6 +1325,14 @@ where
                 FeeRuleT::Error,
             >,
         > {
+            // UTXOs received at a standalone transparent address are signed with the key that
+            // was supplied for that address.
+            if let Some((_, key)) = standalone_addrs.iter().find(|(a, _)| a == addr) {
+                utxos_spent.push(outpoint.clone());
+                builder.add_transparent_input(key.derive_legacy_secret_key(), outpoint, utxo)?;
+                return Ok(());
+            }
+
             let address_metadata = known_addrs
                 .get(addr)
                 .ok_or(Error::AddressNotRecognized(*addr))?
@@ -860,8 +1377,9 @@ where
                         .expect("Payment step references are checked at construction")
                         .recipient_address
                     {
-                        Address::Transparent(t) => Some(t),
-                        Address::Unified(uaddr) => uaddr.transparent(),
+                        Address::Transparent(t) => Some(*t),
+                        Address::Unified(uaddr) => uaddr.transparent().copied(),
+                        Address::Tex(data) => Some(TransparentAddress::PublicKeyHash(*data)),
                         _ => None,
                     }
                     .ok_or(Error::ProposalNotSupported)?;
@@ -884,9 +1402,41 @@ where
                         .ok_or(Error::Proposal(ProposalError::ReferenceError(*input_ref)))?
                         .vout[outpoint.n() as usize];
 
-                    add_transparent_input(recipient_address, outpoint, utxo.clone())?;
+                    add_transparent_input(&recipient_address, outpoint, utxo.clone())?;
+                }
+                proposal::StepOutputIndex::Change(i) => {
+                    // The earlier check ensures that this is an ephemeral transparent change
+                    // output. These were added to the transaction after its transparent payments,
+                    // in change index order.
+                    let (prior_step, result) = &prior_step_results[input_ref.step_index()];
+                    let outpoint = OutPoint::new(
+                        result.transaction().txid().into(),
+                        u32::try_from(
+                            prior_step
+                                .payment_pools()
+                                .values()
+                                .filter(|pool| pool == &&PoolType::Transparent)
+                                .count()
+                                + prior_step.balance().proposed_change()[..i]
+                                    .iter()
+                                    .filter(|change| change.output_pool() == PoolType::Transparent)
+                                    .count(),
+                        )
+                        .expect("Transparent output index fits into a u32"),
+                    );
+                    let utxo = result
+                        .transaction()
+                        .transparent_bundle()
+                        .ok_or(Error::Proposal(ProposalError::ReferenceError(*input_ref)))?
+                        .vout[outpoint.n() as usize]
+                        .clone();
+                    let recipient_address = *utxo
+                        .recipient_address()
+                        .as_ref()
+                        .ok_or(Error::Proposal(ProposalError::ReferenceError(*input_ref)))?;
+
+                    add_transparent_input(&recipient_address, outpoint, utxo)?;
                 }
-                proposal::StepOutputIndex::Change(_) => unreachable!(),
             }
         }
         utxos_spent
@@ -1037,6 +1587,15 @@ where
                 } else {
                     builder.add_transparent_output(to, payment.amount)?;
                 }
+                transparent_output_meta.push((*to, payment.amount));
+            }
+            Address::Tex(data) => {
+                let to = TransparentAddress::PublicKeyHash(*data);
+                if payment.memo.is_some() {
+                    return Err(Error::MemoForbidden);
+                } else {
+                    builder.add_transparent_output(&to, payment.amount)?;
+                }
                 transparent_output_meta.push((to, payment.amount));
             }
         }
@@ -1047,7 +1606,7 @@ where
             .memo()
             .map_or_else(MemoBytes::empty, |m| m.clone());
         match change_value.output_pool() {
-            ShieldedProtocol::Sapling => {
+            PoolType::Shielded(ShieldedProtocol::Sapling) => {
                 builder.add_sapling_output(
                     sapling_internal_ovk(),
                     sapling_dfvk.change_address().1,
@@ -1064,7 +1623,7 @@ where
                     Some(memo),
                 ))
             }
-            ShieldedProtocol::Orchard => {
+            PoolType::Shielded(ShieldedProtocol::Orchard) => {
                 #[cfg(not(feature = "orchard"))]
                 return Err(Error::UnsupportedChangeType(PoolType::Shielded(
                     ShieldedProtocol::Orchard,
@@ -1089,6 +1648,25 @@ where
                     ))
                 }
             }
+            PoolType::Transparent => {
+                // Transparent change is only used for the ephemeral outputs that fund payments
+                // to TEX addresses, each of which is sent to a newly reserved address in the
+                // ZIP 320 ephemeral scope.
+                #[cfg(not(feature = "transparent-inputs"))]
+                return Err(Error::UnsupportedChangeType(PoolType::Transparent));
+
+                #[cfg(feature = "transparent-inputs")]
+                {
+                    let (ephemeral_addr, _) = wallet_db
+                        .reserve_next_ephemeral_addresses(account, 1)
+                        .map_err(Error::DataSource)?
+                        .into_iter()
+                        .next()
+                        .ok_or(Error::UnsupportedChangeType(PoolType::Transparent))?;
+                    builder.add_transparent_output(&ephemeral_addr, change_value.value())?;
+                    transparent_output_meta.push((ephemeral_addr, change_value.value()));
+                }
+            }
         }
     }
 
@@ -1173,7 +1751,7 @@ where
             .map(|(index, _)| index)
             .expect("An output should exist in the transaction for each transparent payment.");
 
-        SentTransactionOutput::from_parts(output_index, Recipient::Transparent(*addr), value, None)
+        SentTransactionOutput::from_parts(output_index, Recipient::Transparent(addr), value, None)
     });
 
     let mut outputs = vec![];
@@ -1276,3 +1854,67 @@ where
         &proposal,
     )
 }
+
+/// Shield transparent UTXOs received at standalone transparent addresses into the account of
+/// the given spending key.
+///
+/// This behaves like [`shield_transparent_funds`], but `from_addrs` may include the addresses of
+/// [`AccountSource::StandaloneTransparent`] accounts, such as those imported with
+/// [`WalletWrite::import_standalone_transparent_address`]. The UTXOs received at such an
+/// address are signed with the key in `standalone_keys` that corresponds to it; these keys must
+/// be single non-HD keys, as constructed with [`AccountPrivKey::from_transparent_key`]. The
+/// shielded output is sent to the account of `usk`, whose own transparent UTXOs at `from_addrs`
+/// are shielded as usual.
+///
+/// Returns [`Error::AddressNotRecognized`] if a selected UTXO was received at an address for
+/// which no key is available.
+///
+/// [`AccountSource::StandaloneTransparent`]: crate::data_api::AccountSource::StandaloneTransparent
+#[cfg(feature = "transparent-inputs")]
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+pub fn shield_standalone_transparent_funds<DbT, ParamsT, InputsT>(
+    wallet_db: &mut DbT,
+    params: &ParamsT,
+    spend_prover: &impl SpendProver,
+    output_prover: &impl OutputProver,
+    input_selector: &InputsT,
+    shielding_threshold: NonNegativeAmount,
+    usk: &UnifiedSpendingKey,
+    standalone_keys: &[AccountPrivKey],
+    from_addrs: &[TransparentAddress],
+    min_confirmations: u32,
+) -> Result<
+    NonEmpty<TxId>,
+    Error<
+        <DbT as WalletRead>::Error,
+        <DbT as WalletCommitmentTrees>::Error,
+        InputsT::Error,
+        <InputsT::FeeRule as FeeRule>::Error,
+    >,
+>
+where
+    ParamsT: consensus::Parameters,
+    DbT: WalletWrite + WalletCommitmentTrees + InputSource<Error = <DbT as WalletRead>::Error>,
+    InputsT: ShieldingSelector<InputSource = DbT>,
+{
+    let proposal = propose_shielding(
+        wallet_db,
+        params,
+        input_selector,
+        shielding_threshold,
+        from_addrs,
+        min_confirmations,
+    )?;
+
+    create_proposed_transactions_inner(
+        wallet_db,
+        params,
+        spend_prover,
+        output_prover,
+        usk,
+        standalone_keys,
+        OvkPolicy::Sender,
+        &proposal,
+    )
+}
@@ -18,6 +18,7 @@ use zcash_primitives::{
         fees::FeeRule,
     },
 };
+use zcash_protocol::value::MAX_MONEY;
 
 use crate::{
     address::{Address, UnifiedAddress},
@@ -31,7 +32,13 @@ use crate::{
 
 #[cfg(feature = "transparent-inputs")]
 use {
-    std::collections::BTreeSet, std::convert::Infallible,
+    crate::{
+        fees::{ChangeValue, TransactionBalance},
+        proposal::{Step, StepOutput, StepOutputIndex},
+        zip321::Payment,
+    },
+    std::collections::BTreeSet,
+    std::convert::Infallible,
     zcash_primitives::legacy::TransparentAddress,
     zcash_primitives::transaction::components::OutPoint,
 };
@@ -206,6 +213,9 @@ pub enum GreedyInputSelectorError<ChangeStrategyErrT, NoteRefT> {
     Balance(BalanceError),
     /// A unified address did not contain a supported receiver.
     UnsupportedAddress(Box<UnifiedAddress>),
+    /// The transaction request included a payment to a ZIP 320 TEX address, which requires the
+    /// `transparent-inputs` feature to be enabled.
+    UnsupportedTexAddress,
     /// An error was encountered in change selection.
     Change(ChangeError<ChangeStrategyErrT, NoteRefT>),
 }
@@ -223,6 +233,10 @@ impl<CE: fmt::Display, N: fmt::Display> fmt::Display for GreedyInputSelectorErro
                 // don't have network parameters here
                 write!(f, "Unified address contains no supported receivers.")
             }
+            GreedyInputSelectorError::UnsupportedTexAddress => write!(
+                f,
+                "Payments to TEX addresses require the transparent-inputs feature."
+            ),
             GreedyInputSelectorError::Change(err) => {
                 write!(f, "An error occurred computing change and fees: {}", err)
             }
@@ -288,14 +302,305 @@ impl orchard_fees::OutputView for OrchardPayment {
     }
 }
 
+/// The order in which a [`GreedyInputSelector`] considers spendable notes for inclusion in a
+/// transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoteSelectionStrategy {
+    /// Select notes of the greatest value first. This minimizes the number of inputs, and
+    /// therefore the fee, required to satisfy a transaction request.
+    LargestFirst,
+    /// Select notes of the least value first. This consolidates small notes into change.
+    SmallestFirst,
+    /// Select notes in the order in which they were added to the note commitment tree.
+    ///
+    /// Note commitment tree positions are not comparable across pools, so Sapling notes are
+    /// considered before Orchard notes.
+    OldestFirst,
+    /// Select notes from the pool that receives the transaction's payments before notes from
+    /// any other pool, so that value only crosses between the Sapling and Orchard pools when
+    /// the preferred pool cannot cover the payments. Within each pool, notes are selected
+    /// oldest first.
+    ///
+    /// If the transaction pays to both shielded pools, or only to transparent recipients, no
+    /// pool is preferred and this behaves like [`NoteSelectionStrategy::OldestFirst`].
+    PreferSamePool,
+}
+
+/// The spendable notes available to an ordered note selection, along with the order in which
+/// they will be considered.
+struct OrderedNotes<NoteRef> {
+    candidates: SpendableNotes<NoteRef>,
+    /// The pool, index within that pool's candidates, and value of each candidate note.
+    order: Vec<(ShieldedProtocol, usize, NonNegativeAmount)>,
+}
+
+impl<NoteRef: Copy + Eq> OrderedNotes<NoteRef> {
+    fn new(
+        candidates: SpendableNotes<NoteRef>,
+        strategy: NoteSelectionStrategy,
+        preferred_pool: Option<ShieldedProtocol>,
+    ) -> Result<Self, BalanceError> {
+        // (pool, index, value, position)
+        let mut entries = candidates
+            .sapling()
+            .iter()
+            .enumerate()
+            .map(|(i, n)| {
+                Ok((
+                    ShieldedProtocol::Sapling,
+                    i,
+                    n.note_value()?,
+                    n.note_commitment_tree_position(),
+                ))
+            })
+            .collect::<Result<Vec<_>, BalanceError>>()?;
+        #[cfg(feature = "orchard")]
+        entries.extend(
+            candidates
+                .orchard()
+                .iter()
+                .enumerate()
+                .map(|(i, n)| {
+                    Ok((
+                        ShieldedProtocol::Orchard,
+                        i,
+                        n.note_value()?,
+                        n.note_commitment_tree_position(),
+                    ))
+                })
+                .collect::<Result<Vec<_>, BalanceError>>()?,
+        );
+
+        // Ties are broken by pool and then by note commitment tree position, so that the
+        // order is deterministic.
+        match strategy {
+            NoteSelectionStrategy::LargestFirst => {
+                entries.sort_by(|a, b| b.2.cmp(&a.2).then((a.0, a.3).cmp(&(b.0, b.3))))
+            }
+            NoteSelectionStrategy::SmallestFirst => entries.sort_by_key(|e| (e.2, e.0, e.3)),
+            NoteSelectionStrategy::OldestFirst => entries.sort_by_key(|e| (e.0, e.3)),
+            NoteSelectionStrategy::PreferSamePool => {
+                entries.sort_by_key(|e| (Some(e.0) != preferred_pool, e.0, e.3))
+            }
+        }
+
+        Ok(OrderedNotes {
+            candidates,
+            order: entries
+                .into_iter()
+                .map(|(pool, i, value, _)| (pool, i, value))
+                .collect(),
+        })
+    }
+
+    fn note_id(&self, pool: ShieldedProtocol, i: usize) -> &NoteRef {
+        match pool {
+            ShieldedProtocol::Sapling => self.candidates.sapling()[i].internal_note_id(),
+            #[cfg(feature = "orchard")]
+            ShieldedProtocol::Orchard => self.candidates.orchard()[i].internal_note_id(),
+            #[cfg(not(feature = "orchard"))]
+            ShieldedProtocol::Orchard => unreachable!("Orchard notes are never selected."),
+        }
+    }
+
+    /// Returns the shortest prefix of the ordered, non-excluded notes having a total value of
+    /// at least `target_value`, or all non-excluded notes if their total value is insufficient.
+    fn select(
+        &self,
+        target_value: NonNegativeAmount,
+        exclude: &[NoteRef],
+    ) -> Result<Vec<(ShieldedProtocol, usize)>, BalanceError> {
+        let mut selected = vec![];
+        let mut total = NonNegativeAmount::ZERO;
+        for (pool, i, value) in self
+            .order
+            .iter()
+            .filter(|(pool, i, _)| !exclude.contains(self.note_id(*pool, *i)))
+        {
+            if total >= target_value {
+                break;
+            }
+            total = (total + *value).ok_or(BalanceError::Overflow)?;
+            selected.push((*pool, *i));
+        }
+
+        Ok(selected)
+    }
+
+    fn to_spendable_notes(
+        &self,
+        selected: &[(ShieldedProtocol, usize)],
+    ) -> SpendableNotes<NoteRef> {
+        let mut sapling = vec![];
+        #[cfg(feature = "orchard")]
+        let mut orchard = vec![];
+        for (pool, i) in selected {
+            match pool {
+                ShieldedProtocol::Sapling => sapling.push(self.candidates.sapling()[*i].clone()),
+                #[cfg(feature = "orchard")]
+                ShieldedProtocol::Orchard => orchard.push(self.candidates.orchard()[*i].clone()),
+                #[cfg(not(feature = "orchard"))]
+                ShieldedProtocol::Orchard => unreachable!("Orchard notes are never selected."),
+            }
+        }
+
+        SpendableNotes::new(
+            sapling,
+            #[cfg(feature = "orchard")]
+            orchard,
+        )
+    }
+}
+
+/// The payments to ZIP 320 TEX addresses within a transaction request, which are made by the
+/// second step of a proposal from an ephemeral transparent output of the first step.
+#[cfg(feature = "transparent-inputs")]
+struct TexStep {
+    payments: BTreeMap<usize, Payment>,
+    outputs: Vec<TxOut>,
+    fee: NonNegativeAmount,
+}
+
+#[cfg(feature = "transparent-inputs")]
+impl TexStep {
+    fn new<P: consensus::Parameters, F: FeeRule, DbErrT, SelectorErrT>(
+        params: &P,
+        fee_rule: &F,
+        target_height: BlockHeight,
+        payments: BTreeMap<usize, Payment>,
+    ) -> Result<Self, InputSelectorError<DbErrT, SelectorErrT>>
+    where
+        InputSelectorError<DbErrT, SelectorErrT>: From<BalanceError>,
+    {
+        let outputs = payments
+            .values()
+            .map(|payment| TxOut {
+                value: payment.amount,
+                script_pubkey: payment
+                    .recipient_address
+                    .to_transparent_address()
+                    .expect("TEX addresses encode a transparent receiver")
+                    .script(),
+            })
+            .collect::<Vec<_>>();
+
+        // The second step spends the ephemeral output as its only input, and has no change. An
+        // ephemeral address is a P2PKH address, as is the receiver of any TEX address.
+        let ephemeral_input = WalletTransparentOutput::from_parts(
+            OutPoint::new([0; 32], 0),
+            TxOut {
+                value: NonNegativeAmount::ZERO,
+                script_pubkey: outputs[0].script_pubkey.clone(),
+            },
+            target_height,
+        )
+        .expect("P2PKH outputs have a recipient address");
+        // A fee can only fail to be computed for a P2PKH input if an amount is out of range.
+        let fee = fee_rule
+            .fee_required(params, target_height, &[ephemeral_input], &outputs, 0, 0, 0)
+            .map_err(|_| BalanceError::Overflow)?;
+
+        Ok(TexStep {
+            payments,
+            outputs,
+            fee,
+        })
+    }
+
+    /// Returns the value of the ephemeral output that funds the TEX payments and their fee.
+    fn ephemeral_value(&self) -> Result<NonNegativeAmount, BalanceError> {
+        self.outputs
+            .iter()
+            .map(|output| output.value)
+            .chain(Some(self.fee))
+            .sum::<Option<NonNegativeAmount>>()
+            .ok_or(BalanceError::Overflow)
+    }
+
+    /// Returns an output that is equivalent, for the purpose of fee computation, to the
+    /// ephemeral output created by the first step.
+    fn ephemeral_output(&self) -> Result<TxOut, BalanceError> {
+        Ok(TxOut {
+            value: self.ephemeral_value()?,
+            script_pubkey: self.outputs[0].script_pubkey.clone(),
+        })
+    }
+
+    /// Constructs a two-step proposal, the first step of which makes the remaining payments of
+    /// the request from the given inputs and creates the ephemeral output in addition to the
+    /// given change, and the second of which makes the TEX payments.
+    #[allow(clippy::too_many_arguments)]
+    fn into_proposal<FeeRuleT, NoteRef>(
+        self,
+        transaction_request: TransactionRequest,
+        payment_pools: BTreeMap<usize, PoolType>,
+        shielded_inputs: Option<ShieldedInputs<NoteRef>>,
+        balance: crate::fees::TransactionBalance,
+        fee_rule: FeeRuleT,
+        target_height: BlockHeight,
+    ) -> Result<Proposal<FeeRuleT, NoteRef>, ProposalError> {
+        let mut change = balance.proposed_change().to_vec();
+        change.push(ChangeValue::ephemeral_transparent(
+            self.ephemeral_value()
+                .map_err(|_| ProposalError::Overflow)?,
+        ));
+        let ephemeral_output = StepOutput::new(0, StepOutputIndex::Change(change.len() - 1));
+
+        let step0 = Step::from_parts(
+            &[],
+            transaction_request,
+            payment_pools,
+            vec![],
+            shielded_inputs,
+            vec![],
+            match balance.change_policy() {
+                Some(decisions) => TransactionBalance::new(change, balance.fee_required())
+                    .map(|b| b.with_change_policy(*decisions)),
+                None => TransactionBalance::new(change, balance.fee_required()),
+            }
+            .map_err(|_| ProposalError::Overflow)?,
+            false,
+        )?;
+
+        let tex_pools = self
+            .payments
+            .keys()
+            .map(|idx| (*idx, PoolType::Transparent))
+            .collect();
+        let step1 = Step::from_parts(
+            std::slice::from_ref(&step0),
+            TransactionRequest::from_indexed(self.payments)
+                .expect("A subset of a valid request is valid"),
+            tex_pools,
+            vec![],
+            None,
+            vec![ephemeral_output],
+            TransactionBalance::new(vec![], self.fee).map_err(|_| ProposalError::Overflow)?,
+            false,
+        )?;
+
+        Proposal::multi_step(
+            fee_rule,
+            target_height,
+            NonEmpty {
+                head: step0,
+                tail: vec![step1],
+            },
+        )
+    }
+}
+
 /// An [`InputSelector`] implementation that uses a greedy strategy to select between available
 /// notes.
 ///
 /// This implementation performs input selection using methods available via the
-/// [`InputSource`] interface.
+/// [`InputSource`] interface. By default, notes are selected in the order in which the
+/// [`InputSource`] returns them; use [`GreedyInputSelector::with_note_selection_strategy`] to
+/// control that order.
 pub struct GreedyInputSelector<DbT, ChangeT> {
     change_strategy: ChangeT,
     dust_output_policy: DustOutputPolicy,
+    note_selection_strategy: Option<NoteSelectionStrategy>,
     _ds_type: PhantomData<DbT>,
 }
 
@@ -306,9 +611,23 @@ impl<DbT, ChangeT: ChangeStrategy> GreedyInputSelector<DbT, ChangeT> {
         GreedyInputSelector {
             change_strategy,
             dust_output_policy,
+            note_selection_strategy: None,
             _ds_type: PhantomData,
         }
     }
+
+    /// Returns this input selector, modified to consider spendable notes in the order given by
+    /// the provided strategy.
+    ///
+    /// When a strategy is set, the selector obtains the wallet's spendable notes by requesting
+    /// the maximum possible value from [`InputSource::select_spendable_notes`], and so relies
+    /// upon the data source returning all of its spendable notes when the wallet cannot cover
+    /// the requested value. Notes that the change strategy reports as dust (notes whose value
+    /// does not cover the marginal fee of spending them) are skipped.
+    pub fn with_note_selection_strategy(mut self, strategy: NoteSelectionStrategy) -> Self {
+        self.note_selection_strategy = Some(strategy);
+        self
+    }
 }
 
 impl<DbT, ChangeT> InputSelector for GreedyInputSelector<DbT, ChangeT>
@@ -343,8 +662,12 @@ where
         #[cfg(feature = "orchard")]
         let mut orchard_outputs = vec![];
         let mut payment_pools = BTreeMap::new();
+        let mut tex_payments = BTreeMap::new();
         for (idx, payment) in transaction_request.payments() {
             match &payment.recipient_address {
+                Address::Tex(_) => {
+                    tex_payments.insert(*idx, payment.clone());
+                }
                 Address::Transparent(addr) => {
                     payment_pools.insert(*idx, PoolType::Transparent);
                     transparent_outputs.push(TxOut {
@@ -386,18 +709,99 @@ where
             }
         }
 
+        // Payments to TEX addresses may only be funded from transparent inputs, so they are made
+        // by a second step that spends an ephemeral transparent output of the first step.
+        let (transaction_request, tex_step) = if tex_payments.is_empty() {
+            (transaction_request, None)
+        } else {
+            #[cfg(not(feature = "transparent-inputs"))]
+            return Err(InputSelectorError::Selection(
+                GreedyInputSelectorError::UnsupportedTexAddress,
+            ));
+
+            #[cfg(feature = "transparent-inputs")]
+            {
+                let tex_step = TexStep::new(
+                    params,
+                    self.change_strategy.fee_rule(),
+                    target_height,
+                    tex_payments,
+                )?;
+                transparent_outputs.push(tex_step.ephemeral_output()?);
+
+                let payments = transaction_request
+                    .payments()
+                    .iter()
+                    .filter(|(idx, _)| payment_pools.contains_key(idx))
+                    .map(|(idx, payment)| (*idx, payment.clone()))
+                    .collect();
+                (
+                    TransactionRequest::from_indexed(payments)
+                        .expect("A subset of a valid request is valid"),
+                    Some(tex_step),
+                )
+            }
+        };
+
+        #[cfg(not(feature = "orchard"))]
+        let selectable_pools = &[ShieldedProtocol::Sapling];
+        #[cfg(feature = "orchard")]
+        let selectable_pools = &[ShieldedProtocol::Sapling, ShieldedProtocol::Orchard];
+
+        let ordered_notes = self
+            .note_selection_strategy
+            .map(|strategy| {
+                #[cfg(not(feature = "orchard"))]
+                let preferred_pool = Some(ShieldedProtocol::Sapling);
+                #[cfg(feature = "orchard")]
+                let preferred_pool = match (sapling_outputs.is_empty(), orchard_outputs.is_empty())
+                {
+                    (false, true) => Some(ShieldedProtocol::Sapling),
+                    (true, false) => Some(ShieldedProtocol::Orchard),
+                    _ => None,
+                };
+
+                let candidates = wallet_db
+                    .select_spendable_notes(
+                        account,
+                        NonNegativeAmount::const_from_u64(MAX_MONEY),
+                        selectable_pools,
+                        anchor_height,
+                        &[],
+                    )
+                    .map_err(InputSelectorError::DataSource)?;
+
+                Ok::<_, InputSelectorError<_, Self::Error>>(OrderedNotes::new(
+                    candidates,
+                    strategy,
+                    preferred_pool,
+                )?)
+            })
+            .transpose()?;
+
         let mut shielded_inputs = SpendableNotes::empty();
         let mut prior_available = NonNegativeAmount::ZERO;
+        let mut prior_selection = vec![];
         let mut amount_required = NonNegativeAmount::ZERO;
         let mut exclude: Vec<DbT::NoteRef> = vec![];
         // This loop is guaranteed to terminate because on each iteration we check that the amount
         // of funds selected is strictly increasing. The loop will either return a successful
         // result or the wallet will eventually run out of funds to select.
+        //
+        // When notes are selected according to a `NoteSelectionStrategy`, we instead check that
+        // the selection has changed. Each selection is the shortest ordered prefix of the
+        // non-excluded notes that covers the amount required; between iterations either the
+        // excluded set grows (when dust inputs were rejected) or the amount required grows past
+        // the value of the current selection, so the selection cannot repeat unless the
+        // wallet's funds have been exhausted.
         loop {
             #[cfg(not(feature = "orchard"))]
             let use_sapling = true;
             #[cfg(feature = "orchard")]
-            let (use_sapling, use_orchard) = {
+            let (use_sapling, use_orchard) = if ordered_notes.is_some() {
+                // All notes chosen by the selection strategy are spent.
+                (true, true)
+            } else {
                 let (sapling_input_total, orchard_input_total) = (
                     shielded_inputs.sapling_value()?,
                     shielded_inputs.orchard_value()?,
@@ -456,21 +860,38 @@ where
 
             match balance {
                 Ok(balance) => {
-                    return Proposal::single_step(
-                        transaction_request,
-                        payment_pools,
-                        vec![],
+                    let shielded_inputs =
                         NonEmpty::from_vec(shielded_inputs.into_vec(&SimpleNoteRetention {
                             sapling: use_sapling,
                             #[cfg(feature = "orchard")]
                             orchard: use_orchard,
                         }))
-                        .map(|notes| ShieldedInputs::from_parts(anchor_height, notes)),
-                        balance,
-                        (*self.change_strategy.fee_rule()).clone(),
-                        target_height,
-                        false,
-                    )
+                        .map(|notes| ShieldedInputs::from_parts(anchor_height, notes));
+                    let fee_rule = (*self.change_strategy.fee_rule()).clone();
+
+                    return match tex_step {
+                        None => Proposal::single_step(
+                            transaction_request,
+                            payment_pools,
+                            vec![],
+                            shielded_inputs,
+                            balance,
+                            fee_rule,
+                            target_height,
+                            false,
+                        ),
+                        #[cfg(feature = "transparent-inputs")]
+                        Some(tex_step) => tex_step.into_proposal(
+                            transaction_request,
+                            payment_pools,
+                            shielded_inputs,
+                            balance,
+                            fee_rule,
+                            target_height,
+                        ),
+                        #[cfg(not(feature = "transparent-inputs"))]
+                        Some(tex_step) => match tex_step {},
+                    }
                     .map_err(InputSelectorError::Proposal);
                 }
                 Err(ChangeError::DustInputs {
@@ -489,10 +910,18 @@ where
                 Err(other) => return Err(other.into()),
             }
 
-            #[cfg(not(feature = "orchard"))]
-            let selectable_pools = &[ShieldedProtocol::Sapling];
-            #[cfg(feature = "orchard")]
-            let selectable_pools = &[ShieldedProtocol::Sapling, ShieldedProtocol::Orchard];
+            if let Some(ordered_notes) = &ordered_notes {
+                let selection = ordered_notes.select(amount_required, &exclude)?;
+                shielded_inputs = ordered_notes.to_spendable_notes(&selection);
+                if selection == prior_selection {
+                    return Err(InputSelectorError::InsufficientFunds {
+                        required: amount_required,
+                        available: shielded_inputs.total_value()?,
+                    });
+                }
+                prior_selection = selection;
+                continue;
+            }
 
             shielded_inputs = wallet_db
                 .select_spendable_notes(
@@ -630,3 +1059,416 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use incrementalmerkletree::Position;
+    use sapling::{value::NoteValue, zip32::ExtendedSpendingKey, Rseed};
+    use zcash_primitives::{
+        consensus::{BlockHeight, Network, NetworkConstants, NetworkUpgrade, Parameters},
+        transaction::{
+            components::amount::NonNegativeAmount, fees::zip317::FeeRule as Zip317FeeRule, TxId,
+        },
+        zip32::Scope,
+    };
+
+    use super::{GreedyInputSelector, InputSelector, InputSelectorError, NoteSelectionStrategy};
+    use crate::{
+        address::Address,
+        data_api::{InputSource, SpendableNotes},
+        fees::{zip317::SingleOutputChangeStrategy, DustOutputPolicy},
+        wallet::{Note, ReceivedNote},
+        zip321::{Payment, TransactionRequest},
+        ShieldedProtocol,
+    };
+
+    #[cfg(feature = "orchard")]
+    use crate::address::UnifiedAddress;
+
+    /// An [`InputSource`] holding a fixed set of notes, all of which it returns from
+    /// [`InputSource::select_spendable_notes`].
+    struct TestInputSource {
+        notes: SpendableNotes<u32>,
+    }
+
+    impl InputSource for TestInputSource {
+        type Error = Infallible;
+        type AccountId = u32;
+        type NoteRef = u32;
+
+        fn get_spendable_note(
+            &self,
+            _txid: &TxId,
+            _protocol: ShieldedProtocol,
+            _index: u32,
+        ) -> Result<Option<ReceivedNote<Self::NoteRef, Note>>, Self::Error> {
+            Ok(None)
+        }
+
+        fn select_spendable_notes(
+            &self,
+            _account: Self::AccountId,
+            _target_value: NonNegativeAmount,
+            sources: &[ShieldedProtocol],
+            _anchor_height: BlockHeight,
+            exclude: &[Self::NoteRef],
+        ) -> Result<SpendableNotes<Self::NoteRef>, Self::Error> {
+            let sapling = if sources.contains(&ShieldedProtocol::Sapling) {
+                self.notes
+                    .sapling()
+                    .iter()
+                    .filter(|n| !exclude.contains(n.internal_note_id()))
+                    .cloned()
+                    .collect()
+            } else {
+                vec![]
+            };
+            #[cfg(feature = "orchard")]
+            let orchard = if sources.contains(&ShieldedProtocol::Orchard) {
+                self.notes
+                    .orchard()
+                    .iter()
+                    .filter(|n| !exclude.contains(n.internal_note_id()))
+                    .cloned()
+                    .collect()
+            } else {
+                vec![]
+            };
+
+            Ok(SpendableNotes::new(
+                sapling,
+                #[cfg(feature = "orchard")]
+                orchard,
+            ))
+        }
+    }
+
+    fn sapling_address() -> sapling::PaymentAddress {
+        ExtendedSpendingKey::master(&[]).default_address().1
+    }
+
+    /// Constructs Sapling notes with the given values, identified by their index in `values`
+    /// and received in that order.
+    fn sapling_notes(values: &[u64]) -> Vec<ReceivedNote<u32, sapling::Note>> {
+        let recipient = sapling_address();
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                ReceivedNote::from_parts(
+                    i as u32,
+                    TxId::from_bytes([i as u8; 32]),
+                    0,
+                    sapling::Note::from_parts(
+                        recipient,
+                        NoteValue::from_raw(*value),
+                        Rseed::AfterZip212([i as u8; 32]),
+                    ),
+                    Scope::External,
+                    Position::from(i as u64),
+                )
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "orchard")]
+    fn orchard_address() -> orchard::Address {
+        let sk = orchard::keys::SpendingKey::from_zip32_seed(
+            &[0; 32],
+            Network::TestNetwork.coin_type(),
+            zip32::AccountId::ZERO,
+        )
+        .unwrap();
+        orchard::keys::FullViewingKey::from(&sk).address_at(0u32, orchard::keys::Scope::External)
+    }
+
+    /// Constructs Orchard notes with the given values, identified by `first_id` plus their
+    /// index in `values` and received in that order.
+    #[cfg(feature = "orchard")]
+    fn orchard_notes(first_id: u32, values: &[u64]) -> Vec<ReceivedNote<u32, orchard::Note>> {
+        let recipient = orchard_address();
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let rho = orchard::note::Rho::from_bytes(&[i as u8; 32]).unwrap();
+                let note = (0u8..)
+                    .find_map(|b| {
+                        Option::from(orchard::note::RandomSeed::from_bytes([b; 32], &rho)).and_then(
+                            |rseed| {
+                                Option::from(orchard::Note::from_parts(
+                                    recipient,
+                                    orchard::value::NoteValue::from_raw(*value),
+                                    rho,
+                                    rseed,
+                                ))
+                            },
+                        )
+                    })
+                    .unwrap();
+                ReceivedNote::from_parts(
+                    first_id + i as u32,
+                    TxId::from_bytes([0xff - i as u8; 32]),
+                    0,
+                    note,
+                    Scope::External,
+                    Position::from(i as u64),
+                )
+            })
+            .collect()
+    }
+
+    fn request_to(recipient_address: Address, amount: u64) -> TransactionRequest {
+        TransactionRequest::new(vec![Payment {
+            recipient_address,
+            amount: NonNegativeAmount::const_from_u64(amount),
+            memo: None,
+            label: None,
+            message: None,
+            other_params: vec![],
+        }])
+        .unwrap()
+    }
+
+    /// Runs input selection for the given request against the given notes, and returns the
+    /// identifiers of the selected notes (in ascending order) along with the required fee.
+    fn select_inputs(
+        notes: SpendableNotes<u32>,
+        strategy: NoteSelectionStrategy,
+        request: TransactionRequest,
+    ) -> Result<(Vec<u32>, NonNegativeAmount), (NonNegativeAmount, NonNegativeAmount)> {
+        let network = Network::TestNetwork;
+        let height = network.activation_height(NetworkUpgrade::Nu5).unwrap();
+        let input_selector = GreedyInputSelector::<TestInputSource, _>::new(
+            SingleOutputChangeStrategy::new(
+                Zip317FeeRule::standard(),
+                None,
+                ShieldedProtocol::Sapling,
+            ),
+            DustOutputPolicy::default(),
+        )
+        .with_note_selection_strategy(strategy);
+
+        match input_selector.propose_transaction(
+            &network,
+            &TestInputSource { notes },
+            height,
+            height,
+            0,
+            request,
+        ) {
+            Ok(proposal) => {
+                let step = proposal.steps().first();
+                let mut selected: Vec<u32> = step
+                    .shielded_inputs()
+                    .unwrap()
+                    .notes()
+                    .iter()
+                    .map(|n| *n.internal_note_id())
+                    .collect();
+                selected.sort_unstable();
+                Ok((selected, step.balance().fee_required()))
+            }
+            Err(InputSelectorError::InsufficientFunds {
+                available,
+                required,
+            }) => Err((available, required)),
+            Err(e) => panic!("unexpected input selection error: {:?}", e),
+        }
+    }
+
+    fn sapling_only(values: &[u64]) -> SpendableNotes<u32> {
+        SpendableNotes::new(
+            sapling_notes(values),
+            #[cfg(feature = "orchard")]
+            vec![],
+        )
+    }
+
+    #[test]
+    fn note_selection_strategies() {
+        // Notes are listed oldest first; the 1000 ZAT note is dust under ZIP 317.
+        let notes = [20000, 60000, 10000, 40000, 1000];
+        let request = || request_to(Address::Sapling(sapling_address()), 30000);
+
+        // A single input suffices, so the fee is the ZIP 317 minimum of two logical actions.
+        assert_eq!(
+            select_inputs(
+                sapling_only(&notes),
+                NoteSelectionStrategy::LargestFirst,
+                request()
+            ),
+            Ok((vec![1], NonNegativeAmount::const_from_u64(10000)))
+        );
+
+        assert_eq!(
+            select_inputs(
+                sapling_only(&notes),
+                NoteSelectionStrategy::OldestFirst,
+                request()
+            ),
+            Ok((vec![0, 1], NonNegativeAmount::const_from_u64(10000)))
+        );
+
+        // The dust note is considered first, but spending it would cost more than its value,
+        // so it is skipped and the next-smallest notes are used instead.
+        assert_eq!(
+            select_inputs(
+                sapling_only(&notes),
+                NoteSelectionStrategy::SmallestFirst,
+                request()
+            ),
+            Ok((vec![0, 2, 3], NonNegativeAmount::const_from_u64(15000)))
+        );
+    }
+
+    #[test]
+    fn note_selection_skips_dust() {
+        let request = || request_to(Address::Sapling(sapling_address()), 30000);
+
+        // A dust note may occupy an action that would otherwise be padding, at no additional
+        // cost; the remaining dust notes would each increase the fee by more than their value,
+        // and so are skipped even though they are considered first.
+        assert_eq!(
+            select_inputs(
+                sapling_only(&[1000, 2000, 3000, 45000]),
+                NoteSelectionStrategy::SmallestFirst,
+                request()
+            ),
+            Ok((vec![0, 3], NonNegativeAmount::const_from_u64(10000)))
+        );
+
+        // Dust notes cannot make up a shortfall, and selection terminates once the excess dust
+        // has been excluded rather than repeatedly reconsidering it.
+        assert_eq!(
+            select_inputs(
+                sapling_only(&[1000, 2000, 35000]),
+                NoteSelectionStrategy::SmallestFirst,
+                request()
+            ),
+            Err((
+                NonNegativeAmount::const_from_u64(36000),
+                NonNegativeAmount::const_from_u64(40000)
+            ))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn note_selection_prefer_same_pool() {
+        let notes = || SpendableNotes::new(sapling_notes(&[50000]), orchard_notes(100, &[50000]));
+        let to_orchard = || {
+            request_to(
+                Address::Unified(
+                    UnifiedAddress::from_receivers(Some(orchard_address()), None, None).unwrap(),
+                ),
+                30000,
+            )
+        };
+
+        // Oldest-first selection considers the Sapling note first, crossing pools at the cost
+        // of an additional fee for the Orchard actions.
+        assert_eq!(
+            select_inputs(notes(), NoteSelectionStrategy::OldestFirst, to_orchard()),
+            Ok((vec![0], NonNegativeAmount::const_from_u64(20000)))
+        );
+
+        // Pool-preserving selection spends from the pool being paid to.
+        assert_eq!(
+            select_inputs(notes(), NoteSelectionStrategy::PreferSamePool, to_orchard()),
+            Ok((vec![100], NonNegativeAmount::const_from_u64(10000)))
+        );
+        assert_eq!(
+            select_inputs(
+                notes(),
+                NoteSelectionStrategy::PreferSamePool,
+                request_to(Address::Sapling(sapling_address()), 30000)
+            ),
+            Ok((vec![0], NonNegativeAmount::const_from_u64(10000)))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn tex_payments_use_ephemeral_step() {
+        use crate::{
+            fees::ChangeValue,
+            proposal::{StepOutput, StepOutputIndex},
+            PoolType,
+        };
+
+        let network = Network::TestNetwork;
+        let height = network.activation_height(NetworkUpgrade::Nu5).unwrap();
+        let input_selector = GreedyInputSelector::<TestInputSource, _>::new(
+            SingleOutputChangeStrategy::new(
+                Zip317FeeRule::standard(),
+                None,
+                ShieldedProtocol::Sapling,
+            ),
+            DustOutputPolicy::default(),
+        );
+        let payment = |recipient_address, amount| Payment {
+            recipient_address,
+            amount: NonNegativeAmount::const_from_u64(amount),
+            memo: None,
+            label: None,
+            message: None,
+            other_params: vec![],
+        };
+        let request = TransactionRequest::new(vec![
+            payment(Address::Sapling(sapling_address()), 20000),
+            payment(Address::Tex([7; 20]), 30000),
+        ])
+        .unwrap();
+
+        let proposal = input_selector
+            .propose_transaction(
+                &network,
+                &TestInputSource {
+                    notes: sapling_only(&[100000]),
+                },
+                height,
+                height,
+                0,
+                request,
+            )
+            .unwrap();
+        assert_eq!(proposal.steps().len(), 2);
+
+        // The first step makes the shielded payment, and funds the TEX payment and its fee
+        // through an ephemeral transparent output.
+        let step0 = proposal.steps().first();
+        assert!(step0.shielded_inputs().is_some());
+        assert_eq!(step0.transaction_request().payments().len(), 1);
+        assert_eq!(
+            step0.balance().fee_required(),
+            NonNegativeAmount::const_from_u64(15000)
+        );
+        assert_eq!(
+            step0.balance().proposed_change(),
+            &[
+                ChangeValue::sapling(NonNegativeAmount::const_from_u64(25000), None),
+                ChangeValue::ephemeral_transparent(NonNegativeAmount::const_from_u64(40000)),
+            ]
+        );
+
+        // The second step spends only the ephemeral output.
+        let step1 = &proposal.steps().tail[0];
+        assert!(step1.shielded_inputs().is_none());
+        assert!(step1.transparent_inputs().is_empty());
+        assert_eq!(
+            step1.prior_step_inputs(),
+            &[StepOutput::new(0, StepOutputIndex::Change(1))]
+        );
+        assert_eq!(
+            step1.payment_pools().values().collect::<Vec<_>>(),
+            vec![&PoolType::Transparent]
+        );
+        assert_eq!(
+            step1.balance().fee_required(),
+            NonNegativeAmount::const_from_u64(10000)
+        );
+    }
+}
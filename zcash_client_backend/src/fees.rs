@@ -1,18 +1,21 @@
+use std::convert::Infallible;
 use std::fmt;
+use std::num::NonZeroUsize;
 
 use zcash_primitives::{
     consensus::{self, BlockHeight},
+    legacy::TransparentAddress,
     memo::MemoBytes,
     transaction::{
         components::{
             amount::{BalanceError, NonNegativeAmount},
-            OutPoint,
+            OutPoint, TxOut,
         },
         fees::{transparent, FeeRule},
     },
 };
 
-use crate::ShieldedProtocol;
+use crate::{PoolType, ShieldedProtocol};
 
 pub(crate) mod common;
 pub mod fixed;
@@ -23,22 +26,26 @@ pub mod standard;
 pub mod zip317;
 
 /// A proposed change amount and output pool.
+///
+/// Change is ordinarily sent to a shielded pool. Transparent change is only proposed as an
+/// intermediate output of a multi-step proposal, such as one that pays a ZIP 320 TEX address;
+/// it is sent to an ephemeral transparent address that is spent by a later step.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ChangeValue {
-    output_pool: ShieldedProtocol,
+    output_pool: PoolType,
     value: NonNegativeAmount,
     memo: Option<MemoBytes>,
 }
 
 impl ChangeValue {
-    /// Constructs a new change value from its constituent parts.
+    /// Constructs a new shielded change value from its constituent parts.
     pub fn new(
         output_pool: ShieldedProtocol,
         value: NonNegativeAmount,
         memo: Option<MemoBytes>,
     ) -> Self {
         Self {
-            output_pool,
+            output_pool: PoolType::Shielded(output_pool),
             value,
             memo,
         }
@@ -46,25 +53,27 @@ impl ChangeValue {
 
     /// Constructs a new change value that will be created as a Sapling output.
     pub fn sapling(value: NonNegativeAmount, memo: Option<MemoBytes>) -> Self {
-        Self {
-            output_pool: ShieldedProtocol::Sapling,
-            value,
-            memo,
-        }
+        Self::new(ShieldedProtocol::Sapling, value, memo)
     }
 
     /// Constructs a new change value that will be created as an Orchard output.
     #[cfg(feature = "orchard")]
     pub fn orchard(value: NonNegativeAmount, memo: Option<MemoBytes>) -> Self {
+        Self::new(ShieldedProtocol::Orchard, value, memo)
+    }
+
+    /// Constructs a new change value that will be sent to an ephemeral transparent address,
+    /// to be spent by a later step of the proposal.
+    pub fn ephemeral_transparent(value: NonNegativeAmount) -> Self {
         Self {
-            output_pool: ShieldedProtocol::Orchard,
+            output_pool: PoolType::Transparent,
             value,
-            memo,
+            memo: None,
         }
     }
 
     /// Returns the pool to which the change output should be sent.
-    pub fn output_pool(&self) -> ShieldedProtocol {
+    pub fn output_pool(&self) -> PoolType {
         self.output_pool
     }
 
@@ -77,6 +86,11 @@ impl ChangeValue {
     pub fn memo(&self) -> Option<&MemoBytes> {
         self.memo.as_ref()
     }
+
+    /// Returns whether the change output is sent to an ephemeral transparent address.
+    pub fn is_ephemeral(&self) -> bool {
+        self.output_pool == PoolType::Transparent
+    }
 }
 
 /// The amount of change and fees required to make a transaction's inputs and
@@ -86,6 +100,7 @@ impl ChangeValue {
 pub struct TransactionBalance {
     proposed_change: Vec<ChangeValue>,
     fee_required: NonNegativeAmount,
+    change_policy: Option<ChangePolicyDecisions>,
 
     // A cache for the sum of proposed change and fee; we compute it on construction anyway, so we
     // cache the resulting value.
@@ -108,15 +123,29 @@ impl TransactionBalance {
         Ok(Self {
             proposed_change,
             fee_required,
+            change_policy: None,
             total,
         })
     }
 
+    /// Records the decisions made by the [`ChangeStrategy`] that computed this balance in
+    /// applying its change policy.
+    pub fn with_change_policy(mut self, decisions: ChangePolicyDecisions) -> Self {
+        self.change_policy = Some(decisions);
+        self
+    }
+
     /// The change values proposed by the [`ChangeStrategy`] that computed this balance.
     pub fn proposed_change(&self) -> &[ChangeValue] {
         &self.proposed_change
     }
 
+    /// Returns the decisions made by the [`ChangeStrategy`] that computed this balance in
+    /// applying its change policy, if they were recorded.
+    pub fn change_policy(&self) -> Option<&ChangePolicyDecisions> {
+        self.change_policy.as_ref()
+    }
+
     /// Returns the fee computed for the transaction, assuming that the suggested
     /// change outputs are added to the transaction.
     pub fn fee_required(&self) -> NonNegativeAmount {
@@ -153,6 +182,14 @@ pub enum ChangeError<E, NoteRefT> {
         #[cfg(feature = "orchard")]
         orchard: Vec<NoteRefT>,
     },
+    /// The change for the transaction would be below the dust threshold, and the
+    /// [`DustOutputPolicy`] in use requires that this be treated as an error.
+    DustChange {
+        /// The value of the change that would have been produced.
+        change: NonNegativeAmount,
+        /// The dust threshold that was applied.
+        dust_threshold: NonNegativeAmount,
+    },
     /// An error occurred that was specific to the change selection strategy in use.
     StrategyError(E),
     /// The proposed bundle structure would violate bundle type construction rules.
@@ -180,6 +217,13 @@ impl<E, NoteRefT> ChangeError<E, NoteRefT> {
                 #[cfg(feature = "orchard")]
                 orchard,
             },
+            ChangeError::DustChange {
+                change,
+                dust_threshold,
+            } => ChangeError::DustChange {
+                change,
+                dust_threshold,
+            },
             ChangeError::StrategyError(e) => ChangeError::StrategyError(f(e)),
             ChangeError::BundleError(e) => ChangeError::BundleError(e),
         }
@@ -217,6 +261,15 @@ impl<CE: fmt::Display, N: fmt::Display> fmt::Display for ChangeError<CE, N> {
                     transparent.len() + sapling.len() + orchard_len,
                 )
             }
+            ChangeError::DustChange {
+                change,
+                dust_threshold,
+            } => write!(
+                f,
+                "The transaction would produce {} zatoshis of change, which is below the dust threshold of {} zatoshis.",
+                u64::from(*change),
+                u64::from(*dust_threshold)
+            ),
             ChangeError::StrategyError(err) => {
                 write!(f, "{}", err)
             }
@@ -247,6 +300,9 @@ pub enum DustAction {
     AllowDustChange,
     /// Allow dust amounts to be added to the transaction fee
     AddDustToFee,
+    /// Do not allow creation of dust outputs, and return [`ChangeError::DustChange`] instead of
+    /// requesting additional inputs.
+    Fail,
 }
 
 /// A policy describing how a [`ChangeStrategy`] should treat potentially dust-valued change
@@ -288,6 +344,304 @@ impl Default for DustOutputPolicy {
     }
 }
 
+/// A preference for the shielded pool to which a [`ChangeStrategy`] sends change.
+///
+/// If the `orchard` feature is not enabled, change is always sent to the Sapling pool.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChangePoolPreference {
+    /// Send change to the Orchard pool.
+    PreferOrchard,
+    /// Send change to the pool of the transaction's shielded inputs and outputs, preferring
+    /// Orchard if both pools are involved, so as to avoid crossing pools. A fully transparent
+    /// transaction sends change to the change strategy's fallback pool.
+    #[default]
+    SameAsInputs,
+    /// Always send change to the specified pool.
+    Explicit(ShieldedProtocol),
+}
+
+/// A rule for splitting change into several outputs of equal value, so that the wallet holds
+/// notes that can be spent in parallel.
+///
+/// Each additional change output may increase the fee for the transaction. Change is only split
+/// if, after paying the fee for the additional outputs, it is still at least
+/// [`Self::threshold`], and each of the resulting outputs is above the dust threshold; otherwise
+/// a single change output is used. This decision is made once for a given set of inputs, so the
+/// computation of change never oscillates between the two shapes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChangeSplitPolicy {
+    threshold: NonNegativeAmount,
+    split_count: NonZeroUsize,
+}
+
+impl ChangeSplitPolicy {
+    /// Constructs a rule that splits change of at least `threshold` into `split_count` outputs.
+    pub fn new(threshold: NonNegativeAmount, split_count: NonZeroUsize) -> Self {
+        Self {
+            threshold,
+            split_count,
+        }
+    }
+
+    /// Returns the minimum value of change that will be split.
+    pub fn threshold(&self) -> NonNegativeAmount {
+        self.threshold
+    }
+
+    /// Returns the number of outputs into which change will be split.
+    pub fn split_count(&self) -> NonZeroUsize {
+        self.split_count
+    }
+}
+
+/// A policy describing how a [`ChangeStrategy`] should construct change outputs.
+///
+/// The default policy sends change to the pool of the transaction's inputs as a single output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChangeOutputPolicy {
+    pool_preference: ChangePoolPreference,
+    split_policy: Option<ChangeSplitPolicy>,
+}
+
+impl ChangeOutputPolicy {
+    /// Constructs a new change output policy.
+    pub fn new(
+        pool_preference: ChangePoolPreference,
+        split_policy: Option<ChangeSplitPolicy>,
+    ) -> Self {
+        Self {
+            pool_preference,
+            split_policy,
+        }
+    }
+
+    /// Returns the preference for the pool to which change is sent.
+    pub fn pool_preference(&self) -> ChangePoolPreference {
+        self.pool_preference
+    }
+
+    /// Returns the rule for splitting change into multiple outputs, if any.
+    pub fn split_policy(&self) -> Option<&ChangeSplitPolicy> {
+        self.split_policy.as_ref()
+    }
+}
+
+/// The decisions made by a [`ChangeStrategy`] in applying its [`ChangeOutputPolicy`] and
+/// [`DustOutputPolicy`] to a transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChangePolicyDecisions {
+    change_pool: Option<ShieldedProtocol>,
+    dust_action: Option<DustAction>,
+    change_split: bool,
+}
+
+impl ChangePolicyDecisions {
+    /// Constructs a record of change policy decisions from its constituent parts.
+    pub fn from_parts(
+        change_pool: Option<ShieldedProtocol>,
+        dust_action: Option<DustAction>,
+        change_split: bool,
+    ) -> Self {
+        Self {
+            change_pool,
+            dust_action,
+            change_split,
+        }
+    }
+
+    /// Returns the pool to which shielded change is sent, or `None` if the transaction has no
+    /// shielded change output.
+    pub fn change_pool(&self) -> Option<ShieldedProtocol> {
+        self.change_pool
+    }
+
+    /// Returns the action that was taken because the change would have been below the dust
+    /// threshold, if any.
+    pub fn dust_action(&self) -> Option<DustAction> {
+        self.dust_action
+    }
+
+    /// Returns whether change was split into multiple outputs by a [`ChangeSplitPolicy`].
+    pub fn change_split(&self) -> bool {
+        self.change_split
+    }
+}
+
+/// The numbers of inputs and outputs of each kind in a prospective transaction, for use in
+/// estimating its fee with [`estimate_fee`].
+///
+/// Transparent inputs and outputs are assumed to be P2PKH.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TransactionShape {
+    transparent_inputs: usize,
+    transparent_outputs: usize,
+    sapling_spends: usize,
+    sapling_outputs: usize,
+    #[cfg(feature = "orchard")]
+    orchard_spends: usize,
+    #[cfg(feature = "orchard")]
+    orchard_outputs: usize,
+}
+
+impl TransactionShape {
+    /// Returns the shape of a transaction having no inputs or outputs.
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// Returns this shape, modified to have the given numbers of transparent inputs and outputs.
+    pub fn with_transparent(mut self, inputs: usize, outputs: usize) -> Self {
+        self.transparent_inputs = inputs;
+        self.transparent_outputs = outputs;
+        self
+    }
+
+    /// Returns this shape, modified to have the given numbers of Sapling spends and outputs.
+    pub fn with_sapling(mut self, spends: usize, outputs: usize) -> Self {
+        self.sapling_spends = spends;
+        self.sapling_outputs = outputs;
+        self
+    }
+
+    /// Returns this shape, modified to have the given numbers of Orchard spends and outputs.
+    #[cfg(feature = "orchard")]
+    pub fn with_orchard(mut self, spends: usize, outputs: usize) -> Self {
+        self.orchard_spends = spends;
+        self.orchard_outputs = outputs;
+        self
+    }
+
+    /// Returns the number of transparent inputs.
+    pub fn transparent_inputs(&self) -> usize {
+        self.transparent_inputs
+    }
+
+    /// Returns the number of transparent outputs.
+    pub fn transparent_outputs(&self) -> usize {
+        self.transparent_outputs
+    }
+
+    /// Returns the number of Sapling spends.
+    pub fn sapling_spends(&self) -> usize {
+        self.sapling_spends
+    }
+
+    /// Returns the number of Sapling outputs.
+    pub fn sapling_outputs(&self) -> usize {
+        self.sapling_outputs
+    }
+
+    /// Returns the number of Orchard spends.
+    #[cfg(feature = "orchard")]
+    pub fn orchard_spends(&self) -> usize {
+        self.orchard_spends
+    }
+
+    /// Returns the number of Orchard outputs.
+    #[cfg(feature = "orchard")]
+    pub fn orchard_outputs(&self) -> usize {
+        self.orchard_outputs
+    }
+}
+
+/// A P2PKH input of a prospective transaction, standing in for a real input in fee estimation.
+#[derive(Debug)]
+struct EstimatedTransparentInput {
+    outpoint: OutPoint,
+    coin: TxOut,
+}
+
+impl transparent::InputView for EstimatedTransparentInput {
+    fn outpoint(&self) -> &OutPoint {
+        &self.outpoint
+    }
+    fn coin(&self) -> &TxOut {
+        &self.coin
+    }
+}
+
+/// Computes the fee that the given fee rule requires for a transaction of the given shape,
+/// without selecting inputs or constructing the transaction.
+///
+/// The Sapling and Orchard components are padded as the transaction builder would pad them, so
+/// for example a transaction with a single Sapling spend is charged for two Sapling outputs.
+/// The fee does not account for any change output that the transaction might need, so a change
+/// output must be included in the shape if one is expected.
+pub fn estimate_fee<P: consensus::Parameters, F: FeeRule>(
+    params: &P,
+    fee_rule: &F,
+    target_height: BlockHeight,
+    shape: &TransactionShape,
+) -> Result<NonNegativeAmount, ChangeError<F::Error, Infallible>> {
+    let p2pkh_coin = || TxOut {
+        value: NonNegativeAmount::ZERO,
+        script_pubkey: TransparentAddress::PublicKeyHash([0; 20]).script(),
+    };
+    let transparent_inputs = (0..shape.transparent_inputs)
+        .map(|_| EstimatedTransparentInput {
+            outpoint: OutPoint::new([0; 32], 0),
+            coin: p2pkh_coin(),
+        })
+        .collect::<Vec<_>>();
+    let transparent_outputs = (0..shape.transparent_outputs)
+        .map(|_| p2pkh_coin())
+        .collect::<Vec<_>>();
+
+    let sapling_bundle_type = ::sapling::builder::BundleType::DEFAULT;
+    let sapling_input_count = sapling_bundle_type
+        .num_spends(shape.sapling_spends)
+        .map_err(ChangeError::BundleError)?;
+    let sapling_output_count = sapling_bundle_type
+        .num_outputs(shape.sapling_spends, shape.sapling_outputs)
+        .map_err(ChangeError::BundleError)?;
+
+    #[cfg(feature = "orchard")]
+    let orchard_action_count = ::orchard::builder::BundleType::DEFAULT
+        .num_actions(shape.orchard_spends, shape.orchard_outputs)
+        .map_err(ChangeError::BundleError)?;
+    #[cfg(not(feature = "orchard"))]
+    let orchard_action_count = 0;
+
+    fee_rule
+        .fee_required(
+            params,
+            target_height,
+            &transparent_inputs,
+            &transparent_outputs,
+            sapling_input_count,
+            sapling_output_count,
+            orchard_action_count,
+        )
+        .map_err(ChangeError::StrategyError)
+}
+
+/// The range of fees that a transaction might require, as estimated without selecting its
+/// inputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeEstimate {
+    minimum: NonNegativeAmount,
+    maximum: NonNegativeAmount,
+}
+
+impl FeeEstimate {
+    /// Constructs a new fee estimate from its constituent parts.
+    ///
+    /// Returns `None` if `minimum` is greater than `maximum`.
+    pub fn from_parts(minimum: NonNegativeAmount, maximum: NonNegativeAmount) -> Option<Self> {
+        (minimum <= maximum).then_some(Self { minimum, maximum })
+    }
+
+    /// Returns the lowest fee that the transaction might require.
+    pub fn minimum(&self) -> NonNegativeAmount {
+        self.minimum
+    }
+
+    /// Returns the highest fee that the transaction might require.
+    pub fn maximum(&self) -> NonNegativeAmount {
+        self.maximum
+    }
+}
+
 /// A trait that represents the ability to compute the suggested change and fees that must be paid
 /// by a transaction having a specified set of inputs and outputs.
 pub trait ChangeStrategy {
@@ -320,15 +674,18 @@ pub trait ChangeStrategy {
 
 #[cfg(test)]
 pub(crate) mod tests {
-    use zcash_primitives::transaction::{
-        components::{
-            amount::NonNegativeAmount,
-            transparent::{OutPoint, TxOut},
+    use zcash_primitives::{
+        consensus::{Network, NetworkUpgrade, Parameters},
+        transaction::{
+            components::{
+                amount::NonNegativeAmount,
+                transparent::{OutPoint, TxOut},
+            },
+            fees::{transparent, zip317::FeeRule as Zip317FeeRule},
         },
-        fees::transparent,
     };
 
-    use super::sapling;
+    use super::{estimate_fee, sapling, TransactionShape};
 
     #[derive(Debug)]
     pub(crate) struct TestTransparentInput {
@@ -358,4 +715,68 @@ pub(crate) mod tests {
             self.value
         }
     }
+
+    #[test]
+    fn estimate_zip317_fees() {
+        let network = Network::TestNetwork;
+        let height = network.activation_height(NetworkUpgrade::Nu5).unwrap();
+        let fee_rule = Zip317FeeRule::standard();
+        let fee = |shape: TransactionShape| {
+            u64::from(estimate_fee(&network, &fee_rule, height, &shape).unwrap())
+        };
+        let empty = TransactionShape::empty;
+
+        // Transactions with fewer logical actions than the grace actions pay the minimum fee.
+        assert_eq!(fee(empty()), 10000);
+        assert_eq!(fee(empty().with_transparent(1, 1)), 10000);
+        assert_eq!(fee(empty().with_transparent(2, 2)), 10000);
+
+        // Transparent logical actions are the larger of the input and output counts.
+        assert_eq!(fee(empty().with_transparent(3, 1)), 15000);
+        assert_eq!(fee(empty().with_transparent(1, 5)), 25000);
+
+        // A Sapling bundle is padded to two outputs.
+        assert_eq!(fee(empty().with_sapling(1, 1)), 10000);
+        assert_eq!(fee(empty().with_sapling(3, 1)), 15000);
+        assert_eq!(fee(empty().with_sapling(0, 3)), 15000);
+
+        // Shielding transparent funds to a single Sapling change output.
+        assert_eq!(
+            fee(empty().with_transparent(5, 0).with_sapling(0, 1)),
+            35000
+        );
+        // Sending from Sapling to a transparent recipient, with Sapling change.
+        assert_eq!(
+            fee(empty().with_transparent(0, 1).with_sapling(1, 1)),
+            15000
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn estimate_zip317_fees_orchard() {
+        let network = Network::TestNetwork;
+        let height = network.activation_height(NetworkUpgrade::Nu5).unwrap();
+        let fee_rule = Zip317FeeRule::standard();
+        let fee = |shape: TransactionShape| {
+            u64::from(estimate_fee(&network, &fee_rule, height, &shape).unwrap())
+        };
+        let empty = TransactionShape::empty;
+
+        // An Orchard bundle is padded to two actions, each of which pairs a spend with an output.
+        assert_eq!(fee(empty().with_orchard(1, 1)), 10000);
+        assert_eq!(fee(empty().with_orchard(1, 2)), 10000);
+        assert_eq!(fee(empty().with_orchard(3, 1)), 15000);
+
+        // A cross-pool transfer from Sapling to Orchard, with Orchard change.
+        assert_eq!(fee(empty().with_sapling(1, 0).with_orchard(0, 2)), 20000);
+        // Every pool at once: two transparent, two Sapling and three Orchard logical actions.
+        assert_eq!(
+            fee(empty()
+                .with_transparent(2, 1)
+                .with_sapling(1, 1)
+                .with_orchard(3, 1)),
+            35000
+        );
+    }
 }
@@ -10,8 +10,8 @@ use zcash_primitives::{
 use crate::ShieldedProtocol;
 
 use super::{
-    sapling as sapling_fees, ChangeError, ChangeValue, DustAction, DustOutputPolicy,
-    TransactionBalance,
+    sapling as sapling_fees, ChangeError, ChangeOutputPolicy, ChangePolicyDecisions,
+    ChangePoolPreference, ChangeValue, DustAction, DustOutputPolicy, TransactionBalance,
 };
 
 #[cfg(feature = "orchard")]
@@ -91,35 +91,43 @@ where
     })
 }
 
+/// Returns the pool to which change will be sent, along with the numbers of Sapling and Orchard
+/// outputs that a single change output adds to the transaction.
 pub(crate) fn single_change_output_policy<NoteRefT: Clone, F: FeeRule, E>(
     _net_flows: &NetFlows,
+    _pool_preference: ChangePoolPreference,
     _fallback_change_pool: ShieldedProtocol,
 ) -> Result<(ShieldedProtocol, usize, usize), ChangeError<E, NoteRefT>>
 where
     E: From<F::Error> + From<BalanceError>,
 {
-    // TODO: implement a less naive strategy for selecting the pool to which change will be sent.
     #[cfg(feature = "orchard")]
-    let (change_pool, sapling_change, orchard_change) =
-        if _net_flows.orchard_in.is_positive() || _net_flows.orchard_out.is_positive() {
-            // Send change to Orchard if we're spending any Orchard inputs or creating any Orchard outputs
-            (ShieldedProtocol::Orchard, 0, 1)
-        } else if _net_flows.sapling_in.is_positive() || _net_flows.sapling_out.is_positive() {
-            // Otherwise, send change to Sapling if we're spending any Sapling inputs or creating any
-            // Sapling outputs, so that we avoid pool-crossing.
-            (ShieldedProtocol::Sapling, 1, 0)
-        } else {
-            // This is a fully-transparent transaction, so the caller gets to decide
-            // where to shield change.
-            match _fallback_change_pool {
-                ShieldedProtocol::Orchard => (_fallback_change_pool, 0, 1),
-                ShieldedProtocol::Sapling => (_fallback_change_pool, 1, 0),
+    let change_pool = match _pool_preference {
+        ChangePoolPreference::PreferOrchard => ShieldedProtocol::Orchard,
+        ChangePoolPreference::Explicit(pool) => pool,
+        ChangePoolPreference::SameAsInputs => {
+            if _net_flows.orchard_in.is_positive() || _net_flows.orchard_out.is_positive() {
+                // Send change to Orchard if we're spending any Orchard inputs or creating any
+                // Orchard outputs
+                ShieldedProtocol::Orchard
+            } else if _net_flows.sapling_in.is_positive() || _net_flows.sapling_out.is_positive() {
+                // Otherwise, send change to Sapling if we're spending any Sapling inputs or
+                // creating any Sapling outputs, so that we avoid pool-crossing.
+                ShieldedProtocol::Sapling
+            } else {
+                // This is a fully-transparent transaction, so the caller gets to decide
+                // where to shield change.
+                _fallback_change_pool
             }
-        };
+        }
+    };
     #[cfg(not(feature = "orchard"))]
-    let (change_pool, sapling_change, orchard_change) = (ShieldedProtocol::Sapling, 1, 0);
+    let change_pool = ShieldedProtocol::Sapling;
 
-    Ok((change_pool, sapling_change, orchard_change))
+    Ok(match change_pool {
+        ShieldedProtocol::Sapling => (change_pool, 1, 0),
+        ShieldedProtocol::Orchard => (change_pool, 0, 1),
+    })
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -139,7 +147,8 @@ pub(crate) fn single_change_output_balance<
     dust_output_policy: &DustOutputPolicy,
     default_dust_threshold: NonNegativeAmount,
     change_memo: Option<MemoBytes>,
-    _fallback_change_pool: ShieldedProtocol,
+    change_output_policy: &ChangeOutputPolicy,
+    fallback_change_pool: ShieldedProtocol,
 ) -> Result<TransactionBalance, ChangeError<E, NoteRefT>>
 where
     E: From<F::Error> + From<BalanceError>,
@@ -155,55 +164,88 @@ where
         orchard,
     )?;
     let (change_pool, sapling_change, _orchard_change) =
-        single_change_output_policy::<NoteRefT, F, E>(&net_flows, _fallback_change_pool)?;
-
-    let sapling_input_count = sapling
-        .bundle_type()
-        .num_spends(sapling.inputs().len())
-        .map_err(ChangeError::BundleError)?;
-    let sapling_output_count = sapling
-        .bundle_type()
-        .num_outputs(
-            sapling.inputs().len(),
-            sapling.outputs().len() + sapling_change,
-        )
-        .map_err(ChangeError::BundleError)?;
+        single_change_output_policy::<NoteRefT, F, E>(
+            &net_flows,
+            change_output_policy.pool_preference(),
+            fallback_change_pool,
+        )?;
 
-    #[cfg(feature = "orchard")]
-    let orchard_action_count = orchard
-        .bundle_type()
-        .num_actions(
-            orchard.inputs().len(),
-            orchard.outputs().len() + _orchard_change,
-        )
-        .map_err(ChangeError::BundleError)?;
-    #[cfg(not(feature = "orchard"))]
-    let orchard_action_count = 0;
-
-    let fee_amount = fee_rule
-        .fee_required(
-            params,
-            target_height,
-            transparent_inputs,
-            transparent_outputs,
-            sapling_input_count,
-            sapling_output_count,
-            orchard_action_count,
-        )
-        .map_err(|fee_error| ChangeError::StrategyError(E::from(fee_error)))?;
+    // Computes the fee for the transaction if it has the given number of change outputs.
+    let fee_for_change_outputs = |change_outputs: usize| {
+        let sapling_input_count = sapling
+            .bundle_type()
+            .num_spends(sapling.inputs().len())
+            .map_err(ChangeError::BundleError)?;
+        let sapling_output_count = sapling
+            .bundle_type()
+            .num_outputs(
+                sapling.inputs().len(),
+                sapling.outputs().len() + sapling_change * change_outputs,
+            )
+            .map_err(ChangeError::BundleError)?;
+
+        #[cfg(feature = "orchard")]
+        let orchard_action_count = orchard
+            .bundle_type()
+            .num_actions(
+                orchard.inputs().len(),
+                orchard.outputs().len() + _orchard_change * change_outputs,
+            )
+            .map_err(ChangeError::BundleError)?;
+        #[cfg(not(feature = "orchard"))]
+        let orchard_action_count = 0;
+
+        fee_rule
+            .fee_required(
+                params,
+                target_height,
+                transparent_inputs,
+                transparent_outputs,
+                sapling_input_count,
+                sapling_output_count,
+                orchard_action_count,
+            )
+            .map_err(|fee_error| ChangeError::StrategyError(E::from(fee_error)))
+    };
 
     let total_in =
         (net_flows.t_in + net_flows.sapling_in + net_flows.orchard_in).ok_or_else(overflow)?;
-    let total_out = (net_flows.t_out + net_flows.sapling_out + net_flows.orchard_out + fee_amount)
-        .ok_or_else(overflow)?;
+    let total_payments =
+        (net_flows.t_out + net_flows.sapling_out + net_flows.orchard_out).ok_or_else(overflow)?;
+
+    let fee_amount = fee_for_change_outputs(1)?;
+    let total_out = (total_payments + fee_amount).ok_or_else(overflow)?;
 
     let proposed_change = (total_in - total_out).ok_or(ChangeError::InsufficientFunds {
         available: total_in,
         required: total_out,
     })?;
 
+    let single_output = |dust_action| {
+        TransactionBalance::new(
+            vec![ChangeValue::new(
+                change_pool,
+                proposed_change,
+                change_memo.clone(),
+            )],
+            fee_amount,
+        )
+        .map(|balance| {
+            balance.with_change_policy(ChangePolicyDecisions::from_parts(
+                Some(change_pool),
+                dust_action,
+                false,
+            ))
+        })
+        .map_err(|_| overflow())
+    };
+
     if proposed_change.is_zero() {
-        TransactionBalance::new(vec![], fee_amount).map_err(|_| overflow())
+        TransactionBalance::new(vec![], fee_amount)
+            .map(|balance| {
+                balance.with_change_policy(ChangePolicyDecisions::from_parts(None, None, false))
+            })
+            .map_err(|_| overflow())
     } else {
         let dust_threshold = dust_output_policy
             .dust_threshold()
@@ -219,23 +261,73 @@ where
                         required: (total_in + shortfall).ok_or_else(overflow)?,
                     })
                 }
-                DustAction::AllowDustChange => TransactionBalance::new(
-                    vec![ChangeValue::new(change_pool, proposed_change, change_memo)],
-                    fee_amount,
-                )
-                .map_err(|_| overflow()),
+                DustAction::AllowDustChange => single_output(Some(DustAction::AllowDustChange)),
                 DustAction::AddDustToFee => TransactionBalance::new(
                     vec![],
                     (fee_amount + proposed_change).ok_or_else(overflow)?,
                 )
+                .map(|balance| {
+                    balance.with_change_policy(ChangePolicyDecisions::from_parts(
+                        None,
+                        Some(DustAction::AddDustToFee),
+                        false,
+                    ))
+                })
                 .map_err(|_| overflow()),
+                DustAction::Fail => Err(ChangeError::DustChange {
+                    change: proposed_change,
+                    dust_threshold,
+                }),
             }
         } else {
-            TransactionBalance::new(
-                vec![ChangeValue::new(change_pool, proposed_change, change_memo)],
-                fee_amount,
-            )
-            .map_err(|_| overflow())
+            match change_output_policy.split_policy() {
+                Some(split_policy) if split_policy.split_count().get() > 1 => {
+                    let split_count = split_policy.split_count().get();
+                    let split_fee = fee_for_change_outputs(split_count)?;
+                    let split_change = (total_payments + split_fee)
+                        .and_then(|split_out| total_in - split_out)
+                        .filter(|split_change| *split_change >= split_policy.threshold());
+
+                    let split_values = split_change.and_then(|split_change| {
+                        split_change_value(split_change, split_count)
+                            .filter(|values| values.iter().all(|v| *v >= dust_threshold))
+                    });
+
+                    match split_values {
+                        Some(values) => TransactionBalance::new(
+                            values
+                                .into_iter()
+                                .map(|value| {
+                                    ChangeValue::new(change_pool, value, change_memo.clone())
+                                })
+                                .collect(),
+                            split_fee,
+                        )
+                        .map(|balance| {
+                            balance.with_change_policy(ChangePolicyDecisions::from_parts(
+                                Some(change_pool),
+                                None,
+                                true,
+                            ))
+                        })
+                        .map_err(|_| overflow()),
+                        None => single_output(None),
+                    }
+                }
+                _ => single_output(None),
+            }
         }
     }
 }
+
+/// Splits `value` into `count` values that differ by at most one zatoshi, with the larger values
+/// first.
+fn split_change_value(value: NonNegativeAmount, count: usize) -> Option<Vec<NonNegativeAmount>> {
+    let total = u64::from(value);
+    let count_u64 = u64::try_from(count).ok()?;
+    let base = total / count_u64;
+    let remainder = total % count_u64;
+    (0..count_u64)
+        .map(|i| NonNegativeAmount::from_u64(base + u64::from(i < remainder)).ok())
+        .collect()
+}
@@ -12,8 +12,8 @@ use zcash_primitives::{
 use crate::ShieldedProtocol;
 
 use super::{
-    common::single_change_output_balance, sapling as sapling_fees, ChangeError, ChangeStrategy,
-    DustOutputPolicy, TransactionBalance,
+    common::single_change_output_balance, sapling as sapling_fees, ChangeError, ChangeOutputPolicy,
+    ChangeStrategy, DustOutputPolicy, TransactionBalance,
 };
 
 #[cfg(feature = "orchard")]
@@ -25,6 +25,7 @@ pub struct SingleOutputChangeStrategy {
     fee_rule: FixedFeeRule,
     change_memo: Option<MemoBytes>,
     fallback_change_pool: ShieldedProtocol,
+    change_output_policy: ChangeOutputPolicy,
 }
 
 impl SingleOutputChangeStrategy {
@@ -42,8 +43,18 @@ impl SingleOutputChangeStrategy {
             fee_rule,
             change_memo,
             fallback_change_pool,
+            change_output_policy: ChangeOutputPolicy::default(),
         }
     }
+
+    /// Sets the policy that determines the pool to which change is sent, and whether change is
+    /// split into multiple outputs.
+    ///
+    /// By default, change is sent as a single output to the pool of the transaction's inputs.
+    pub fn with_change_output_policy(mut self, change_output_policy: ChangeOutputPolicy) -> Self {
+        self.change_output_policy = change_output_policy;
+        self
+    }
 }
 
 impl ChangeStrategy for SingleOutputChangeStrategy {
@@ -76,6 +87,7 @@ impl ChangeStrategy for SingleOutputChangeStrategy {
             dust_output_policy,
             self.fee_rule().fixed_fee(),
             self.change_memo.clone(),
+            &self.change_output_policy,
             self.fallback_change_pool,
         )
     }
@@ -17,8 +17,8 @@ use zcash_primitives::{
 use crate::ShieldedProtocol;
 
 use super::{
-    fixed, sapling as sapling_fees, zip317, ChangeError, ChangeStrategy, DustOutputPolicy,
-    TransactionBalance,
+    fixed, sapling as sapling_fees, zip317, ChangeError, ChangeOutputPolicy, ChangeStrategy,
+    DustOutputPolicy, TransactionBalance,
 };
 
 #[cfg(feature = "orchard")]
@@ -30,6 +30,7 @@ pub struct SingleOutputChangeStrategy {
     fee_rule: StandardFeeRule,
     change_memo: Option<MemoBytes>,
     fallback_change_pool: ShieldedProtocol,
+    change_output_policy: ChangeOutputPolicy,
 }
 
 impl SingleOutputChangeStrategy {
@@ -47,8 +48,18 @@ impl SingleOutputChangeStrategy {
             fee_rule,
             change_memo,
             fallback_change_pool,
+            change_output_policy: ChangeOutputPolicy::default(),
         }
     }
+
+    /// Sets the policy that determines the pool to which change is sent, and whether change is
+    /// split into multiple outputs.
+    ///
+    /// By default, change is sent as a single output to the pool of the transaction's inputs.
+    pub fn with_change_output_policy(mut self, change_output_policy: ChangeOutputPolicy) -> Self {
+        self.change_output_policy = change_output_policy;
+        self
+    }
 }
 
 impl ChangeStrategy for SingleOutputChangeStrategy {
@@ -76,6 +87,7 @@ impl ChangeStrategy for SingleOutputChangeStrategy {
                 self.change_memo.clone(),
                 self.fallback_change_pool,
             )
+            .with_change_output_policy(self.change_output_policy)
             .compute_balance(
                 params,
                 target_height,
@@ -92,6 +104,7 @@ impl ChangeStrategy for SingleOutputChangeStrategy {
                 self.change_memo.clone(),
                 self.fallback_change_pool,
             )
+            .with_change_output_policy(self.change_output_policy)
             .compute_balance(
                 params,
                 target_height,
@@ -108,6 +121,7 @@ impl ChangeStrategy for SingleOutputChangeStrategy {
                 self.change_memo.clone(),
                 self.fallback_change_pool,
             )
+            .with_change_output_policy(self.change_output_policy)
             .compute_balance(
                 params,
                 target_height,
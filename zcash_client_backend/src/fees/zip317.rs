@@ -17,7 +17,8 @@ use crate::ShieldedProtocol;
 
 use super::{
     common::{calculate_net_flows, single_change_output_balance, single_change_output_policy},
-    sapling as sapling_fees, ChangeError, ChangeStrategy, DustOutputPolicy, TransactionBalance,
+    sapling as sapling_fees, ChangeError, ChangeOutputPolicy, ChangeStrategy, DustOutputPolicy,
+    TransactionBalance,
 };
 
 #[cfg(feature = "orchard")]
@@ -29,6 +30,7 @@ pub struct SingleOutputChangeStrategy {
     fee_rule: Zip317FeeRule,
     change_memo: Option<MemoBytes>,
     fallback_change_pool: ShieldedProtocol,
+    change_output_policy: ChangeOutputPolicy,
 }
 
 impl SingleOutputChangeStrategy {
@@ -46,8 +48,18 @@ impl SingleOutputChangeStrategy {
             fee_rule,
             change_memo,
             fallback_change_pool,
+            change_output_policy: ChangeOutputPolicy::default(),
         }
     }
+
+    /// Sets the policy that determines the pool to which change is sent, and whether change is
+    /// split into multiple outputs.
+    ///
+    /// By default, change is sent as a single output to the pool of the transaction's inputs.
+    pub fn with_change_output_policy(mut self, change_output_policy: ChangeOutputPolicy) -> Self {
+        self.change_output_policy = change_output_policy;
+        self
+    }
 }
 
 impl ChangeStrategy for SingleOutputChangeStrategy {
@@ -128,6 +140,7 @@ impl ChangeStrategy for SingleOutputChangeStrategy {
             let (_, sapling_change, orchard_change) =
                 single_change_output_policy::<NoteRefT, Self::FeeRule, Self::Error>(
                     &net_flows,
+                    self.change_output_policy.pool_preference(),
                     self.fallback_change_pool,
                 )?;
 
@@ -208,6 +221,7 @@ impl ChangeStrategy for SingleOutputChangeStrategy {
             dust_output_policy,
             self.fee_rule.marginal_fee(),
             self.change_memo.clone(),
+            &self.change_output_policy,
             self.fallback_change_pool,
         )
     }
@@ -217,13 +231,15 @@ impl ChangeStrategy for SingleOutputChangeStrategy {
 mod tests {
 
     use std::convert::Infallible;
+    use std::num::NonZeroUsize;
 
+    use proptest::prelude::{prop_assert, prop_assert_eq, prop_oneof, proptest, Just};
     use zcash_primitives::{
         consensus::{Network, NetworkUpgrade, Parameters},
         legacy::Script,
         transaction::{
             components::{amount::NonNegativeAmount, transparent::TxOut},
-            fees::zip317::FeeRule as Zip317FeeRule,
+            fees::{zip317::FeeRule as Zip317FeeRule, FeeRule},
         },
     };
 
@@ -232,11 +248,16 @@ mod tests {
         data_api::wallet::input_selection::SaplingPayment,
         fees::{
             tests::{TestSaplingInput, TestTransparentInput},
-            ChangeError, ChangeStrategy, ChangeValue, DustOutputPolicy,
+            ChangeError, ChangeOutputPolicy, ChangePolicyDecisions, ChangePoolPreference,
+            ChangeSplitPolicy, ChangeStrategy, ChangeValue, DustAction, DustOutputPolicy,
+            TransactionBalance,
         },
         ShieldedProtocol,
     };
 
+    #[cfg(feature = "orchard")]
+    use crate::PoolType;
+
     #[cfg(feature = "orchard")]
     use crate::data_api::wallet::input_selection::OrchardPayment;
 
@@ -469,4 +490,306 @@ mod tests {
             Err(ChangeError::DustInputs { sapling, .. }) if sapling == vec![2]
         );
     }
+
+    /// Computes the balance of a transaction that spends the given Sapling notes to make a
+    /// single Sapling payment.
+    fn sapling_balance(
+        change_strategy: &SingleOutputChangeStrategy,
+        inputs: &[u64],
+        payment: u64,
+        dust_output_policy: &DustOutputPolicy,
+    ) -> Result<
+        TransactionBalance,
+        ChangeError<<SingleOutputChangeStrategy as ChangeStrategy>::Error, u32>,
+    > {
+        let inputs = inputs
+            .iter()
+            .zip(0..)
+            .map(|(value, note_id)| TestSaplingInput {
+                note_id,
+                value: NonNegativeAmount::const_from_u64(*value),
+            })
+            .collect::<Vec<_>>();
+
+        change_strategy.compute_balance(
+            &Network::TestNetwork,
+            Network::TestNetwork
+                .activation_height(NetworkUpgrade::Nu5)
+                .unwrap(),
+            &Vec::<TestTransparentInput>::new(),
+            &Vec::<TxOut>::new(),
+            &(
+                sapling::builder::BundleType::DEFAULT,
+                &inputs[..],
+                &[SaplingPayment::new(NonNegativeAmount::const_from_u64(
+                    payment,
+                ))][..],
+            ),
+            #[cfg(feature = "orchard")]
+            &(
+                orchard::builder::BundleType::DEFAULT,
+                &Vec::<Infallible>::new()[..],
+                &Vec::<Infallible>::new()[..],
+            ),
+            dust_output_policy,
+        )
+    }
+
+    fn sapling_change_strategy() -> SingleOutputChangeStrategy {
+        SingleOutputChangeStrategy::new(Zip317FeeRule::standard(), None, ShieldedProtocol::Sapling)
+    }
+
+    #[test]
+    fn dust_change_rejected() {
+        // 4000 zatoshis of change would remain, which is below the marginal fee, so additional
+        // inputs are requested to avoid creating a dust change output.
+        let result = sapling_balance(
+            &sapling_change_strategy(),
+            &[54000],
+            40000,
+            &DustOutputPolicy::new(DustAction::Reject, None),
+        );
+
+        assert_matches!(
+            result,
+            Err(ChangeError::InsufficientFunds { available, required })
+                if available == NonNegativeAmount::const_from_u64(54000)
+                && required == NonNegativeAmount::const_from_u64(55000)
+        );
+    }
+
+    #[test]
+    fn dust_change_allowed() {
+        let result = sapling_balance(
+            &sapling_change_strategy(),
+            &[54000],
+            40000,
+            &DustOutputPolicy::new(DustAction::AllowDustChange, None),
+        );
+
+        assert_matches!(
+            result,
+            Ok(balance) if
+                balance.proposed_change() == [ChangeValue::sapling(NonNegativeAmount::const_from_u64(4000), None)] &&
+                balance.fee_required() == NonNegativeAmount::const_from_u64(10000) &&
+                balance.change_policy() == Some(&ChangePolicyDecisions::from_parts(
+                    Some(ShieldedProtocol::Sapling),
+                    Some(DustAction::AllowDustChange),
+                    false,
+                ))
+        );
+    }
+
+    #[test]
+    fn dust_change_added_to_fee() {
+        let result = sapling_balance(
+            &sapling_change_strategy(),
+            &[54000],
+            40000,
+            &DustOutputPolicy::new(DustAction::AddDustToFee, None),
+        );
+
+        assert_matches!(
+            result,
+            Ok(balance) if
+                balance.proposed_change().is_empty() &&
+                balance.fee_required() == NonNegativeAmount::const_from_u64(14000) &&
+                balance.change_policy() == Some(&ChangePolicyDecisions::from_parts(
+                    None,
+                    Some(DustAction::AddDustToFee),
+                    false,
+                ))
+        );
+    }
+
+    #[test]
+    fn dust_change_fails() {
+        let result = sapling_balance(
+            &sapling_change_strategy(),
+            &[54000],
+            40000,
+            &DustOutputPolicy::new(DustAction::Fail, None),
+        );
+
+        assert_matches!(
+            result,
+            Err(ChangeError::DustChange { change, dust_threshold })
+                if change == NonNegativeAmount::const_from_u64(4000)
+                && dust_threshold == NonNegativeAmount::const_from_u64(5000)
+        );
+    }
+
+    #[test]
+    fn change_split_into_equal_outputs() {
+        let change_strategy =
+            sapling_change_strategy().with_change_output_policy(ChangeOutputPolicy::new(
+                ChangePoolPreference::SameAsInputs,
+                Some(ChangeSplitPolicy::new(
+                    NonNegativeAmount::const_from_u64(100000),
+                    NonZeroUsize::new(3).unwrap(),
+                )),
+            ));
+
+        // The two additional change outputs raise the fee from 2 to 4 logical actions.
+        let result = sapling_balance(
+            &change_strategy,
+            &[200000],
+            40000,
+            &DustOutputPolicy::default(),
+        );
+
+        assert_matches!(
+            result,
+            Ok(balance) if
+                balance.proposed_change() == [
+                    ChangeValue::sapling(NonNegativeAmount::const_from_u64(46667), None),
+                    ChangeValue::sapling(NonNegativeAmount::const_from_u64(46667), None),
+                    ChangeValue::sapling(NonNegativeAmount::const_from_u64(46666), None),
+                ] &&
+                balance.fee_required() == NonNegativeAmount::const_from_u64(20000) &&
+                balance.change_policy() == Some(&ChangePolicyDecisions::from_parts(
+                    Some(ShieldedProtocol::Sapling),
+                    None,
+                    true,
+                ))
+        );
+    }
+
+    #[test]
+    fn change_not_split_below_threshold_after_fee() {
+        let change_strategy =
+            sapling_change_strategy().with_change_output_policy(ChangeOutputPolicy::new(
+                ChangePoolPreference::SameAsInputs,
+                Some(ChangeSplitPolicy::new(
+                    NonNegativeAmount::const_from_u64(75000),
+                    NonZeroUsize::new(3).unwrap(),
+                )),
+            ));
+
+        // A single change output would be 80000 zatoshis, but paying for the additional outputs
+        // would leave only 70000 zatoshis, so the change is not split.
+        let result = sapling_balance(
+            &change_strategy,
+            &[130000],
+            40000,
+            &DustOutputPolicy::default(),
+        );
+
+        assert_matches!(
+            result,
+            Ok(balance) if
+                balance.proposed_change() == [ChangeValue::sapling(NonNegativeAmount::const_from_u64(80000), None)] &&
+                balance.fee_required() == NonNegativeAmount::const_from_u64(10000) &&
+                balance.change_policy().map(|d| d.change_split()) == Some(false)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn change_pool_preference() {
+        let balance_with = |pool_preference| {
+            sapling_balance(
+                &sapling_change_strategy()
+                    .with_change_output_policy(ChangeOutputPolicy::new(pool_preference, None)),
+                &[100000],
+                40000,
+                &DustOutputPolicy::default(),
+            )
+            .unwrap()
+        };
+
+        // A transaction with only Sapling inputs and outputs sends change to Sapling by
+        // default, and to Orchard when that is preferred.
+        let same_pool = balance_with(ChangePoolPreference::SameAsInputs);
+        assert_eq!(
+            same_pool.proposed_change()[0].output_pool(),
+            PoolType::Shielded(ShieldedProtocol::Sapling)
+        );
+
+        let orchard = balance_with(ChangePoolPreference::PreferOrchard);
+        assert_eq!(
+            orchard.proposed_change()[0].output_pool(),
+            PoolType::Shielded(ShieldedProtocol::Orchard)
+        );
+        assert_eq!(
+            orchard.change_policy().and_then(|d| d.change_pool()),
+            Some(ShieldedProtocol::Orchard)
+        );
+        // Crossing into the Orchard pool adds the (padded) Orchard actions to the fee.
+        assert!(orchard.fee_required() > same_pool.fee_required());
+
+        let explicit = balance_with(ChangePoolPreference::Explicit(ShieldedProtocol::Orchard));
+        assert_eq!(explicit, orchard);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_balance_is_exact(
+            inputs in proptest::collection::vec(10000u64..1_000_000, 1..5),
+            payment in 1u64..2_000_000,
+            split_threshold in 0u64..2_000_000,
+            split_count in 1usize..5,
+            dust_action in prop_oneof![
+                Just(DustAction::Reject),
+                Just(DustAction::AllowDustChange),
+                Just(DustAction::AddDustToFee),
+                Just(DustAction::Fail),
+            ],
+        ) {
+            let change_strategy = sapling_change_strategy().with_change_output_policy(
+                ChangeOutputPolicy::new(
+                    ChangePoolPreference::SameAsInputs,
+                    Some(ChangeSplitPolicy::new(
+                        NonNegativeAmount::from_u64(split_threshold).unwrap(),
+                        NonZeroUsize::new(split_count).unwrap(),
+                    )),
+                ),
+            );
+            let dust_output_policy = DustOutputPolicy::new(dust_action, None);
+
+            if let Ok(balance) = sapling_balance(&change_strategy, &inputs, payment, &dust_output_policy) {
+                // The inputs are exactly consumed by the payment, the change, and the fee.
+                let total_in = inputs.iter().sum::<u64>();
+                prop_assert_eq!(total_in, payment + u64::from(balance.total()));
+
+                // The fee is the one required for the resulting transaction shape, plus any dust
+                // that was added to it.
+                let decisions = balance.change_policy().unwrap();
+                let change_count = balance.proposed_change().len();
+                let fee_rule = Zip317FeeRule::standard();
+                let shape_fee = fee_rule
+                    .fee_required(
+                        &Network::TestNetwork,
+                        Network::TestNetwork.activation_height(NetworkUpgrade::Nu5).unwrap(),
+                        &Vec::<TestTransparentInput>::new(),
+                        &Vec::<TxOut>::new(),
+                        inputs.len(),
+                        sapling::builder::BundleType::DEFAULT
+                            .num_outputs(inputs.len(), 1 + change_count)
+                            .unwrap(),
+                        0,
+                    )
+                    .unwrap();
+                if decisions.dust_action() == Some(DustAction::AddDustToFee) {
+                    prop_assert!(balance.fee_required() > shape_fee);
+                    prop_assert!(
+                        (balance.fee_required() - shape_fee).unwrap() < fee_rule.marginal_fee()
+                    );
+                } else {
+                    prop_assert_eq!(balance.fee_required(), shape_fee);
+                }
+
+                // Change is only split when requested, and only dust change that was explicitly
+                // allowed is below the dust threshold.
+                prop_assert_eq!(decisions.change_split(), change_count > 1);
+                prop_assert!(change_count <= split_count);
+                if decisions.dust_action() != Some(DustAction::AllowDustChange) {
+                    prop_assert!(balance
+                        .proposed_change()
+                        .iter()
+                        .all(|c| c.value() >= fee_rule.marginal_fee()));
+                }
+            }
+        }
+    }
 }
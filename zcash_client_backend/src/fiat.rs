@@ -0,0 +1,269 @@
+//! Conversion between fiat currency amounts and ZEC values.
+//!
+//! Conversions use an [`ExchangeRateObservation`], which gives the value of 1 ZEC in a fiat
+//! currency. Rounding is always performed using the round-half-even strategy: fiat amounts
+//! are converted to a whole number of zatoshis, and ZEC values are converted to a whole
+//! number of the currency's minor units (for example, cents for USD, or whole yen for JPY).
+
+use std::fmt;
+
+use iso_currency::Currency;
+use rust_decimal::{prelude::ToPrimitive, Decimal, RoundingStrategy};
+use zcash_primitives::transaction::components::amount::{NonNegativeAmount, COIN};
+
+use crate::data_api::ExchangeRateObservation;
+
+/// Errors that may be produced when converting between fiat and ZEC values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConversionError {
+    /// The currency of the amount being converted differs from the currency of the exchange
+    /// rate.
+    CurrencyMismatch {
+        amount_currency: Currency,
+        rate_currency: Currency,
+    },
+    /// The exchange rate is zero or negative, or is so large that the smallest unit of the
+    /// currency is worth less than one zatoshi.
+    InvalidRate(Decimal),
+    /// The fiat amount to be converted is negative.
+    NegativeAmount(Decimal),
+    /// The result of the conversion is outside the range of valid ZEC values.
+    OutOfRange,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConversionError::CurrencyMismatch {
+                amount_currency,
+                rate_currency,
+            } => write!(
+                f,
+                "Cannot convert an amount in {} using an exchange rate for {}",
+                amount_currency.code(),
+                rate_currency.code()
+            ),
+            ConversionError::InvalidRate(rate) => write!(f, "Invalid exchange rate: {}", rate),
+            ConversionError::NegativeAmount(amount) => {
+                write!(f, "Cannot convert a negative amount: {}", amount)
+            }
+            ConversionError::OutOfRange => write!(
+                f,
+                "The converted value is outside the range of valid ZEC values"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// Returns the number of decimal places in the minor unit of the given currency.
+///
+/// Currencies without a minor unit, such as precious metals, are treated as having none.
+fn minor_unit_scale(currency: Currency) -> u32 {
+    currency.exponent().map_or(0, u32::from)
+}
+
+/// Returns the rate of the given observation, checking that it can be used for conversions.
+fn checked_rate(rate: &ExchangeRateObservation) -> Result<Decimal, ConversionError> {
+    let value = rate.rate();
+    // The rate must be positive, and one minor unit of the currency must be worth at least
+    // one zatoshi.
+    let max_rate =
+        Decimal::from(COIN) / Decimal::from(10u64.pow(minor_unit_scale(rate.currency())));
+    if value <= Decimal::ZERO || value > max_rate {
+        Err(ConversionError::InvalidRate(value))
+    } else {
+        Ok(value)
+    }
+}
+
+/// Converts an amount of fiat currency to ZEC, using the given exchange rate.
+///
+/// The result is rounded to the nearest zatoshi, with values exactly halfway between two
+/// zatoshi values rounded to the even value.
+///
+/// Returns an error if `currency` does not match the currency of `rate`, if `amount` is
+/// negative, if the rate is invalid, or if the result would exceed `MAX_MONEY`.
+pub fn fiat_to_zatoshis(
+    amount: Decimal,
+    currency: Currency,
+    rate: &ExchangeRateObservation,
+) -> Result<NonNegativeAmount, ConversionError> {
+    if currency != rate.currency() {
+        return Err(ConversionError::CurrencyMismatch {
+            amount_currency: currency,
+            rate_currency: rate.currency(),
+        });
+    }
+    if amount.is_sign_negative() && !amount.is_zero() {
+        return Err(ConversionError::NegativeAmount(amount));
+    }
+    let rate = checked_rate(rate)?;
+
+    amount
+        .checked_mul(Decimal::from(COIN))
+        .and_then(|zatoshis| zatoshis.checked_div(rate))
+        .map(|zatoshis| zatoshis.round_dp_with_strategy(0, RoundingStrategy::MidpointNearestEven))
+        .and_then(|zatoshis| zatoshis.to_u64())
+        .and_then(|zatoshis| NonNegativeAmount::from_u64(zatoshis).ok())
+        .ok_or(ConversionError::OutOfRange)
+}
+
+/// Converts a ZEC value to an amount in the currency of the given exchange rate.
+///
+/// The result is rounded to the currency's minor unit, with values exactly halfway between
+/// two minor-unit values rounded to the even value. The returned [`Decimal`] has the scale of
+/// the currency's minor unit, so that its [`Display`] implementation always renders that many
+/// decimal places (for example, `"12.30"` for USD, or `"1230"` for JPY).
+///
+/// Returns an error if the rate is invalid.
+///
+/// [`Display`]: fmt::Display
+pub fn zatoshis_to_fiat(
+    value: NonNegativeAmount,
+    rate: &ExchangeRateObservation,
+) -> Result<Decimal, ConversionError> {
+    let scale = minor_unit_scale(rate.currency());
+    let rate = checked_rate(rate)?;
+
+    let mut amount = (Decimal::from(u64::from(value)) * rate / Decimal::from(COIN))
+        .round_dp_with_strategy(scale, RoundingStrategy::MidpointNearestEven);
+    amount.rescale(scale);
+    Ok(amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use iso_currency::Currency;
+    use rust_decimal::Decimal;
+    use zcash_primitives::transaction::components::amount::NonNegativeAmount;
+    use zcash_protocol::value::MAX_MONEY;
+
+    use super::{fiat_to_zatoshis, zatoshis_to_fiat, ConversionError};
+    use crate::data_api::ExchangeRateObservation;
+
+    fn rate(currency: Currency, rate: &str) -> ExchangeRateObservation {
+        ExchangeRateObservation::from_parts(
+            currency,
+            rate.parse().unwrap(),
+            time::OffsetDateTime::UNIX_EPOCH,
+        )
+    }
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn fiat_to_zatoshis_rounds_half_to_even() {
+        let usd = rate(Currency::USD, "1");
+        let convert =
+            |amount| u64::from(fiat_to_zatoshis(dec(amount), Currency::USD, &usd).unwrap());
+
+        assert_eq!(convert("0.000000004"), 0);
+        assert_eq!(convert("0.000000005"), 0);
+        assert_eq!(convert("0.000000006"), 1);
+        assert_eq!(convert("0.000000015"), 2);
+        assert_eq!(convert("0.000000025"), 2);
+        assert_eq!(convert("12.34"), 1_234_000_000);
+    }
+
+    #[test]
+    fn fiat_to_zatoshis_uses_rate() {
+        let usd = rate(Currency::USD, "40");
+        assert_eq!(
+            fiat_to_zatoshis(dec("10"), Currency::USD, &usd),
+            Ok(NonNegativeAmount::const_from_u64(25_000_000))
+        );
+        assert_eq!(
+            fiat_to_zatoshis(Decimal::ZERO, Currency::USD, &usd),
+            Ok(NonNegativeAmount::ZERO)
+        );
+    }
+
+    #[test]
+    fn fiat_to_zatoshis_rejects_invalid_inputs() {
+        let usd = rate(Currency::USD, "40");
+        assert_eq!(
+            fiat_to_zatoshis(dec("10"), Currency::EUR, &usd),
+            Err(ConversionError::CurrencyMismatch {
+                amount_currency: Currency::EUR,
+                rate_currency: Currency::USD,
+            })
+        );
+        assert_eq!(
+            fiat_to_zatoshis(dec("-0.01"), Currency::USD, &usd),
+            Err(ConversionError::NegativeAmount(dec("-0.01")))
+        );
+
+        // A zero, negative, or absurdly large rate is rejected rather than producing zero.
+        for invalid in ["0", "-40", "1000000.01"] {
+            assert_eq!(
+                fiat_to_zatoshis(dec("10"), Currency::USD, &rate(Currency::USD, invalid)),
+                Err(ConversionError::InvalidRate(dec(invalid)))
+            );
+        }
+        assert!(
+            fiat_to_zatoshis(dec("10"), Currency::USD, &rate(Currency::USD, "1000000")).is_ok()
+        );
+    }
+
+    #[test]
+    fn fiat_to_zatoshis_rejects_values_above_max_money() {
+        let usd = rate(Currency::USD, "1");
+        let max_money_usd = Decimal::from(MAX_MONEY) / Decimal::from(100_000_000u64);
+        assert_eq!(
+            fiat_to_zatoshis(max_money_usd, Currency::USD, &usd),
+            Ok(NonNegativeAmount::const_from_u64(MAX_MONEY))
+        );
+        assert_eq!(
+            fiat_to_zatoshis(max_money_usd + dec("0.00000001"), Currency::USD, &usd),
+            Err(ConversionError::OutOfRange)
+        );
+        assert_eq!(
+            fiat_to_zatoshis(Decimal::MAX, Currency::USD, &usd),
+            Err(ConversionError::OutOfRange)
+        );
+    }
+
+    #[test]
+    fn zatoshis_to_fiat_uses_minor_units() {
+        let value = NonNegativeAmount::const_from_u64(30_750_000);
+
+        let usd = zatoshis_to_fiat(value, &rate(Currency::USD, "40")).unwrap();
+        assert_eq!(usd.to_string(), "12.30");
+
+        let jpy = zatoshis_to_fiat(value, &rate(Currency::JPY, "6000")).unwrap();
+        assert_eq!(jpy.to_string(), "1845");
+
+        // Values halfway between two minor units are rounded to the even value.
+        let half_cent = NonNegativeAmount::const_from_u64(12_500);
+        assert_eq!(
+            zatoshis_to_fiat(half_cent, &rate(Currency::USD, "40"))
+                .unwrap()
+                .to_string(),
+            "0.00"
+        );
+        let one_and_a_half_yen = NonNegativeAmount::const_from_u64(25_000);
+        assert_eq!(
+            zatoshis_to_fiat(one_and_a_half_yen, &rate(Currency::JPY, "6000"))
+                .unwrap()
+                .to_string(),
+            "2"
+        );
+    }
+
+    #[test]
+    fn zatoshis_to_fiat_rejects_invalid_rate() {
+        assert_eq!(
+            zatoshis_to_fiat(NonNegativeAmount::ZERO, &rate(Currency::JPY, "0")),
+            Err(ConversionError::InvalidRate(Decimal::ZERO))
+        );
+        // One yen must be worth at least one zatoshi.
+        assert_eq!(
+            zatoshis_to_fiat(NonNegativeAmount::ZERO, &rate(Currency::JPY, "100000001")),
+            Err(ConversionError::InvalidRate(dec("100000001")))
+        );
+    }
+}
@@ -66,6 +66,7 @@ pub mod data_api;
 mod decrypt;
 pub use zcash_keys::encoding;
 pub mod fees;
+pub mod fiat;
 pub use zcash_keys::keys;
 pub mod proposal;
 pub mod proto;
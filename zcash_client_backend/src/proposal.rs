@@ -12,6 +12,7 @@ use zcash_primitives::{
 };
 
 use crate::{
+    address::Address,
     fees::TransactionBalance,
     wallet::{Note, ReceivedNote, WalletTransparentOutput},
     zip321::TransactionRequest,
@@ -47,6 +48,9 @@ pub enum ProposalError {
     /// There was a mismatch between the payments in the proposal's transaction request
     /// and the payment pool selection values.
     PaymentPoolsMismatch,
+    /// The proposal tried to pay a ZIP 320 TEX address from shielded inputs. Payments to TEX
+    /// addresses may only be funded from transparent inputs.
+    PaysTexFromShielded,
 }
 
 impl Display for ProposalError {
@@ -90,6 +94,10 @@ impl Display for ProposalError {
                 f,
                 "The chosen payment pools did not match the payments of the transaction request."
             ),
+            ProposalError::PaysTexFromShielded => write!(
+                f,
+                "A payment to a TEX address may only be funded from transparent inputs."
+            ),
         }
     }
 }
@@ -353,7 +361,8 @@ impl<NoteRef> Step<NoteRef> {
     ///   address at that index (or the address itself in the case of bare transparent or Sapling
     ///   addresses).
     /// * `transparent_inputs`: The set of previous transparent outputs to be spent.
-    /// * `shielded_inputs`: The sets of previous shielded outputs to be spent.
+    /// * `shielded_inputs`: The sets of previous shielded outputs to be spent. These may not be
+    ///   provided if any payment in the transaction request is to a ZIP 320 TEX address.
     /// * `balance`: The change outputs to be added the transaction and the fee to be paid.
     /// * `is_shielding`: A flag that identifies whether this is a wallet-internal shielding
     ///   transaction.
@@ -383,6 +392,15 @@ impl<NoteRef> Step<NoteRef> {
             }
         }
 
+        if shielded_inputs.is_some()
+            && transaction_request
+                .payments()
+                .values()
+                .any(|payment| matches!(payment.recipient_address, Address::Tex(_)))
+        {
+            return Err(ProposalError::PaysTexFromShielded);
+        }
+
         let transparent_input_total = transparent_inputs
             .iter()
             .map(|out| out.txout().value)
@@ -497,10 +515,16 @@ impl<NoteRef> Step<NoteRef> {
             PoolType::Transparent => {
                 self.is_shielding
                     || !self.transparent_inputs.is_empty()
+                    || !self.prior_step_inputs.is_empty()
                     || self
                         .payment_pools()
                         .values()
                         .any(|pool| matches!(pool, PoolType::Transparent))
+                    || self
+                        .balance
+                        .proposed_change()
+                        .iter()
+                        .any(|c| c.output_pool() == PoolType::Transparent)
             }
             PoolType::Shielded(ShieldedProtocol::Sapling) => {
                 let sapling_in = self.shielded_inputs.iter().any(|s_in| {
@@ -516,7 +540,7 @@ impl<NoteRef> Step<NoteRef> {
                     .balance
                     .proposed_change()
                     .iter()
-                    .any(|c| c.output_pool() == ShieldedProtocol::Sapling);
+                    .any(|c| c.output_pool() == PoolType::Shielded(ShieldedProtocol::Sapling));
 
                 sapling_in || sapling_out || sapling_change
             }
@@ -537,7 +561,7 @@ impl<NoteRef> Step<NoteRef> {
                     .balance
                     .proposed_change()
                     .iter()
-                    .any(|c| c.output_pool() == ShieldedProtocol::Orchard);
+                    .any(|c| c.output_pool() == PoolType::Shielded(ShieldedProtocol::Orchard));
 
                 orchard_in || orchard_out || orchard_change
             }
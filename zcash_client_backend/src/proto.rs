@@ -10,6 +10,7 @@ use std::{
 };
 
 use sapling::{self, note::ExtractedNoteCommitment, Node};
+use shardtree::error::ShardTreeError;
 use zcash_note_encryption::{EphemeralKeyBytes, COMPACT_NOTE_SIZE};
 use zcash_primitives::{
     block::{BlockHash, BlockHeader},
@@ -20,8 +21,10 @@ use zcash_primitives::{
 };
 
 use crate::{
-    data_api::{chain::ChainState, InputSource},
-    fees::{ChangeValue, TransactionBalance},
+    data_api::{
+        chain::ChainState, wallet::anchor_checkpoint_exists, InputSource, WalletCommitmentTrees,
+    },
+    fees::{ChangePolicyDecisions, ChangeValue, DustAction, TransactionBalance},
     proposal::{Proposal, ProposalError, ShieldedInputs, Step, StepOutput, StepOutputIndex},
     zip321::{TransactionRequest, Zip321Error},
     PoolType, ShieldedProtocol,
@@ -345,11 +348,18 @@ pub enum ProposalDecodingError<DbError> {
     TxIdInvalid(TryFromSliceError),
     /// An invalid value pool identifier was encountered.
     ValuePoolNotSupported(i32),
-    /// A failure occurred trying to retrieve an unspent note or UTXO from the wallet database.
+    /// A failure occurred trying to retrieve an unspent note or UTXO, or the note commitment
+    /// tree checkpoint at an anchor height, from the wallet database.
     InputRetrieval(DbError),
     /// The unspent note or UTXO corresponding to a proposal input was not found in the wallet
     /// database.
     InputNotFound(TxId, PoolType, u32),
+    /// The value of the unspent note or UTXO corresponding to a proposal input differs from the
+    /// value recorded for that input in the proposal.
+    InputValueMismatch(TxId, PoolType, u32),
+    /// The wallet's note commitment tree has no checkpoint at the anchor height of a proposal
+    /// step, so that the step's shielded inputs cannot be spent.
+    AnchorNotAvailable(BlockHeight),
     /// The transaction balance, or a component thereof, failed to decode correctly.
     BalanceInvalid,
     /// Failed to decode a ZIP-302-compliant memo from the provided memo bytes.
@@ -366,6 +376,8 @@ pub enum ProposalDecodingError<DbError> {
     TransparentMemo,
     /// Change outputs to the specified pool are not supported.
     InvalidChangeRecipient(PoolType),
+    /// Ephemeral outputs to the specified pool are not supported.
+    InvalidEphemeralRecipient(PoolType),
 }
 
 impl<E> From<Zip321Error> for ProposalDecodingError<E> {
@@ -398,6 +410,16 @@ impl<E: Display> Display for ProposalDecodingError<E> {
                 "No {} input found for txid {}, index {}",
                 pool, txid, idx
             ),
+            ProposalDecodingError::InputValueMismatch(txid, pool, idx) => write!(
+                f,
+                "The value of the {} input for txid {}, index {} does not match the proposal",
+                pool, txid, idx
+            ),
+            ProposalDecodingError::AnchorNotAvailable(height) => write!(
+                f,
+                "No note commitment tree checkpoint is available for anchor height {}",
+                height
+            ),
             ProposalDecodingError::BalanceInvalid => {
                 write!(f, "An error occurred decoding the proposal balance.")
             }
@@ -424,6 +446,11 @@ impl<E: Display> Display for ProposalDecodingError<E> {
                 "Change outputs to the {} pool are not supported.",
                 pool_type
             ),
+            ProposalDecodingError::InvalidEphemeralRecipient(pool_type) => write!(
+                f,
+                "Ephemeral outputs to the {} pool are not supported.",
+                pool_type
+            ),
         }
     }
 }
@@ -464,6 +491,35 @@ impl proposal::ChangeValue {
     }
 }
 
+impl proposal::ChangePolicyDecisions {
+    /// Parses the change policy decisions recorded for a proposal step.
+    pub fn parse_decisions<T>(&self) -> Result<ChangePolicyDecisions, ProposalDecodingError<T>> {
+        let change_pool = match proposal::ValuePool::try_from(self.change_pool) {
+            Ok(proposal::ValuePool::PoolNotSpecified) => None,
+            _ => match pool_type(self.change_pool)? {
+                PoolType::Shielded(protocol) => Some(protocol),
+                t @ PoolType::Transparent => {
+                    return Err(ProposalDecodingError::InvalidChangeRecipient(t))
+                }
+            },
+        };
+        let dust_action = match proposal::DustAction::try_from(self.dust_action) {
+            Ok(proposal::DustAction::NotApplied) => None,
+            Ok(proposal::DustAction::AllowDustChange) => Some(DustAction::AllowDustChange),
+            Ok(proposal::DustAction::AddDustToFee) => Some(DustAction::AddDustToFee),
+            Ok(proposal::DustAction::DustRejected) => Some(DustAction::Reject),
+            Ok(proposal::DustAction::DustFailed) => Some(DustAction::Fail),
+            Err(_) => return Err(ProposalDecodingError::BalanceInvalid),
+        };
+
+        Ok(ChangePolicyDecisions::from_parts(
+            change_pool,
+            dust_action,
+            self.change_split,
+        ))
+    }
+}
+
 impl From<PoolType> for proposal::ValuePool {
     fn from(value: PoolType) -> Self {
         match value {
@@ -575,9 +631,31 @@ impl proposal::Proposal {
                             memo: change.memo().map(|memo_bytes| proposal::MemoBytes {
                                 value: memo_bytes.as_slice().to_vec(),
                             }),
+                            is_ephemeral: change.is_ephemeral(),
                         })
                         .collect(),
                     fee_required: step.balance().fee_required().into(),
+                    change_policy: step.balance().change_policy().map(|decisions| {
+                        proposal::ChangePolicyDecisions {
+                            change_pool: decisions
+                                .change_pool()
+                                .map_or(proposal::ValuePool::PoolNotSpecified, |pool| pool.into())
+                                .into(),
+                            dust_action: match decisions.dust_action() {
+                                Some(DustAction::AllowDustChange) => {
+                                    proposal::DustAction::AllowDustChange
+                                }
+                                Some(DustAction::AddDustToFee) => {
+                                    proposal::DustAction::AddDustToFee
+                                }
+                                Some(DustAction::Reject) => proposal::DustAction::DustRejected,
+                                Some(DustAction::Fail) => proposal::DustAction::DustFailed,
+                                None => proposal::DustAction::NotApplied,
+                            }
+                            .into(),
+                            change_split: decisions.change_split(),
+                        }
+                    }),
                 });
 
                 proposal::ProposalStep {
@@ -672,22 +750,30 @@ impl proposal::Proposal {
                                         #[cfg(feature = "transparent-inputs")]
                                         {
                                             let outpoint = OutPoint::new(txid.into(), out.index);
-                                            transparent_inputs.push(
-                                                wallet_db
-                                                    .get_unspent_transparent_output(&outpoint)
-                                                    .map_err(ProposalDecodingError::InputRetrieval)?
-                                                    .ok_or({
-                                                        ProposalDecodingError::InputNotFound(
-                                                            txid,
-                                                            PoolType::Transparent,
-                                                            out.index,
-                                                        )
-                                                    })?,
-                                            );
+                                            let utxo = wallet_db
+                                                .get_unspent_transparent_output(&outpoint)
+                                                .map_err(ProposalDecodingError::InputRetrieval)?
+                                                .ok_or({
+                                                    ProposalDecodingError::InputNotFound(
+                                                        txid,
+                                                        PoolType::Transparent,
+                                                        out.index,
+                                                    )
+                                                })?;
+                                            if u64::from(utxo.txout().value) != out.value {
+                                                return Err(
+                                                    ProposalDecodingError::InputValueMismatch(
+                                                        txid,
+                                                        PoolType::Transparent,
+                                                        out.index,
+                                                    ),
+                                                );
+                                            }
+                                            transparent_inputs.push(utxo);
                                         }
                                     }
-                                    PoolType::Shielded(protocol) => received_notes.push(
-                                        wallet_db
+                                    PoolType::Shielded(protocol) => {
+                                        let note = wallet_db
                                             .get_spendable_note(&txid, protocol, out.index)
                                             .map_err(ProposalDecodingError::InputRetrieval)
                                             .and_then(|opt| {
@@ -698,8 +784,16 @@ impl proposal::Proposal {
                                                         out.index,
                                                     )
                                                 })
-                                            })?,
-                                    ),
+                                            })?;
+                                        if u64::from(note.note().value()) != out.value {
+                                            return Err(ProposalDecodingError::InputValueMismatch(
+                                                txid,
+                                                PoolType::Shielded(protocol),
+                                                out.index,
+                                            ));
+                                        }
+                                        received_notes.push(note);
+                                    }
                                 }
                             }
                             PriorStepOutput(s_ref) => {
@@ -756,6 +850,9 @@ impl proposal::Proposal {
                                     })
                                     .transpose()?;
                                 match cv.pool_type()? {
+                                    t @ PoolType::Shielded(_) if cv.is_ephemeral => {
+                                        Err(ProposalDecodingError::InvalidEphemeralRecipient(t))
+                                    }
                                     PoolType::Shielded(ShieldedProtocol::Sapling) => {
                                         Ok(ChangeValue::sapling(value, memo))
                                     }
@@ -766,6 +863,11 @@ impl proposal::Proposal {
                                     PoolType::Transparent if memo.is_some() => {
                                         Err(ProposalDecodingError::TransparentMemo)
                                     }
+                                    // Transparent change is only supported as an ephemeral
+                                    // output that is spent by a later step.
+                                    PoolType::Transparent if cv.is_ephemeral => {
+                                        Ok(ChangeValue::ephemeral_transparent(value))
+                                    }
                                     t => Err(ProposalDecodingError::InvalidChangeRecipient(t)),
                                 }
                             })
@@ -774,6 +876,10 @@ impl proposal::Proposal {
                             .map_err(|_| ProposalDecodingError::BalanceInvalid)?,
                     )
                     .map_err(|_| ProposalDecodingError::BalanceInvalid)?;
+                    let balance = match &proto_balance.change_policy {
+                        Some(decisions) => balance.with_change_policy(decisions.parse_decisions()?),
+                        None => balance,
+                    };
 
                     let step = Step::from_parts(
                         &steps,
@@ -800,6 +906,46 @@ impl proposal::Proposal {
             other => Err(ProposalDecodingError::VersionInvalid(other)),
         }
     }
+
+    /// Attempts to parse a [`Proposal`] based upon a supported [`StandardFeeRule`] from its
+    /// protobuf representation, and checks that it can be executed by the given wallet.
+    ///
+    /// As for [`Self::try_into_standard_proposal`], each input of the proposal must be
+    /// spendable by the wallet and have the value recorded in the proposal. In addition, the
+    /// wallet's note commitment trees must have a checkpoint at the anchor height of each step
+    /// that spends shielded notes; otherwise [`ProposalDecodingError::AnchorNotAvailable`] is
+    /// returned. This should be used to decode a proposal that was created elsewhere, such as
+    /// by a watch-only wallet, before it is executed.
+    pub fn try_into_checked_standard_proposal<P: consensus::Parameters, DbT, DbError>(
+        &self,
+        params: &P,
+        wallet_db: &mut DbT,
+    ) -> Result<Proposal<StandardFeeRule, DbT::NoteRef>, ProposalDecodingError<DbError>>
+    where
+        DbT: InputSource<Error = DbError> + WalletCommitmentTrees,
+        DbError: From<ShardTreeError<<DbT as WalletCommitmentTrees>::Error>>,
+    {
+        let proposal = self.try_into_standard_proposal(params, wallet_db)?;
+
+        for step in proposal.steps() {
+            if let Some(inputs) = step.shielded_inputs() {
+                let anchor_height = inputs.anchor_height();
+                for protocol in [ShieldedProtocol::Sapling, ShieldedProtocol::Orchard] {
+                    if inputs
+                        .notes()
+                        .iter()
+                        .any(|n| n.note().protocol() == protocol)
+                        && !anchor_checkpoint_exists(wallet_db, protocol, anchor_height)
+                            .map_err(|e| ProposalDecodingError::InputRetrieval(e.into()))?
+                    {
+                        return Err(ProposalDecodingError::AnchorNotAvailable(anchor_height));
+                    }
+                }
+            }
+        }
+
+        Ok(proposal)
+    }
 }
 
 #[cfg(feature = "lightwalletd-tonic-transport")]
@@ -121,9 +121,30 @@ pub struct TransactionBalance {
     /// The fee to be paid by the proposed transaction, in zatoshis.
     #[prost(uint64, tag = "2")]
     pub fee_required: u64,
+    /// The decisions made by the change strategy in computing this balance, if
+    /// they were recorded.
+    #[prost(message, optional, tag = "3")]
+    pub change_policy: ::core::option::Option<ChangePolicyDecisions>,
+}
+/// The decisions made by a change strategy in applying its change and dust
+/// output policies.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ChangePolicyDecisions {
+    /// The pool to which shielded change is sent. PoolNotSpecified indicates
+    /// that the transaction has no shielded change output.
+    #[prost(enumeration = "ValuePool", tag = "1")]
+    pub change_pool: i32,
+    /// The action taken because the change would have been below the dust
+    /// threshold.
+    #[prost(enumeration = "DustAction", tag = "2")]
+    pub dust_action: i32,
+    /// Whether change was split into multiple outputs of equal value.
+    #[prost(bool, tag = "3")]
+    pub change_split: bool,
 }
 /// A proposed change output. If the transparent value pool is selected,
-/// the `memo` field must be null.
+/// the `memo` field must be null and the `isEphemeral` field must be set.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ChangeValue {
@@ -137,6 +158,11 @@ pub struct ChangeValue {
     /// Memos must not be present for transparent change outputs.
     #[prost(message, optional, tag = "3")]
     pub memo: ::core::option::Option<MemoBytes>,
+    /// Whether the change output is sent to an ephemeral transparent address,
+    /// to be spent by a later step of the proposal. This must be set for, and
+    /// only for, transparent change outputs.
+    #[prost(bool, tag = "4")]
+    pub is_ephemeral: bool,
 }
 /// An object wrapper for memo bytes, to facilitate representing the
 /// `change_memo == None` case.
@@ -225,3 +251,45 @@ impl FeeRule {
         }
     }
 }
+/// The action taken by a change strategy when a transaction's change would be
+/// below the dust threshold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum DustAction {
+    /// The change was not below the dust threshold, so no action was taken.
+    NotApplied = 0,
+    /// A change output below the dust threshold was created.
+    AllowDustChange = 1,
+    /// The change was added to the transaction fee.
+    AddDustToFee = 2,
+    /// Dust change was rejected, and additional inputs were required.
+    DustRejected = 3,
+    /// Dust change caused the change strategy to fail.
+    DustFailed = 4,
+}
+impl DustAction {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            DustAction::NotApplied => "DustActionNotApplied",
+            DustAction::AllowDustChange => "AllowDustChange",
+            DustAction::AddDustToFee => "AddDustToFee",
+            DustAction::DustRejected => "DustRejected",
+            DustAction::DustFailed => "DustFailed",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "DustActionNotApplied" => Some(Self::NotApplied),
+            "AllowDustChange" => Some(Self::AllowDustChange),
+            "AddDustToFee" => Some(Self::AddDustToFee),
+            "DustRejected" => Some(Self::DustRejected),
+            "DustFailed" => Some(Self::DustFailed),
+            _ => None,
+        }
+    }
+}
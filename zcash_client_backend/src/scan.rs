@@ -168,6 +168,29 @@ impl<IvkTag, D: Domain, M> DynamicUsage for BatchReceiver<IvkTag, D, M> {
     }
 }
 
+/// The executor on which a [`BatchRunner`] runs its batches of trial decryptions.
+#[derive(Clone, Debug, Default)]
+pub(crate) enum Executor {
+    /// Batches are run on the global `rayon` thread pool.
+    #[default]
+    Global,
+    /// Batches are run on the thread that flushes them, without spawning any threads.
+    CurrentThread,
+    /// Batches are run on the given `rayon` thread pool.
+    Pool(Arc<rayon::ThreadPool>),
+}
+
+impl Executor {
+    /// Runs the given closure on this executor.
+    pub(crate) fn spawn(&self, f: impl FnOnce() + Send + 'static) {
+        match self {
+            Executor::Global => rayon::spawn_fifo(f),
+            Executor::CurrentThread => f(),
+            Executor::Pool(pool) => pool.spawn_fifo(f),
+        }
+    }
+}
+
 /// A tracker for the batch scanning tasks that are currently running.
 ///
 /// This enables a [`BatchRunner`] to be optionally configured to track heap memory usage.
@@ -175,9 +198,9 @@ pub(crate) trait Tasks<Item> {
     type Task: Task;
     fn new() -> Self;
     fn add_task(&self, item: Item) -> Self::Task;
-    fn run_task(&self, item: Item) {
+    fn run_task(&self, executor: &Executor, item: Item) {
         let task = self.add_task(item);
-        rayon::spawn_fifo(|| task.run());
+        executor.spawn(|| task.run());
     }
 }
 
@@ -439,7 +462,7 @@ impl DynamicUsage for ResultKey {
     }
 }
 
-/// Logic to run batches of trial decryptions on the global threadpool.
+/// Logic to run batches of trial decryptions on an [`Executor`].
 pub(crate) struct BatchRunner<IvkTag, D, Output, Dec, T>
 where
     D: BatchDomain,
@@ -447,6 +470,8 @@ where
     T: Tasks<Batch<IvkTag, D, Output, Dec>>,
 {
     batch_size_threshold: usize,
+    // The executor on which batches are run.
+    executor: Executor,
     // The batch currently being accumulated.
     acc: Batch<IvkTag, D, Output, Dec>,
     // The running batches.
@@ -495,14 +520,17 @@ where
     Dec: Decryptor<D, Output>,
     T: Tasks<Batch<IvkTag, D, Output, Dec>>,
 {
-    /// Constructs a new batch runner for the given incoming viewing keys.
+    /// Constructs a new batch runner for the given incoming viewing keys, which runs its
+    /// batches on the given executor.
     pub(crate) fn new(
         batch_size_threshold: usize,
+        executor: Executor,
         ivks: impl Iterator<Item = (IvkTag, D::IncomingViewingKey)>,
     ) -> Self {
         let (tags, ivks) = ivks.unzip();
         Self {
             batch_size_threshold,
+            executor,
             acc: Batch::new(tags, ivks),
             running_tasks: T::new(),
             pending_results: HashMap::default(),
@@ -548,14 +576,14 @@ where
         }
     }
 
-    /// Runs the currently accumulated batch on the global threadpool.
+    /// Runs the currently accumulated batch on this runner's executor.
     ///
     /// Subsequent calls to `Self::add_outputs` will be accumulated into a new batch.
     pub(crate) fn flush(&mut self) {
         if !self.acc.is_empty() {
             let mut batch = Batch::new(self.acc.tags.clone(), self.acc.ivks.clone());
             mem::swap(&mut batch, &mut self.acc);
-            self.running_tasks.run_task(batch);
+            self.running_tasks.run_task(&self.executor, batch);
         }
     }
 
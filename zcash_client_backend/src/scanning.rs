@@ -4,6 +4,8 @@ use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fmt::{self, Debug};
 use std::hash::Hash;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
 
 use incrementalmerkletree::{Position, Retention};
 use sapling::{
@@ -12,8 +14,8 @@ use sapling::{
 };
 use subtle::{ConditionallySelectable, ConstantTimeEq, CtOption};
 
-use tracing::{debug,/* trace,*/ warn};
-use zcash_keys::keys::UnifiedFullViewingKey;
+use tracing::{debug, /* trace,*/ warn};
+use zcash_keys::keys::{UnifiedFullViewingKey, UnifiedIncomingViewingKey};
 use zcash_note_encryption::{batch, BatchDomain, Domain, ShieldedOutput, COMPACT_NOTE_SIZE};
 use zcash_primitives::{
     consensus::{self, BlockHeight, NetworkUpgrade},
@@ -24,7 +26,7 @@ use zip32::Scope;
 use crate::{
     data_api::{BlockMetadata, ScannedBlock, ScannedBundles},
     proto::compact_formats::CompactBlock,
-    scan::{Batch, BatchRunner, CompactDecryptor, DecryptedOutput, Tasks},
+    scan::{Batch, BatchRunner, CompactDecryptor, DecryptedOutput, Executor, Tasks},
     wallet::{WalletOutput, WalletSpend, WalletTx},
     ShieldedProtocol,
 };
@@ -160,6 +162,26 @@ impl<AccountId> ScanningKeyOps<SaplingDomain, AccountId, sapling::Nullifier>
     }
 }
 
+impl<AccountId> ScanningKeyOps<SaplingDomain, AccountId, sapling::Nullifier>
+    for ScanningKey<sapling::zip32::IncomingViewingKey, sapling::NullifierDerivingKey, AccountId>
+{
+    fn prepare(&self) -> sapling::note_encryption::PreparedIncomingViewingKey {
+        self.ivk.prepare()
+    }
+
+    fn nf(&self, note: &sapling::Note, position: Position) -> Option<sapling::Nullifier> {
+        self.nk.as_ref().map(|key| note.nf(key, position.into()))
+    }
+
+    fn account_id(&self) -> &AccountId {
+        &self.account_id
+    }
+
+    fn key_scope(&self) -> Option<Scope> {
+        self.key_scope
+    }
+}
+
 #[cfg(feature = "orchard")]
 impl<AccountId> ScanningKeyOps<OrchardDomain, AccountId, orchard::note::Nullifier>
     for ScanningKey<orchard::keys::IncomingViewingKey, orchard::keys::FullViewingKey, AccountId>
@@ -246,6 +268,20 @@ impl<AccountId: Copy + Eq + Hash + 'static> ScanningKeys<AccountId, (AccountId,
     /// along with the account identifiers corresponding to those UFVKs.
     pub fn from_account_ufvks(
         ufvks: impl IntoIterator<Item = (AccountId, UnifiedFullViewingKey)>,
+    ) -> Self {
+        Self::from_account_viewing_keys(ufvks, [])
+    }
+
+    /// Constructs a [`ScanningKeys`] from iterators of [`UnifiedFullViewingKey`]s and
+    /// [`UnifiedIncomingViewingKey`]s, along with the account identifiers corresponding to
+    /// those keys.
+    ///
+    /// An account for which only a UIVK is provided can only be scanned for notes received
+    /// to its external addresses. No nullifiers are derived for those notes, so spends of
+    /// them will not be detected.
+    pub fn from_account_viewing_keys(
+        ufvks: impl IntoIterator<Item = (AccountId, UnifiedFullViewingKey)>,
+        uivks: impl IntoIterator<Item = (AccountId, UnifiedIncomingViewingKey)>,
     ) -> Self {
         #![allow(clippy::type_complexity)]
 
@@ -290,6 +326,34 @@ impl<AccountId: Copy + Eq + Hash + 'static> ScanningKeys<AccountId, (AccountId,
             }
         }
 
+        // A UIVK only covers the external scope, and provides no nullifier deriving key.
+        for (account_id, uivk) in uivks {
+            if let Some(ivk) = uivk.sapling() {
+                sapling.insert(
+                    (account_id, Scope::External),
+                    Box::new(ScanningKey {
+                        ivk: ivk.clone(),
+                        nk: None::<sapling::NullifierDerivingKey>,
+                        account_id,
+                        key_scope: Some(Scope::External),
+                    }),
+                );
+            }
+
+            #[cfg(feature = "orchard")]
+            if let Some(ivk) = uivk.orchard() {
+                orchard.insert(
+                    (account_id, Scope::External),
+                    Box::new(ScanningKey {
+                        ivk: ivk.clone(),
+                        nk: None::<orchard::keys::FullViewingKey>,
+                        account_id,
+                        key_scope: Some(Scope::External),
+                    }),
+                );
+            }
+        }
+
         Self {
             sapling,
             #[cfg(feature = "orchard")]
@@ -504,6 +568,121 @@ where
     )
 }
 
+/// The default number of outputs that are accumulated before a batch of trial decryptions is
+/// dispatched.
+const DEFAULT_DECRYPTION_BATCH_SIZE: usize = 100;
+
+/// Controls how the trial decryption of compact outputs is parallelized when scanning blocks
+/// with [`scan_cached_blocks_with_progress`].
+///
+/// By default, trial decryptions are run on the global `rayon` thread pool, which uses all
+/// available cores. On constrained devices it may be preferable to limit the number of
+/// threads used, or to avoid spawning threads altogether. The setting is provided to each
+/// scan call, so it may differ between calls.
+///
+/// This setting only governs trial decryption. Storing the scanned blocks is left to
+/// [`WalletWrite::put_blocks`], which may use parallelism of its own; wallet backends that do
+/// so may accept a `ScanParallelism` to control it, using [`Self::is_single_threaded`] and
+/// [`Self::install`].
+///
+/// [`scan_cached_blocks_with_progress`]: crate::data_api::chain::scan_cached_blocks_with_progress
+/// [`WalletWrite::put_blocks`]: crate::data_api::WalletWrite::put_blocks
+#[derive(Clone, Debug)]
+pub struct ScanParallelism {
+    executor: Executor,
+    decryption_batch_size: usize,
+}
+
+impl Default for ScanParallelism {
+    fn default() -> Self {
+        ScanParallelism {
+            executor: Executor::Global,
+            decryption_batch_size: DEFAULT_DECRYPTION_BATCH_SIZE,
+        }
+    }
+}
+
+impl ScanParallelism {
+    /// Returns a setting under which trial decryptions are performed on the scanning thread,
+    /// without spawning or using any thread pool.
+    pub fn single_threaded() -> Self {
+        ScanParallelism {
+            executor: Executor::CurrentThread,
+            ..Default::default()
+        }
+    }
+
+    /// Returns a setting under which trial decryptions are performed on a new thread pool
+    /// with the given number of threads.
+    ///
+    /// If `num_threads` is 1, this is equivalent to [`Self::single_threaded`], and no thread
+    /// pool is created.
+    pub fn with_threads(num_threads: NonZeroUsize) -> Result<Self, rayon::ThreadPoolBuildError> {
+        if num_threads.get() == 1 {
+            Ok(Self::single_threaded())
+        } else {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(num_threads.get())
+                .build()
+                .map(|pool| Self::with_thread_pool(Arc::new(pool)))
+        }
+    }
+
+    /// Returns a setting under which trial decryptions are performed on the given thread pool.
+    pub fn with_thread_pool(pool: Arc<rayon::ThreadPool>) -> Self {
+        ScanParallelism {
+            executor: Executor::Pool(pool),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the number of outputs that are accumulated before a batch of trial decryptions is
+    /// dispatched.
+    pub fn with_decryption_batch_size(self, decryption_batch_size: NonZeroUsize) -> Self {
+        ScanParallelism {
+            decryption_batch_size: decryption_batch_size.get(),
+            ..self
+        }
+    }
+
+    /// Returns the number of threads on which trial decryptions will be performed.
+    pub fn num_threads(&self) -> usize {
+        match &self.executor {
+            Executor::Global => rayon::current_num_threads(),
+            Executor::CurrentThread => 1,
+            Executor::Pool(pool) => pool.current_num_threads(),
+        }
+    }
+
+    /// Returns the number of outputs that are accumulated before a batch of trial decryptions
+    /// is dispatched.
+    pub fn decryption_batch_size(&self) -> usize {
+        self.decryption_batch_size
+    }
+
+    /// Returns `true` if this setting performs all work on the calling thread, without
+    /// spawning or using any thread pool.
+    pub fn is_single_threaded(&self) -> bool {
+        matches!(self.executor, Executor::CurrentThread)
+    }
+
+    /// Runs `op`, executing any `rayon` parallel iterators that it uses on the thread pool
+    /// selected by this setting.
+    ///
+    /// Under [`Self::single_threaded`], `op` is simply run on the calling thread; it must not
+    /// use parallel iterators, as these would run on the global `rayon` thread pool.
+    pub fn install<OP, R>(&self, op: OP) -> R
+    where
+        OP: FnOnce() -> R + Send,
+        R: Send,
+    {
+        match &self.executor {
+            Executor::Global | Executor::CurrentThread => op(),
+            Executor::Pool(pool) => pool.install(op),
+        }
+    }
+}
+
 type TaggedSaplingBatch<IvkTag> = Batch<
     IvkTag,
     SaplingDomain,
@@ -558,12 +737,13 @@ where
     TO: OrchardTasks<IvkTag>,
 {
     pub(crate) fn for_keys<AccountId>(
-        batch_size_threshold: usize,
+        parallelism: &ScanParallelism,
         scanning_keys: &ScanningKeys<AccountId, IvkTag>,
     ) -> Self {
         BatchRunners {
             sapling: BatchRunner::new(
-                batch_size_threshold,
+                parallelism.decryption_batch_size,
+                parallelism.executor.clone(),
                 scanning_keys
                     .sapling()
                     .iter()
@@ -571,7 +751,8 @@ where
             ),
             #[cfg(feature = "orchard")]
             orchard: BatchRunner::new(
-                batch_size_threshold,
+                parallelism.decryption_batch_size,
+                parallelism.executor.clone(),
                 scanning_keys
                     .orchard()
                     .iter()
@@ -1325,7 +1506,7 @@ pub mod testing {
 #[cfg(test)]
 mod tests {
 
-    use std::convert::Infallible;
+    use std::{convert::Infallible, num::NonZeroUsize, sync::mpsc, thread};
 
     use incrementalmerkletree::{Position, Retention};
     use sapling::Nullifier;
@@ -1339,7 +1520,7 @@ mod tests {
 
     use crate::{
         data_api::BlockMetadata,
-        scanning::{BatchRunners, ScanningKeys},
+        scanning::{BatchRunners, ScanParallelism, ScanningKeys},
     };
 
     use super::{scan_block, scan_block_with_runners, testing::fake_compact_block, Nullifiers};
@@ -1350,7 +1531,7 @@ mod tests {
             let network = Network::TestNetwork;
             let account = AccountId::ZERO;
             let usk =
-                UnifiedSpendingKey::from_seed(&network, &[0u8; 32], account).expect("Valid USK");
+                UnifiedSpendingKey::from_seed(&network, &[], &[0u8; 32], account).expect("Valid USK");
             let ufvk = usk.to_unified_full_viewing_key();
             let sapling_dfvk = ufvk.sapling().expect("Sapling key is present").clone();
             let scanning_keys = ScanningKeys::from_account_ufvks([(account, ufvk)]);
@@ -1367,7 +1548,11 @@ mod tests {
             assert_eq!(cb.vtx.len(), 2);
 
             let mut batch_runners = if scan_multithreaded {
-                let mut runners = BatchRunners::<_, (), ()>::for_keys(10, &scanning_keys);
+                let mut runners = BatchRunners::<_, (), ()>::for_keys(
+                    &ScanParallelism::default()
+                        .with_decryption_batch_size(NonZeroUsize::new(10).unwrap()),
+                    &scanning_keys,
+                );
                 runners
                     .add_block(&Network::TestNetwork, cb.clone())
                     .unwrap();
@@ -1430,13 +1615,49 @@ mod tests {
         go(true);
     }
 
+    #[test]
+    fn scan_block_with_uivk() {
+        let network = Network::TestNetwork;
+        let account = AccountId::ZERO;
+        let usk =
+            UnifiedSpendingKey::from_seed(&network, &[], &[0u8; 32], account).expect("Valid USK");
+        let ufvk = usk.to_unified_full_viewing_key();
+        let sapling_dfvk = ufvk.sapling().expect("Sapling key is present").clone();
+        let scanning_keys = ScanningKeys::from_account_viewing_keys(
+            [],
+            [(account, ufvk.to_unified_incoming_viewing_key())],
+        );
+
+        let cb = fake_compact_block(
+            1u32.into(),
+            BlockHash([0; 32]),
+            Nullifier([0; 32]),
+            &sapling_dfvk,
+            NonNegativeAmount::const_from_u64(5),
+            false,
+            None,
+        );
+
+        let scanned_block =
+            scan_block(&network, cb, &scanning_keys, &Nullifiers::empty(), None).unwrap();
+        let txs = scanned_block.transactions();
+        assert_eq!(txs.len(), 1);
+
+        // The output is detected, but no nullifier can be computed for it.
+        let tx = &txs[0];
+        assert_eq!(tx.sapling_outputs().len(), 1);
+        assert_eq!(tx.sapling_outputs()[0].account_id(), &account);
+        assert_eq!(tx.sapling_outputs()[0].note().value().inner(), 5);
+        assert!(tx.sapling_outputs()[0].nf().is_none());
+    }
+
     #[test]
     fn scan_block_with_txs_after_my_tx() {
         fn go(scan_multithreaded: bool) {
             let network = Network::TestNetwork;
             let account = AccountId::ZERO;
             let usk =
-                UnifiedSpendingKey::from_seed(&network, &[0u8; 32], account).expect("Valid USK");
+                UnifiedSpendingKey::from_seed(&network, &[], &[0u8; 32], account).expect("Valid USK");
             let ufvk = usk.to_unified_full_viewing_key();
             let sapling_dfvk = ufvk.sapling().expect("Sapling key is present").clone();
             let scanning_keys = ScanningKeys::from_account_ufvks([(account, ufvk)]);
@@ -1453,7 +1674,11 @@ mod tests {
             assert_eq!(cb.vtx.len(), 3);
 
             let mut batch_runners = if scan_multithreaded {
-                let mut runners = BatchRunners::<_, (), ()>::for_keys(10, &scanning_keys);
+                let mut runners = BatchRunners::<_, (), ()>::for_keys(
+                    &ScanParallelism::default()
+                        .with_decryption_batch_size(NonZeroUsize::new(10).unwrap()),
+                    &scanning_keys,
+                );
                 runners
                     .add_block(&Network::TestNetwork, cb.clone())
                     .unwrap();
@@ -1506,11 +1731,119 @@ mod tests {
         go(true);
     }
 
+    #[test]
+    fn scan_parallelism_uses_configured_threads() {
+        let single = ScanParallelism::single_threaded();
+        assert_eq!(single.num_threads(), 1);
+        assert_eq!(
+            ScanParallelism::with_threads(NonZeroUsize::new(1).unwrap())
+                .unwrap()
+                .num_threads(),
+            1
+        );
+        let pooled = ScanParallelism::with_threads(NonZeroUsize::new(3).unwrap()).unwrap();
+        assert_eq!(pooled.num_threads(), 3);
+
+        // Returns the thread on which work was run, and the size of the pool it was run in.
+        let run = |parallelism: &ScanParallelism| {
+            let (tx, rx) = mpsc::channel();
+            parallelism.executor.spawn(move || {
+                tx.send((
+                    thread::current().id(),
+                    rayon::current_thread_index().map(|_| rayon::current_num_threads()),
+                ))
+                .unwrap();
+            });
+            rx.recv().unwrap()
+        };
+
+        // Single-threaded work is run on the calling thread, outside of any thread pool.
+        assert_eq!(run(&single), (thread::current().id(), None));
+
+        let (thread_id, pool_size) = run(&pooled);
+        assert_ne!(thread_id, thread::current().id());
+        assert_eq!(pool_size, Some(3));
+    }
+
+    #[test]
+    fn scan_block_results_independent_of_parallelism() {
+        let network = Network::TestNetwork;
+        let account = AccountId::ZERO;
+        let usk =
+            UnifiedSpendingKey::from_seed(&network, &[], &[0u8; 32], account).expect("Valid USK");
+        let ufvk = usk.to_unified_full_viewing_key();
+        let sapling_dfvk = ufvk.sapling().expect("Sapling key is present").clone();
+        let scanning_keys = ScanningKeys::from_account_ufvks([(account, ufvk)]);
+
+        let cb = fake_compact_block(
+            1u32.into(),
+            BlockHash([0; 32]),
+            Nullifier([0; 32]),
+            &sapling_dfvk,
+            NonNegativeAmount::const_from_u64(5),
+            true,
+            Some((0, 0)),
+        );
+
+        let scan = |parallelism: Option<ScanParallelism>| {
+            let mut batch_runners = parallelism.map(|parallelism| {
+                let mut runners = BatchRunners::<_, (), ()>::for_keys(&parallelism, &scanning_keys);
+                runners.add_block(&network, cb.clone()).unwrap();
+                runners.flush();
+                runners
+            });
+
+            let scanned_block = scan_block_with_runners(
+                &network,
+                cb.clone(),
+                &scanning_keys,
+                &Nullifiers::empty(),
+                None,
+                batch_runners.as_mut(),
+            )
+            .unwrap();
+
+            let outputs = scanned_block
+                .transactions()
+                .iter()
+                .flat_map(|tx| {
+                    tx.sapling_outputs().iter().map(|output| {
+                        (
+                            tx.txid(),
+                            output.index(),
+                            output.note().value().inner(),
+                            output.note_commitment_tree_position(),
+                        )
+                    })
+                })
+                .collect::<Vec<_>>();
+            let retentions = scanned_block
+                .sapling()
+                .commitments()
+                .iter()
+                .map(|(_, retention)| *retention)
+                .collect::<Vec<_>>();
+            (outputs, retentions)
+        };
+
+        let expected = scan(None);
+        assert_eq!(expected.0.len(), 1);
+        for parallelism in [
+            ScanParallelism::default(),
+            ScanParallelism::single_threaded(),
+            ScanParallelism::with_threads(NonZeroUsize::new(2).unwrap()).unwrap(),
+            ScanParallelism::single_threaded()
+                .with_decryption_batch_size(NonZeroUsize::new(1).unwrap()),
+        ] {
+            assert_eq!(scan(Some(parallelism)), expected);
+        }
+    }
+
     #[test]
     fn scan_block_with_my_spend() {
         let network = Network::TestNetwork;
         let account = AccountId::try_from(12).unwrap();
-        let usk = UnifiedSpendingKey::from_seed(&network, &[0u8; 32], account).expect("Valid USK");
+        let usk = UnifiedSpendingKey::from_seed(&network, &[], &[0u8; 32], account).expect("Valid USK");
         let ufvk = usk.to_unified_full_viewing_key();
         let scanning_keys = ScanningKeys::<AccountId, Infallible>::empty();
 
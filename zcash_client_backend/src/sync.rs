@@ -19,8 +19,9 @@ use tonic::{
     client::GrpcService,
     codegen::{Body, Bytes, StdError},
 };
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 use zcash_primitives::{
+    block::BlockHash,
     consensus::{BlockHeight, Parameters},
     merkle_tree::HashSer,
 };
@@ -28,8 +29,8 @@ use zcash_primitives::{
 use crate::{
     data_api::{
         chain::{
-            error::Error as ChainError, scan_cached_blocks, BlockCache, ChainState,
-            CommitmentTreeRoot,
+            error::Error as ChainError, scan_cached_blocks, truncate_to_chain_fork, BlockCache,
+            ChainState, CommitmentTreeRoot,
         },
         scanning::{ScanPriority, ScanRange},
         WalletCommitmentTrees, WalletRead, WalletWrite,
@@ -62,6 +63,16 @@ where
     <DbT as WalletRead>::Error: std::error::Error + Send + Sync + 'static,
     <DbT as WalletCommitmentTrees>::Error: std::error::Error + Send + Sync + 'static,
 {
+    // 0) Check that the wallet's view of the chain is consistent with the server's. A
+    //    shallow fork is left to the verification step in `running`; a deeper one
+    //    requires the caller to confirm the truncation.
+    if let action @ ReconcileAction::Truncate { .. } = reconcile_chain_view(client, db_data).await?
+    {
+        if action.rewind_depth() > MAX_AUTOMATIC_REWIND {
+            return Err(Error::ChainViewMismatch(action));
+        }
+    }
+
     // 1) Download note commitment tree data from lightwalletd
     // 2) Pass the commitment tree data to the database.
     update_subtree_roots(client, db_data).await?;
@@ -71,6 +82,214 @@ where
     Ok(())
 }
 
+/// The number of blocks below the wallet's chain tip that [`run`] is willing to rewind
+/// without asking the caller. This matches the rewind distance used when a chain reorg is
+/// detected during scanning.
+const MAX_AUTOMATIC_REWIND: u32 = 10;
+
+/// The maximum number of stored block hashes that [`reconcile_chain_view`] compares
+/// against the server when searching for a common ancestor.
+const MAX_RECONCILE_PROBES: u32 = 10;
+
+/// The result of comparing the wallet's view of the chain with that of a lightwalletd
+/// server. Returned by [`reconcile_chain_view`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReconcileAction {
+    /// The wallet has no stored blocks, or its chain tip is the server's chain tip. Nothing
+    /// needs to be done.
+    Consistent,
+    /// The wallet's stored blocks are on the server's chain, and the server's chain tip is
+    /// above the wallet's. This is the normal state at the start of a sync; the wallet's
+    /// chain tip has already been updated via [`WalletWrite::update_chain_tip`].
+    Behind {
+        wallet_tip: BlockHeight,
+        server_tip: BlockHeight,
+    },
+    /// The server's chain tip is below the wallet's, and the blocks the server does have
+    /// agree with the wallet. The server is lagging; the wallet's data is left untouched
+    /// and the caller may wish to wait or to choose a different server.
+    ServerBehind {
+        wallet_tip: BlockHeight,
+        server_tip: BlockHeight,
+    },
+    /// The wallet's chain tip is on a different fork from the server's chain. The wallet
+    /// must be truncated to `common_ancestor` via [`WalletWrite::truncate_to_height`]
+    /// before it can sync. This discards wallet data, so it is not done automatically.
+    ///
+    /// `common_ancestor` is the highest probed height at which the wallet and the server
+    /// agree. If none of the probed heights agree, it is one below the lowest probed
+    /// height, and the caller should expect to find another divergence after truncating.
+    Truncate {
+        wallet_tip: BlockHeight,
+        common_ancestor: BlockHeight,
+    },
+}
+
+impl ReconcileAction {
+    /// Returns the number of blocks that would be removed from the wallet by applying
+    /// this action, which is zero for everything other than [`ReconcileAction::Truncate`].
+    pub fn rewind_depth(&self) -> u32 {
+        match self {
+            ReconcileAction::Truncate {
+                wallet_tip,
+                common_ancestor,
+            } => u32::from(*wallet_tip).saturating_sub(u32::from(*common_ancestor)),
+            _ => 0,
+        }
+    }
+}
+
+/// Compares the wallet's view of the chain with the lightwalletd server's.
+///
+/// The wallet's stored chain tip hash is compared against the server's block at the same
+/// height (or at the server's chain tip, if that is lower), followed by up to nine older
+/// blocks that the wallet has stored, spaced exponentially below the tip. The first of these
+/// that matches the server is the common ancestor.
+///
+/// If the wallet is simply behind the server, its chain tip is updated. All other
+/// corrective actions are returned to the caller without being performed; see
+/// [`ReconcileAction`].
+pub async fn reconcile_chain_view<ChT, DbT, CaErr, TrErr>(
+    client: &mut CompactTxStreamerClient<ChT>,
+    db_data: &mut DbT,
+) -> Result<ReconcileAction, Error<CaErr, <DbT as WalletRead>::Error, TrErr>>
+where
+    ChT: GrpcService<BoxBody>,
+    ChT::Error: Into<StdError>,
+    ChT::ResponseBody: Body<Data = Bytes> + Send + 'static,
+    <ChT::ResponseBody as Body>::Error: Into<StdError> + Send,
+    DbT: WalletWrite,
+    DbT::Error: std::error::Error + Send + Sync + 'static,
+{
+    let (wallet_tip, wallet_tip_hash) =
+        match db_data.get_max_height_hash().map_err(Error::Wallet)? {
+            Some(tip) => tip,
+            None => return Ok(ReconcileAction::Consistent),
+        };
+
+    let server_tip: BlockHeight = client
+        .get_latest_block(service::ChainSpec::default())
+        .await?
+        .get_ref()
+        .height
+        .try_into()
+        .map_err(|_| Error::MisbehavingServer)?;
+
+    let mut probes = vec![];
+    for height in probe_heights(std::cmp::min(wallet_tip, server_tip)) {
+        let wallet_hash = if height == wallet_tip {
+            Some(wallet_tip_hash)
+        } else {
+            db_data.get_block_hash(height).map_err(Error::Wallet)?
+        };
+
+        if let Some(wallet_hash) = wallet_hash {
+            let server_hash = get_block_hash(client, height).await?;
+            let matches = wallet_hash == server_hash;
+            probes.push((height, matches));
+            if matches {
+                break;
+            }
+        }
+    }
+
+    let action = reconcile_action(wallet_tip, server_tip, &probes);
+    match &action {
+        ReconcileAction::Behind { server_tip, .. } => {
+            db_data
+                .update_chain_tip(*server_tip)
+                .map_err(Error::Wallet)?;
+        }
+        ReconcileAction::ServerBehind { server_tip, .. } => {
+            warn!(
+                "lightwalletd server chain tip {} is below the wallet's chain tip {}",
+                server_tip, wallet_tip
+            );
+        }
+        ReconcileAction::Truncate {
+            common_ancestor, ..
+        } => {
+            warn!(
+                "Wallet chain tip {} is not on the server's chain; common ancestor is {}",
+                wallet_tip, common_ancestor
+            );
+        }
+        ReconcileAction::Consistent => (),
+    }
+
+    Ok(action)
+}
+
+/// Returns the heights at which [`reconcile_chain_view`] compares block hashes, in
+/// descending order: `from`, then `from - 1`, `from - 2`, `from - 4`, and so on.
+fn probe_heights(from: BlockHeight) -> impl Iterator<Item = BlockHeight> {
+    let from = u32::from(from);
+    std::iter::once(0)
+        .chain((0..MAX_RECONCILE_PROBES - 1).map(|i| 1 << i))
+        .take_while(move |offset| *offset <= from)
+        .map(move |offset| BlockHeight::from_u32(from - offset))
+}
+
+/// Determines the [`ReconcileAction`] from the results of comparing block hashes at
+/// `probes`, which are `(height, wallet_hash == server_hash)` pairs in descending height
+/// order, ending at the first match.
+fn reconcile_action(
+    wallet_tip: BlockHeight,
+    server_tip: BlockHeight,
+    probes: &[(BlockHeight, bool)],
+) -> ReconcileAction {
+    match probes.first() {
+        // Either the compared tip matches, or there is nothing that we can compare.
+        None | Some((_, true)) => match wallet_tip.cmp(&server_tip) {
+            std::cmp::Ordering::Less => ReconcileAction::Behind {
+                wallet_tip,
+                server_tip,
+            },
+            std::cmp::Ordering::Equal => ReconcileAction::Consistent,
+            std::cmp::Ordering::Greater => ReconcileAction::ServerBehind {
+                wallet_tip,
+                server_tip,
+            },
+        },
+        Some((_, false)) => {
+            let common_ancestor = match probes.last() {
+                Some((height, true)) => *height,
+                Some((height, false)) => height.saturating_sub(1),
+                None => unreachable!(),
+            };
+            ReconcileAction::Truncate {
+                wallet_tip,
+                common_ancestor,
+            }
+        }
+    }
+}
+
+async fn get_block_hash<ChT, CaErr, DbErr, TrErr>(
+    client: &mut CompactTxStreamerClient<ChT>,
+    height: BlockHeight,
+) -> Result<BlockHash, Error<CaErr, DbErr, TrErr>>
+where
+    ChT: GrpcService<BoxBody>,
+    ChT::Error: Into<StdError>,
+    ChT::ResponseBody: Body<Data = Bytes> + Send + 'static,
+    <ChT::ResponseBody as Body>::Error: Into<StdError> + Send,
+{
+    let block = client
+        .get_block(BlockId {
+            height: height.into(),
+            hash: vec![],
+        })
+        .await?
+        .into_inner();
+
+    if block.height != u64::from(height) || (block.header().is_none() && block.hash.len() != 32) {
+        return Err(Error::MisbehavingServer);
+    }
+
+    Ok(block.hash())
+}
+
 async fn running<P, ChT, CaT, DbT, TrErr>(
     client: &mut CompactTxStreamerClient<ChT>,
     params: &P,
@@ -389,10 +608,18 @@ where
                 rewind_height,
             );
 
-            // Rewind to the chosen height.
-            db_data
-                .truncate_to_height(rewind_height)
-                .map_err(Error::Wallet)?;
+            // Rewind to the chosen height. The wallet may have rewound further than requested.
+            let reorg_info =
+                truncate_to_chain_fork(db_data, rewind_height).map_err(Error::Wallet)?;
+            let rewind_height = reorg_info.fork_height();
+            if !reorg_info.orphaned_txids().is_empty() {
+                warn!(
+                    "Chain reorg unmined {} wallet transactions ({} sent by the wallet): {:?}",
+                    reorg_info.orphaned_txids().len(),
+                    reorg_info.unmined_wallet_txids().len(),
+                    reorg_info.orphaned_txids(),
+                );
+            }
 
             // Delete cached blocks from rewind_height onwards.
             //
@@ -427,6 +654,10 @@ where
 pub enum Error<CaErr, DbErr, TrErr> {
     /// An error while interacting with a [`BlockCache`].
     Cache(CaErr),
+    /// The wallet's view of the chain diverges from the lightwalletd server's by more than
+    /// can be corrected automatically. The caller must decide whether to apply the given
+    /// action (see [`reconcile_chain_view`]).
+    ChainViewMismatch(ReconcileAction),
     /// The lightwalletd server returned invalid information, and is misbehaving.
     MisbehavingServer,
     /// An error while scanning blocks.
@@ -449,6 +680,11 @@ where
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::Cache(e) => write!(f, "Error while interacting with block cache: {}", e),
+            Error::ChainViewMismatch(action) => write!(
+                f,
+                "Wallet's view of the chain does not match the lightwalletd server: {:?}",
+                action
+            ),
             Error::MisbehavingServer => write!(f, "lightwalletd server is misbehaving"),
             Error::Scan(e) => write!(f, "Error while scanning blocks: {}", e),
             Error::Server(e) => write!(
@@ -489,3 +725,91 @@ impl<CaErr, DbErr, TrErr> From<tonic::Status> for Error<CaErr, DbErr, TrErr> {
         Error::Server(status)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use zcash_primitives::consensus::BlockHeight;
+
+    use super::{probe_heights, reconcile_action, ReconcileAction};
+
+    fn h(height: u32) -> BlockHeight {
+        BlockHeight::from_u32(height)
+    }
+
+    #[test]
+    fn probe_heights_are_exponentially_spaced() {
+        assert_eq!(
+            probe_heights(h(1000)).collect::<Vec<_>>(),
+            [1000, 999, 998, 996, 992, 984, 968, 936, 872, 744].map(h),
+        );
+        assert_eq!(probe_heights(h(3)).collect::<Vec<_>>(), [3, 2, 1].map(h));
+    }
+
+    #[test]
+    fn wallet_behind_server() {
+        assert_eq!(
+            reconcile_action(h(100), h(120), &[(h(100), true)]),
+            ReconcileAction::Behind {
+                wallet_tip: h(100),
+                server_tip: h(120),
+            },
+        );
+        assert_eq!(
+            reconcile_action(h(100), h(100), &[(h(100), true)]),
+            ReconcileAction::Consistent,
+        );
+    }
+
+    #[test]
+    fn server_behind_wallet() {
+        let action = reconcile_action(h(120), h(100), &[(h(100), true)]);
+        assert_eq!(
+            action,
+            ReconcileAction::ServerBehind {
+                wallet_tip: h(120),
+                server_tip: h(100),
+            },
+        );
+        assert_eq!(action.rewind_depth(), 0);
+
+        // If the wallet has none of the server's blocks, there is nothing to compare.
+        assert_eq!(
+            reconcile_action(h(120), h(100), &[]),
+            ReconcileAction::ServerBehind {
+                wallet_tip: h(120),
+                server_tip: h(100),
+            },
+        );
+    }
+
+    #[test]
+    fn wallet_on_different_fork() {
+        let action = reconcile_action(
+            h(100),
+            h(105),
+            &[
+                (h(100), false),
+                (h(99), false),
+                (h(98), false),
+                (h(96), true),
+            ],
+        );
+        assert_eq!(
+            action,
+            ReconcileAction::Truncate {
+                wallet_tip: h(100),
+                common_ancestor: h(96),
+            },
+        );
+        assert_eq!(action.rewind_depth(), 4);
+
+        // No common ancestor among the probes.
+        assert_eq!(
+            reconcile_action(h(100), h(100), &[(h(100), false), (h(99), false)]),
+            ReconcileAction::Truncate {
+                wallet_tip: h(100),
+                common_ancestor: h(98),
+            },
+        );
+    }
+}
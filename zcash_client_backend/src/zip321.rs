@@ -37,10 +37,16 @@ pub enum Zip321Error {
     /// Parsing encountered a duplicate ZIP 321 URI parameter for the returned payment index.
     DuplicateParameter(parse::Param, usize),
     /// The payment at the wrapped index attempted to include a memo when sending to a
-    /// transparent recipient address, which is not supported by the protocol.
+    /// transparent or TEX recipient address, which is not supported by the protocol.
     TransparentMemo(usize),
     /// The payment at the wrapped index did not include a recipient address.
     RecipientMissing(usize),
+    /// Including the payment at the wrapped index would cause the total value of the request
+    /// to exceed `MAX_MONEY`.
+    AmountOutOfRange(usize),
+    /// The ZIP 321 URI included a required parameter with the wrapped name (which has the
+    /// `req-` prefix) that is not understood, so the request must be rejected.
+    UnknownRequiredParameter(String),
     /// The ZIP 321 URI was malformed and failed to parse.
     ParseError(String),
 }
@@ -75,6 +81,14 @@ impl Display for Zip321Error {
             Zip321Error::RecipientMissing(idx) => {
                 write!(f, "Payment {} is missing its recipient address", idx)
             }
+            Zip321Error::AmountOutOfRange(idx) => write!(
+                f,
+                "Payment {} is invalid: the total value of the request would exceed MAX_MONEY",
+                idx
+            ),
+            Zip321Error::UnknownRequiredParameter(name) => {
+                write!(f, "Required parameter {} not recognized", name)
+            }
             Zip321Error::ParseError(s) => write!(f, "Parse failure: {}", s),
         }
     }
@@ -107,6 +121,9 @@ pub fn memo_from_base64(s: &str) -> Result<MemoBytes, Zip321Error> {
         .and_then(|b| MemoBytes::from_bytes(&b).map_err(Zip321Error::MemoBytesError))
 }
 
+/// The maximum number of payments in a request, as payment indices are limited to 4 digits.
+const MAX_PAYMENTS: usize = 9999;
+
 /// A single payment being requested.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Payment {
@@ -127,6 +144,11 @@ pub struct Payment {
     /// purpose of this payment.
     pub message: Option<String>,
     /// A list of other arbitrary key/value pairs associated with this payment.
+    ///
+    /// When parsing a ZIP 321 URI, this holds any parameters for this payment that are not
+    /// otherwise recognized, in the order that they appear, with their values percent-decoded.
+    /// These parameters are included when the request is rendered as a URI, so that requests
+    /// using extension parameters round-trip without loss.
     pub other_params: Vec<(String, String)>,
 }
 
@@ -169,10 +191,28 @@ impl TransactionRequest {
         }
     }
 
+    /// Returns a builder for constructing a transaction request one payment at a time.
+    pub fn builder() -> TransactionRequestBuilder {
+        TransactionRequestBuilder::new()
+    }
+
+    /// Returns a builder that is initialized with the payments of this request, so that the
+    /// request may be amended.
+    ///
+    /// The payments are kept in payment index order, and are renumbered sequentially when the
+    /// builder's request is built. Any payment that violates the ZIP 321 invariants will cause
+    /// [`TransactionRequestBuilder::build`] to fail.
+    pub fn into_builder(self) -> TransactionRequestBuilder {
+        self.payments
+            .into_values()
+            .fold(TransactionRequestBuilder::new(), |builder, payment| {
+                builder.push(payment)
+            })
+    }
+
     /// Constructs a new transaction request that obeys the ZIP-321 invariants.
     pub fn new(payments: Vec<Payment>) -> Result<TransactionRequest, Zip321Error> {
-        // Payment indices are limited to 4 digits
-        if payments.len() > 9999 {
+        if payments.len() > MAX_PAYMENTS {
             return Err(Zip321Error::TooManyPayments(payments.len()));
         }
 
@@ -318,7 +358,28 @@ impl TransactionRequest {
     }
 
     /// Parse the provided URI to a payment request value.
+    ///
+    /// Parameters that are not recognized are retained in [`Payment::other_params`], unless they
+    /// have the `req-` prefix, in which case [`Zip321Error::UnknownRequiredParameter`] is
+    /// returned. Use [`TransactionRequest::from_uri_with_extensions`] to parse URIs that use
+    /// required extension parameters.
     pub fn from_uri<P: consensus::Parameters>(params: &P, uri: &str) -> Result<Self, Zip321Error> {
+        Self::from_uri_with_extensions(params, uri, &[])
+    }
+
+    /// Parse the provided URI to a payment request value, treating the given `req-` parameter
+    /// names as understood by the caller.
+    ///
+    /// Each name in `extensions` must include the `req-` prefix, and must not include a payment
+    /// index suffix. Required parameters with these names are retained in
+    /// [`Payment::other_params`] like any other unrecognized parameter; it is the caller's
+    /// responsibility to interpret them. Any other `req-` parameter causes
+    /// [`Zip321Error::UnknownRequiredParameter`] to be returned.
+    pub fn from_uri_with_extensions<P: consensus::Parameters>(
+        params: &P,
+        uri: &str,
+        extensions: &[&str],
+    ) -> Result<Self, Zip321Error> {
         // Parse the leading zcash:<address>
         let (rest, primary_addr_param) = parse::lead_addr(params)(uri)
             .map_err(|e| Zip321Error::ParseError(format!("Error parsing lead address: {}", e)))?;
@@ -346,6 +407,12 @@ impl TransactionRequest {
 
         // Group the remaining parameters by payment index
         for p in xs {
+            if let parse::Param::Other(name, _) = &p.param {
+                if name.starts_with("req-") && !extensions.contains(&name.as_str()) {
+                    return Err(Zip321Error::UnknownRequiredParameter(name.clone()));
+                }
+            }
+
             match params_by_index.get_mut(&p.payment_index) {
                 None => {
                     params_by_index.insert(p.payment_index, vec![p.param]);
@@ -370,6 +437,134 @@ impl TransactionRequest {
     }
 }
 
+/// A builder for [`TransactionRequest`] values.
+///
+/// Payments are added with [`add_payment`], and the `with_*` methods set the fields of the
+/// most recently added payment. Each change is checked against the ZIP 321 invariants as it is
+/// made; the first violation is retained and returned by [`build`], and all later changes are
+/// ignored.
+///
+/// The payments of the built request are assigned sequential payment indices in the order that
+/// they were added, so that the URI rendered for the request is in canonical form.
+///
+/// [`add_payment`]: TransactionRequestBuilder::add_payment
+/// [`build`]: TransactionRequestBuilder::build
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionRequestBuilder {
+    payments: Vec<Payment>,
+    total: NonNegativeAmount,
+    error: Option<Zip321Error>,
+}
+
+impl TransactionRequestBuilder {
+    /// Constructs a builder for a request containing no payments.
+    pub fn new() -> Self {
+        Self {
+            payments: vec![],
+            total: NonNegativeAmount::ZERO,
+            error: None,
+        }
+    }
+
+    fn push(mut self, payment: Payment) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+
+        let idx = self.payments.len();
+        if idx >= MAX_PAYMENTS {
+            self.error = Some(Zip321Error::TooManyPayments(idx + 1));
+        } else if payment.memo.is_some() && !payment.recipient_address.can_receive_memo() {
+            self.error = Some(Zip321Error::TransparentMemo(idx));
+        } else {
+            match self.total + payment.amount {
+                Some(total) => {
+                    self.total = total;
+                    self.payments.push(payment);
+                }
+                None => self.error = Some(Zip321Error::AmountOutOfRange(idx)),
+            }
+        }
+
+        self
+    }
+
+    fn modify_last(
+        mut self,
+        f: impl FnOnce(usize, &mut Payment) -> Result<(), Zip321Error>,
+    ) -> Self {
+        if self.error.is_none() {
+            let idx = self.payments.len().saturating_sub(1);
+            self.error = match self.payments.last_mut() {
+                Some(payment) => f(idx, payment).err(),
+                None => Some(Zip321Error::RecipientMissing(0)),
+            };
+        }
+
+        self
+    }
+
+    /// Adds a payment of the given amount to the given address.
+    ///
+    /// This fails if the request would contain more payments than can be created within a
+    /// single Zcash transaction, or if the total value of the request would exceed `MAX_MONEY`.
+    pub fn add_payment(self, recipient_address: Address, amount: NonNegativeAmount) -> Self {
+        self.push(Payment::without_memo(recipient_address, amount))
+    }
+
+    /// Sets the memo of the most recently added payment.
+    ///
+    /// This fails if the payment is to a transparent or TEX address, or if no payment has been
+    /// added.
+    pub fn with_memo(self, memo: MemoBytes) -> Self {
+        self.modify_last(|idx, payment| {
+            if payment.recipient_address.can_receive_memo() {
+                payment.memo = Some(memo);
+                Ok(())
+            } else {
+                Err(Zip321Error::TransparentMemo(idx))
+            }
+        })
+    }
+
+    /// Sets the label of the most recently added payment.
+    ///
+    /// This fails if no payment has been added.
+    pub fn with_label(self, label: impl Into<String>) -> Self {
+        self.modify_last(|_, payment| {
+            payment.label = Some(label.into());
+            Ok(())
+        })
+    }
+
+    /// Sets the message of the most recently added payment.
+    ///
+    /// This fails if no payment has been added.
+    pub fn with_message(self, message: impl Into<String>) -> Self {
+        self.modify_last(|_, payment| {
+            payment.message = Some(message.into());
+            Ok(())
+        })
+    }
+
+    /// Returns the transaction request, or the first error that was encountered while
+    /// constructing it.
+    pub fn build(self) -> Result<TransactionRequest, Zip321Error> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(TransactionRequest {
+                payments: self.payments.into_iter().enumerate().collect(),
+            }),
+        }
+    }
+}
+
+impl Default for TransactionRequestBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 mod render {
     use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
 
@@ -561,10 +756,13 @@ mod parse {
         for v in vs {
             match v {
                 Param::Amount(a) => payment.amount = a,
-                Param::Memo(m) => match payment.recipient_address {
-                    Address::Sapling(_) | Address::Unified(_) => payment.memo = Some(m),
-                    Address::Transparent(_) => return Err(Zip321Error::TransparentMemo(i)),
-                },
+                Param::Memo(m) => {
+                    if payment.recipient_address.can_receive_memo() {
+                        payment.memo = Some(m);
+                    } else {
+                        return Err(Zip321Error::TransparentMemo(i));
+                    }
+                }
 
                 Param::Label(m) => payment.label = Some(m),
                 Param::Message(m) => payment.message = Some(m),
@@ -715,10 +913,6 @@ mod parse {
                 .map(Param::Memo)
                 .map_err(|e| format!("Decoded memo was invalid: {:?}", e)),
 
-            other if other.starts_with("req-") => {
-                Err(format!("Required parameter {} not recognized", other))
-            }
-
             other => percent_decode(value.as_bytes())
                 .decode_utf8()
                 .map(|s| Param::Other(other.to_string(), s.into_owned()))
@@ -749,8 +943,6 @@ pub mod testing {
         consensus::TEST_NETWORK, transaction::components::amount::testing::arb_nonnegative_amount,
     };
 
-    use crate::address::Address;
-
     use super::{MemoBytes, Payment, TransactionRequest};
     pub const VALID_PARAMNAME: &str = "[a-zA-Z][a-zA-Z0-9+-]*";
 
@@ -778,10 +970,7 @@ pub mod testing {
             // prevent duplicates by generating a set rather than a vec
             other_params in btree_map(VALID_PARAMNAME, any::<String>(), 0..3),
         ) -> Payment {
-            let is_shielded = match recipient_address {
-                Address::Transparent(_) => false,
-                Address::Sapling(_) | Address::Unified(_) => true,
-            };
+            let is_shielded = recipient_address.can_receive_memo();
 
             Payment {
                 recipient_address,
@@ -827,15 +1016,23 @@ pub mod testing {
 
 #[cfg(test)]
 mod tests {
-    use proptest::prelude::{any, proptest};
+    use assert_matches::assert_matches;
+    use proptest::{
+        collection::vec,
+        prelude::{any, proptest, Strategy},
+    };
     use std::str::FromStr;
 
     use zcash_keys::address::testing::arb_addr;
     use zcash_primitives::{
+        legacy::TransparentAddress,
         memo::Memo,
         transaction::components::amount::{testing::arb_nonnegative_amount, NonNegativeAmount},
     };
-    use zcash_protocol::consensus::{NetworkConstants, NetworkType, TEST_NETWORK};
+    use zcash_protocol::{
+        consensus::{NetworkConstants, NetworkType, TEST_NETWORK},
+        value::MAX_MONEY,
+    };
 
     #[cfg(feature = "local-consensus")]
     use zcash_primitives::{local_consensus::LocalNetwork, BlockHeight};
@@ -846,10 +1043,22 @@ mod tests {
         memo_from_base64, memo_to_base64,
         parse::{parse_amount, zcashparam, Param},
         render::{amount_str, memo_param, str_param},
-        testing::{arb_addr_str, arb_valid_memo, arb_zip321_request, arb_zip321_uri},
-        MemoBytes, Payment, TransactionRequest,
+        testing::{
+            arb_addr_str, arb_valid_memo, arb_zip321_payment, arb_zip321_request, arb_zip321_uri,
+        },
+        MemoBytes, Payment, TransactionRequest, Zip321Error, MAX_PAYMENTS,
     };
 
+    fn sapling_addr() -> Address {
+        Address::Sapling(
+            decode_payment_address(
+                NetworkType::Test.hrp_sapling_payment_address(),
+                "ztestsapling1n65uaftvs2g7075q2x2a04shfk066u3lldzxsrprfrqtzxnhc9ps73v4lhx4l9yfxj46sl0q90k",
+            )
+            .unwrap(),
+        )
+    }
+
     fn check_roundtrip(req: TransactionRequest) {
         let req_uri = req.to_uri(&TEST_NETWORK);
         let parsed = TransactionRequest::from_uri(&TEST_NETWORK, &req_uri).unwrap();
@@ -1097,6 +1306,260 @@ mod tests {
         assert!(i11r.is_err());
     }
 
+    #[test]
+    fn test_zip321_extension_params() {
+        let addr = "ztestsapling10yy2ex5dcqkclhc7z7yrnjq2z6feyjad56ptwlfgmy77dmaqqrl9gyhprdx59qgmsnyfska2kez";
+        let uri = format!(
+            "zcash:?address={}&amount=1&label=Tip&foo=bar&x-vendor-id=1+1&address.1={}&amount.1=2&foo.1=caf%C3%A9%20%26%20%3D%25",
+            addr, addr
+        );
+        let req = TransactionRequest::from_uri(&TEST_NETWORK, &uri).unwrap();
+
+        // Unrecognized parameters are retained in order for the payment they belong to, with
+        // their values percent-decoded.
+        assert_eq!(
+            req.payments()[&0].other_params,
+            vec![
+                ("foo".to_owned(), "bar".to_owned()),
+                ("x-vendor-id".to_owned(), "1+1".to_owned()),
+            ]
+        );
+        assert_eq!(req.payments()[&0].label.as_deref(), Some("Tip"));
+        assert_eq!(
+            req.payments()[&1].other_params,
+            vec![("foo".to_owned(), "café & =%".to_owned())]
+        );
+
+        // ... and are re-emitted, so that the URI round-trips unchanged.
+        assert_eq!(req.to_uri(&TEST_NETWORK), uri);
+    }
+
+    #[test]
+    fn test_zip321_duplicate_extension_params() {
+        let addr = "ztestsapling10yy2ex5dcqkclhc7z7yrnjq2z6feyjad56ptwlfgmy77dmaqqrl9gyhprdx59qgmsnyfska2kez";
+
+        let duplicated = format!("zcash:{}?foo=1&amount=1&foo=2", addr);
+        assert_eq!(
+            TransactionRequest::from_uri(&TEST_NETWORK, &duplicated),
+            Err(Zip321Error::DuplicateParameter(
+                Param::Other("foo".to_owned(), "2".to_owned()),
+                0
+            ))
+        );
+
+        let duplicated_1 = format!("zcash:?address.1={}&foo.1=1&foo.1=1", addr);
+        assert_eq!(
+            TransactionRequest::from_uri(&TEST_NETWORK, &duplicated_1),
+            Err(Zip321Error::DuplicateParameter(
+                Param::Other("foo".to_owned(), "1".to_owned()),
+                1
+            ))
+        );
+
+        // The same parameter may be given once for each payment.
+        let distinct = format!("zcash:?address={}&foo=1&address.1={}&foo.1=1", addr, addr);
+        assert!(TransactionRequest::from_uri(&TEST_NETWORK, &distinct).is_ok());
+    }
+
+    #[test]
+    fn test_zip321_required_params() {
+        let addr = "ztestsapling10yy2ex5dcqkclhc7z7yrnjq2z6feyjad56ptwlfgmy77dmaqqrl9gyhprdx59qgmsnyfska2kez";
+
+        let uri = format!("zcash:{}?amount=1&req-expiry=1717171717", addr);
+        assert_eq!(
+            TransactionRequest::from_uri(&TEST_NETWORK, &uri),
+            Err(Zip321Error::UnknownRequiredParameter(
+                "req-expiry".to_owned()
+            ))
+        );
+
+        let indexed = format!(
+            "zcash:?address={}&amount=1&address.1={}&amount.1=2&req-expiry.1=1717171717",
+            addr, addr
+        );
+        assert_eq!(
+            TransactionRequest::from_uri(&TEST_NETWORK, &indexed),
+            Err(Zip321Error::UnknownRequiredParameter(
+                "req-expiry".to_owned()
+            ))
+        );
+
+        // Required parameters that the caller understands are retained and re-emitted.
+        let req =
+            TransactionRequest::from_uri_with_extensions(&TEST_NETWORK, &indexed, &["req-expiry"])
+                .unwrap();
+        assert_eq!(
+            req.payments()[&1].other_params,
+            vec![("req-expiry".to_owned(), "1717171717".to_owned())]
+        );
+        assert_eq!(req.to_uri(&TEST_NETWORK), indexed);
+
+        // Registering one extension does not cause others to be accepted.
+        assert_eq!(
+            TransactionRequest::from_uri_with_extensions(&TEST_NETWORK, &indexed, &["req-other"]),
+            Err(Zip321Error::UnknownRequiredParameter(
+                "req-expiry".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_zip321_builder_roundtrip() {
+        let memo: MemoBytes = Memo::from_str("This is a simple memo.").unwrap().into();
+
+        let single = TransactionRequest::builder()
+            .add_payment(sapling_addr(), NonNegativeAmount::const_from_u64(100000000))
+            .with_memo(memo.clone())
+            .with_message("Thank you for your purchase")
+            .build()
+            .unwrap();
+        assert_eq!(
+            single.to_uri(&TEST_NETWORK),
+            format!(
+                "zcash:{}?amount=1&memo={}&message=Thank%20you%20for%20your%20purchase",
+                sapling_addr().encode(&TEST_NETWORK),
+                memo_to_base64(&memo),
+            )
+        );
+        check_roundtrip(single);
+
+        let multiple = TransactionRequest::builder()
+            .add_payment(
+                sapling_addr(),
+                NonNegativeAmount::const_from_u64(12345600000),
+            )
+            .with_label("Coffee")
+            .add_payment(sapling_addr(), NonNegativeAmount::const_from_u64(78900000))
+            .with_memo(memo)
+            .build()
+            .unwrap();
+        assert_eq!(
+            multiple.payments().keys().copied().collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        assert!(multiple
+            .to_uri(&TEST_NETWORK)
+            .starts_with("zcash:?address="));
+        check_roundtrip(multiple);
+    }
+
+    #[test]
+    fn test_zip321_builder_amend() {
+        let original = TransactionRequest::from_indexed(
+            [
+                (
+                    3,
+                    Payment::without_memo(sapling_addr(), NonNegativeAmount::const_from_u64(1)),
+                ),
+                (
+                    7,
+                    Payment::without_memo(sapling_addr(), NonNegativeAmount::const_from_u64(2)),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        )
+        .unwrap();
+
+        let amended = original
+            .clone()
+            .into_builder()
+            .add_payment(sapling_addr(), NonNegativeAmount::const_from_u64(3))
+            .with_label("Fee")
+            .build()
+            .unwrap();
+
+        // The payments are renumbered sequentially, preserving their order.
+        assert_eq!(
+            amended
+                .payments()
+                .iter()
+                .map(|(i, p)| (*i, u64::from(p.amount)))
+                .collect::<Vec<_>>(),
+            vec![(0, 1), (1, 2), (2, 3)]
+        );
+        assert_eq!(amended.payments()[&2].label.as_deref(), Some("Fee"));
+        check_roundtrip(amended);
+
+        // Rebuilding an unmodified request only normalizes its payment indices.
+        let rebuilt = original.clone().into_builder().build().unwrap();
+        assert_eq!(
+            rebuilt.payments().values().collect::<Vec<_>>(),
+            original.payments().values().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            rebuilt.payments().keys().copied().collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn test_zip321_builder_rejects_transparent_memo() {
+        let taddr = Address::Transparent(TransparentAddress::PublicKeyHash([7; 20]));
+        let memo: MemoBytes = Memo::from_str("This is a simple memo.").unwrap().into();
+
+        assert_eq!(
+            TransactionRequest::builder()
+                .add_payment(sapling_addr(), NonNegativeAmount::ZERO)
+                .add_payment(taddr.clone(), NonNegativeAmount::ZERO)
+                .with_memo(memo.clone())
+                .build(),
+            Err(Zip321Error::TransparentMemo(1))
+        );
+
+        // Amending a request that already includes such a payment fails as well.
+        let mut payment = Payment::without_memo(taddr, NonNegativeAmount::ZERO);
+        payment.memo = Some(memo);
+        let request =
+            TransactionRequest::from_indexed([(0, payment)].into_iter().collect()).unwrap();
+        assert_eq!(
+            request.into_builder().build(),
+            Err(Zip321Error::TransparentMemo(0))
+        );
+    }
+
+    #[test]
+    fn test_zip321_builder_rejects_missing_payment() {
+        assert_eq!(
+            TransactionRequest::builder().with_label("Orphan").build(),
+            Err(Zip321Error::RecipientMissing(0))
+        );
+    }
+
+    #[test]
+    fn test_zip321_builder_rejects_too_many_payments() {
+        let full = (0..MAX_PAYMENTS).fold(TransactionRequest::builder(), |b, _| {
+            b.add_payment(sapling_addr(), NonNegativeAmount::ZERO)
+        });
+        assert_eq!(
+            full.clone().build().map(|r| r.payments().len()),
+            Ok(MAX_PAYMENTS)
+        );
+        assert_eq!(
+            full.add_payment(sapling_addr(), NonNegativeAmount::ZERO)
+                .build(),
+            Err(Zip321Error::TooManyPayments(MAX_PAYMENTS + 1))
+        );
+    }
+
+    #[test]
+    fn test_zip321_builder_rejects_excess_total() {
+        let max_money = NonNegativeAmount::const_from_u64(MAX_MONEY);
+        assert!(TransactionRequest::builder()
+            .add_payment(sapling_addr(), max_money)
+            .build()
+            .is_ok());
+        assert_eq!(
+            TransactionRequest::builder()
+                .add_payment(sapling_addr(), max_money)
+                .add_payment(sapling_addr(), NonNegativeAmount::const_from_u64(1))
+                // Changes made after the first error are ignored.
+                .with_label("Ignored")
+                .build(),
+            Err(Zip321Error::AmountOutOfRange(1))
+        );
+    }
+
     proptest! {
         #[test]
         fn prop_zip321_roundtrip_address(addr in arb_addr(UA_REQUEST)) {
@@ -1143,6 +1606,42 @@ mod tests {
             assert!(TransactionRequest::normalize_and_eq(&mut parsed, &mut req));
         }
 
+        // Transparent recipients are excluded, as their Base58Check encoding does not currently
+        // round-trip (see `prop_zip321_roundtrip_address`).
+        #[test]
+        fn prop_zip321_roundtrip_builder(
+            payments in vec(
+                arb_zip321_payment().prop_filter("shielded or unified recipient", |p| {
+                    !matches!(p.recipient_address, Address::Transparent(_))
+                }),
+                1..10,
+            )
+        ) {
+            let req = payments
+                .iter()
+                .fold(TransactionRequest::builder(), |b, p| {
+                    let b = b.add_payment(p.recipient_address.clone(), p.amount);
+                    let b = match &p.memo {
+                        Some(memo) => b.with_memo(memo.clone()),
+                        None => b,
+                    };
+                    let b = match &p.label {
+                        Some(label) => b.with_label(label),
+                        None => b,
+                    };
+                    match &p.message {
+                        Some(message) => b.with_message(message),
+                        None => b,
+                    }
+                });
+
+            // Payments with amounts that would overflow the total are rejected.
+            match req.build() {
+                Ok(req) => check_roundtrip(req),
+                Err(e) => assert_matches!(e, Zip321Error::AmountOutOfRange(_)),
+            }
+        }
+
         #[test]
         fn prop_zip321_roundtrip_uri(uri in arb_zip321_uri()) {
             let mut parsed = TransactionRequest::from_uri(&TEST_NETWORK, &uri).unwrap();
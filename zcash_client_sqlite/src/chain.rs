@@ -12,9 +12,9 @@ use crate::{error::SqliteClientError, BlockDb};
 #[cfg(feature = "unstable")]
 use {
     crate::{BlockHash, FsBlockDb, FsBlockDbError},
-    rusqlite::Connection,
-    std::fs::File,
-    std::io::Read,
+    rusqlite::{Connection, OptionalExtension},
+    std::fs::{self, File},
+    std::io::{self, Read},
     std::path::{Path, PathBuf},
 };
 
@@ -114,6 +114,28 @@ impl BlockMeta {
     }
 }
 
+/// The blocks removed from a filesystem-backed block cache by
+/// [`FsBlockDb::truncate_cached_blocks_below`].
+#[cfg(feature = "unstable")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PrunedBlocks {
+    block_count: u64,
+    byte_count: u64,
+}
+
+#[cfg(feature = "unstable")]
+impl PrunedBlocks {
+    /// Returns the number of blocks whose metadata and files were deleted.
+    pub fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    /// Returns the total size, in bytes, of the block files that were deleted.
+    pub fn byte_count(&self) -> u64 {
+        self.byte_count
+    }
+}
+
 /// Inserts a batch of rows into the block metadata database.
 #[cfg(feature = "unstable")]
 pub(crate) fn blockmetadb_insert(
@@ -200,6 +222,74 @@ pub(crate) fn blockmetadb_get_max_cached_height(
     })
 }
 
+/// Returns the height up to which cached blocks have been pruned, if any have been.
+#[cfg(feature = "unstable")]
+pub(crate) fn blockmetadb_get_pruned_height(
+    conn: &Connection,
+) -> Result<Option<BlockHeight>, rusqlite::Error> {
+    conn.query_row("SELECT height FROM compactblocks_pruned", [], |row| {
+        row.get::<_, u32>(0).map(BlockHeight::from)
+    })
+    .optional()
+}
+
+/// Deletes the metadata and files of all cached blocks at or below the given height, and
+/// records that the cache has been pruned to that height.
+///
+/// Block files are deleted before their metadata, so that if an error occurs the remaining
+/// files will be deleted by a subsequent call.
+#[cfg(feature = "unstable")]
+pub(crate) fn blockmetadb_prune_to_height(
+    conn: &Connection,
+    blocks_dir: &Path,
+    prune_height: BlockHeight,
+) -> Result<PrunedBlocks, FsBlockDbError> {
+    let mut stmt_blocks =
+        conn.prepare("SELECT height, blockhash FROM compactblocks_meta WHERE height <= ?")?;
+    let block_paths = stmt_blocks
+        .query_map([u32::from(prune_height)], |row| {
+            Ok(BlockMeta {
+                height: BlockHeight::from_u32(row.get(0)?),
+                block_hash: BlockHash::from_slice(&row.get::<_, Vec<_>>(1)?),
+                block_time: 0,
+                sapling_outputs_count: 0,
+                orchard_actions_count: 0,
+            }
+            .block_file_path(&blocks_dir))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut pruned = PrunedBlocks {
+        block_count: block_paths.len() as u64,
+        byte_count: 0,
+    };
+    for block_path in block_paths {
+        match fs::metadata(&block_path) {
+            Ok(meta) => {
+                fs::remove_file(&block_path)?;
+                pruned.byte_count += meta.len();
+            }
+            // The file may have been deleted by an earlier, interrupted call.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(FsBlockDbError::Fs(e)),
+        }
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "DELETE FROM compactblocks_meta WHERE height <= ?",
+        [u32::from(prune_height)],
+    )?;
+    tx.execute(
+        "INSERT INTO compactblocks_pruned (id, height) VALUES (0, :height)
+        ON CONFLICT (id) DO UPDATE SET height = MAX(height, :height)",
+        rusqlite::named_params![":height": u32::from(prune_height)],
+    )?;
+    tx.commit()?;
+
+    Ok(pruned)
+}
+
 /// Returns the metadata for the block with the given height, if it exists in the database.
 #[cfg(feature = "unstable")]
 pub(crate) fn blockmetadb_find_block(
@@ -246,6 +336,15 @@ where
         Error::BlockSource(err.into())
     }
 
+    // A block that is missing from the cache because it was pruned must be refetched.
+    let cache_miss = |from_height: BlockHeight| match blockmetadb_get_pruned_height(&cache.conn) {
+        Ok(Some(pruned_height)) if from_height <= pruned_height => {
+            to_chain_error(FsBlockDbError::BlocksPruned(from_height, pruned_height))
+        }
+        Ok(_) => to_chain_error(FsBlockDbError::CacheMiss(from_height)),
+        Err(e) => to_chain_error(e),
+    };
+
     // Fetch the CompactBlocks we need to scan
     let mut stmt_blocks = cache
         .conn
@@ -285,7 +384,7 @@ where
             // We will only perform this check on the first row.
             let from_height = from_height.expect("can only reach here if set");
             if from_height != cbr.height {
-                return Err(to_chain_error(FsBlockDbError::CacheMiss(from_height)));
+                return Err(cache_miss(from_height));
             } else {
                 from_height_found = true;
             }
@@ -313,7 +412,7 @@ where
 
     if !from_height_found {
         let from_height = from_height.expect("can only reach here if set");
-        return Err(to_chain_error(FsBlockDbError::CacheMiss(from_height)));
+        return Err(cache_miss(from_height));
     }
 
     Ok(())
@@ -362,6 +461,72 @@ mod tests {
         testing::pool::data_db_truncation::<OrchardPoolTester>()
     }
 
+    #[test]
+    fn data_db_truncation_to_prior_checkpoint_sapling() {
+        testing::pool::data_db_truncation_to_prior_checkpoint::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn data_db_truncation_to_prior_checkpoint_orchard() {
+        testing::pool::data_db_truncation_to_prior_checkpoint::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn data_db_truncation_across_birthday_sapling() {
+        testing::pool::data_db_truncation_across_birthday::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn data_db_truncation_across_birthday_orchard() {
+        testing::pool::data_db_truncation_across_birthday::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn data_db_truncation_below_oldest_checkpoint_sapling() {
+        testing::pool::data_db_truncation_below_oldest_checkpoint::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn data_db_truncation_below_oldest_checkpoint_orchard() {
+        testing::pool::data_db_truncation_below_oldest_checkpoint::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn reorg_orphans_sent_transaction_sapling() {
+        testing::pool::reorg_orphans_sent_transaction::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn reorg_orphans_sent_transaction_orchard() {
+        testing::pool::reorg_orphans_sent_transaction::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn reorg_orphans_received_note_sapling() {
+        testing::pool::reorg_orphans_received_note::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn reorg_orphans_received_note_orchard() {
+        testing::pool::reorg_orphans_received_note::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn reorg_orphans_spend_detected_by_nullifier_sapling() {
+        testing::pool::reorg_orphans_spend_detected_by_nullifier::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn reorg_orphans_spend_detected_by_nullifier_orchard() {
+        testing::pool::reorg_orphans_spend_detected_by_nullifier::<OrchardPoolTester>()
+    }
+
     #[test]
     fn scan_cached_blocks_allows_blocks_out_of_order_sapling() {
         testing::pool::scan_cached_blocks_allows_blocks_out_of_order::<SaplingPoolTester>()
@@ -405,4 +570,48 @@ mod tests {
     fn scan_cached_blocks_detects_spends_out_of_order_orchard() {
         testing::pool::scan_cached_blocks_detects_spends_out_of_order::<OrchardPoolTester>()
     }
+
+    #[test]
+    fn detect_spends_then_full_scan_sapling() {
+        testing::pool::detect_spends_then_full_scan::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn detect_spends_then_full_scan_orchard() {
+        testing::pool::detect_spends_then_full_scan::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn scan_cached_blocks_reports_progress_sapling() {
+        testing::pool::scan_cached_blocks_reports_progress::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn scan_cached_blocks_reports_progress_orchard() {
+        testing::pool::scan_cached_blocks_reports_progress::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn scan_cached_blocks_with_parallelism_sapling() {
+        testing::pool::scan_cached_blocks_with_parallelism::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn scan_cached_blocks_with_parallelism_orchard() {
+        testing::pool::scan_cached_blocks_with_parallelism::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn scan_cached_blocks_cancellation_is_resumable_sapling() {
+        testing::pool::scan_cached_blocks_cancellation_is_resumable::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn scan_cached_blocks_cancellation_is_resumable_orchard() {
+        testing::pool::scan_cached_blocks_cancellation_is_resumable::<OrchardPoolTester>()
+    }
 }
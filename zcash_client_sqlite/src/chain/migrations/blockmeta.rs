@@ -1,7 +1,10 @@
 use schemer_rusqlite::RusqliteMigration;
 
 pub fn all_migrations() -> Vec<Box<dyn RusqliteMigration<Error = rusqlite::Error>>> {
-    vec![Box::new(init::Migration {})]
+    vec![
+        Box::new(init::Migration {}),
+        Box::new(pruned_height::Migration {}),
+    ]
 }
 
 pub mod init {
@@ -51,3 +54,49 @@ pub mod init {
         }
     }
 }
+
+pub mod pruned_height {
+    use rusqlite::{self};
+    use schemer::{self, migration};
+    use schemer_rusqlite::RusqliteMigration;
+    use uuid::Uuid;
+
+    pub struct Migration;
+
+    /// The migration that added the `compactblocks_pruned` table, which records the height up
+    /// to which cached blocks have been pruned.
+    ///
+    /// 0d1b6a5e-7f3c-4e2a-9c84-b5e2f19a6d37
+    pub const MIGRATION_ID: Uuid = Uuid::from_fields(
+        0x0d1b6a5e,
+        0x7f3c,
+        0x4e2a,
+        b"\x9c\x84\xb5\xe2\xf1\x9a\x6d\x37",
+    );
+
+    migration!(
+        Migration,
+        &format!("{}", MIGRATION_ID),
+        [&format!("{}", super::init::MIGRATION_ID)],
+        "Record the height up to which cached blocks have been pruned."
+    );
+
+    impl RusqliteMigration for Migration {
+        type Error = rusqlite::Error;
+
+        fn up(&self, transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+            transaction.execute_batch(
+                "CREATE TABLE compactblocks_pruned (
+                    id INTEGER PRIMARY KEY CHECK (id = 0),
+                    height INTEGER NOT NULL
+                )",
+            )?;
+            Ok(())
+        }
+
+        fn down(&self, transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+            transaction.execute_batch("DROP TABLE compactblocks_pruned;")?;
+            Ok(())
+        }
+    }
+}
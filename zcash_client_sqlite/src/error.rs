@@ -4,8 +4,10 @@ use std::error;
 use std::fmt;
 
 use shardtree::error::ShardTreeError;
+use uuid::Uuid;
 use zcash_client_backend::{
     encoding::{Bech32DecodeError, TransparentCodecError},
+    wallet::NoteId,
     PoolType,
 };
 use zcash_keys::keys::AddressGenerationError;
@@ -13,10 +15,12 @@ use zcash_primitives::zip32;
 use zcash_primitives::{consensus::BlockHeight, transaction::components::amount::BalanceError};
 
 use crate::wallet::commitment_tree;
-use crate::PRUNING_DEPTH;
 
 #[cfg(feature = "transparent-inputs")]
-use zcash_primitives::legacy::TransparentAddress;
+use {
+    crate::AccountId,
+    zcash_primitives::{legacy::TransparentAddress, transaction::components::OutPoint},
+};
 
 /// The primary error type for the SQLite wallet backend.
 #[derive(Debug)]
@@ -61,8 +65,9 @@ pub enum SqliteClientError {
     NonSequentialBlocks,
 
     /// A requested rewind would violate invariants of the storage layer. The payload returned with
-    /// this error is (safe rewind height, requested height).
-    RequestedRewindInvalid(BlockHeight, BlockHeight),
+    /// this error is (safe rewind height, requested height), where the safe rewind height is the
+    /// lowest height to which the wallet can be truncated, if any.
+    RequestedRewindInvalid(Option<BlockHeight>, BlockHeight),
 
     /// An error occurred in generating a Zcash address.
     AddressGeneration(AddressGenerationError),
@@ -70,6 +75,10 @@ pub enum SqliteClientError {
     /// The account for which information was requested does not belong to the wallet.
     AccountUnknown,
 
+    /// A caller attempted to give an account a name that is already used by another account in
+    /// the wallet.
+    AccountNameConflict(String),
+
     /// The account was imported, and ZIP-32 derivation information is not known for it.
     UnknownZip32Derivation,
 
@@ -110,6 +119,32 @@ pub enum SqliteClientError {
 
     /// An error occurred in computing wallet balance
     BalanceError(BalanceError),
+
+    /// The note for which a spend policy was being set does not belong to the wallet.
+    NoteNotFound(NoteId),
+
+    /// The UTXO for which a spend policy was being set does not belong to the wallet.
+    #[cfg(feature = "transparent-inputs")]
+    UtxoNotFound(OutPoint),
+
+    /// No more external transparent addresses could be reserved for the account, because every
+    /// address within the gap limit of its last used address has already been reserved.
+    #[cfg(feature = "transparent-inputs")]
+    ReachedGapLimit(AccountId, u32),
+
+    /// A wallet snapshot could not be restored because it was produced for a different network
+    /// or database schema than that of the wallet it was being restored into.
+    SnapshotIncompatible(String),
+
+    /// A wallet database could not be opened read-only, because the migrations with the given
+    /// identifiers, which are required by this version of the library, have not been applied to
+    /// it.
+    SchemaOutdated(Vec<Uuid>),
+
+    /// The passphrase provided to open an encrypted wallet database did not decrypt it, or the
+    /// database is not encrypted.
+    #[cfg(feature = "sqlcipher")]
+    IncorrectKey,
 }
 
 impl error::Error for SqliteClientError {
@@ -134,8 +169,10 @@ impl fmt::Display for SqliteClientError {
             }
             SqliteClientError::Protobuf(e) => write!(f, "Failed to parse protobuf-encoded record: {}", e),
             SqliteClientError::InvalidNote => write!(f, "Invalid note"),
-            SqliteClientError::RequestedRewindInvalid(h, r) =>
-                write!(f, "A rewind must be either of less than {} blocks, or at least back to block {} for your wallet; the requested height was {}.", PRUNING_DEPTH, h, r),
+            SqliteClientError::RequestedRewindInvalid(Some(h), r) =>
+                write!(f, "A rewind must be to a height of at least {} for your wallet; the requested height was {}.", h, r),
+            SqliteClientError::RequestedRewindInvalid(None, r) =>
+                write!(f, "The wallet cannot be rewound; the requested height was {}.", r),
             SqliteClientError::Bech32DecodeError(e) => write!(f, "{}", e),
             #[cfg(feature = "transparent-inputs")]
             SqliteClientError::HdwalletError(e) => write!(f, "{:?}", e),
@@ -148,6 +185,7 @@ impl fmt::Display for SqliteClientError {
             SqliteClientError::NonSequentialBlocks => write!(f, "`put_blocks` requires that the provided block range be sequential"),
             SqliteClientError::AddressGeneration(e) => write!(f, "{}", e),
             SqliteClientError::AccountUnknown => write!(f, "The account with the given ID does not belong to this wallet."),
+            SqliteClientError::AccountNameConflict(name) => write!(f, "Another account in this wallet is already named {:?}.", name),
             SqliteClientError::UnknownZip32Derivation => write!(f, "ZIP-32 derivation information is not known for this account."),
             SqliteClientError::KeyDerivationError(acct_id) => write!(f, "Key derivation failed for account {}", u32::from(*acct_id)),
             SqliteClientError::BadAccountData(e) => write!(f, "Failed to add account: {}", e),
@@ -160,6 +198,15 @@ impl fmt::Display for SqliteClientError {
             SqliteClientError::ChainHeightUnknown => write!(f, "Chain height unknown; please call `update_chain_tip`"),
             SqliteClientError::UnsupportedPoolType(t) => write!(f, "Pool type is not currently supported: {}", t),
             SqliteClientError::BalanceError(e) => write!(f, "Balance error: {}", e),
+            SqliteClientError::NoteNotFound(id) => write!(f, "The note {:?} does not belong to the wallet.", id),
+            #[cfg(feature = "transparent-inputs")]
+            SqliteClientError::UtxoNotFound(outpoint) => write!(f, "The UTXO {:?} does not belong to the wallet.", outpoint),
+            #[cfg(feature = "transparent-inputs")]
+            SqliteClientError::ReachedGapLimit(account_id, gap_limit) => write!(f, "All of the transparent addresses within the gap limit of {} addresses for account {:?} have already been reserved.", gap_limit, account_id),
+            SqliteClientError::SnapshotIncompatible(reason) => write!(f, "The wallet snapshot cannot be restored into this wallet: {}", reason),
+            SqliteClientError::SchemaOutdated(missing) => write!(f, "The wallet database schema is out of date; {} required migrations have not been applied.", missing.len()),
+            #[cfg(feature = "sqlcipher")]
+            SqliteClientError::IncorrectKey => write!(f, "The wallet database could not be decrypted with the provided passphrase."),
         }
     }
 }
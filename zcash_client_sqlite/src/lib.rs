@@ -38,21 +38,22 @@
 #[cfg(target_os = "android")]
 extern crate android_logger;
 
-use incrementalmerkletree::{Position, Retention};
-use maybe_rayon::{
-    prelude::{IndexedParallelIterator, ParallelIterator},
-    slice::ParallelSliceMut,
-};
+use iso_currency::Currency;
 use nonempty::NonEmpty;
-use rusqlite::{self, Connection};
+use rusqlite::{self, Connection, OpenFlags};
 use secrecy::{ExposeSecret, SecretVec};
 use shardtree::{error::ShardTreeError, ShardTree};
 use std::{
-    borrow::Borrow, collections::HashMap, convert::AsRef, fmt, num::NonZeroU32, ops::Range,
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap},
+    convert::AsRef,
+    fmt,
+    num::NonZeroU32,
     path::Path,
+    time::Duration,
 };
 use subtle::ConditionallySelectable;
-use tracing::{debug, trace, warn};
+use tracing::warn;
 
 use zcash_client_backend::{
     address::UnifiedAddress,
@@ -60,14 +61,19 @@ use zcash_client_backend::{
         self,
         chain::{BlockSource, ChainState, CommitmentTreeRoot},
         scanning::{ScanPriority, ScanRange},
-        Account, AccountBirthday, AccountSource, BlockMetadata, DecryptedTransaction, InputSource,
-        NullifierQuery, ScannedBlock, SeedRelevance, SentTransaction, SpendableNotes,
+        snapshot::{WalletSnapshot, WalletSnapshotStore},
+        Account, AccountBirthday, AccountDetails, AccountMetadataValue, AccountPoolBalances,
+        AccountSource, BlockMetadata, DecryptedTransaction, DraftId, DraftPayment,
+        ExchangeRateObservation, InputSource, MemoQuery, NullifierQuery, ScannedBlock,
+        SeedRelevance, SentTransaction, SpendableNotes, TransactionFilter, TransactionSummary,
         WalletCommitmentTrees, WalletRead, WalletSummary, WalletWrite, SAPLING_SHARD_HEIGHT,
     },
     keys::{
-        AddressGenerationError, UnifiedAddressRequest, UnifiedFullViewingKey, UnifiedSpendingKey,
+        AddressGenerationError, UnifiedAddressRequest, UnifiedFullViewingKey,
+        UnifiedIncomingViewingKey, UnifiedSpendingKey,
     },
     proto::compact_formats::CompactBlock,
+    scanning::ScanParallelism,
     wallet::{Note, NoteId, ReceivedNote, Recipient, WalletTransparentOutput},
     DecryptedOutput, PoolType, ShieldedProtocol, TransferType,
 };
@@ -77,26 +83,30 @@ use zcash_primitives::{
     consensus::{self, BlockHeight},
     memo::{Memo, MemoBytes},
     transaction::{components::amount::NonNegativeAmount, Transaction, TxId},
-    zip32::{self, DiversifierIndex, Scope},
+    zip32::{self, DiversifierIndex},
 };
 use zip32::fingerprint::SeedFingerprint;
 
 use crate::{error::SqliteClientError, wallet::commitment_tree::SqliteShardStore};
 
 #[cfg(feature = "orchard")]
-use {
-    incrementalmerkletree::frontier::Frontier,
-    shardtree::store::{Checkpoint, ShardStore},
-    std::collections::BTreeMap,
-    zcash_client_backend::data_api::ORCHARD_SHARD_HEIGHT,
-};
+use zcash_client_backend::data_api::ORCHARD_SHARD_HEIGHT;
 
 #[cfg(feature = "transparent-inputs")]
 use {
-    zcash_client_backend::wallet::TransparentAddressMetadata,
-    zcash_primitives::{legacy::TransparentAddress, transaction::components::OutPoint},
+    zcash_client_backend::{
+        data_api::{TransparentAddressUsage, DEFAULT_TRANSPARENT_GAP_LIMIT},
+        wallet::TransparentAddressMetadata,
+    },
+    zcash_primitives::{
+        legacy::{keys::TransparentKeyScope, TransparentAddress},
+        transaction::components::OutPoint,
+    },
 };
 
+#[cfg(feature = "sqlcipher")]
+use secrecy::SecretString;
+
 #[cfg(feature = "unstable")]
 use {
     crate::chain::{fsblockdb_with_blocks, BlockMeta},
@@ -120,6 +130,10 @@ mod testing;
 /// this delta from the chain tip to be pruned.
 pub(crate) const PRUNING_DEPTH: u32 = 100;
 
+/// The length of time for which a read-only connection to the wallet database will wait for a
+/// lock held by a writer to be released before failing.
+pub(crate) const READ_ONLY_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// The number of blocks to verify ahead when the chain tip is updated.
 pub(crate) const VERIFY_LOOKAHEAD: u32 = 10;
 
@@ -174,6 +188,9 @@ pub struct UtxoId(pub i64);
 pub struct WalletDb<C, P> {
     conn: C,
     params: P,
+    #[cfg(feature = "transparent-inputs")]
+    transparent_gap_limit: u32,
+    scan_parallelism: ScanParallelism,
 }
 
 /// A wrapper for a SQLite transaction affecting the wallet database.
@@ -185,15 +202,97 @@ impl Borrow<rusqlite::Connection> for SqlTransaction<'_> {
     }
 }
 
+/// A wrapper for a read-only SQLite connection to the wallet database.
+///
+/// A [`WalletDb`] using this connection type implements [`WalletRead`] and [`InputSource`], but
+/// not [`WalletWrite`]. It is constructed with [`WalletDb::open_read_only`].
+pub struct ReadOnlyConnection(rusqlite::Connection);
+
+impl Borrow<rusqlite::Connection> for ReadOnlyConnection {
+    fn borrow(&self) -> &rusqlite::Connection {
+        &self.0
+    }
+}
+
 impl<P: consensus::Parameters + Clone> WalletDb<Connection, P> {
     /// Construct a connection to the wallet database stored at the specified path.
     pub fn for_path<F: AsRef<Path>>(path: F, params: P) -> Result<Self, rusqlite::Error> {
         Connection::open(path).and_then(move |conn| {
             rusqlite::vtab::array::load_module(&conn)?;
-            Ok(WalletDb { conn, params })
+            Ok(WalletDb {
+                conn,
+                params,
+                #[cfg(feature = "transparent-inputs")]
+                transparent_gap_limit: DEFAULT_TRANSPARENT_GAP_LIMIT,
+                scan_parallelism: ScanParallelism::default(),
+            })
+        })
+    }
+
+    /// Construct a connection to the encrypted wallet database stored at the specified path,
+    /// using the given passphrase to decrypt it.
+    ///
+    /// If no database exists at the path, one will be created that is encrypted with the given
+    /// passphrase. The passphrase is applied before the database schema is accessed, so
+    /// [`wallet::init::init_wallet_db`] will run migrations against the decrypted database.
+    ///
+    /// Returns [`SqliteClientError::IncorrectKey`] if the passphrase does not decrypt the
+    /// database, including when the database is not encrypted. An unencrypted database can be
+    /// opened with [`WalletDb::for_path`], or encrypted with
+    /// [`wallet::init::encrypt_wallet_db`].
+    #[cfg(feature = "sqlcipher")]
+    pub fn for_path_with_key<F: AsRef<Path>>(
+        path: F,
+        params: P,
+        key: &SecretString,
+    ) -> Result<Self, SqliteClientError> {
+        let conn = Connection::open(path)?;
+        wallet::init::apply_key(&conn, key)?;
+        rusqlite::vtab::array::load_module(&conn)?;
+        Ok(WalletDb {
+            conn,
+            params,
+            #[cfg(feature = "transparent-inputs")]
+            transparent_gap_limit: DEFAULT_TRANSPARENT_GAP_LIMIT,
+            scan_parallelism: ScanParallelism::default(),
         })
     }
 
+    /// Changes the passphrase with which the wallet database is encrypted.
+    ///
+    /// The database must have been opened with [`WalletDb::for_path_with_key`]; an unencrypted
+    /// database must instead be encrypted with [`wallet::init::encrypt_wallet_db`].
+    #[cfg(feature = "sqlcipher")]
+    pub fn rekey(&self, new_key: &SecretString) -> Result<(), SqliteClientError> {
+        self.conn
+            .pragma_update(None, "rekey", new_key.expose_secret())?;
+        Ok(())
+    }
+
+    /// Sets the number of consecutive unused external transparent addresses that the wallet
+    /// derives for each account beyond the last such address known to have received funds.
+    ///
+    /// Defaults to [`DEFAULT_TRANSPARENT_GAP_LIMIT`].
+    #[cfg(feature = "transparent-inputs")]
+    pub fn with_transparent_gap_limit(mut self, gap_limit: u32) -> Self {
+        self.transparent_gap_limit = gap_limit;
+        self
+    }
+
+    /// Sets the parallelism with which [`WalletWrite::put_blocks`] builds the note commitment
+    /// subtrees of the scanned blocks.
+    ///
+    /// This is usually the same setting as is given to
+    /// [`scan_cached_blocks_with_progress`] for trial decryption. Under
+    /// [`ScanParallelism::single_threaded`], the subtrees are built on the calling thread and
+    /// no thread pool is used. Defaults to the global `rayon` thread pool.
+    ///
+    /// [`scan_cached_blocks_with_progress`]: zcash_client_backend::data_api::chain::scan_cached_blocks_with_progress
+    pub fn with_scan_parallelism(mut self, parallelism: ScanParallelism) -> Self {
+        self.scan_parallelism = parallelism;
+        self
+    }
+
     pub fn transactionally<F, A, E: From<rusqlite::Error>>(&mut self, f: F) -> Result<A, E>
     where
         F: FnOnce(&mut WalletDb<SqlTransaction<'_>, P>) -> Result<A, E>,
@@ -202,6 +301,9 @@ impl<P: consensus::Parameters + Clone> WalletDb<Connection, P> {
         let mut wdb = WalletDb {
             conn: SqlTransaction(&tx),
             params: self.params.clone(),
+            #[cfg(feature = "transparent-inputs")]
+            transparent_gap_limit: self.transparent_gap_limit,
+            scan_parallelism: self.scan_parallelism.clone(),
         };
         let result = f(&mut wdb)?;
         tx.commit()?;
@@ -209,6 +311,42 @@ impl<P: consensus::Parameters + Clone> WalletDb<Connection, P> {
     }
 }
 
+impl<P: consensus::Parameters + 'static> WalletDb<ReadOnlyConnection, P> {
+    /// Opens a read-only connection to the wallet database stored at the specified path.
+    ///
+    /// This never runs migrations; instead, it returns [`SqliteClientError::SchemaOutdated`] if
+    /// any of the migrations required by this version of the library have not yet been applied
+    /// to the database, for example by a call to [`wallet::init::init_wallet_db`] from the
+    /// process that owns the writable database.
+    ///
+    /// The database may be concurrently modified by a writer, including one that has put the
+    /// database into WAL mode. Reads made while the writer holds a lock on the database will
+    /// wait for the lock to be released for up to a few seconds before failing.
+    pub fn open_read_only<F: AsRef<Path>>(path: F, params: P) -> Result<Self, SqliteClientError> {
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX
+                | OpenFlags::SQLITE_OPEN_URI,
+        )?;
+        conn.busy_timeout(READ_ONLY_BUSY_TIMEOUT)?;
+        rusqlite::vtab::array::load_module(&conn)?;
+
+        let missing = wallet::init::missing_migrations(&conn, &params)?;
+        if !missing.is_empty() {
+            return Err(SqliteClientError::SchemaOutdated(missing));
+        }
+
+        Ok(WalletDb {
+            conn: ReadOnlyConnection(conn),
+            params,
+            #[cfg(feature = "transparent-inputs")]
+            transparent_gap_limit: DEFAULT_TRANSPARENT_GAP_LIMIT,
+            scan_parallelism: ScanParallelism::default(),
+        })
+    }
+}
+
 impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> InputSource for WalletDb<C, P> {
     type Error = SqliteClientError;
     type NoteRef = ReceivedNoteId;
@@ -342,7 +480,7 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
                     seed,
                     &seed_fingerprint,
                     account_index,
-                    &account.uivk(),
+                    &account.uivk().expect("derived accounts have a UIVK"),
                 )
             } else {
                 Err(SqliteClientError::UnknownZip32Derivation)
@@ -383,7 +521,7 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
                     seed,
                     &seed_fingerprint,
                     account_index,
-                    &account.uivk(),
+                    &account.uivk().expect("derived accounts have a UIVK"),
                 )? {
                     // The seed is relevant to this account.
                     relevant_account_ids.push(account_id);
@@ -423,6 +561,24 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
         wallet::account_birthday(self.conn.borrow(), account).map_err(SqliteClientError::from)
     }
 
+    fn get_account_details(
+        &self,
+        account: AccountId,
+    ) -> Result<Option<AccountDetails<AccountId>>, Self::Error> {
+        wallet::get_account_details(self.conn.borrow(), &self.params, account)
+    }
+
+    fn get_account_name(&self, account: AccountId) -> Result<Option<String>, Self::Error> {
+        wallet::get_account_name(self.conn.borrow(), account)
+    }
+
+    fn get_account_metadata(
+        &self,
+        account: AccountId,
+    ) -> Result<BTreeMap<String, AccountMetadataValue>, Self::Error> {
+        wallet::get_account_metadata(self.conn.borrow(), account)
+    }
+
     fn get_wallet_birthday(&self) -> Result<Option<BlockHeight>, Self::Error> {
         wallet::wallet_birthday(self.conn.borrow()).map_err(SqliteClientError::from)
     }
@@ -441,6 +597,16 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
         )
     }
 
+    fn get_account_pool_balances(
+        &self,
+        min_confirmations: u32,
+    ) -> Result<HashMap<Self::AccountId, AccountPoolBalances>, Self::Error> {
+        wallet::get_account_pool_balances(
+            &self.conn.borrow().unchecked_transaction()?,
+            min_confirmations,
+        )
+    }
+
     fn chain_height(&self) -> Result<Option<BlockHeight>, Self::Error> {
         wallet::scan_queue_extrema(self.conn.borrow())
             .map(|h| h.map(|range| *range.end()))
@@ -484,6 +650,13 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
         wallet::get_min_unspent_height(self.conn.borrow()).map_err(SqliteClientError::from)
     }
 
+    fn get_truncation_height(
+        &self,
+        max_height: BlockHeight,
+    ) -> Result<Option<BlockHeight>, Self::Error> {
+        wallet::get_truncation_height(self.conn.borrow(), max_height)
+    }
+
     fn get_tx_height(&self, txid: TxId) -> Result<Option<BlockHeight>, Self::Error> {
         wallet::get_tx_height(self.conn.borrow(), txid).map_err(SqliteClientError::from)
     }
@@ -539,6 +712,73 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
     ) -> Result<HashMap<TransparentAddress, NonNegativeAmount>, Self::Error> {
         wallet::get_transparent_balances(self.conn.borrow(), &self.params, account, max_height)
     }
+
+    #[cfg(feature = "transparent-inputs")]
+    fn get_transparent_address_usage(
+        &self,
+        account: AccountId,
+    ) -> Result<Vec<TransparentAddressUsage>, Self::Error> {
+        wallet::transparent::get_address_usage(
+            self.conn.borrow(),
+            &self.params,
+            account,
+            TransparentKeyScope::EXTERNAL,
+        )
+    }
+
+    #[cfg(feature = "transparent-inputs")]
+    fn get_ephemeral_address_usage(
+        &self,
+        account: AccountId,
+    ) -> Result<Vec<TransparentAddressUsage>, Self::Error> {
+        wallet::transparent::get_address_usage(
+            self.conn.borrow(),
+            &self.params,
+            account,
+            TransparentKeyScope::EPHEMERAL,
+        )
+    }
+
+    fn get_exchange_rate_nearest(
+        &self,
+        currency: Currency,
+        time: time::OffsetDateTime,
+        max_distance: time::Duration,
+    ) -> Result<Option<ExchangeRateObservation>, Self::Error> {
+        wallet::get_exchange_rate_nearest(self.conn.borrow(), currency, time, max_distance)
+    }
+
+    fn get_drafts(
+        &self,
+        account: Self::AccountId,
+    ) -> Result<Vec<(DraftId, DraftPayment)>, Self::Error> {
+        wallet::get_drafts(self.conn.borrow(), &self.params, account)
+    }
+
+    fn get_draft(
+        &self,
+        draft_id: DraftId,
+    ) -> Result<Option<(Self::AccountId, DraftPayment)>, Self::Error> {
+        wallet::get_draft(self.conn.borrow(), &self.params, draft_id)
+    }
+
+    fn get_transactions(
+        &self,
+        account: Self::AccountId,
+        filter: &TransactionFilter,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<TransactionSummary>, Self::Error> {
+        wallet::get_transactions(self.conn.borrow(), account, filter, offset, limit)
+    }
+
+    fn find_memos(
+        &self,
+        account: Self::AccountId,
+        query: &MemoQuery,
+    ) -> Result<Vec<(NoteId, MemoBytes)>, Self::Error> {
+        wallet::find_memos(self.conn.borrow(), account, query)
+    }
 }
 
 impl<P: consensus::Parameters> WalletWrite for WalletDb<rusqlite::Connection, P> {
@@ -567,32 +807,79 @@ impl<P: consensus::Parameters> WalletWrite for WalletDb<rusqlite::Connection, P>
                     .map_err(|_| SqliteClientError::KeyDerivationError(account_index))?;
             let ufvk = usk.to_unified_full_viewing_key();
 
-            let mut account_id;
-
-            if transparentkey.expose_secret().len() != 32 {
-                account_id = wallet::add_account(
+            let account_id = if transparentkey.expose_secret().len() != 32 {
+                wallet::add_account(
                     wdb.conn.0,
                     &wdb.params,
                     AccountSource::Derived {
-                       seed_fingerprint,
+                        seed_fingerprint,
                         account_index,
                     },
                     wallet::ViewingKey::Full(Box::new(ufvk)),
                     birthday,
-               )?;
+                )?
             } else {
-                account_id = wallet::add_account(
+                wallet::add_account(
                     wdb.conn.0,
                     &wdb.params,
                     AccountSource::Imported,
                     wallet::ViewingKey::Full(Box::new(ufvk)),
                     birthday,
-               )?;
-            } 
+                )?
+            };
+            #[cfg(feature = "transparent-inputs")]
+            wallet::transparent::extend_address_window(
+                wdb.conn.0,
+                &wdb.params,
+                account_id,
+                wdb.transparent_gap_limit,
+            )?;
+
             Ok((account_id, usk))
         })
     }
 
+    fn import_account_uivk(
+        &mut self,
+        uivk: &UnifiedIncomingViewingKey,
+        birthday: &AccountBirthday,
+    ) -> Result<AccountId, Self::Error> {
+        self.transactionally(|wdb| {
+            let account_id = wallet::add_account(
+                wdb.conn.0,
+                &wdb.params,
+                AccountSource::Imported,
+                wallet::ViewingKey::Incoming(Box::new(uivk.clone())),
+                birthday,
+            )?;
+            #[cfg(feature = "transparent-inputs")]
+            wallet::transparent::extend_address_window(
+                wdb.conn.0,
+                &wdb.params,
+                account_id,
+                wdb.transparent_gap_limit,
+            )?;
+
+            Ok(account_id)
+        })
+    }
+
+    #[cfg(feature = "transparent-inputs")]
+    fn import_standalone_transparent_address(
+        &mut self,
+        address: &TransparentAddress,
+        birthday_height: BlockHeight,
+    ) -> Result<AccountId, Self::Error> {
+        self.transactionally(|wdb| {
+            wallet::transparent::add_standalone_account(
+                wdb.conn.0,
+                &wdb.params,
+                address,
+                birthday_height,
+            )
+        })
+    }
+
     fn get_next_available_address(
         &mut self,
         account: AccountId,
@@ -624,11 +911,35 @@ impl<P: consensus::Parameters> WalletWrite for WalletDb<rusqlite::Connection, P>
 
                     Ok(Some(addr))
                 }
-                None => Ok(None),
+                None => match wallet::get_account(wdb.conn.0, &wdb.params, account)? {
+                    // Standalone transparent accounts have no keys from which to derive
+                    // Unified Addresses.
+                    Some(account) if account.source() == AccountSource::StandaloneTransparent => {
+                        Err(AddressGenerationError::ShieldedReceiverRequired.into())
+                    }
+                    _ => Ok(None),
+                },
             },
         )
     }
 
+    fn set_account_name(
+        &mut self,
+        account: AccountId,
+        name: Option<&str>,
+    ) -> Result<(), Self::Error> {
+        self.transactionally(|wdb| wallet::set_account_name(wdb.conn.0, account, name))
+    }
+
+    fn set_account_metadata(
+        &mut self,
+        account: AccountId,
+        key: &str,
+        value: Option<AccountMetadataValue>,
+    ) -> Result<(), Self::Error> {
+        self.transactionally(|wdb| wallet::set_account_metadata(wdb.conn.0, account, key, value))
+    }
+
     fn update_chain_tip(&mut self, tip_height: BlockHeight) -> Result<(), Self::Error> {
         let tx = self.conn.transaction()?;
         wallet::scanning::update_chain_tip(&tx, &self.params, tip_height)?;
@@ -643,416 +954,15 @@ impl<P: consensus::Parameters> WalletWrite for WalletDb<rusqlite::Connection, P>
         from_state: &ChainState,
         blocks: Vec<ScannedBlock<Self::AccountId>>,
     ) -> Result<(), Self::Error> {
-        struct BlockPositions {
-            height: BlockHeight,
-            sapling_start_position: Position,
-            #[cfg(feature = "orchard")]
-            orchard_start_position: Position,
-        }
-
-        self.transactionally(|wdb| {
-            let start_positions = blocks.first().map(|block| BlockPositions {
-                height: block.height(),
-                sapling_start_position: Position::from(
-                    u64::from(block.sapling().final_tree_size())
-                        - u64::try_from(block.sapling().commitments().len()).unwrap(),
-                ),
-                #[cfg(feature = "orchard")]
-                orchard_start_position: Position::from(
-                    u64::from(block.orchard().final_tree_size())
-                        - u64::try_from(block.orchard().commitments().len()).unwrap(),
-                ),
-            });
-            let mut sapling_commitments = vec![];
-            #[cfg(feature = "orchard")]
-            let mut orchard_commitments = vec![];
-            let mut last_scanned_height = None;
-            let mut note_positions = vec![];
-            for block in blocks.into_iter() {
-                if last_scanned_height
-                    .iter()
-                    .any(|prev| block.height() != *prev + 1)
-                {
-                    return Err(SqliteClientError::NonSequentialBlocks);
-                }
-
-                // Insert the block into the database.
-                wallet::put_block(
-                    wdb.conn.0,
-                    block.height(),
-                    block.block_hash(),
-                    block.block_time(),
-                    block.sapling().final_tree_size(),
-                    block.sapling().commitments().len().try_into().unwrap(),
-                    #[cfg(feature = "orchard")]
-                    block.orchard().final_tree_size(),
-                    #[cfg(feature = "orchard")]
-                    block.orchard().commitments().len().try_into().unwrap(),
-                )?;
-
-                for tx in block.transactions() {
-                    let tx_row = wallet::put_tx_meta(wdb.conn.0, tx, block.height())?;
-
-                    // Mark notes as spent and remove them from the scanning cache
-                    for spend in tx.sapling_spends() {
-                        wallet::sapling::mark_sapling_note_spent(wdb.conn.0, tx_row, spend.nf())?;
-                    }
-                    #[cfg(feature = "orchard")]
-                    for spend in tx.orchard_spends() {
-                        wallet::orchard::mark_orchard_note_spent(wdb.conn.0, tx_row, spend.nf())?;
-                    }
-
-                    for output in tx.sapling_outputs() {
-                        // Check whether this note was spent in a later block range that
-                        // we previously scanned.
-                        let spent_in = output
-                            .nf()
-                            .map(|nf| {
-                                wallet::query_nullifier_map::<_, Scope>(
-                                    wdb.conn.0,
-                                    ShieldedProtocol::Sapling,
-                                    nf,
-                                )
-                            })
-                            .transpose()?
-                            .flatten();
-
-                        wallet::sapling::put_received_note(wdb.conn.0, output, tx_row, spent_in)?;
-                    }
-                    #[cfg(feature = "orchard")]
-                    for output in tx.orchard_outputs() {
-                        // Check whether this note was spent in a later block range that
-                        // we previously scanned.
-                        let spent_in = output
-                            .nf()
-                            .map(|nf| {
-                                wallet::query_nullifier_map::<_, Scope>(
-                                    wdb.conn.0,
-                                    ShieldedProtocol::Orchard,
-                                    &nf.to_bytes(),
-                                )
-                            })
-                            .transpose()?
-                            .flatten();
-
-                        wallet::orchard::put_received_note(wdb.conn.0, output, tx_row, spent_in)?;
-                    }
-                }
-
-                // Insert the new nullifiers from this block into the nullifier map.
-                wallet::insert_nullifier_map(
-                    wdb.conn.0,
-                    block.height(),
-                    ShieldedProtocol::Sapling,
-                    block.sapling().nullifier_map(),
-                )?;
-                #[cfg(feature = "orchard")]
-                wallet::insert_nullifier_map(
-                    wdb.conn.0,
-                    block.height(),
-                    ShieldedProtocol::Orchard,
-                    &block
-                        .orchard()
-                        .nullifier_map()
-                        .iter()
-                        .map(|(txid, idx, nfs)| {
-                            (*txid, *idx, nfs.iter().map(|nf| nf.to_bytes()).collect())
-                        })
-                        .collect::<Vec<_>>(),
-                )?;
-
-                note_positions.extend(block.transactions().iter().flat_map(|wtx| {
-                    let iter = wtx.sapling_outputs().iter().map(|out| {
-                        (
-                            ShieldedProtocol::Sapling,
-                            out.note_commitment_tree_position(),
-                        )
-                    });
-                    #[cfg(feature = "orchard")]
-                    let iter = iter.chain(wtx.orchard_outputs().iter().map(|out| {
-                        (
-                            ShieldedProtocol::Orchard,
-                            out.note_commitment_tree_position(),
-                        )
-                    }));
-
-                    iter
-                }));
-
-                last_scanned_height = Some(block.height());
-                let block_commitments = block.into_commitments();
-                trace!(
-                    "Sapling commitments for {:?}: {:?}",
-                    last_scanned_height,
-                    block_commitments
-                        .sapling
-                        .iter()
-                        .map(|(_, r)| *r)
-                        .collect::<Vec<_>>()
-                );
-                #[cfg(feature = "orchard")]
-                trace!(
-                    "Orchard commitments for {:?}: {:?}",
-                    last_scanned_height,
-                    block_commitments
-                        .orchard
-                        .iter()
-                        .map(|(_, r)| *r)
-                        .collect::<Vec<_>>()
-                );
-
-                sapling_commitments.extend(block_commitments.sapling.into_iter().map(Some));
-                #[cfg(feature = "orchard")]
-                orchard_commitments.extend(block_commitments.orchard.into_iter().map(Some));
-            }
-
-            // Prune the nullifier map of entries we no longer need.
-            if let Some(meta) = wdb.block_fully_scanned()? {
-                wallet::prune_nullifier_map(
-                    wdb.conn.0,
-                    meta.block_height().saturating_sub(PRUNING_DEPTH),
-                )?;
-            }
-
-            // We will have a start position and a last scanned height in all cases where
-            // `blocks` is non-empty.
-            if let Some((start_positions, last_scanned_height)) =
-                start_positions.zip(last_scanned_height)
-            {
-                // Create subtrees from the note commitments in parallel.
-                const CHUNK_SIZE: usize = 1024;
-                let sapling_subtrees = sapling_commitments
-                    .par_chunks_mut(CHUNK_SIZE)
-                    .enumerate()
-                    .filter_map(|(i, chunk)| {
-                        let start =
-                            start_positions.sapling_start_position + (i * CHUNK_SIZE) as u64;
-                        let end = start + chunk.len() as u64;
-
-                        shardtree::LocatedTree::from_iter(
-                            start..end,
-                            SAPLING_SHARD_HEIGHT.into(),
-                            chunk.iter_mut().map(|n| n.take().expect("always Some")),
-                        )
-                    })
-                    .map(|res| (res.subtree, res.checkpoints))
-                    .collect::<Vec<_>>();
-
-                #[cfg(feature = "orchard")]
-                let orchard_subtrees = orchard_commitments
-                    .par_chunks_mut(CHUNK_SIZE)
-                    .enumerate()
-                    .filter_map(|(i, chunk)| {
-                        let start =
-                            start_positions.orchard_start_position + (i * CHUNK_SIZE) as u64;
-                        let end = start + chunk.len() as u64;
-
-                        shardtree::LocatedTree::from_iter(
-                            start..end,
-                            ORCHARD_SHARD_HEIGHT.into(),
-                            chunk.iter_mut().map(|n| n.take().expect("always Some")),
-                        )
-                    })
-                    .map(|res| (res.subtree, res.checkpoints))
-                    .collect::<Vec<_>>();
-
-                // Collect the complete set of Sapling checkpoints
-		// TODO: check if this sapling-based code should actually be behind this
-		// conditional compilation cfg macro
-                #[cfg(feature = "orchard")]
-                let sapling_checkpoint_positions: BTreeMap<BlockHeight, Position> =
-                    sapling_subtrees
-                        .iter()
-                        .flat_map(|(_, checkpoints)| checkpoints.iter())
-                        .map(|(k, v)| (*k, *v))
-                        .collect();
-
-                #[cfg(feature = "orchard")]
-                let orchard_checkpoint_positions: BTreeMap<BlockHeight, Position> =
-                    orchard_subtrees
-                        .iter()
-                        .flat_map(|(_, checkpoints)| checkpoints.iter())
-                        .map(|(k, v)| (*k, *v))
-                        .collect();
-
-                #[cfg(feature = "orchard")]
-                fn ensure_checkpoints<
-                    'a,
-                    H,
-                    I: Iterator<Item = &'a BlockHeight>,
-                    const DEPTH: u8,
-                >(
-                    // An iterator of checkpoints heights for which we wish to ensure that
-                    // checkpoints exists.
-                    ensure_heights: I,
-                    // The map of checkpoint positions from which we will draw note commitment tree
-                    // position information for the newly created checkpoints.
-                    existing_checkpoint_positions: &BTreeMap<BlockHeight, Position>,
-                    // The frontier whose position will be used for an inserted checkpoint when
-                    // there is no preceding checkpoint in existing_checkpoint_positions.
-                    state_final_tree: &Frontier<H, DEPTH>,
-                ) -> Vec<(BlockHeight, Checkpoint)> {
-                    ensure_heights
-                        .flat_map(|ensure_height| {
-                            existing_checkpoint_positions
-                                .range::<BlockHeight, _>(..=*ensure_height)
-                                .last()
-                                .map_or_else(
-                                    || {
-                                        Some((
-                                            *ensure_height,
-                                            state_final_tree
-                                                .value()
-                                                .map_or_else(Checkpoint::tree_empty, |t| {
-                                                    Checkpoint::at_position(t.position())
-                                                }),
-                                        ))
-                                    },
-                                    |(existing_checkpoint_height, position)| {
-                                        if *existing_checkpoint_height < *ensure_height {
-                                            Some((
-                                                *ensure_height,
-                                                Checkpoint::at_position(*position),
-                                            ))
-                                        } else {
-                                            // The checkpoint already exists, so we don't need to
-                                            // do anything.
-                                            None
-                                        }
-                                    },
-                                )
-                                .into_iter()
-                        })
-                        .collect::<Vec<_>>()
-                }
-
-                #[cfg(feature = "orchard")]
-                let (missing_sapling_checkpoints, missing_orchard_checkpoints) = (
-                    ensure_checkpoints(
-                        orchard_checkpoint_positions.keys(),
-                        &sapling_checkpoint_positions,
-                        from_state.final_sapling_tree(),
-                    ),
-                    ensure_checkpoints(
-                        sapling_checkpoint_positions.keys(),
-                        &orchard_checkpoint_positions,
-                        from_state.final_orchard_tree(),
-                    ),
-                );
-
-                // Update the Sapling note commitment tree with all newly read note commitments
-                {
-                    let mut sapling_subtrees_iter = sapling_subtrees.into_iter();
-                    wdb.with_sapling_tree_mut::<_, _, Self::Error>(|sapling_tree| {
-                        debug!(
-                            "Sapling initial tree size at {:?}: {:?}",
-                            from_state.block_height(),
-                            from_state.final_sapling_tree().tree_size()
-                        );
-                        sapling_tree.insert_frontier(
-                            from_state.final_sapling_tree().clone(),
-                            Retention::Checkpoint {
-                                id: from_state.block_height(),
-                                is_marked: false,
-                            },
-                        )?;
-
-                        for (tree, checkpoints) in &mut sapling_subtrees_iter {
-                            sapling_tree.insert_tree(tree, checkpoints)?;
-                        }
-
-                        // Ensure we have a Sapling checkpoint for each checkpointed Orchard block height.
-                        // We skip all checkpoints below the minimum retained checkpoint in the
-                        // Sapling tree, because branches below this height may be pruned.
-                        #[cfg(feature = "orchard")]
-                        {
-                            let min_checkpoint_height = sapling_tree
-                                .store()
-                                .min_checkpoint_id()
-                                .map_err(ShardTreeError::Storage)?
-                                .expect(
-                                    "At least one checkpoint was inserted (by insert_frontier)",
-                                );
-
-                            for (height, checkpoint) in &missing_sapling_checkpoints {
-                                if *height > min_checkpoint_height {
-                                    sapling_tree
-                                        .store_mut()
-                                        .add_checkpoint(*height, checkpoint.clone())
-                                        .map_err(ShardTreeError::Storage)?;
-                                }
-                            }
-                        }
-
-                        Ok(())
-                    })?;
-                }
-
-                // Update the Orchard note commitment tree with all newly read note commitments
-                #[cfg(feature = "orchard")]
-                {
-                    let mut orchard_subtrees = orchard_subtrees.into_iter();
-                    wdb.with_orchard_tree_mut::<_, _, Self::Error>(|orchard_tree| {
-                        debug!(
-                            "Orchard initial tree size at {:?}: {:?}",
-                            from_state.block_height(),
-                            from_state.final_orchard_tree().tree_size()
-                        );
-                        orchard_tree.insert_frontier(
-                            from_state.final_orchard_tree().clone(),
-                            Retention::Checkpoint {
-                                id: from_state.block_height(),
-                                is_marked: false,
-                            },
-                        )?;
-
-                        for (tree, checkpoints) in &mut orchard_subtrees {
-                            orchard_tree.insert_tree(tree, checkpoints)?;
-                        }
-
-                        // Ensure we have an Orchard checkpoint for each checkpointed Sapling block height.
-                        // We skip all checkpoints below the minimum retained checkpoint in the
-                        // Orchard tree, because branches below this height may be pruned.
-                        {
-                            let min_checkpoint_height = orchard_tree
-                                .store()
-                                .min_checkpoint_id()
-                                .map_err(ShardTreeError::Storage)?
-                                .expect(
-                                    "At least one checkpoint was inserted (by insert_frontier)",
-                                );
-
-                            for (height, checkpoint) in &missing_orchard_checkpoints {
-                                if *height > min_checkpoint_height {
-                                    debug!(
-                                        "Adding missing Orchard checkpoint for height: {:?}: {:?}",
-                                        height,
-                                        checkpoint.position()
-                                    );
-                                    orchard_tree
-                                        .store_mut()
-                                        .add_checkpoint(*height, checkpoint.clone())
-                                        .map_err(ShardTreeError::Storage)?;
-                                }
-                            }
-                        }
-                        Ok(())
-                    })?;
-                }
-
-                wallet::scanning::scan_complete(
-                    wdb.conn.0,
-                    &wdb.params,
-                    Range {
-                        start: start_positions.height,
-                        end: last_scanned_height + 1,
-                    },
-                    &note_positions,
-                )?;
-            }
+        self.transactionally(|wdb| wallet::put_blocks(wdb, from_state, blocks, false))
+    }
 
-            Ok(())
-        })
+    fn put_spend_scanned_blocks(
+        &mut self,
+        from_state: &ChainState,
+        blocks: Vec<ScannedBlock<Self::AccountId>>,
+    ) -> Result<(), Self::Error> {
+        self.transactionally(|wdb| wallet::put_blocks(wdb, from_state, blocks, true))
     }
 
     fn put_received_transparent_utxo(
@@ -1060,7 +970,14 @@ impl<P: consensus::Parameters> WalletWrite for WalletDb<rusqlite::Connection, P>
         _output: &WalletTransparentOutput,
     ) -> Result<Self::UtxoRef, Self::Error> {
         #[cfg(feature = "transparent-inputs")]
-        return wallet::put_received_transparent_utxo(&self.conn, &self.params, _output);
+        return self.transactionally(|wdb| {
+            wallet::put_received_transparent_utxo(
+                wdb.conn.0,
+                &wdb.params,
+                _output,
+                wdb.transparent_gap_limit,
+            )
+        });
 
         #[cfg(not(feature = "transparent-inputs"))]
         panic!(
@@ -1387,9 +1304,108 @@ impl<P: consensus::Parameters> WalletWrite for WalletDb<rusqlite::Connection, P>
         })
     }
 
-    fn truncate_to_height(&mut self, block_height: BlockHeight) -> Result<(), Self::Error> {
+    fn truncate_to_height(&mut self, max_height: BlockHeight) -> Result<BlockHeight, Self::Error> {
+        self.transactionally(|wdb| wallet::truncate_to_height(wdb.conn.0, &wdb.params, max_height))
+    }
+
+    fn insert_exchange_rate(
+        &mut self,
+        observation: &ExchangeRateObservation,
+    ) -> Result<(), Self::Error> {
+        wallet::insert_exchange_rate(self.conn.borrow(), observation)
+    }
+
+    fn save_draft(
+        &mut self,
+        account: Self::AccountId,
+        draft: &DraftPayment,
+    ) -> Result<DraftId, Self::Error> {
+        wallet::save_draft(self.conn.borrow(), &self.params, account, draft)
+    }
+
+    fn delete_draft(&mut self, draft_id: DraftId) -> Result<(), Self::Error> {
+        wallet::delete_draft(self.conn.borrow(), draft_id)
+    }
+
+    fn set_note_spend_policy(&mut self, note_id: NoteId, locked: bool) -> Result<(), Self::Error> {
+        wallet::set_note_spend_policy(self.conn.borrow(), note_id, locked)
+    }
+
+    #[cfg(feature = "transparent-inputs")]
+    fn set_utxo_spend_policy(
+        &mut self,
+        outpoint: &OutPoint,
+        locked: bool,
+    ) -> Result<(), Self::Error> {
+        wallet::set_utxo_spend_policy(self.conn.borrow(), outpoint, locked)
+    }
+
+    #[cfg(feature = "transparent-inputs")]
+    fn reserve_next_transparent_addresses(
+        &mut self,
+        account: AccountId,
+        n: u32,
+    ) -> Result<Vec<(TransparentAddress, TransparentAddressMetadata)>, Self::Error> {
+        self.transactionally(|wdb| {
+            wallet::transparent::reserve_next_addresses(
+                wdb.conn.0,
+                &wdb.params,
+                account,
+                TransparentKeyScope::EXTERNAL,
+                n,
+                wdb.transparent_gap_limit,
+            )
+        })
+    }
+
+    #[cfg(feature = "transparent-inputs")]
+    fn reserve_next_ephemeral_addresses(
+        &mut self,
+        account: AccountId,
+        n: u32,
+    ) -> Result<Vec<(TransparentAddress, TransparentAddressMetadata)>, Self::Error> {
+        self.transactionally(|wdb| {
+            wallet::transparent::reserve_next_addresses(
+                wdb.conn.0,
+                &wdb.params,
+                account,
+                TransparentKeyScope::EPHEMERAL,
+                n,
+                wdb.transparent_gap_limit,
+            )
+        })
+    }
+
+    #[cfg(feature = "transparent-inputs")]
+    fn mark_transparent_address_used(
+        &mut self,
+        address: &TransparentAddress,
+        height: BlockHeight,
+    ) -> Result<(), Self::Error> {
+        self.transactionally(|wdb| {
+            wallet::transparent::mark_address_used(
+                wdb.conn.0,
+                &wdb.params,
+                address,
+                height,
+                wdb.transparent_gap_limit,
+            )?
+            .map(|_| ())
+            .ok_or(SqliteClientError::AddressNotRecognized(*address))
+        })
+    }
+}
+
+impl<P: consensus::Parameters> WalletSnapshotStore for WalletDb<rusqlite::Connection, P> {
+    type Error = SqliteClientError;
+
+    fn read_snapshot(&self) -> Result<WalletSnapshot, Self::Error> {
+        wallet::snapshot::read_snapshot(&self.conn, &self.params)
+    }
+
+    fn restore_snapshot(&mut self, snapshot: WalletSnapshot) -> Result<(), Self::Error> {
         self.transactionally(|wdb| {
-            wallet::truncate_to_height(wdb.conn.0, &wdb.params, block_height)
+            wallet::snapshot::restore_snapshot(wdb.conn.0, &wdb.params, snapshot)
         })
     }
 }
@@ -1641,11 +1657,12 @@ impl BlockSource for BlockDb {
 ///     written to disk.
 /// * The cache can then be scanned using the [`BlockSource`] implementation, providing the
 ///   wallet's synced-to-height as a starting point.
-/// * When part of the cache is no longer needed:
-///   * The caller determines some height `H` that is the earliest block data it needs to preserve.
-///     This might be determined based on where the wallet is fully-synced to, or other heuristics.
-///   * The caller searches the defined filesystem folder for all files beginning in `HEIGHT-*` where
-///     `HEIGHT < H`, and deletes those files.
+/// * When part of the cache is no longer needed, the caller invokes
+///   [`FsBlockDb::truncate_cached_blocks_below`] with a safety margin of blocks below the
+///   wallet's fully-scanned height to retain in case of a chain reorganization. This deletes both
+///   the block files and their metadata. If the wallet is later rewound below the pruned height,
+///   scanning will fail with [`FsBlockDbError::BlocksPruned`] and the caller must refetch the
+///   pruned blocks before scanning them again.
 ///
 /// Note: This API is unstable, and may change in the future. In particular, the [`BlockSource`]
 /// API and the above description currently assume that scanning is performed in linear block
@@ -1669,6 +1686,9 @@ pub enum FsBlockDbError {
     InvalidBlockPath(PathBuf),
     CorruptedData(String),
     CacheMiss(BlockHeight),
+    /// The block at the first height was requested, but blocks up to and including the second
+    /// height have been pruned from the cache.
+    BlocksPruned(BlockHeight, BlockHeight),
 }
 
 #[cfg(feature = "unstable")]
@@ -1773,6 +1793,48 @@ impl FsBlockDb {
             block_height,
         )?)
     }
+
+    /// Deletes the files and metadata of cached blocks that the wallet no longer needs.
+    ///
+    /// Blocks are retained if they are within `safety_margin` blocks of the wallet's
+    /// fully-scanned height, so that the wallet can recover from a chain reorganization of up to
+    /// that depth without refetching blocks, or if they are within a range of blocks that the
+    /// wallet has not yet scanned. Returns the number and total size of the blocks that were
+    /// deleted.
+    pub fn truncate_cached_blocks_below<DbT: WalletRead>(
+        &self,
+        wallet_db: &DbT,
+        safety_margin: u32,
+    ) -> Result<chain::PrunedBlocks, data_api::chain::error::Error<DbT::Error, FsBlockDbError>>
+    {
+        let fully_scanned_height = wallet_db
+            .block_fully_scanned()
+            .map_err(data_api::chain::error::Error::Wallet)?
+            .map(|meta| meta.block_height());
+        let unscanned_start = wallet_db
+            .suggest_scan_ranges()
+            .map_err(data_api::chain::error::Error::Wallet)?
+            .iter()
+            .map(|range| range.block_range().start)
+            .min();
+
+        // Nothing can be pruned if the wallet has not scanned the genesis block.
+        let prune_height = fully_scanned_height
+            .and_then(|h| u32::from(h).checked_sub(safety_margin))
+            .and_then(|h| match unscanned_start {
+                Some(start) => u32::from(start)
+                    .checked_sub(1)
+                    .map(|last_scanned| std::cmp::min(h, last_scanned)),
+                None => Some(h),
+            })
+            .map(BlockHeight::from);
+
+        match prune_height {
+            Some(h) => chain::blockmetadb_prune_to_height(&self.conn, &self.blocks_dir, h)
+                .map_err(data_api::chain::error::Error::BlockSource),
+            None => Ok(chain::PrunedBlocks::default()),
+        }
+    }
 }
 
 #[cfg(feature = "unstable")]
@@ -1840,6 +1902,13 @@ impl std::fmt::Display for FsBlockDbError {
                     height
                 )
             }
+            FsBlockDbError::BlocksPruned(height, pruned_height) => {
+                write!(
+                    f,
+                    "Blocks at height {} have been pruned from the block cache (up to height {}) and must be refetched",
+                    height, pruned_height
+                )
+            }
         }
     }
 }
@@ -1850,11 +1919,20 @@ extern crate assert_matches;
 
 #[cfg(test)]
 mod tests {
+    use std::{collections::BTreeMap, convert::Infallible};
+
+    use assert_matches::assert_matches;
     use secrecy::SecretVec;
-    use zcash_client_backend::data_api::{WalletRead, WalletWrite};
-    use zcash_primitives::block::BlockHash;
+    use zcash_client_backend::{
+        data_api::{WalletRead, WalletWrite},
+        sync::{self, reconcile_chain_view, ReconcileAction},
+    };
+    use zcash_primitives::{block::BlockHash, consensus::BlockHeight};
 
-    use crate::{testing::TestBuilder, AccountId, DEFAULT_UA_REQUEST};
+    use crate::{
+        testing::{lightwalletd::MockLightwalletd, TestBuilder},
+        AccountId, DEFAULT_UA_REQUEST,
+    };
 
     #[cfg(feature = "unstable")]
     use {
@@ -1871,7 +1949,7 @@ mod tests {
 
         assert!({
             st.wallet()
-                .validate_seed(account.account_id(), st.test_seed().unwrap())
+                .validate_seed(account.account_id(), &SecretVec::new(vec![]), st.test_seed().unwrap())
                 .unwrap()
         });
 
@@ -1879,14 +1957,14 @@ mod tests {
         assert!({
             let wrong_account_index = AccountId(3);
             !st.wallet()
-                .validate_seed(wrong_account_index, st.test_seed().unwrap())
+                .validate_seed(wrong_account_index, &SecretVec::new(vec![]), st.test_seed().unwrap())
                 .unwrap()
         });
 
         // check that passing an invalid seed results in a failure
         assert!({
             !st.wallet()
-                .validate_seed(account.account_id(), &SecretVec::new(vec![1u8; 32]))
+                .validate_seed(account.account_id(), &SecretVec::new(vec![]), &SecretVec::new(vec![1u8; 32]))
                 .unwrap()
         });
     }
@@ -1919,6 +1997,59 @@ mod tests {
         assert_eq!(addr2, addr2_cur);
     }
 
+    #[test]
+    fn open_read_only() {
+        use tempfile::NamedTempFile;
+        use zcash_primitives::transaction::components::amount::NonNegativeAmount;
+
+        use crate::{error::SqliteClientError, testing::AddressType, WalletDb};
+
+        let mut st = TestBuilder::new()
+            .with_block_cache()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let account = st.test_account().cloned().unwrap();
+        let dfvk = st.test_account_sapling().unwrap();
+
+        // Put the writer into WAL mode before the reader is opened.
+        let journal_mode: String = st
+            .wallet()
+            .conn
+            .query_row("PRAGMA journal_mode = WAL", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(journal_mode, "wal");
+
+        let reader = WalletDb::open_read_only(st.wallet_path(), st.network()).unwrap();
+        assert_eq!(
+            reader.get_account_ids().unwrap(),
+            vec![account.account_id()]
+        );
+        assert_eq!(reader.get_wallet_summary(0).unwrap(), None);
+
+        // A note received through the writer is visible through the reader.
+        let value = NonNegativeAmount::const_from_u64(50000);
+        let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+        st.scan_cached_blocks(h, 1);
+
+        let summary = reader.get_wallet_summary(0).unwrap().unwrap();
+        assert_eq!(summary.fully_scanned_height(), h);
+        assert_eq!(
+            summary
+                .account_balances()
+                .get(&account.account_id())
+                .unwrap()
+                .total(),
+            value
+        );
+
+        // A database to which migrations have not been applied cannot be opened.
+        let uninitialized = NamedTempFile::new().unwrap();
+        assert_matches!(
+            WalletDb::open_read_only(uninitialized.path(), st.network()).map(|_| ()),
+            Err(SqliteClientError::SchemaOutdated(missing)) if !missing.is_empty()
+        );
+    }
+
     #[cfg(feature = "transparent-inputs")]
     #[test]
     fn transparent_receivers() {
@@ -1929,7 +2060,7 @@ mod tests {
             .build();
         let account = st.test_account().unwrap();
         let ufvk = account.usk().to_unified_full_viewing_key();
-        let (taddr, _) = account.usk().default_transparent_address();
+        let taddr = account.usk().default_transparent_address();
 
         let receivers = st
             .wallet()
@@ -1995,4 +2126,207 @@ mod tests {
         assert_eq!(st.cache().find_block(h2).unwrap(), None);
         assert_eq!(st.cache().find_block(h2 + 1).unwrap(), None);
     }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn fsblockdb_truncate_cached_blocks_below() {
+        use zcash_client_backend::data_api::chain::error::Error;
+
+        use crate::FsBlockDbError;
+
+        let mut st = TestBuilder::new()
+            .with_fs_block_cache()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let dfvk = st.test_account_sapling().unwrap();
+
+        // Nothing can be pruned before the wallet has scanned any blocks.
+        let pruned = st
+            .cache()
+            .truncate_cached_blocks_below(st.wallet(), 5)
+            .unwrap();
+        assert_eq!(pruned.block_count(), 0);
+
+        let (h1, meta1, _) = st.generate_next_block(
+            &dfvk,
+            AddressType::DefaultExternal,
+            NonNegativeAmount::const_from_u64(5),
+        );
+        let mut metas = vec![meta1];
+        for _ in 1..20 {
+            let (_, meta) = st.generate_empty_block();
+            metas.push(meta);
+        }
+        st.cache().write_block_metadata(&metas).unwrap();
+        st.scan_cached_blocks(h1, 20);
+
+        // Blocks within the safety margin of the fully-scanned height are retained.
+        let h20 = h1 + 19;
+        let prune_height = h20 - 5;
+        let pruned = st
+            .cache()
+            .truncate_cached_blocks_below(st.wallet(), 5)
+            .unwrap();
+        assert_eq!(pruned.block_count(), 15);
+        assert!(pruned.byte_count() > 0);
+        assert_eq!(st.cache().find_block(prune_height).unwrap(), None);
+        assert_eq!(
+            st.cache().find_block(prune_height + 1).unwrap(),
+            Some(metas[15])
+        );
+        assert!(!metas[0].block_file_path(&st.cache().blocks_dir).exists());
+        assert!(metas[15].block_file_path(&st.cache().blocks_dir).exists());
+
+        // Pruning again is a no-op.
+        let pruned = st
+            .cache()
+            .truncate_cached_blocks_below(st.wallet(), 5)
+            .unwrap();
+        assert_eq!(pruned.block_count(), 0);
+
+        // The retained blocks can still be scanned.
+        st.scan_cached_blocks(prune_height + 1, 5);
+
+        // Scanning pruned blocks reports that they must be refetched.
+        assert_matches!(
+            st.try_scan_cached_blocks(h1 + 5, 5),
+            Err(Error::BlockSource(FsBlockDbError::BlocksPruned(h, ph)))
+                if h == h1 + 5 && ph == prune_height
+        );
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn fsblockdb_rewind_within_safety_margin() {
+        let mut st = TestBuilder::new()
+            .with_fs_block_cache()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let dfvk = st.test_account_sapling().unwrap();
+
+        // Each block receives a note, so that the wallet can rewind to any of them.
+        let metas = (0..20)
+            .map(|_| {
+                st.generate_next_block(
+                    &dfvk,
+                    AddressType::DefaultExternal,
+                    NonNegativeAmount::const_from_u64(5),
+                )
+                .1
+            })
+            .collect::<Vec<_>>();
+        st.cache().write_block_metadata(&metas).unwrap();
+        let h1 = metas[0].height;
+        st.scan_cached_blocks(h1, 20);
+
+        let h20 = h1 + 19;
+        let pruned = st
+            .cache()
+            .truncate_cached_blocks_below(st.wallet(), 10)
+            .unwrap();
+        assert_eq!(pruned.block_count(), 10);
+
+        // A rewind that stays within the safety margin can be rescanned from the cache, without
+        // refetching any blocks.
+        let rewind_height = st.wallet_mut().truncate_to_height(h20 - 5).unwrap();
+        assert_eq!(rewind_height, h20 - 5);
+        st.scan_cached_blocks(rewind_height + 1, 5);
+        assert_eq!(
+            st.wallet()
+                .block_fully_scanned()
+                .unwrap()
+                .map(|meta| meta.block_height()),
+            Some(h20)
+        );
+    }
+
+    /// Scans ten empty blocks into a new wallet, and returns the wallet's chain tip along
+    /// with the chain as the wallet has stored it.
+    fn scanned_wallet() -> (
+        crate::testing::TestState<crate::testing::BlockCache>,
+        BTreeMap<BlockHeight, BlockHash>,
+    ) {
+        let mut st = TestBuilder::new()
+            .with_block_cache()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let (h1, _) = st.generate_empty_block();
+        for _ in 1..10 {
+            st.generate_empty_block();
+        }
+        st.scan_cached_blocks(h1, 10);
+
+        let chain = (0..10)
+            .map(|i| {
+                let height = h1 + i;
+                (height, st.wallet().get_block_hash(height).unwrap().unwrap())
+            })
+            .collect();
+        (st, chain)
+    }
+
+    fn reconcile(
+        st: &mut crate::testing::TestState<crate::testing::BlockCache>,
+        server: MockLightwalletd,
+    ) -> Result<ReconcileAction, sync::Error<Infallible, crate::SqliteClientError, Infallible>>
+    {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(reconcile_chain_view::<_, _, Infallible, Infallible>(
+                &mut server.client(),
+                st.wallet_mut(),
+            ))
+    }
+
+    #[test]
+    fn reconcile_chain_view_wallet_behind_server() {
+        let (mut st, mut chain) = scanned_wallet();
+        let wallet_tip = *chain.keys().next_back().unwrap();
+        for i in 1..=5 {
+            chain.insert(wallet_tip + i, BlockHash([i as u8; 32]));
+        }
+
+        assert_eq!(
+            reconcile(&mut st, MockLightwalletd::new(chain)).unwrap(),
+            ReconcileAction::Behind {
+                wallet_tip,
+                server_tip: wallet_tip + 5,
+            }
+        );
+        assert_eq!(st.wallet().chain_height().unwrap(), Some(wallet_tip + 5));
+    }
+
+    #[test]
+    fn reconcile_chain_view_wallet_on_different_fork() {
+        let (mut st, mut chain) = scanned_wallet();
+        let wallet_tip = *chain.keys().next_back().unwrap();
+        // The server has replaced the top three of the wallet's blocks.
+        for i in 0..3 {
+            chain.insert(wallet_tip - i, BlockHash([0xff; 32]));
+        }
+
+        let action = reconcile(&mut st, MockLightwalletd::new(chain)).unwrap();
+        assert_eq!(
+            action,
+            ReconcileAction::Truncate {
+                wallet_tip,
+                common_ancestor: wallet_tip - 4,
+            }
+        );
+        // The wallet's data is left untouched.
+        assert_eq!(st.wallet().chain_height().unwrap(), Some(wallet_tip));
+    }
+
+    #[test]
+    fn reconcile_chain_view_server_unavailable() {
+        let (mut st, chain) = scanned_wallet();
+        let wallet_tip = *chain.keys().next_back().unwrap();
+
+        assert_matches!(
+            reconcile(&mut st, MockLightwalletd::failing(tonic::Code::Unavailable)),
+            Err(sync::Error::Server(status)) if status.code() == tonic::Code::Unavailable
+        );
+        assert_eq!(st.wallet().chain_height().unwrap(), Some(wallet_tip));
+    }
 }
@@ -31,13 +31,18 @@ use zcash_client_backend::{
     address::Address,
     data_api::{
         self,
-        chain::{scan_cached_blocks, BlockSource, CommitmentTreeRoot, ScanSummary},
+        chain::{
+            detect_spends_in_cached_blocks, scan_cached_blocks, scan_cached_blocks_with_progress,
+            BlockSource, CommitmentTreeRoot, ScanCancellation, ScanProgressObserver, ScanSummary,
+        },
+        snapshot::{import_wallet_snapshot, SnapshotError},
         wallet::{
             create_proposed_transactions, create_spend_to_address,
             input_selection::{GreedyInputSelector, GreedyInputSelectorError, InputSelector},
-            propose_standard_transfer_to_address, propose_transfer, spend,
+            promote_draft_to_proposal, propose_standard_transfer_to_address, propose_transfer,
+            spend,
         },
-        AccountBalance, AccountBirthday, WalletCommitmentTrees, WalletRead, WalletSummary,
+        AccountBalance, AccountBirthday, DraftId, WalletCommitmentTrees, WalletRead, WalletSummary,
         WalletWrite,
     },
     keys::UnifiedSpendingKey,
@@ -46,6 +51,7 @@ use zcash_client_backend::{
         self as compact, CompactBlock, CompactSaplingOutput, CompactSaplingSpend, CompactTx,
     },
     proto::proposal,
+    scanning::ScanParallelism,
     wallet::OvkPolicy,
     zip321,
 };
@@ -90,9 +96,10 @@ use {
 #[cfg(feature = "transparent-inputs")]
 use {
     zcash_client_backend::data_api::wallet::{
-        input_selection::ShieldingSelector, propose_shielding, shield_transparent_funds,
+        input_selection::ShieldingSelector, propose_shielding, shield_standalone_transparent_funds,
+        shield_transparent_funds,
     },
-    zcash_primitives::legacy::TransparentAddress,
+    zcash_primitives::legacy::{keys::AccountPrivKey, TransparentAddress},
 };
 
 #[cfg(feature = "unstable")]
@@ -101,6 +108,7 @@ use crate::{
     FsBlockDb,
 };
 
+pub(crate) mod lightwalletd;
 pub(crate) mod pool;
 
 pub(crate) struct InitialChainState {
@@ -231,7 +239,7 @@ impl<Cache> TestBuilder<Cache> {
     pub(crate) fn build(self) -> TestState<Cache> {
         let data_file = NamedTempFile::new().unwrap();
         let mut db_data = WalletDb::for_path(data_file.path(), self.network).unwrap();
-        init_wallet_db(&mut db_data, None).unwrap();
+        init_wallet_db(&mut db_data, None, None).unwrap();
 
         let mut cached_blocks = BTreeMap::new();
 
@@ -288,7 +296,7 @@ impl<Cache> TestBuilder<Cache> {
 
         let test_account = self.account_birthday.map(|birthday| {
             let seed = Secret::new(vec![0u8; 32]);
-            let (account_id, usk) = db_data.create_account(&seed, &birthday).unwrap();
+            let (account_id, usk) = db_data.create_account(&SecretVec::new(vec![]), &seed, &birthday).unwrap();
             (
                 seed,
                 TestAccount {
@@ -329,6 +337,11 @@ impl CachedBlock {
         }
     }
 
+    /// Returns the chain state as of the end of this block.
+    pub(crate) fn chain_state(&self) -> &ChainState {
+        &self.chain_state
+    }
+
     fn at(chain_state: ChainState, sapling_end_size: u32, orchard_end_size: u32) -> Self {
         assert_eq!(
             chain_state.final_sapling_tree().tree_size() as u32,
@@ -429,7 +442,6 @@ where
     <Cache::BlockSource as BlockSource>::Error: fmt::Debug,
 {
     /// Exposes an immutable reference to the test's [`BlockSource`].
-    #[cfg(feature = "unstable")]
     pub(crate) fn cache(&self) -> &Cache::BlockSource {
         self.cache.block_source()
     }
@@ -444,6 +456,14 @@ where
         self.cached_blocks.range(..height).last().map(|(_, b)| b)
     }
 
+    /// Removes all cached blocks above the given height, so that a different chain can be
+    /// generated on top of the block at that height.
+    pub(crate) fn truncate_cache_to_height(&mut self, height: BlockHeight) {
+        self.cache.truncate_to_height(height);
+        self.cached_blocks.split_off(&(height + 1));
+        self.latest_block_height = self.cached_blocks.keys().last().copied();
+    }
+
     fn cache_block(
         &mut self,
         prev_block: &CachedBlock,
@@ -734,6 +754,60 @@ where
         result
     }
 
+    /// Invokes [`scan_cached_blocks_with_progress`] with the given arguments, expecting success.
+    pub(crate) fn scan_cached_blocks_with_progress(
+        &mut self,
+        from_height: BlockHeight,
+        limit: usize,
+        batch_size: usize,
+        parallelism: &ScanParallelism,
+        observer: &mut impl ScanProgressObserver,
+        cancellation: &ScanCancellation,
+    ) -> ScanSummary {
+        let prior_cached_block = self
+            .latest_cached_block_below_height(from_height)
+            .cloned()
+            .unwrap_or_else(|| CachedBlock::none(from_height - 1));
+
+        let result = scan_cached_blocks_with_progress(
+            &self.network(),
+            self.cache.block_source(),
+            &mut self.db_data,
+            from_height,
+            &prior_cached_block.chain_state,
+            limit,
+            batch_size,
+            parallelism,
+            observer,
+            cancellation,
+        );
+        assert_matches!(result, Ok(_));
+        result.unwrap()
+    }
+
+    /// Invokes [`detect_spends_in_cached_blocks`] with the given arguments, expecting success.
+    pub(crate) fn detect_spends_in_cached_blocks(
+        &mut self,
+        from_height: BlockHeight,
+        limit: usize,
+    ) -> ScanSummary {
+        let prior_cached_block = self
+            .latest_cached_block_below_height(from_height)
+            .cloned()
+            .unwrap_or_else(|| CachedBlock::none(from_height - 1));
+
+        let result = detect_spends_in_cached_blocks(
+            &self.network(),
+            self.cache.block_source(),
+            &mut self.db_data,
+            from_height,
+            &prior_cached_block.chain_state,
+            limit,
+        );
+        assert_matches!(result, Ok(_));
+        result.unwrap()
+    }
+
     /// Resets the wallet using a new wallet database but with the same cache of blocks,
     /// and returns the old wallet database file.
     ///
@@ -746,10 +820,27 @@ where
         let tf = std::mem::replace(&mut self._data_file, NamedTempFile::new().unwrap());
         self.db_data = WalletDb::for_path(self._data_file.path(), network).unwrap();
         self.test_account = None;
-        init_wallet_db(&mut self.db_data, None).unwrap();
+        init_wallet_db(&mut self.db_data, None, None).unwrap();
         tf
     }
 
+    /// Replaces the wallet with a new wallet database populated from the given snapshot, and
+    /// returns the old wallet database file.
+    ///
+    /// Unlike [`Self::reset`], this retains the test account and the latest cached block, so
+    /// scanning and spending can continue against the restored wallet.
+    pub(crate) fn restore_snapshot(
+        &mut self,
+        snapshot: &[u8],
+    ) -> Result<NamedTempFile, SnapshotError<SqliteClientError>> {
+        let data_file = NamedTempFile::new().unwrap();
+        let mut db_data = WalletDb::for_path(data_file.path(), self.network()).unwrap();
+        init_wallet_db(&mut db_data, None, None).unwrap();
+        import_wallet_snapshot(&mut db_data, snapshot)?;
+        self.db_data = db_data;
+        Ok(std::mem::replace(&mut self._data_file, data_file))
+    }
+
     //    /// Reset the latest cached block to the most recent one in the cache database.
     //    #[allow(dead_code)]
     //    pub(crate) fn reset_latest_cached_block(&mut self) {
@@ -780,6 +871,11 @@ impl<Cache> TestState<Cache> {
         &mut self.db_data
     }
 
+    /// Returns the path of the file backing the test's [`WalletDb`].
+    pub(crate) fn wallet_path(&self) -> &std::path::Path {
+        self._data_file.path()
+    }
+
     /// Exposes the test framework's source of randomness.
     pub(crate) fn rng_mut(&mut self) -> &mut ChaChaRng {
         &mut self.rng
@@ -950,6 +1046,33 @@ impl<Cache> TestState<Cache> {
         )
     }
 
+    /// Invokes [`promote_draft_to_proposal`] with the given arguments.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn promote_draft_to_proposal<InputsT>(
+        &mut self,
+        input_selector: &InputsT,
+        draft_id: DraftId,
+    ) -> Result<
+        Proposal<InputsT::FeeRule, ReceivedNoteId>,
+        data_api::error::Error<
+            SqliteClientError,
+            Infallible,
+            InputsT::Error,
+            <InputsT::FeeRule as FeeRule>::Error,
+        >,
+    >
+    where
+        InputsT: InputSelector<InputSource = WalletDb<Connection, LocalNetwork>>,
+    {
+        let params = self.network();
+        promote_draft_to_proposal::<_, _, _, Infallible>(
+            &mut self.db_data,
+            &params,
+            input_selector,
+            draft_id,
+        )
+    }
+
     /// Invokes [`propose_standard_transfer`] with the given arguments.
     #[allow(clippy::type_complexity)]
     #[allow(clippy::too_many_arguments)]
@@ -1094,6 +1217,46 @@ impl<Cache> TestState<Cache> {
         )
     }
 
+    /// Invokes [`shield_standalone_transparent_funds`] with the given arguments.
+    #[cfg(feature = "transparent-inputs")]
+    #[allow(clippy::type_complexity)]
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn shield_standalone_transparent_funds<InputsT>(
+        &mut self,
+        input_selector: &InputsT,
+        shielding_threshold: NonNegativeAmount,
+        usk: &UnifiedSpendingKey,
+        standalone_keys: &[AccountPrivKey],
+        from_addrs: &[TransparentAddress],
+        min_confirmations: u32,
+    ) -> Result<
+        NonEmpty<TxId>,
+        data_api::error::Error<
+            SqliteClientError,
+            commitment_tree::Error,
+            InputsT::Error,
+            <InputsT::FeeRule as FeeRule>::Error,
+        >,
+    >
+    where
+        InputsT: ShieldingSelector<InputSource = WalletDb<Connection, LocalNetwork>>,
+    {
+        let params = self.network();
+        let prover = test_prover();
+        shield_standalone_transparent_funds(
+            &mut self.db_data,
+            &params,
+            &prover,
+            &prover,
+            input_selector,
+            shielding_threshold,
+            usk,
+            standalone_keys,
+            from_addrs,
+            min_confirmations,
+        )
+    }
+
     fn with_account_balance<T, F: FnOnce(&AccountBalance) -> T>(
         &self,
         account: AccountId,
@@ -1689,7 +1852,9 @@ fn fake_compact_block_spending<P: consensus::Parameters, Fvk: TestFvk>(
             )
             .0,
         ),
-        Address::Transparent(_) => panic!("transparent addresses not supported in compact blocks"),
+        Address::Transparent(_) | Address::Tex(_) => {
+            panic!("transparent addresses not supported in compact blocks")
+        }
         Address::Unified(ua) => {
             // This is annoying to implement, because the protocol-aware UA type has no
             // concept of ZIP 316 preference order.
@@ -1785,6 +1950,9 @@ pub(crate) trait TestCache {
 
     /// Inserts a CompactBlock into the cache DB.
     fn insert(&self, cb: &CompactBlock) -> Self::InsertResult;
+
+    /// Deletes all CompactBlocks above the given height from the cache DB.
+    fn truncate_to_height(&self, height: BlockHeight);
 }
 
 pub(crate) struct BlockCache {
@@ -1822,6 +1990,16 @@ impl TestCache for BlockCache {
             .execute(params![u32::from(cb.height()), cb_bytes,])
             .unwrap();
     }
+
+    fn truncate_to_height(&self, height: BlockHeight) {
+        self.db_cache
+            .0
+            .execute(
+                "DELETE FROM compactblocks WHERE height > ?",
+                [u32::from(height)],
+            )
+            .unwrap();
+    }
 }
 
 #[cfg(feature = "unstable")]
@@ -1874,6 +2052,10 @@ impl TestCache for FsBlockCache {
 
         meta
     }
+
+    fn truncate_to_height(&self, height: BlockHeight) {
+        self.db_meta.truncate_to_height(height).unwrap();
+    }
 }
 
 pub(crate) fn input_selector(
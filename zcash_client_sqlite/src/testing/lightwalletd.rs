@@ -0,0 +1,153 @@
+//! An in-process stand-in for a lightwalletd server.
+//!
+//! [`MockLightwalletd`] implements just enough of the `CompactTxStreamer` gRPC service to
+//! exercise the chain-view reconciliation performed by [`zcash_client_backend::sync`].
+
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use prost::Message;
+use tonic::{
+    body::BoxBody,
+    codegen::{
+        http::{self, HeaderMap},
+        Body, BoxFuture, Bytes, Service,
+    },
+    Code,
+};
+use zcash_client_backend::proto::{
+    compact_formats::CompactBlock,
+    service::{compact_tx_streamer_client::CompactTxStreamerClient, BlockId},
+};
+use zcash_primitives::{block::BlockHash, consensus::BlockHeight};
+
+const SERVICE_PATH: &str = "/cash.z.wallet.sdk.rpc.CompactTxStreamer";
+
+/// A mock lightwalletd server that serves the given chain of block hashes, or fails every
+/// request with a fixed status code.
+#[derive(Clone)]
+pub(crate) struct MockLightwalletd {
+    chain: Arc<BTreeMap<BlockHeight, BlockHash>>,
+    failure: Option<Code>,
+}
+
+impl MockLightwalletd {
+    /// Constructs a server whose chain consists of the given blocks. The chain tip is the
+    /// highest of them.
+    pub(crate) fn new(chain: BTreeMap<BlockHeight, BlockHash>) -> Self {
+        MockLightwalletd {
+            chain: Arc::new(chain),
+            failure: None,
+        }
+    }
+
+    /// Constructs a server that responds to every request with the given status code.
+    pub(crate) fn failing(code: Code) -> Self {
+        MockLightwalletd {
+            chain: Arc::new(BTreeMap::new()),
+            failure: Some(code),
+        }
+    }
+
+    /// Returns a client connected to this server.
+    pub(crate) fn client(self) -> CompactTxStreamerClient<Self> {
+        CompactTxStreamerClient::new(self)
+    }
+
+    fn respond(&self, path: &str, request: &[u8]) -> Result<Vec<u8>, Code> {
+        if let Some(code) = self.failure {
+            return Err(code);
+        }
+
+        let block = |height: BlockHeight, hash: &BlockHash| CompactBlock {
+            height: u32::from(height).into(),
+            hash: hash.0.to_vec(),
+            ..Default::default()
+        };
+
+        match path.strip_prefix(SERVICE_PATH) {
+            Some("/GetLatestBlock") => {
+                let (height, hash) = self.chain.iter().next_back().ok_or(Code::NotFound)?;
+                Ok(BlockId {
+                    height: u32::from(*height).into(),
+                    hash: hash.0.to_vec(),
+                }
+                .encode_to_vec())
+            }
+            Some("/GetBlock") => {
+                // Skip the gRPC message header.
+                let id = BlockId::decode(&request[5..]).map_err(|_| Code::InvalidArgument)?;
+                let height = u32::try_from(id.height)
+                    .map(BlockHeight::from_u32)
+                    .map_err(|_| Code::InvalidArgument)?;
+                let hash = self.chain.get(&height).ok_or(Code::NotFound)?;
+                Ok(block(height, hash).encode_to_vec())
+            }
+            _ => Err(Code::Unimplemented),
+        }
+    }
+}
+
+impl Service<http::Request<BoxBody>> for MockLightwalletd {
+    type Response = http::Response<UnaryBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<BoxBody>) -> Self::Future {
+        let server = self.clone();
+        Box::pin(async move {
+            let path = req.uri().path().to_owned();
+            let mut body = req.into_body();
+            let mut request = vec![];
+            while let Some(chunk) = body.data().await {
+                request.extend_from_slice(&chunk.expect("request body is readable"));
+            }
+
+            let response = http::Response::builder().header("content-type", "application/grpc");
+            Ok(match server.respond(&path, &request) {
+                Ok(message) => {
+                    let mut frame = vec![0];
+                    frame.extend_from_slice(&u32::try_from(message.len()).unwrap().to_be_bytes());
+                    frame.extend_from_slice(&message);
+                    response.body(UnaryBody(Some(Bytes::from(frame))))
+                }
+                // A "trailers-only" response, which carries the status in its headers.
+                Err(code) => response
+                    .header("grpc-status", (code as i32).to_string())
+                    .body(UnaryBody(None)),
+            }
+            .expect("response is valid"))
+        })
+    }
+}
+
+/// A response body consisting of at most one gRPC frame.
+pub(crate) struct UnaryBody(Option<Bytes>);
+
+impl Body for UnaryBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Poll::Ready(self.0.take().map(Ok))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+}
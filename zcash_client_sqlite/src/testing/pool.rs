@@ -4,7 +4,7 @@
 
 use std::{
     convert::Infallible,
-    num::{NonZeroU32, NonZeroU8},
+    num::{NonZeroU32, NonZeroU8, NonZeroUsize},
 };
 
 use incrementalmerkletree::{frontier::Frontier, Level};
@@ -18,34 +18,46 @@ use zcash_primitives::{
     legacy::TransparentAddress,
     memo::{Memo, MemoBytes},
     transaction::{
-        components::amount::NonNegativeAmount,
+        components::amount::{Amount, NonNegativeAmount},
         fees::{
             fixed::FeeRule as FixedFeeRule, zip317::FeeError as Zip317FeeError, StandardFeeRule,
         },
-        Transaction,
+        Transaction, TxId,
     },
-    zip32::Scope,
+    zip32::{self, Scope},
 };
 
 use zcash_client_backend::{
     address::Address,
     data_api::{
         self,
-        chain::{self, ChainState, CommitmentTreeRoot, ScanSummary},
+        chain::{
+            self, check_chain_continuity, truncate_to_chain_fork, BatchScanProgress, BlockSource,
+            ChainState, CommitmentTreeRoot, ScanCancellation, ScanSummary,
+        },
         error::Error,
+        snapshot::{export_wallet_snapshot, import_wallet_snapshot, SnapshotError},
         wallet::{
             decrypt_and_store_transaction,
-            input_selection::{GreedyInputSelector, GreedyInputSelectorError},
+            input_selection::{
+                GreedyInputSelector, GreedyInputSelectorError, NoteSelectionStrategy,
+            },
         },
-        AccountBirthday, DecryptedTransaction, Ratio, WalletRead, WalletSummary, WalletWrite,
+        AccountBirthday, AccountPoolBalances, DecryptedTransaction, DraftPayment, MemoQuery, Ratio,
+        TransactionDirection, TransactionFilter, WalletCommitmentTrees, WalletRead, WalletSummary,
+        WalletWrite,
     },
     decrypt_transaction,
-    fees::{fixed, standard, DustOutputPolicy},
+    fees::{
+        fixed, standard, ChangeOutputPolicy, ChangePoolPreference, ChangeSplitPolicy,
+        DustOutputPolicy,
+    },
     keys::UnifiedSpendingKey,
-    scanning::ScanError,
+    proto::compact_formats::CompactBlock,
+    scanning::{ScanError, ScanParallelism},
     wallet::{Note, OvkPolicy, ReceivedNote},
     zip321::{self, Payment, TransactionRequest},
-    ShieldedProtocol,
+    PoolType, ShieldedProtocol,
 };
 use zcash_protocol::consensus::BlockHeight;
 
@@ -54,15 +66,22 @@ use crate::{
     error::SqliteClientError,
     testing::{input_selector, AddressType, BlockCache, InitialChainState, TestBuilder, TestState},
     wallet::{block_max_scanned, commitment_tree, parse_scope, truncate_to_height},
-    AccountId, NoteId, ReceivedNoteId,
+    AccountId, NoteId, ReceivedNoteId, PRUNING_DEPTH,
 };
 
 #[cfg(feature = "transparent-inputs")]
 use {
+    crate::wallet::sapling::tests::test_prover,
     zcash_client_backend::{
-        fees::TransactionBalance, proposal::Step, wallet::WalletTransparentOutput, PoolType,
+        data_api::wallet::{create_proposed_transactions, input_selection::ShieldingSelector},
+        fees::TransactionBalance,
+        proposal::Step,
+        wallet::WalletTransparentOutput,
+    },
+    zcash_primitives::{
+        legacy::keys::{AccountPrivKey, IncomingViewingKey},
+        transaction::components::{OutPoint, TxOut},
     },
-    zcash_primitives::transaction::components::{OutPoint, TxOut},
 };
 
 pub(crate) type OutputRecoveryError = Error<
@@ -300,6 +319,97 @@ pub(crate) fn send_single_step_proposed_transfer<T: ShieldedPoolTester>() {
     );
 }
 
+pub(crate) fn send_with_split_change<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(500000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    let to_extsk = T::sk(&[0xf5; 32]);
+    let to: Address = T::sk_default_address(&to_extsk);
+    let request = zip321::TransactionRequest::new(vec![Payment::without_memo(
+        to,
+        NonNegativeAmount::const_from_u64(10000),
+    )])
+    .unwrap();
+
+    // Split change of at least 100000 zatoshis into three notes.
+    let change_strategy = standard::SingleOutputChangeStrategy::new(
+        StandardFeeRule::Zip317,
+        None,
+        T::SHIELDED_PROTOCOL,
+    )
+    .with_change_output_policy(ChangeOutputPolicy::new(
+        ChangePoolPreference::SameAsInputs,
+        Some(ChangeSplitPolicy::new(
+            NonNegativeAmount::const_from_u64(100000),
+            NonZeroUsize::new(3).unwrap(),
+        )),
+    ));
+    let input_selector = &GreedyInputSelector::new(change_strategy, DustOutputPolicy::default());
+
+    let proposal = st
+        .propose_transfer(
+            account.account_id(),
+            input_selector,
+            request,
+            NonZeroU32::new(1).unwrap(),
+        )
+        .unwrap();
+
+    // The proposal records that the change was split.
+    let step = proposal.steps().head.clone();
+    let decisions = step.balance().change_policy().unwrap();
+    assert!(decisions.change_split());
+    assert_eq!(decisions.change_pool(), Some(T::SHIELDED_PROTOCOL));
+    assert_eq!(decisions.dust_action(), None);
+    assert_eq!(step.balance().proposed_change().len(), 3);
+
+    let create_proposed_result = st.create_proposed_transactions::<Infallible, _>(
+        account.usk(),
+        OvkPolicy::Sender,
+        &proposal,
+    );
+    assert_matches!(&create_proposed_result, Ok(txids) if txids.len() == 1);
+    let sent_tx_id = create_proposed_result.unwrap()[0];
+
+    // The transaction contains the payment and each of the change outputs.
+    let tx = st
+        .wallet()
+        .get_transaction(sent_tx_id)
+        .unwrap()
+        .expect("Created transaction was stored.");
+    let ufvks = [(
+        account.account_id(),
+        account.usk().to_unified_full_viewing_key(),
+    )]
+    .into_iter()
+    .collect();
+    let d_tx = decrypt_transaction(&st.network(), h + 1, &tx, &ufvks);
+    assert_eq!(T::decrypted_pool_outputs_count(&d_tx), 4);
+
+    // Once the transaction is mined, the change is spendable as three separate notes.
+    let (h, _) = st.generate_next_block_including(sent_tx_id);
+    st.scan_cached_blocks(h, 1);
+    let expected_change =
+        (value - NonNegativeAmount::const_from_u64(10000) - step.balance().fee_required()).unwrap();
+    assert_eq!(
+        st.get_spendable_balance(account.account_id(), 1),
+        expected_change
+    );
+    let spendable_notes =
+        T::select_spendable_notes(&st, account.account_id(), expected_change, h, &[]).unwrap();
+    assert_eq!(spendable_notes.len(), 3);
+}
+
 #[cfg(feature = "transparent-inputs")]
 pub(crate) fn send_multi_step_proposed_transfer<T: ShieldedPoolTester>() {
     use nonempty::NonEmpty;
@@ -336,7 +446,7 @@ pub(crate) fn send_multi_step_proposed_transfer<T: ShieldedPoolTester>() {
     // spends the first step's output.
 
     // The first step will deshield to the wallet's default transparent address
-    let to0 = Address::Transparent(account.usk().default_transparent_address().0);
+    let to0 = Address::Transparent(account.usk().default_transparent_address());
     let request0 = zip321::TransactionRequest::new(vec![Payment {
         recipient_address: to0,
         amount: NonNegativeAmount::const_from_u64(50000),
@@ -457,665 +567,981 @@ pub(crate) fn send_multi_step_proposed_transfer<T: ShieldedPoolTester>() {
     );
 }
 
-#[allow(deprecated)]
-pub(crate) fn create_to_address_fails_on_incorrect_usk<T: ShieldedPoolTester>() {
+pub(crate) fn estimate_transfer_fee_range<T: ShieldedPoolTester>() {
+    use zcash_client_backend::data_api::wallet::estimate_transfer_fee;
+    use zcash_primitives::transaction::fees::zip317::FeeRule as Zip317FeeRule;
+
     let mut st = TestBuilder::new()
+        .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32]))
         .build();
+
+    let account = st.test_account().cloned().unwrap();
     let dfvk = T::test_account_fvk(&st);
-    let to = T::fvk_default_address(&dfvk);
 
-    // Create a USK that doesn't exist in the wallet
-    let acct1 = zip32::AccountId::try_from(1).unwrap();
-    let usk1 = UnifiedSpendingKey::from_seed(&st.network(), &[1u8; 32], acct1).unwrap();
+    // Add funds to the wallet in three notes, along with a note that is dust under ZIP 317.
+    for value in [50000, 50000, 50000, 1000] {
+        let (h, _, _) = st.generate_next_block(
+            &dfvk,
+            AddressType::DefaultExternal,
+            NonNegativeAmount::const_from_u64(value),
+        );
+        st.scan_cached_blocks(h, 1);
+    }
 
-    // Attempting to spend with a USK that is not in the wallet results in an error
-    assert_matches!(
-        st.create_spend_to_address(
-            &usk1,
-            &to,
-            NonNegativeAmount::const_from_u64(1),
-            None,
-            OvkPolicy::Sender,
+    let estimate = |payment_pool, payment_count| {
+        let estimate = estimate_transfer_fee::<_, _, Infallible>(
+            st.wallet(),
+            &st.network(),
+            &Zip317FeeRule::standard(),
+            account.account_id(),
+            payment_pool,
+            payment_count,
             NonZeroU32::new(1).unwrap(),
-            None,
-            T::SHIELDED_PROTOCOL,
-        ),
-        Err(data_api::error::Error::KeyNotRecognized)
-    );
+        )
+        .unwrap();
+        (u64::from(estimate.minimum()), u64::from(estimate.maximum()))
+    };
+
+    // A single note covers a payment and change within the grace actions, while spending all
+    // three non-dust notes requires a third logical action.
+    let shielded = PoolType::Shielded(T::SHIELDED_PROTOCOL);
+    assert_eq!(estimate(shielded, 1), (10000, 15000));
+    // With enough payments, the outputs rather than the spends determine the fee.
+    assert_eq!(estimate(shielded, 4), (25000, 25000));
+    // A transparent payment adds a transparent logical action.
+    assert_eq!(estimate(PoolType::Transparent, 1), (15000, 20000));
 }
 
-#[allow(deprecated)]
-pub(crate) fn proposal_fails_with_no_blocks<T: ShieldedPoolTester>() {
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn send_to_tex_address<T: ShieldedPoolTester>() {
+    use zcash_client_backend::{
+        data_api::TransparentAddressUsage, encoding::AddressCodec, proto::proposal,
+        wallet::TransparentAddressMetadata,
+    };
+    use zcash_primitives::legacy::keys::{
+        IncomingViewingKey, NonHardenedChildIndex, TransparentKeyScope,
+    };
+
     let mut st = TestBuilder::new()
+        .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32]))
         .build();
 
-    let account_id = st.test_account().unwrap().account_id();
+    let account = st.test_account().cloned().unwrap();
     let dfvk = T::test_account_fvk(&st);
-    let to = T::fvk_default_address(&dfvk);
 
-    // Wallet summary is not yet available
-    assert_eq!(st.get_wallet_summary(0), None);
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(100000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
 
-    // We cannot do anything if we aren't synchronised
-    assert_matches!(
-        st.propose_standard_transfer::<Infallible>(
-            account_id,
-            StandardFeeRule::PreZip313,
+    let request = zip321::TransactionRequest::new(vec![Payment {
+        recipient_address: Address::Tex([7; 20]),
+        amount: NonNegativeAmount::const_from_u64(30000),
+        memo: None,
+        label: None,
+        message: None,
+        other_params: vec![],
+    }])
+    .unwrap();
+
+    let fee_rule = StandardFeeRule::Zip317;
+    let input_selector = GreedyInputSelector::new(
+        standard::SingleOutputChangeStrategy::new(fee_rule, None, T::SHIELDED_PROTOCOL),
+        DustOutputPolicy::default(),
+    );
+    let proposal = st
+        .propose_transfer(
+            account.account_id(),
+            &input_selector,
+            request,
             NonZeroU32::new(1).unwrap(),
-            &to,
-            NonNegativeAmount::const_from_u64(1),
-            None,
-            None,
-            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+    assert_eq!(proposal.steps().len(), 2);
+
+    // The first step funds the TEX payment and the fee of the second step through an
+    // ephemeral transparent output.
+    let step0 = &proposal.steps().head;
+    let ephemeral_value = NonNegativeAmount::const_from_u64(40000);
+    assert_eq!(
+        step0
+            .balance()
+            .proposed_change()
+            .iter()
+            .map(|change| (change.output_pool(), change.value()))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                PoolType::Shielded(T::SHIELDED_PROTOCOL),
+                NonNegativeAmount::const_from_u64(45000)
+            ),
+            (PoolType::Transparent, ephemeral_value),
+        ]
+    );
+
+    // The ephemeral output is marked as such in the serialized proposal, which decodes to the
+    // original proposal.
+    let proposal_proto = proposal::Proposal::from_standard_proposal(&st.network(), &proposal);
+    assert_eq!(
+        proposal_proto.steps[0]
+            .balance
+            .as_ref()
+            .unwrap()
+            .proposed_change
+            .iter()
+            .map(|change| change.is_ephemeral)
+            .collect::<Vec<_>>(),
+        vec![false, true]
+    );
+    assert_eq!(
+        proposal_proto
+            .try_into_checked_standard_proposal(&st.network(), st.wallet_mut())
+            .unwrap(),
+        proposal
+    );
+
+    let create_proposed_result = st.create_proposed_transactions::<Infallible, _>(
+        account.usk(),
+        OvkPolicy::Sender,
+        &proposal,
+    );
+    assert_matches!(&create_proposed_result, Ok(txids) if txids.len() == 2);
+    let txids = create_proposed_result.unwrap();
+
+    // The ephemeral output is sent to a newly reserved address in the account's ephemeral
+    // scope, leaving its external addresses untouched.
+    let reserved_in = |usage: Vec<TransparentAddressUsage>| {
+        usage
+            .into_iter()
+            .filter(|usage| usage.is_reserved())
+            .map(|usage| (*usage.address(), usage.metadata().clone()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        reserved_in(
+            st.wallet()
+                .get_transparent_address_usage(account.account_id())
+                .unwrap()
         ),
-        Err(data_api::error::Error::ScanRequired)
+        vec![]
+    );
+    let reserved = reserved_in(
+        st.wallet()
+            .get_ephemeral_address_usage(account.account_id())
+            .unwrap(),
+    );
+    assert_eq!(
+        reserved,
+        vec![(
+            account
+                .usk()
+                .transparent()
+                .to_account_pubkey()
+                .derive_ephemeral_ivk()
+                .unwrap()
+                .derive_address(NonHardenedChildIndex::ZERO)
+                .unwrap(),
+            TransparentAddressMetadata::new(
+                TransparentKeyScope::EPHEMERAL,
+                NonHardenedChildIndex::ZERO
+            ),
+        )]
+    );
+    let reserved = reserved.into_iter().map(|(a, _)| a).collect::<Vec<_>>();
+
+    let sent_transparent = |txid: &TxId| -> Vec<(String, u64)> {
+        st.wallet()
+            .conn
+            .prepare(
+                "SELECT to_address, value
+                FROM sent_notes
+                JOIN transactions ON transactions.id_tx = sent_notes.tx
+                WHERE transactions.txid = ?
+                AND sent_notes.output_pool = 0",
+            )
+            .unwrap()
+            .query_map(rusqlite::params![txid.as_ref()], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    };
+    assert_eq!(
+        sent_transparent(&txids[0]),
+        vec![(reserved[0].encode(&st.network()), 40000)]
+    );
+    assert_eq!(
+        sent_transparent(&txids[1]),
+        vec![(
+            TransparentAddress::PublicKeyHash([7; 20]).encode(&st.network()),
+            30000
+        )]
     );
 }
 
-pub(crate) fn spend_fails_on_unverified_notes<T: ShieldedPoolTester>() {
+pub(crate) fn proposal_roundtrip_multi_output_transfer<T: ShieldedPoolTester>() {
+    use prost::Message;
+    use zcash_client_backend::proto::proposal;
+
     let mut st = TestBuilder::new()
         .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32]))
         .build();
 
     let account = st.test_account().cloned().unwrap();
-    let account_id = account.account_id();
     let dfvk = T::test_account_fvk(&st);
 
-    // Add funds to the wallet in a single note
-    let value = NonNegativeAmount::const_from_u64(50000);
-    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
-    st.scan_cached_blocks(h1, 1);
+    // Add funds to the wallet in two notes, so that both must be spent.
+    for _ in 0..2 {
+        let (h, _, _) = st.generate_next_block(
+            &dfvk,
+            AddressType::DefaultExternal,
+            NonNegativeAmount::const_from_u64(50000),
+        );
+        st.scan_cached_blocks(h, 1);
+    }
 
-    // Spendable balance matches total balance at 1 confirmation.
-    assert_eq!(st.get_total_balance(account_id), value);
-    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+    let payment = |seed: u8, value: u64, memo: Option<MemoBytes>| Payment {
+        recipient_address: T::sk_default_address(&T::sk(&[seed; 32])),
+        amount: NonNegativeAmount::const_from_u64(value),
+        memo,
+        label: None,
+        message: None,
+        other_params: vec![],
+    };
+    let request = TransactionRequest::new(vec![
+        payment(0xf5, 30000, Some("first".parse::<Memo>().unwrap().into())),
+        payment(0xf6, 40000, None),
+    ])
+    .unwrap();
 
-    // Value is considered pending at 10 confirmations.
-    assert_eq!(st.get_pending_shielded_balance(account_id, 10), value);
+    let proposal = st
+        .propose_transfer(
+            account.account_id(),
+            &input_selector(
+                StandardFeeRule::Zip317,
+                Some("change"),
+                T::SHIELDED_PROTOCOL,
+            ),
+            request,
+            NonZeroU32::new(1).unwrap(),
+        )
+        .unwrap();
     assert_eq!(
-        st.get_spendable_balance(account_id, 10),
-        NonNegativeAmount::ZERO
+        proposal
+            .steps()
+            .head
+            .shielded_inputs()
+            .unwrap()
+            .notes()
+            .len(),
+        2
     );
 
-    // Wallet is fully scanned
-    let summary = st.get_wallet_summary(1);
-    assert_eq!(
-        summary.and_then(|s| s.scan_progress()),
-        Some(Ratio::new(1, 1))
+    // The proposal survives being encoded to bytes and decoded against the same wallet.
+    let bytes =
+        proposal::Proposal::from_standard_proposal(&st.network(), &proposal).encode_to_vec();
+    let decoded = proposal::Proposal::decode(&bytes[..])
+        .unwrap()
+        .try_into_checked_standard_proposal(&st.network(), st.wallet_mut())
+        .unwrap();
+    assert_eq!(decoded, proposal);
+
+    // The decoded proposal can be executed.
+    assert_matches!(
+        st.create_proposed_transactions::<Infallible, _>(
+            account.usk(),
+            OvkPolicy::Sender,
+            &decoded
+        ),
+        Ok(txids) if txids.len() == 1
     );
+}
 
-    // Add more funds to the wallet in a second note
-    let (h2, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
-    st.scan_cached_blocks(h2, 1);
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn proposal_roundtrip_shielding<T: ShieldedPoolTester>() {
+    use prost::Message;
+    use zcash_client_backend::proto::proposal;
 
-    // Verified balance does not include the second note
-    let total = (value + value).unwrap();
-    assert_eq!(st.get_spendable_balance(account_id, 2), value);
-    assert_eq!(st.get_pending_shielded_balance(account_id, 2), value);
-    assert_eq!(st.get_total_balance(account_id), total);
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
 
-    // Wallet is still fully scanned
-    let summary = st.get_wallet_summary(1);
-    assert_eq!(
-        summary.and_then(|s| s.scan_progress()),
-        Some(Ratio::new(2, 2))
-    );
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
 
-    // Spend fails because there are insufficient verified notes
-    let extsk2 = T::sk(&[0xf5; 32]);
-    let to = T::sk_default_address(&extsk2);
-    assert_matches!(
-        st.propose_standard_transfer::<Infallible>(
-            account_id,
-            StandardFeeRule::Zip317,
-            NonZeroU32::new(2).unwrap(),
-            &to,
-            NonNegativeAmount::const_from_u64(70000),
-            None,
-            None,
-            T::SHIELDED_PROTOCOL,
-        ),
-        Err(data_api::error::Error::InsufficientFunds {
-            available,
-            required
-        })
-        if available == NonNegativeAmount::const_from_u64(50000)
-            && required == NonNegativeAmount::const_from_u64(80000)
-    );
-
-    // Mine blocks SAPLING_ACTIVATION_HEIGHT + 2 to 9 until just before the second
-    // note is verified
-    for _ in 2..10 {
-        st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
-    }
-    st.scan_cached_blocks(h2 + 1, 8);
-
-    // Total balance is value * number of blocks scanned (10).
-    assert_eq!(st.get_total_balance(account_id), (value * 10).unwrap());
+    let uaddr = st
+        .wallet()
+        .get_current_address(account.account_id())
+        .unwrap()
+        .unwrap();
+    let taddr = uaddr.transparent().unwrap();
 
-    // Spend still fails
-    assert_matches!(
-        st.propose_standard_transfer::<Infallible>(
-            account_id,
-            StandardFeeRule::Zip317,
-            NonZeroU32::new(10).unwrap(),
-            &to,
-            NonNegativeAmount::const_from_u64(70000),
-            None,
-            None,
-            T::SHIELDED_PROTOCOL,
-        ),
-        Err(data_api::error::Error::InsufficientFunds {
-            available,
-            required
-        })
-        if available == NonNegativeAmount::const_from_u64(50000)
-            && required == NonNegativeAmount::const_from_u64(80000)
+    // Ensure that the wallet has at least one block
+    let (h, _, _) = st.generate_next_block(
+        &dfvk,
+        AddressType::Internal,
+        NonNegativeAmount::const_from_u64(50000),
     );
+    st.scan_cached_blocks(h, 1);
 
-    // Mine block 11 so that the second note becomes verified
-    let (h11, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
-    st.scan_cached_blocks(h11, 1);
+    let utxo = WalletTransparentOutput::from_parts(
+        OutPoint::new([1u8; 32], 1),
+        TxOut {
+            value: NonNegativeAmount::const_from_u64(50000),
+            script_pubkey: taddr.script(),
+        },
+        h,
+    )
+    .unwrap();
+    st.wallet_mut()
+        .put_received_transparent_utxo(&utxo)
+        .unwrap();
 
-    // Total balance is value * number of blocks scanned (11).
-    assert_eq!(st.get_total_balance(account_id), (value * 11).unwrap());
-    // Spendable balance at 10 confirmations is value * 2.
+    let proposal = st
+        .propose_shielding(
+            &input_selector(StandardFeeRule::Zip317, None, T::SHIELDED_PROTOCOL),
+            NonNegativeAmount::const_from_u64(10000),
+            &[*taddr],
+            1,
+        )
+        .unwrap();
+
+    // Decoding the encoded proposal against the same wallet recovers the original proposal,
+    // which re-encodes to the same bytes.
+    let bytes =
+        proposal::Proposal::from_standard_proposal(&st.network(), &proposal).encode_to_vec();
+    let decoded = proposal::Proposal::decode(&bytes[..])
+        .unwrap()
+        .try_into_standard_proposal(&st.network(), st.wallet())
+        .unwrap();
+    assert!(decoded.steps().head.is_shielding());
     assert_eq!(
-        st.get_spendable_balance(account_id, 10),
-        (value * 2).unwrap()
+        decoded.steps().head.transparent_inputs(),
+        proposal.steps().head.transparent_inputs()
     );
     assert_eq!(
-        st.get_pending_shielded_balance(account_id, 10),
-        (value * 9).unwrap()
+        proposal::Proposal::from_standard_proposal(&st.network(), &decoded).encode_to_vec(),
+        bytes
     );
+}
 
-    // Should now be able to generate a proposal
-    let amount_sent = NonNegativeAmount::from_u64(70000).unwrap();
-    let min_confirmations = NonZeroU32::new(10).unwrap();
-    let proposal = st
-        .propose_standard_transfer::<Infallible>(
-            account_id,
-            StandardFeeRule::Zip317,
-            min_confirmations,
-            &to,
-            amount_sent,
-            None,
-            None,
-            T::SHIELDED_PROTOCOL,
+pub(crate) fn execute_stale_proposal<T: ShieldedPoolTester>() {
+    use prost::Message;
+    use zcash_client_backend::proto::{proposal, ProposalDecodingError};
+
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet in a single note
+    let (h, _, _) = st.generate_next_block(
+        &dfvk,
+        AddressType::DefaultExternal,
+        NonNegativeAmount::const_from_u64(60000),
+    );
+    st.scan_cached_blocks(h, 1);
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let propose = |st: &mut TestState<_>, value| {
+        let request = TransactionRequest::new(vec![Payment {
+            recipient_address: to.clone(),
+            amount: NonNegativeAmount::const_from_u64(value),
+            memo: None,
+            label: None,
+            message: None,
+            other_params: vec![],
+        }])
+        .unwrap();
+        st.propose_transfer(
+            account.account_id(),
+            &input_selector(StandardFeeRule::Zip317, None, T::SHIELDED_PROTOCOL),
+            request,
+            NonZeroU32::new(1).unwrap(),
         )
+        .unwrap()
+    };
+
+    // Create a proposal and move it elsewhere in its serialized form.
+    let stale = propose(&mut st, 10000);
+    let bytes = proposal::Proposal::from_standard_proposal(&st.network(), &stale).encode_to_vec();
+    let decoded = proposal::Proposal::decode(&bytes[..])
+        .unwrap()
+        .try_into_checked_standard_proposal(&st.network(), st.wallet_mut())
         .unwrap();
 
-    // Executing the proposal should succeed
+    // Meanwhile, spend the same note in another transaction, which is then mined.
+    let other = propose(&mut st, 20000);
     let txid = st
-        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &other)
         .unwrap()[0];
-
     let (h, _) = st.generate_next_block_including(txid);
     st.scan_cached_blocks(h, 1);
 
-    // TODO: send to an account so that we can check its balance.
-    assert_eq!(
-        st.get_total_balance(account_id),
-        ((value * 11).unwrap()
-            - (amount_sent + NonNegativeAmount::from_u64(10000).unwrap()).unwrap())
-        .unwrap()
+    // Executing the stale proposal fails before any transaction is built.
+    let note = &decoded.steps().head.shielded_inputs().unwrap().notes()[0];
+    assert_matches!(
+        st.create_proposed_transactions::<Infallible, _>(
+            account.usk(),
+            OvkPolicy::Sender,
+            &decoded
+        ),
+        Err(Error::InputNotSpendable(txid, PoolType::Shielded(protocol), index))
+            if &txid == note.txid()
+                && protocol == T::SHIELDED_PROTOCOL
+                && index == u32::from(note.output_index())
+    );
+
+    // The serialized proposal can no longer be decoded against the wallet either.
+    assert_matches!(
+        proposal::Proposal::decode(&bytes[..])
+            .unwrap()
+            .try_into_checked_standard_proposal(&st.network(), st.wallet_mut()),
+        Err(ProposalDecodingError::InputNotFound(txid, PoolType::Shielded(_), _))
+            if &txid == note.txid()
     );
 }
 
-pub(crate) fn spend_fails_on_locked_notes<T: ShieldedPoolTester>() {
+pub(crate) fn decode_proposal_checks_wallet_state<T: ShieldedPoolTester>() {
+    use zcash_client_backend::proto::{proposal, ProposalDecodingError};
+
     let mut st = TestBuilder::new()
         .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32]))
         .build();
 
     let account = st.test_account().cloned().unwrap();
-    let account_id = account.account_id();
     let dfvk = T::test_account_fvk(&st);
 
-    // TODO: This test was originally written to use the pre-zip-313 fee rule
-    // and has not yet been updated.
-    #[allow(deprecated)]
-    let fee_rule = StandardFeeRule::PreZip313;
-
     // Add funds to the wallet in a single note
-    let value = NonNegativeAmount::const_from_u64(50000);
-    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
-    st.scan_cached_blocks(h1, 1);
-
-    // Spendable balance matches total balance at 1 confirmation.
-    assert_eq!(st.get_total_balance(account_id), value);
-    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+    let (h, _, _) = st.generate_next_block(
+        &dfvk,
+        AddressType::DefaultExternal,
+        NonNegativeAmount::const_from_u64(60000),
+    );
+    st.scan_cached_blocks(h, 1);
 
-    // Send some of the funds to another address, but don't mine the tx.
-    let extsk2 = T::sk(&[0xf5; 32]);
-    let to = T::sk_default_address(&extsk2);
-    let min_confirmations = NonZeroU32::new(1).unwrap();
+    let request = TransactionRequest::new(vec![Payment {
+        recipient_address: T::sk_default_address(&T::sk(&[0xf5; 32])),
+        amount: NonNegativeAmount::const_from_u64(10000),
+        memo: None,
+        label: None,
+        message: None,
+        other_params: vec![],
+    }])
+    .unwrap();
     let proposal = st
-        .propose_standard_transfer::<Infallible>(
-            account_id,
-            fee_rule,
-            min_confirmations,
-            &to,
-            NonNegativeAmount::const_from_u64(15000),
-            None,
-            None,
-            T::SHIELDED_PROTOCOL,
+        .propose_transfer(
+            account.account_id(),
+            &input_selector(StandardFeeRule::Zip317, None, T::SHIELDED_PROTOCOL),
+            request,
+            NonZeroU32::new(1).unwrap(),
         )
         .unwrap();
-
-    // Executing the proposal should succeed
+    let proposal_proto = proposal::Proposal::from_standard_proposal(&st.network(), &proposal);
+    let note = &proposal.steps().head.shielded_inputs().unwrap().notes()[0];
+
+    // An input whose value differs from that of the wallet's note is rejected.
+    let mut tampered = proposal_proto.clone();
+    match tampered.steps[0].inputs[0].value.as_mut() {
+        Some(proposal::proposed_input::Value::ReceivedOutput(out)) => out.value += 1,
+        other => panic!("Unexpected input {:?}", other),
+    }
     assert_matches!(
-        st.create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal,),
-        Ok(txids) if txids.len() == 1
+        tampered.try_into_checked_standard_proposal(&st.network(), st.wallet_mut()),
+        Err(ProposalDecodingError::InputValueMismatch(txid, PoolType::Shielded(protocol), index))
+            if &txid == note.txid()
+                && protocol == T::SHIELDED_PROTOCOL
+                && index == u32::from(note.output_index())
     );
 
-    // A second proposal fails because there are no usable notes
+    // An anchor height at which the wallet has no checkpoint is rejected by the checked
+    // decoding, and by the execution of a proposal decoded without that check.
+    let missing_anchor = h + 10;
+    let mut tampered = proposal_proto;
+    tampered.steps[0].anchor_height = missing_anchor.into();
     assert_matches!(
-        st.propose_standard_transfer::<Infallible>(
-            account_id,
-            fee_rule,
-            NonZeroU32::new(1).unwrap(),
-            &to,
-            NonNegativeAmount::const_from_u64(2000),
-            None,
-            None,
-            T::SHIELDED_PROTOCOL,
+        tampered.try_into_checked_standard_proposal(&st.network(), st.wallet_mut()),
+        Err(ProposalDecodingError::AnchorNotAvailable(height)) if height == missing_anchor
+    );
+    let unchecked = tampered
+        .try_into_standard_proposal(&st.network(), st.wallet())
+        .unwrap();
+    assert_matches!(
+        st.create_proposed_transactions::<Infallible, _>(
+            account.usk(),
+            OvkPolicy::Sender,
+            &unchecked
         ),
-        Err(data_api::error::Error::InsufficientFunds {
-            available,
-            required
-        })
-        if available == NonNegativeAmount::ZERO && required == NonNegativeAmount::const_from_u64(12000)
+        Err(Error::AnchorNotAvailable(height)) if height == missing_anchor
     );
+}
 
-    // Mine blocks SAPLING_ACTIVATION_HEIGHT + 1 to 41 (that don't send us funds)
-    // until just before the first transaction expires
-    for i in 1..42 {
-        st.generate_next_block(
-            &T::sk_to_fvk(&T::sk(&[i as u8; 32])),
-            AddressType::DefaultExternal,
-            value,
-        );
-    }
-    st.scan_cached_blocks(h1 + 1, 40);
+#[allow(deprecated)]
+pub(crate) fn create_to_address_fails_on_incorrect_usk<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+    let dfvk = T::test_account_fvk(&st);
+    let to = T::fvk_default_address(&dfvk);
 
-    // Second proposal still fails
+    // Create a USK that doesn't exist in the wallet
+    let acct1 = zip32::AccountId::try_from(1).unwrap();
+    let usk1 = UnifiedSpendingKey::from_seed(&st.network(), &[], &[1u8; 32], acct1).unwrap();
+
+    // Attempting to spend with a USK that is not in the wallet results in an error
     assert_matches!(
-        st.propose_standard_transfer::<Infallible>(
-            account_id,
-            fee_rule,
-            NonZeroU32::new(1).unwrap(),
+        st.create_spend_to_address(
+            &usk1,
             &to,
-            NonNegativeAmount::const_from_u64(2000),
+            NonNegativeAmount::const_from_u64(1),
             None,
+            OvkPolicy::Sender,
+            NonZeroU32::new(1).unwrap(),
             None,
             T::SHIELDED_PROTOCOL,
         ),
-        Err(data_api::error::Error::InsufficientFunds {
-            available,
-            required
-        })
-        if available == NonNegativeAmount::ZERO && required == NonNegativeAmount::const_from_u64(12000)
+        Err(data_api::error::Error::KeyNotRecognized)
     );
+}
 
-    // Mine block SAPLING_ACTIVATION_HEIGHT + 42 so that the first transaction expires
-    let (h43, _, _) = st.generate_next_block(
-        &T::sk_to_fvk(&T::sk(&[42; 32])),
-        AddressType::DefaultExternal,
-        value,
-    );
-    st.scan_cached_blocks(h43, 1);
+#[allow(deprecated)]
+pub(crate) fn proposal_fails_with_no_blocks<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
 
-    // Spendable balance matches total balance at 1 confirmation.
-    assert_eq!(st.get_total_balance(account_id), value);
-    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+    let account_id = st.test_account().unwrap().account_id();
+    let dfvk = T::test_account_fvk(&st);
+    let to = T::fvk_default_address(&dfvk);
 
-    // Second spend should now succeed
-    let amount_sent2 = NonNegativeAmount::const_from_u64(2000);
-    let min_confirmations = NonZeroU32::new(1).unwrap();
-    let proposal = st
-        .propose_standard_transfer::<Infallible>(
+    // Wallet summary is not yet available
+    assert_eq!(st.get_wallet_summary(0), None);
+
+    // We cannot do anything if we aren't synchronised
+    assert_matches!(
+        st.propose_standard_transfer::<Infallible>(
             account_id,
-            fee_rule,
-            min_confirmations,
+            StandardFeeRule::PreZip313,
+            NonZeroU32::new(1).unwrap(),
             &to,
-            amount_sent2,
+            NonNegativeAmount::const_from_u64(1),
             None,
             None,
             T::SHIELDED_PROTOCOL,
-        )
-        .unwrap();
-
-    let txid2 = st
-        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
-        .unwrap()[0];
-
-    let (h, _) = st.generate_next_block_including(txid2);
-    st.scan_cached_blocks(h, 1);
-
-    // TODO: send to an account so that we can check its balance.
-    assert_eq!(
-        st.get_total_balance(account_id),
-        (value - (amount_sent2 + NonNegativeAmount::from_u64(10000).unwrap()).unwrap()).unwrap()
+        ),
+        Err(data_api::error::Error::ScanRequired)
     );
 }
 
-pub(crate) fn ovk_policy_prevents_recovery_from_chain<T: ShieldedPoolTester>() {
+pub(crate) fn promote_draft_after_chain_advance<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32]))
         .build();
 
     let account = st.test_account().cloned().unwrap();
-    let account_id = account.account_id();
     let dfvk = T::test_account_fvk(&st);
 
     // Add funds to the wallet in a single note
-    let value = NonNegativeAmount::const_from_u64(50000);
-    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
-    st.scan_cached_blocks(h1, 1);
-
-    // Spendable balance matches total balance at 1 confirmation.
-    assert_eq!(st.get_total_balance(account_id), value);
-    assert_eq!(st.get_spendable_balance(account_id, 1), value);
-
-    let extsk2 = T::sk(&[0xf5; 32]);
-    let addr2 = T::sk_default_address(&extsk2);
-
-    // TODO: This test was originally written to use the pre-zip-313 fee rule
-    // and has not yet been updated.
-    #[allow(deprecated)]
-    let fee_rule = StandardFeeRule::PreZip313;
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
 
-    #[allow(clippy::type_complexity)]
-    let send_and_recover_with_policy = |st: &mut TestState<BlockCache>,
-                                        ovk_policy|
-     -> Result<
-        Option<(Note, Address, MemoBytes)>,
-        Error<
-            SqliteClientError,
-            commitment_tree::Error,
-            GreedyInputSelectorError<Zip317FeeError, ReceivedNoteId>,
-            Zip317FeeError,
-        >,
-    > {
-        let min_confirmations = NonZeroU32::new(1).unwrap();
-        let proposal = st.propose_standard_transfer(
-            account_id,
-            fee_rule,
-            min_confirmations,
-            &addr2,
-            NonNegativeAmount::const_from_u64(15000),
-            None,
-            None,
-            T::SHIELDED_PROTOCOL,
-        )?;
+    let to_extsk = T::sk(&[0xf5; 32]);
+    let to: Address = T::sk_default_address(&to_extsk);
+    let request = zip321::TransactionRequest::new(vec![Payment {
+        recipient_address: to,
+        amount: NonNegativeAmount::const_from_u64(10000),
+        memo: None,
+        label: None,
+        message: None,
+        other_params: vec![],
+    }])
+    .unwrap();
+    let draft = DraftPayment::new(
+        request,
+        NonZeroU32::new(1).unwrap(),
+        "Pay back lunch".to_string(),
+    );
 
-        // Executing the proposal should succeed
-        let txid = st.create_proposed_transactions(account.usk(), ovk_policy, &proposal)?[0];
+    let draft_id = st
+        .wallet_mut()
+        .save_draft(account.account_id(), &draft)
+        .unwrap();
+    assert_eq!(
+        st.wallet().get_drafts(account.account_id()).unwrap(),
+        vec![(draft_id, draft.clone())]
+    );
 
-        // Fetch the transaction from the database
-        let raw_tx: Vec<_> = st
-            .wallet()
-            .conn
-            .query_row(
-                "SELECT raw FROM transactions
-                WHERE txid = ?",
-                [txid.as_ref()],
-                |row| row.get(0),
-            )
-            .unwrap();
-        let tx = Transaction::read(&raw_tx[..], BranchId::Canopy).unwrap();
+    // Saving a draft has no effect on the wallet's balance.
+    assert_eq!(st.get_total_balance(account.account_id()), value);
+    assert_eq!(st.get_spendable_balance(account.account_id(), 1), value);
 
-        T::try_output_recovery(st, h1, &tx, &dfvk)
-    };
+    // Advance the chain before the draft is confirmed.
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
 
-    // Send some of the funds to another address, keeping history.
-    // The recipient output is decryptable by the sender.
+    // The draft is proposed against the current chain state, and the proposal can be
+    // executed.
+    #[allow(deprecated)]
+    let fee_rule = StandardFeeRule::PreZip313;
+    let change_strategy =
+        standard::SingleOutputChangeStrategy::new(fee_rule, None, T::SHIELDED_PROTOCOL);
+    let input_selector = &GreedyInputSelector::new(change_strategy, DustOutputPolicy::default());
+    let proposal = st
+        .promote_draft_to_proposal(input_selector, draft_id)
+        .unwrap();
+    assert_eq!(proposal.min_target_height(), h + 1);
     assert_matches!(
-        send_and_recover_with_policy(&mut st, OvkPolicy::Sender),
-        Ok(Some((_, recovered_to, _))) if recovered_to == addr2
+        st.create_proposed_transactions::<Infallible, _>(
+            account.usk(),
+            OvkPolicy::Sender,
+            &proposal,
+        ),
+        Ok(txids) if txids.len() == 1
     );
 
-    // Mine blocks SAPLING_ACTIVATION_HEIGHT + 1 to 42 (that don't send us funds)
-    // so that the first transaction expires
-    for i in 1..=42 {
-        st.generate_next_block(
-            &T::sk_to_fvk(&T::sk(&[i as u8; 32])),
-            AddressType::DefaultExternal,
-            value,
-        );
-    }
-    st.scan_cached_blocks(h1 + 1, 42);
+    // Promotion leaves the draft in place; deleting it removes every trace of it.
+    assert_eq!(
+        st.wallet().get_draft(draft_id).unwrap(),
+        Some((account.account_id(), draft))
+    );
+    st.wallet_mut().delete_draft(draft_id).unwrap();
+    assert_eq!(st.wallet().get_draft(draft_id).unwrap(), None);
+    assert_eq!(
+        st.wallet().get_drafts(account.account_id()).unwrap(),
+        vec![]
+    );
+    let draft_count: u32 = st
+        .wallet()
+        .conn
+        .query_row("SELECT COUNT(*) FROM payment_drafts", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(draft_count, 0);
 
-    // Send the funds again, discarding history.
-    // Neither transaction output is decryptable by the sender.
+    // A deleted draft can no longer be promoted.
     assert_matches!(
-        send_and_recover_with_policy(&mut st, OvkPolicy::Discard),
-        Ok(None)
+        st.promote_draft_to_proposal(input_selector, draft_id),
+        Err(data_api::error::Error::DraftNotFound(id)) if id == draft_id
     );
 }
 
-pub(crate) fn spend_succeeds_to_t_addr_zero_change<T: ShieldedPoolTester>() {
+pub(crate) fn uivk_account_receives_but_cannot_spend<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32]))
         .build();
 
     let account = st.test_account().cloned().unwrap();
-    let account_id = account.account_id();
-    let dfvk = T::test_account_fvk(&st);
+    let usk =
+        UnifiedSpendingKey::from_seed(&st.network(), &[], &[0xab; 32], zip32::AccountId::ZERO)
+            .unwrap();
+    let uivk = usk
+        .to_unified_full_viewing_key()
+        .to_unified_incoming_viewing_key();
+    let uivk_account = st
+        .wallet_mut()
+        .import_account_uivk(&uivk, account.birthday())
+        .unwrap();
 
-    // Add funds to the wallet in a single note
+    // Funds sent to the UIVK account are detected by scanning.
+    let fvk = T::sk_to_fvk(T::usk_to_sk(&usk));
     let value = NonNegativeAmount::const_from_u64(60000);
-    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    let (h, _, _) = st.generate_next_block(&fvk, AddressType::DefaultExternal, value);
     st.scan_cached_blocks(h, 1);
 
-    // Spendable balance matches total balance at 1 confirmation.
-    assert_eq!(st.get_total_balance(account_id), value);
-    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+    assert_eq!(st.get_total_balance(uivk_account), value);
+    assert_eq!(
+        st.get_spendable_balance(uivk_account, 1),
+        NonNegativeAmount::ZERO
+    );
 
-    // TODO: This test was originally written to use the pre-zip-313 fee rule
-    // and has not yet been updated.
-    #[allow(deprecated)]
-    let fee_rule = StandardFeeRule::PreZip313;
+    let summary = st.get_wallet_summary(1).unwrap();
+    assert!(summary.account_balances()[&uivk_account].is_received_only());
+    assert!(!summary.account_balances()[&account.account_id()].is_received_only());
 
-    // TODO: generate_next_block_from_tx does not currently support transparent outputs.
-    let to = TransparentAddress::PublicKeyHash([7; 20]).into();
-    let min_confirmations = NonZeroU32::new(1).unwrap();
-    let proposal = st
-        .propose_standard_transfer::<Infallible>(
-            account_id,
-            fee_rule,
-            min_confirmations,
+    // No nullifier could be derived for the received note.
+    let null_nf_count: u32 = st
+        .wallet()
+        .conn
+        .query_row(
+            &format!(
+                "SELECT COUNT(*) FROM {}_received_notes WHERE account_id = ? AND nf IS NULL",
+                T::TABLES_PREFIX
+            ),
+            [uivk_account.0],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(null_nf_count, 1);
+
+    // The account cannot be used as a source of funds.
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    assert_matches!(
+        st.propose_standard_transfer::<Infallible>(
+            uivk_account,
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
             &to,
-            NonNegativeAmount::const_from_u64(50000),
+            NonNegativeAmount::const_from_u64(10000),
             None,
             None,
             T::SHIELDED_PROTOCOL,
-        )
-        .unwrap();
-
-    // Executing the proposal should succeed
+        ),
+        Err(data_api::error::Error::AccountCannotSpend)
+    );
+    let request = TransactionRequest::new(vec![Payment::without_memo(
+        to,
+        NonNegativeAmount::const_from_u64(10000),
+    )])
+    .unwrap();
     assert_matches!(
-        st.create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal),
-        Ok(txids) if txids.len() == 1
+        st.propose_transfer(
+            uivk_account,
+            &input_selector(StandardFeeRule::Zip317, None, T::SHIELDED_PROTOCOL),
+            request,
+            NonZeroU32::new(1).unwrap(),
+        ),
+        Err(data_api::error::Error::AccountCannotSpend)
     );
 }
 
-pub(crate) fn change_note_spends_succeed<T: ShieldedPoolTester>() {
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn uivk_account_cannot_shield<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32]))
         .build();
 
     let account = st.test_account().cloned().unwrap();
-    let account_id = account.account_id();
     let dfvk = T::test_account_fvk(&st);
+    let usk =
+        UnifiedSpendingKey::from_seed(&st.network(), &[], &[0xab; 32], zip32::AccountId::ZERO)
+            .unwrap();
+    let uivk = usk
+        .to_unified_full_viewing_key()
+        .to_unified_incoming_viewing_key();
+    let uivk_account = st
+        .wallet_mut()
+        .import_account_uivk(&uivk, account.birthday())
+        .unwrap();
+    let taddr = *st
+        .wallet()
+        .get_transparent_receivers(uivk_account)
+        .unwrap()
+        .keys()
+        .next()
+        .unwrap();
 
-    // Add funds to the wallet in a single note owned by the internal spending key
-    let value = NonNegativeAmount::const_from_u64(60000);
-    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::Internal, value);
+    let (h, _, _) = st.generate_next_block(
+        &dfvk,
+        AddressType::DefaultExternal,
+        NonNegativeAmount::const_from_u64(50000),
+    );
     st.scan_cached_blocks(h, 1);
 
-    // Spendable balance matches total balance at 1 confirmation.
-    assert_eq!(st.get_total_balance(account_id), value);
-    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+    let utxo = WalletTransparentOutput::from_parts(
+        OutPoint::new([1u8; 32], 1),
+        TxOut {
+            value: NonNegativeAmount::const_from_u64(50000),
+            script_pubkey: taddr.script(),
+        },
+        h,
+    )
+    .unwrap();
+    st.wallet_mut()
+        .put_received_transparent_utxo(&utxo)
+        .unwrap();
 
-    // Value is considered pending at 10 confirmations.
-    assert_eq!(st.get_pending_shielded_balance(account_id, 10), value);
-    assert_eq!(
-        st.get_spendable_balance(account_id, 10),
-        NonNegativeAmount::ZERO
+    let input_selector = input_selector(StandardFeeRule::Zip317, None, T::SHIELDED_PROTOCOL);
+    assert_matches!(
+        st.propose_shielding(
+            &input_selector,
+            NonNegativeAmount::const_from_u64(10000),
+            &[taddr],
+            1
+        ),
+        Err(data_api::error::Error::AccountCannotSpend)
     );
 
-    let change_note_scope = st.wallet().conn.query_row(
-        &format!(
-            "SELECT recipient_key_scope
-             FROM {}_received_notes
-             WHERE value = ?",
-            T::TABLES_PREFIX,
+    // A proposal constructed without the wallet's checks is also rejected.
+    let network = st.network();
+    let proposal = input_selector
+        .propose_shielding(
+            &network,
+            st.wallet(),
+            NonNegativeAmount::const_from_u64(10000),
+            &[taddr],
+            h + 1,
+            1,
+        )
+        .unwrap();
+    let prover = test_prover();
+    assert_matches!(
+        create_proposed_transactions::<_, _, Infallible, _, _>(
+            st.wallet_mut(),
+            &network,
+            &prover,
+            &prover,
+            account.usk(),
+            OvkPolicy::Sender,
+            &proposal,
         ),
-        params![u64::from(value)],
-        |row| Ok(parse_scope(row.get(0)?)),
+        Err(data_api::error::Error::AccountCannotSpend)
     );
-    assert_matches!(change_note_scope, Ok(Some(Scope::Internal)));
+}
 
-    // TODO: This test was originally written to use the pre-zip-313 fee rule
-    // and has not yet been updated.
-    #[allow(deprecated)]
-    let fee_rule = StandardFeeRule::PreZip313;
+pub(crate) fn transaction_history_filtering_and_paging<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
 
-    // TODO: generate_next_block_from_tx does not currently support transparent outputs.
-    let to = TransparentAddress::PublicKeyHash([7; 20]).into();
-    let min_confirmations = NonZeroU32::new(1).unwrap();
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+    let pool = PoolType::Shielded(T::SHIELDED_PROTOCOL);
+
+    // Receive funds in two separate blocks.
+    let value1 = NonNegativeAmount::const_from_u64(50000);
+    let value2 = NonNegativeAmount::const_from_u64(60000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value1);
+    let (h2, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value2);
+    st.scan_cached_blocks(h1, 2);
+
+    // Send funds with a memo, leaving the transaction unmined.
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let amount_sent = NonNegativeAmount::const_from_u64(10000);
+    let memo = "Pay back lunch".parse::<Memo>().unwrap();
     let proposal = st
         .propose_standard_transfer::<Infallible>(
             account_id,
-            fee_rule,
-            min_confirmations,
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
             &to,
-            NonNegativeAmount::const_from_u64(50000),
-            None,
+            amount_sent,
+            Some(memo.into()),
             None,
             T::SHIELDED_PROTOCOL,
         )
         .unwrap();
-
-    // Executing the proposal should succeed
-    assert_matches!(
-        st.create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal),
-        Ok(txids) if txids.len() == 1
-    );
-}
-
-pub(crate) fn external_address_change_spends_detected_in_restore_from_seed<
-    T: ShieldedPoolTester,
->() {
-    let mut st = TestBuilder::new().with_block_cache().build();
-
-    // Add two accounts to the wallet.
-    let seed = Secret::new([0u8; 32].to_vec());
-    let birthday = AccountBirthday::from_sapling_activation(&st.network(), BlockHash([0; 32]));
-    let (account_id, usk) = st.wallet_mut().create_account(&seed, &birthday).unwrap();
-    let dfvk = T::sk_to_fvk(T::usk_to_sk(&usk));
-
-    let (account2, usk2) = st.wallet_mut().create_account(&seed, &birthday).unwrap();
-    let dfvk2 = T::sk_to_fvk(T::usk_to_sk(&usk2));
-
-    // Add funds to the wallet in a single note
-    let value = NonNegativeAmount::from_u64(100000).unwrap();
-    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
-    st.scan_cached_blocks(h, 1);
-
-    // Spendable balance matches total balance
-    assert_eq!(st.get_total_balance(account_id), value);
-    assert_eq!(st.get_spendable_balance(account_id, 1), value);
-    assert_eq!(st.get_total_balance(account2), NonNegativeAmount::ZERO);
-
-    let amount_sent = NonNegativeAmount::from_u64(20000).unwrap();
-    let amount_legacy_change = NonNegativeAmount::from_u64(30000).unwrap();
-    let addr = T::fvk_default_address(&dfvk);
-    let addr2 = T::fvk_default_address(&dfvk2);
-    let req = TransactionRequest::new(vec![
-        // payment to an external recipient
-        Payment {
-            recipient_address: addr2,
-            amount: amount_sent,
-            memo: None,
-            label: None,
-            message: None,
-            other_params: vec![],
-        },
-        // payment back to the originating wallet, simulating legacy change
-        Payment {
-            recipient_address: addr,
-            amount: amount_legacy_change,
-            memo: None,
-            label: None,
-            message: None,
-            other_params: vec![],
-        },
-    ])
-    .unwrap();
-
-    #[allow(deprecated)]
-    let fee_rule = FixedFeeRule::standard();
-    let input_selector = GreedyInputSelector::new(
-        fixed::SingleOutputChangeStrategy::new(fee_rule, None, T::SHIELDED_PROTOCOL),
-        DustOutputPolicy::default(),
-    );
-
-    let txid = st
-        .spend(
-            &input_selector,
-            &usk,
-            req,
-            OvkPolicy::Sender,
-            NonZeroU32::new(1).unwrap(),
-        )
+    let fee = proposal.steps().head.balance().fee_required();
+    let sent_txid = st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
         .unwrap()[0];
 
-    let amount_left = (value - (amount_sent + fee_rule.fixed_fee()).unwrap()).unwrap();
-    let pending_change = (amount_left - amount_legacy_change).unwrap();
-
-    // The "legacy change" is not counted by get_pending_change().
-    assert_eq!(st.get_pending_change(account_id, 1), pending_change);
-    // We spent the only note so we only have pending change.
-    assert_eq!(st.get_total_balance(account_id), pending_change);
-
-    let (h, _) = st.generate_next_block_including(txid);
-    st.scan_cached_blocks(h, 1);
-
-    assert_eq!(st.get_total_balance(account2), amount_sent,);
-    assert_eq!(st.get_total_balance(account_id), amount_left);
-
-    st.reset();
+    let get_transactions =
+        |st: &TestState<BlockCache>, filter: &TransactionFilter, offset, limit| {
+            st.wallet()
+                .get_transactions(account_id, filter, offset, limit)
+                .unwrap()
+        };
 
-    // Account creation and DFVK derivation should be deterministic.
-    let (_, restored_usk) = st.wallet_mut().create_account(&seed, &birthday).unwrap();
-    assert!(T::fvks_equal(
-        &T::sk_to_fvk(T::usk_to_sk(&restored_usk)),
-        &dfvk,
-    ));
+    // The unmined transaction comes first, followed by mined transactions in descending order
+    // of height.
+    let all = get_transactions(&st, &TransactionFilter::new(), 0, 10);
+    assert_eq!(all.len(), 3);
+    assert_eq!(all[0].txid(), sent_txid);
+    assert_eq!(all[0].mined_height(), None);
+    assert_eq!(all[0].fee_paid(), Some(fee));
+    assert_eq!(
+        all[0].account_value_delta(),
+        -Amount::from((amount_sent + fee).unwrap())
+    );
+    assert!(all[0].has_memo());
+    // The payment and the change output.
+    assert_eq!(all[0].output_count(pool), 2);
+    assert_eq!(all[0].output_count(PoolType::Transparent), 0);
+    assert_eq!(all[1].mined_height(), Some(h2));
+    assert_eq!(all[1].account_value_delta(), Amount::from(value2));
+    assert!(!all[1].has_memo());
+    assert_eq!(all[1].output_count(pool), 1);
+    assert_eq!(all[2].mined_height(), Some(h1));
+    assert_eq!(all[2].account_value_delta(), Amount::from(value1));
+
+    // Filters are applied before paging.
+    let sent = get_transactions(
+        &st,
+        &TransactionFilter::new().with_direction(TransactionDirection::Sent),
+        0,
+        10,
+    );
+    assert_eq!(sent, all[..1].to_vec());
+    let received = get_transactions(
+        &st,
+        &TransactionFilter::new().with_direction(TransactionDirection::Received),
+        0,
+        10,
+    );
+    assert_eq!(received, all[1..].to_vec());
+    let at_h2 = get_transactions(
+        &st,
+        &TransactionFilter::new().with_mined_heights(h2..h2 + 1),
+        0,
+        10,
+    );
+    assert_eq!(at_h2, all[1..2].to_vec());
+    let mined = get_transactions(
+        &st,
+        &TransactionFilter::new().with_block_times(0..u32::MAX),
+        0,
+        10,
+    );
+    assert_eq!(mined, all[1..].to_vec());
+    let large = get_transactions(
+        &st,
+        &TransactionFilter::new().with_min_value(NonNegativeAmount::const_from_u64(55000)),
+        0,
+        10,
+    );
+    assert_eq!(large, all[1..2].to_vec());
 
-    let (_, restored_usk2) = st.wallet_mut().create_account(&seed, &birthday).unwrap();
-    assert!(T::fvks_equal(
-        &T::sk_to_fvk(T::usk_to_sk(&restored_usk2)),
-        &dfvk2,
-    ));
+    // A filter that excludes everything produces an empty result.
+    let none = get_transactions(
+        &st,
+        &TransactionFilter::new()
+            .with_direction(TransactionDirection::Sent)
+            .with_mined_heights(h1..h2 + 1),
+        0,
+        10,
+    );
+    assert!(none.is_empty());
 
-    st.scan_cached_blocks(st.sapling_activation_height(), 2);
+    // Pages partition the results.
+    assert_eq!(
+        get_transactions(&st, &TransactionFilter::new(), 0, 2),
+        all[..2]
+    );
+    assert_eq!(
+        get_transactions(&st, &TransactionFilter::new(), 2, 2),
+        all[2..]
+    );
+    assert!(get_transactions(&st, &TransactionFilter::new(), 3, 2).is_empty());
+    assert!(get_transactions(&st, &TransactionFilter::new(), 0, 0).is_empty());
 
-    assert_eq!(st.get_total_balance(account2), amount_sent,);
-    assert_eq!(st.get_total_balance(account_id), amount_left);
+    // Capping the mined height keeps pages consistent when new blocks arrive between them.
+    let snapshot = TransactionFilter::new().with_mined_heights(h1..h2 + 1);
+    let page1 = get_transactions(&st, &snapshot, 0, 1);
+    let (h3, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value1);
+    st.scan_cached_blocks(h3, 1);
+    let page2 = get_transactions(&st, &snapshot, 1, 1);
+    assert_eq!([page1, page2].concat(), all[1..]);
+    assert_eq!(
+        get_transactions(&st, &TransactionFilter::new(), 1, 1)[0].mined_height(),
+        Some(h3)
+    );
 }
 
-#[allow(dead_code)]
-pub(crate) fn zip317_spend<T: ShieldedPoolTester>() {
+pub(crate) fn spend_fails_on_unverified_notes<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32]))
@@ -1125,60 +1551,661 @@ pub(crate) fn zip317_spend<T: ShieldedPoolTester>() {
     let account_id = account.account_id();
     let dfvk = T::test_account_fvk(&st);
 
-    // Add funds to the wallet
-    let (h1, _, _) = st.generate_next_block(
-        &dfvk,
-        AddressType::Internal,
-        NonNegativeAmount::const_from_u64(50000),
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 1);
+
+    // Spendable balance matches total balance at 1 confirmation.
+    assert_eq!(st.get_total_balance(account_id), value);
+    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+
+    // Value is considered pending at 10 confirmations.
+    assert_eq!(st.get_pending_shielded_balance(account_id, 10), value);
+    assert_eq!(
+        st.get_spendable_balance(account_id, 10),
+        NonNegativeAmount::ZERO
     );
 
-    // Add 10 dust notes to the wallet
-    for _ in 1..=10 {
-        st.generate_next_block(
-            &dfvk,
-            AddressType::DefaultExternal,
-            NonNegativeAmount::const_from_u64(1000),
-        );
-    }
+    // Wallet is fully scanned
+    let summary = st.get_wallet_summary(1);
+    assert_eq!(
+        summary.and_then(|s| s.scan_progress()),
+        Some(Ratio::new(1, 1))
+    );
 
-    st.scan_cached_blocks(h1, 11);
+    // Add more funds to the wallet in a second note
+    let (h2, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h2, 1);
 
-    // Spendable balance matches total balance
-    let total = NonNegativeAmount::const_from_u64(60000);
+    // Verified balance does not include the second note
+    let total = (value + value).unwrap();
+    assert_eq!(st.get_spendable_balance(account_id, 2), value);
+    assert_eq!(st.get_pending_shielded_balance(account_id, 2), value);
     assert_eq!(st.get_total_balance(account_id), total);
-    assert_eq!(st.get_spendable_balance(account_id, 1), total);
-
-    let input_selector = input_selector(StandardFeeRule::Zip317, None, T::SHIELDED_PROTOCOL);
 
-    // This first request will fail due to insufficient non-dust funds
-    let req = TransactionRequest::new(vec![Payment {
-        recipient_address: T::fvk_default_address(&dfvk),
-        amount: NonNegativeAmount::const_from_u64(50000),
-        memo: None,
-        label: None,
-        message: None,
-        other_params: vec![],
-    }])
-    .unwrap();
+    // Wallet is still fully scanned
+    let summary = st.get_wallet_summary(1);
+    assert_eq!(
+        summary.and_then(|s| s.scan_progress()),
+        Some(Ratio::new(2, 2))
+    );
 
+    // Spend fails because there are insufficient verified notes
+    let extsk2 = T::sk(&[0xf5; 32]);
+    let to = T::sk_default_address(&extsk2);
     assert_matches!(
-        st.spend(
-            &input_selector,
-            account.usk(),
-            req,
-            OvkPolicy::Sender,
-            NonZeroU32::new(1).unwrap(),
+        st.propose_standard_transfer::<Infallible>(
+            account_id,
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(2).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(70000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
         ),
-        Err(Error::InsufficientFunds { available, required })
-            if available == NonNegativeAmount::const_from_u64(51000)
-            && required == NonNegativeAmount::const_from_u64(60000)
+        Err(data_api::error::Error::InsufficientFunds {
+            available,
+            required
+        })
+        if available == NonNegativeAmount::const_from_u64(50000)
+            && required == NonNegativeAmount::const_from_u64(80000)
     );
 
-    // This request will succeed, spending a single dust input to pay the 10000
-    // ZAT fee in addition to the 41000 ZAT output to the recipient
-    let req = TransactionRequest::new(vec![Payment {
-        recipient_address: T::fvk_default_address(&dfvk),
-        amount: NonNegativeAmount::const_from_u64(41000),
+    // Mine blocks SAPLING_ACTIVATION_HEIGHT + 2 to 9 until just before the second
+    // note is verified
+    for _ in 2..10 {
+        st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    }
+    st.scan_cached_blocks(h2 + 1, 8);
+
+    // Total balance is value * number of blocks scanned (10).
+    assert_eq!(st.get_total_balance(account_id), (value * 10).unwrap());
+
+    // Spend still fails
+    assert_matches!(
+        st.propose_standard_transfer::<Infallible>(
+            account_id,
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(10).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(70000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        ),
+        Err(data_api::error::Error::InsufficientFunds {
+            available,
+            required
+        })
+        if available == NonNegativeAmount::const_from_u64(50000)
+            && required == NonNegativeAmount::const_from_u64(80000)
+    );
+
+    // Mine block 11 so that the second note becomes verified
+    let (h11, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h11, 1);
+
+    // Total balance is value * number of blocks scanned (11).
+    assert_eq!(st.get_total_balance(account_id), (value * 11).unwrap());
+    // Spendable balance at 10 confirmations is value * 2.
+    assert_eq!(
+        st.get_spendable_balance(account_id, 10),
+        (value * 2).unwrap()
+    );
+    assert_eq!(
+        st.get_pending_shielded_balance(account_id, 10),
+        (value * 9).unwrap()
+    );
+
+    // Should now be able to generate a proposal
+    let amount_sent = NonNegativeAmount::from_u64(70000).unwrap();
+    let min_confirmations = NonZeroU32::new(10).unwrap();
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account_id,
+            StandardFeeRule::Zip317,
+            min_confirmations,
+            &to,
+            amount_sent,
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+
+    // Executing the proposal should succeed
+    let txid = st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()[0];
+
+    let (h, _) = st.generate_next_block_including(txid);
+    st.scan_cached_blocks(h, 1);
+
+    // TODO: send to an account so that we can check its balance.
+    assert_eq!(
+        st.get_total_balance(account_id),
+        ((value * 11).unwrap()
+            - (amount_sent + NonNegativeAmount::from_u64(10000).unwrap()).unwrap())
+        .unwrap()
+    );
+}
+
+pub(crate) fn spend_fails_on_locked_notes<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    // TODO: This test was originally written to use the pre-zip-313 fee rule
+    // and has not yet been updated.
+    #[allow(deprecated)]
+    let fee_rule = StandardFeeRule::PreZip313;
+
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 1);
+
+    // Spendable balance matches total balance at 1 confirmation.
+    assert_eq!(st.get_total_balance(account_id), value);
+    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+
+    // Send some of the funds to another address, but don't mine the tx.
+    let extsk2 = T::sk(&[0xf5; 32]);
+    let to = T::sk_default_address(&extsk2);
+    let min_confirmations = NonZeroU32::new(1).unwrap();
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account_id,
+            fee_rule,
+            min_confirmations,
+            &to,
+            NonNegativeAmount::const_from_u64(15000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+
+    // Executing the proposal should succeed
+    assert_matches!(
+        st.create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal,),
+        Ok(txids) if txids.len() == 1
+    );
+
+    // A second proposal fails because there are no usable notes
+    assert_matches!(
+        st.propose_standard_transfer::<Infallible>(
+            account_id,
+            fee_rule,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(2000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        ),
+        Err(data_api::error::Error::InsufficientFunds {
+            available,
+            required
+        })
+        if available == NonNegativeAmount::ZERO && required == NonNegativeAmount::const_from_u64(12000)
+    );
+
+    // Mine blocks SAPLING_ACTIVATION_HEIGHT + 1 to 41 (that don't send us funds)
+    // until just before the first transaction expires
+    for i in 1..42 {
+        st.generate_next_block(
+            &T::sk_to_fvk(&T::sk(&[i as u8; 32])),
+            AddressType::DefaultExternal,
+            value,
+        );
+    }
+    st.scan_cached_blocks(h1 + 1, 40);
+
+    // Second proposal still fails
+    assert_matches!(
+        st.propose_standard_transfer::<Infallible>(
+            account_id,
+            fee_rule,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(2000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        ),
+        Err(data_api::error::Error::InsufficientFunds {
+            available,
+            required
+        })
+        if available == NonNegativeAmount::ZERO && required == NonNegativeAmount::const_from_u64(12000)
+    );
+
+    // Mine block SAPLING_ACTIVATION_HEIGHT + 42 so that the first transaction expires
+    let (h43, _, _) = st.generate_next_block(
+        &T::sk_to_fvk(&T::sk(&[42; 32])),
+        AddressType::DefaultExternal,
+        value,
+    );
+    st.scan_cached_blocks(h43, 1);
+
+    // Spendable balance matches total balance at 1 confirmation.
+    assert_eq!(st.get_total_balance(account_id), value);
+    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+
+    // Second spend should now succeed
+    let amount_sent2 = NonNegativeAmount::const_from_u64(2000);
+    let min_confirmations = NonZeroU32::new(1).unwrap();
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account_id,
+            fee_rule,
+            min_confirmations,
+            &to,
+            amount_sent2,
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+
+    let txid2 = st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()[0];
+
+    let (h, _) = st.generate_next_block_including(txid2);
+    st.scan_cached_blocks(h, 1);
+
+    // TODO: send to an account so that we can check its balance.
+    assert_eq!(
+        st.get_total_balance(account_id),
+        (value - (amount_sent2 + NonNegativeAmount::from_u64(10000).unwrap()).unwrap()).unwrap()
+    );
+}
+
+pub(crate) fn ovk_policy_prevents_recovery_from_chain<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 1);
+
+    // Spendable balance matches total balance at 1 confirmation.
+    assert_eq!(st.get_total_balance(account_id), value);
+    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+
+    let extsk2 = T::sk(&[0xf5; 32]);
+    let addr2 = T::sk_default_address(&extsk2);
+
+    // TODO: This test was originally written to use the pre-zip-313 fee rule
+    // and has not yet been updated.
+    #[allow(deprecated)]
+    let fee_rule = StandardFeeRule::PreZip313;
+
+    #[allow(clippy::type_complexity)]
+    let send_and_recover_with_policy = |st: &mut TestState<BlockCache>,
+                                        ovk_policy|
+     -> Result<
+        Option<(Note, Address, MemoBytes)>,
+        Error<
+            SqliteClientError,
+            commitment_tree::Error,
+            GreedyInputSelectorError<Zip317FeeError, ReceivedNoteId>,
+            Zip317FeeError,
+        >,
+    > {
+        let min_confirmations = NonZeroU32::new(1).unwrap();
+        let proposal = st.propose_standard_transfer(
+            account_id,
+            fee_rule,
+            min_confirmations,
+            &addr2,
+            NonNegativeAmount::const_from_u64(15000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )?;
+
+        // Executing the proposal should succeed
+        let txid = st.create_proposed_transactions(account.usk(), ovk_policy, &proposal)?[0];
+
+        // Fetch the transaction from the database
+        let raw_tx: Vec<_> = st
+            .wallet()
+            .conn
+            .query_row(
+                "SELECT raw FROM transactions
+                WHERE txid = ?",
+                [txid.as_ref()],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let tx = Transaction::read(&raw_tx[..], BranchId::Canopy).unwrap();
+
+        T::try_output_recovery(st, h1, &tx, &dfvk)
+    };
+
+    // Send some of the funds to another address, keeping history.
+    // The recipient output is decryptable by the sender.
+    assert_matches!(
+        send_and_recover_with_policy(&mut st, OvkPolicy::Sender),
+        Ok(Some((_, recovered_to, _))) if recovered_to == addr2
+    );
+
+    // Mine blocks SAPLING_ACTIVATION_HEIGHT + 1 to 42 (that don't send us funds)
+    // so that the first transaction expires
+    for i in 1..=42 {
+        st.generate_next_block(
+            &T::sk_to_fvk(&T::sk(&[i as u8; 32])),
+            AddressType::DefaultExternal,
+            value,
+        );
+    }
+    st.scan_cached_blocks(h1 + 1, 42);
+
+    // Send the funds again, discarding history.
+    // Neither transaction output is decryptable by the sender.
+    assert_matches!(
+        send_and_recover_with_policy(&mut st, OvkPolicy::Discard),
+        Ok(None)
+    );
+}
+
+pub(crate) fn spend_succeeds_to_t_addr_zero_change<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    // Spendable balance matches total balance at 1 confirmation.
+    assert_eq!(st.get_total_balance(account_id), value);
+    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+
+    // TODO: This test was originally written to use the pre-zip-313 fee rule
+    // and has not yet been updated.
+    #[allow(deprecated)]
+    let fee_rule = StandardFeeRule::PreZip313;
+
+    // TODO: generate_next_block_from_tx does not currently support transparent outputs.
+    let to = TransparentAddress::PublicKeyHash([7; 20]).into();
+    let min_confirmations = NonZeroU32::new(1).unwrap();
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account_id,
+            fee_rule,
+            min_confirmations,
+            &to,
+            NonNegativeAmount::const_from_u64(50000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+
+    // Executing the proposal should succeed
+    assert_matches!(
+        st.create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal),
+        Ok(txids) if txids.len() == 1
+    );
+}
+
+pub(crate) fn change_note_spends_succeed<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet in a single note owned by the internal spending key
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::Internal, value);
+    st.scan_cached_blocks(h, 1);
+
+    // Spendable balance matches total balance at 1 confirmation.
+    assert_eq!(st.get_total_balance(account_id), value);
+    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+
+    // Value is considered pending at 10 confirmations.
+    assert_eq!(st.get_pending_shielded_balance(account_id, 10), value);
+    assert_eq!(
+        st.get_spendable_balance(account_id, 10),
+        NonNegativeAmount::ZERO
+    );
+
+    let change_note_scope = st.wallet().conn.query_row(
+        &format!(
+            "SELECT recipient_key_scope
+             FROM {}_received_notes
+             WHERE value = ?",
+            T::TABLES_PREFIX,
+        ),
+        params![u64::from(value)],
+        |row| Ok(parse_scope(row.get(0)?)),
+    );
+    assert_matches!(change_note_scope, Ok(Some(Scope::Internal)));
+
+    // TODO: This test was originally written to use the pre-zip-313 fee rule
+    // and has not yet been updated.
+    #[allow(deprecated)]
+    let fee_rule = StandardFeeRule::PreZip313;
+
+    // TODO: generate_next_block_from_tx does not currently support transparent outputs.
+    let to = TransparentAddress::PublicKeyHash([7; 20]).into();
+    let min_confirmations = NonZeroU32::new(1).unwrap();
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account_id,
+            fee_rule,
+            min_confirmations,
+            &to,
+            NonNegativeAmount::const_from_u64(50000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+
+    // Executing the proposal should succeed
+    assert_matches!(
+        st.create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal),
+        Ok(txids) if txids.len() == 1
+    );
+}
+
+pub(crate) fn external_address_change_spends_detected_in_restore_from_seed<
+    T: ShieldedPoolTester,
+>() {
+    let mut st = TestBuilder::new().with_block_cache().build();
+
+    // Add two accounts to the wallet.
+    let seed = Secret::new([0u8; 32].to_vec());
+    let birthday = AccountBirthday::from_sapling_activation(&st.network(), BlockHash([0; 32]));
+    let (account_id, usk) = st.wallet_mut().create_account(&secrecy::SecretVec::new(vec![]), &seed, &birthday).unwrap();
+    let dfvk = T::sk_to_fvk(T::usk_to_sk(&usk));
+
+    let (account2, usk2) = st.wallet_mut().create_account(&secrecy::SecretVec::new(vec![]), &seed, &birthday).unwrap();
+    let dfvk2 = T::sk_to_fvk(T::usk_to_sk(&usk2));
+
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::from_u64(100000).unwrap();
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    // Spendable balance matches total balance
+    assert_eq!(st.get_total_balance(account_id), value);
+    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+    assert_eq!(st.get_total_balance(account2), NonNegativeAmount::ZERO);
+
+    let amount_sent = NonNegativeAmount::from_u64(20000).unwrap();
+    let amount_legacy_change = NonNegativeAmount::from_u64(30000).unwrap();
+    let addr = T::fvk_default_address(&dfvk);
+    let addr2 = T::fvk_default_address(&dfvk2);
+    let req = TransactionRequest::new(vec![
+        // payment to an external recipient
+        Payment {
+            recipient_address: addr2,
+            amount: amount_sent,
+            memo: None,
+            label: None,
+            message: None,
+            other_params: vec![],
+        },
+        // payment back to the originating wallet, simulating legacy change
+        Payment {
+            recipient_address: addr,
+            amount: amount_legacy_change,
+            memo: None,
+            label: None,
+            message: None,
+            other_params: vec![],
+        },
+    ])
+    .unwrap();
+
+    #[allow(deprecated)]
+    let fee_rule = FixedFeeRule::standard();
+    let input_selector = GreedyInputSelector::new(
+        fixed::SingleOutputChangeStrategy::new(fee_rule, None, T::SHIELDED_PROTOCOL),
+        DustOutputPolicy::default(),
+    );
+
+    let txid = st
+        .spend(
+            &input_selector,
+            &usk,
+            req,
+            OvkPolicy::Sender,
+            NonZeroU32::new(1).unwrap(),
+        )
+        .unwrap()[0];
+
+    let amount_left = (value - (amount_sent + fee_rule.fixed_fee()).unwrap()).unwrap();
+    let pending_change = (amount_left - amount_legacy_change).unwrap();
+
+    // The "legacy change" is not counted by get_pending_change().
+    assert_eq!(st.get_pending_change(account_id, 1), pending_change);
+    // We spent the only note so we only have pending change.
+    assert_eq!(st.get_total_balance(account_id), pending_change);
+
+    let (h, _) = st.generate_next_block_including(txid);
+    st.scan_cached_blocks(h, 1);
+
+    assert_eq!(st.get_total_balance(account2), amount_sent,);
+    assert_eq!(st.get_total_balance(account_id), amount_left);
+
+    st.reset();
+
+    // Account creation and DFVK derivation should be deterministic.
+    let (_, restored_usk) = st.wallet_mut().create_account(&secrecy::SecretVec::new(vec![]), &seed, &birthday).unwrap();
+    assert!(T::fvks_equal(
+        &T::sk_to_fvk(T::usk_to_sk(&restored_usk)),
+        &dfvk,
+    ));
+
+    let (_, restored_usk2) = st.wallet_mut().create_account(&secrecy::SecretVec::new(vec![]), &seed, &birthday).unwrap();
+    assert!(T::fvks_equal(
+        &T::sk_to_fvk(T::usk_to_sk(&restored_usk2)),
+        &dfvk2,
+    ));
+
+    st.scan_cached_blocks(st.sapling_activation_height(), 2);
+
+    assert_eq!(st.get_total_balance(account2), amount_sent,);
+    assert_eq!(st.get_total_balance(account_id), amount_left);
+}
+
+#[allow(dead_code)]
+pub(crate) fn zip317_spend<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet
+    let (h1, _, _) = st.generate_next_block(
+        &dfvk,
+        AddressType::Internal,
+        NonNegativeAmount::const_from_u64(50000),
+    );
+
+    // Add 10 dust notes to the wallet
+    for _ in 1..=10 {
+        st.generate_next_block(
+            &dfvk,
+            AddressType::DefaultExternal,
+            NonNegativeAmount::const_from_u64(1000),
+        );
+    }
+
+    st.scan_cached_blocks(h1, 11);
+
+    // Spendable balance matches total balance
+    let total = NonNegativeAmount::const_from_u64(60000);
+    assert_eq!(st.get_total_balance(account_id), total);
+    assert_eq!(st.get_spendable_balance(account_id, 1), total);
+
+    let input_selector = input_selector(StandardFeeRule::Zip317, None, T::SHIELDED_PROTOCOL);
+
+    // This first request will fail due to insufficient non-dust funds
+    let req = TransactionRequest::new(vec![Payment {
+        recipient_address: T::fvk_default_address(&dfvk),
+        amount: NonNegativeAmount::const_from_u64(50000),
+        memo: None,
+        label: None,
+        message: None,
+        other_params: vec![],
+    }])
+    .unwrap();
+
+    assert_matches!(
+        st.spend(
+            &input_selector,
+            account.usk(),
+            req,
+            OvkPolicy::Sender,
+            NonZeroU32::new(1).unwrap(),
+        ),
+        Err(Error::InsufficientFunds { available, required })
+            if available == NonNegativeAmount::const_from_u64(51000)
+            && required == NonNegativeAmount::const_from_u64(60000)
+    );
+
+    // This request will succeed, spending a single dust input to pay the 10000
+    // ZAT fee in addition to the 41000 ZAT output to the recipient
+    let req = TransactionRequest::new(vec![Payment {
+        recipient_address: T::fvk_default_address(&dfvk),
+        amount: NonNegativeAmount::const_from_u64(41000),
         memo: None,
         label: None,
         message: None,
@@ -1186,25 +2213,517 @@ pub(crate) fn zip317_spend<T: ShieldedPoolTester>() {
     }])
     .unwrap();
 
-    let txid = st
-        .spend(
-            &input_selector,
-            account.usk(),
-            req,
-            OvkPolicy::Sender,
+    let txid = st
+        .spend(
+            &input_selector,
+            account.usk(),
+            req,
+            OvkPolicy::Sender,
+            NonZeroU32::new(1).unwrap(),
+        )
+        .unwrap()[0];
+
+    let (h, _) = st.generate_next_block_including(txid);
+    st.scan_cached_blocks(h, 1);
+
+    // TODO: send to an account so that we can check its balance.
+    // We sent back to the same account so the amount_sent should be included
+    // in the total balance.
+    assert_eq!(
+        st.get_total_balance(account_id),
+        (total - NonNegativeAmount::const_from_u64(10000)).unwrap()
+    );
+}
+
+pub(crate) fn note_selection_strategies<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet in a known order.
+    let note_values = [20000, 60000, 10000, 40000];
+    let mut h1 = None;
+    for value in note_values {
+        let (h, _, _) = st.generate_next_block(
+            &dfvk,
+            AddressType::DefaultExternal,
+            NonNegativeAmount::const_from_u64(value),
+        );
+        h1.get_or_insert(h);
+    }
+    st.scan_cached_blocks(h1.unwrap(), note_values.len());
+
+    let to = T::random_address(&mut st.rng);
+    let propose_with = |st: &mut TestState<_>, strategy| {
+        let input_selector = input_selector(StandardFeeRule::Zip317, None, T::SHIELDED_PROTOCOL)
+            .with_note_selection_strategy(strategy);
+        let request = TransactionRequest::new(vec![Payment {
+            recipient_address: to.clone(),
+            amount: NonNegativeAmount::const_from_u64(30000),
+            memo: None,
+            label: None,
+            message: None,
+            other_params: vec![],
+        }])
+        .unwrap();
+
+        let proposal = st
+            .propose_transfer(
+                account.account_id(),
+                &input_selector,
+                request,
+                NonZeroU32::new(1).unwrap(),
+            )
+            .unwrap();
+        let step = proposal.steps().first();
+        let mut input_values: Vec<u64> = step
+            .shielded_inputs()
+            .unwrap()
+            .notes()
+            .iter()
+            .map(|n| n.note().value().into_u64())
+            .collect();
+        input_values.sort_unstable();
+        (input_values, step.balance().fee_required())
+    };
+
+    // A single note covers the payment, so the fee is the ZIP 317 minimum.
+    assert_eq!(
+        propose_with(&mut st, NoteSelectionStrategy::LargestFirst),
+        (vec![60000], NonNegativeAmount::const_from_u64(10000))
+    );
+    assert_eq!(
+        propose_with(&mut st, NoteSelectionStrategy::OldestFirst),
+        (vec![20000, 60000], NonNegativeAmount::const_from_u64(10000))
+    );
+    // Three inputs require three logical actions.
+    assert_eq!(
+        propose_with(&mut st, NoteSelectionStrategy::SmallestFirst),
+        (
+            vec![10000, 20000, 40000],
+            NonNegativeAmount::const_from_u64(15000)
+        )
+    );
+}
+
+pub(crate) fn locked_notes_are_not_spendable<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account_id = st.test_account().unwrap().account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add two notes to the wallet
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 2);
+    assert_eq!(
+        st.get_spendable_balance(account_id, 1),
+        (value * 2).unwrap()
+    );
+
+    // Lock one of the received notes
+    let spendable = T::select_spendable_notes(&st, account_id, value, h + 1, &[]).unwrap();
+    assert_eq!(spendable.len(), 1);
+    let note_id = NoteId::new(
+        *spendable[0].txid(),
+        T::SHIELDED_PROTOCOL,
+        spendable[0].output_index(),
+    );
+    st.wallet_mut()
+        .set_note_spend_policy(note_id, true)
+        .unwrap();
+
+    // The locked note remains part of the total balance, but is not spendable
+    let spendable = T::select_spendable_notes(&st, account_id, value, h + 1, &[]).unwrap();
+    assert_eq!(spendable.len(), 1);
+    assert_ne!(spendable[0].txid(), note_id.txid());
+    assert_eq!(st.get_total_balance(account_id), (value * 2).unwrap());
+    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+    assert_eq!(
+        st.with_account_balance(account_id, 1, |balance| balance.locked_value()),
+        value
+    );
+
+    // A spend that could only be funded using the locked note reports it
+    let to = T::random_address(&mut st.rng);
+    assert_matches!(
+        st.propose_standard_transfer::<Infallible>(
+            account_id,
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(70000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        ),
+        Err(Error::InsufficientUnlockedFunds {
+            available,
+            required,
+            locked,
+        })
+        if available == value
+            && required == NonNegativeAmount::const_from_u64(80000)
+            && locked == value
+    );
+
+    // Once unlocked, the note can be spent again
+    st.wallet_mut()
+        .set_note_spend_policy(note_id, false)
+        .unwrap();
+    assert_eq!(
+        st.get_spendable_balance(account_id, 1),
+        (value * 2).unwrap()
+    );
+    assert_matches!(
+        st.propose_standard_transfer::<Infallible>(
+            account_id,
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(70000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        ),
+        Ok(_)
+    );
+
+    // Notes that do not belong to the wallet cannot be locked
+    assert_matches!(
+        st.wallet_mut().set_note_spend_policy(
+            NoteId::new(TxId::from_bytes([0; 32]), T::SHIELDED_PROTOCOL, 0),
+            true
+        ),
+        Err(SqliteClientError::NoteNotFound(_))
+    );
+}
+
+pub(crate) fn account_pool_balances_match_summary<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account_a = st.test_account().cloned().unwrap();
+    let dfvk_a = T::test_account_fvk(&st);
+
+    // Add a second funded account, and a third account that never receives any funds.
+    let birthday = account_a.birthday().clone();
+    let (account_b, usk_b) = st
+        .wallet_mut()
+        .create_account(&Secret::new(vec![]), &Secret::new(vec![1u8; 32]), &birthday)
+        .unwrap();
+    let dfvk_b = T::sk_to_fvk(T::usk_to_sk(&usk_b));
+    let (account_c, _) = st
+        .wallet_mut()
+        .create_account(&Secret::new(vec![]), &Secret::new(vec![2u8; 32]), &birthday)
+        .unwrap();
+
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk_a, AddressType::DefaultExternal, value);
+    st.generate_next_block(&dfvk_b, AddressType::DefaultExternal, value);
+    st.generate_next_block(&dfvk_a, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 3);
+
+    // Spend from the first account, leaving its change unmined.
+    let to = T::random_address(&mut st.rng);
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account_a.account_id(),
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(10000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+    st.create_proposed_transactions::<Infallible, _>(account_a.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap();
+
+    let pool = PoolType::Shielded(T::SHIELDED_PROTOCOL);
+    for min_confirmations in [1, 2, 10] {
+        let balances = st
+            .wallet()
+            .get_account_pool_balances(min_confirmations)
+            .unwrap();
+        let summary = st.get_wallet_summary(min_confirmations).unwrap();
+        assert_eq!(balances.len(), 3);
+        assert_eq!(summary.account_balances().len(), 3);
+
+        for (account_id, account_balance) in summary.account_balances() {
+            let pool_balances = &balances[account_id];
+            for (pool, balance) in [
+                (
+                    PoolType::Shielded(ShieldedProtocol::Sapling),
+                    account_balance.sapling_balance(),
+                ),
+                (
+                    PoolType::Shielded(ShieldedProtocol::Orchard),
+                    account_balance.orchard_balance(),
+                ),
+            ] {
+                let pool_balance = pool_balances.pool(pool);
+                assert_eq!(pool_balance.spendable_value(), balance.spendable_value());
+                assert_eq!(
+                    pool_balance.change_pending_confirmation(),
+                    balance.change_pending_confirmation()
+                );
+                assert_eq!(
+                    (pool_balance.value_pending_confirmation() + pool_balance.unconfirmed_value())
+                        .unwrap(),
+                    balance.value_pending_spendability()
+                );
+                assert_eq!(pool_balance.locked_value(), balance.locked_value());
+            }
+            assert_eq!(
+                pool_balances.pool(PoolType::Transparent).spendable_value(),
+                account_balance.unshielded()
+            );
+            assert_eq!(pool_balances.total(), account_balance.total());
+        }
+
+        // The spend consumed at least one of the first account's notes, and its change has not
+        // yet been mined.
+        let balance_a = balances[&account_a.account_id()].pool(pool);
+        assert!(balance_a.change_pending_confirmation() > NonNegativeAmount::ZERO);
+        assert!(balance_a.total() < (value * 2).unwrap());
+        assert_eq!(balances[&account_b].pool(pool).total(), value);
+        assert_eq!(balances[&account_c], AccountPoolBalances::ZERO);
+    }
+
+    // With a single confirmation, everything that has been mined is spendable.
+    let balances = st.wallet().get_account_pool_balances(1).unwrap();
+    assert_eq!(balances[&account_b].pool(pool).spendable_value(), value);
+}
+
+pub(crate) fn wallet_snapshot_round_trip<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Receive two notes, but only scan the block containing the first of them.
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    let (h2, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.wallet_mut().update_chain_tip(h2).unwrap();
+    st.scan_cached_blocks(h1, 1);
+    assert_eq!(st.get_total_balance(account.account_id()), value);
+
+    let snapshot = export_wallet_snapshot(st.wallet()).unwrap();
+    let scan_ranges = st.wallet().suggest_scan_ranges().unwrap();
+    let fully_scanned = st.wallet().block_fully_scanned().unwrap();
+    assert!(!scan_ranges.is_empty());
+
+    // A snapshot cannot be imported into a wallet that already contains data.
+    assert_matches!(
+        import_wallet_snapshot(st.wallet_mut(), &snapshot),
+        Err(SnapshotError::Wallet(SqliteClientError::TableNotEmpty))
+    );
+
+    // A corrupted snapshot is rejected.
+    let mut corrupted = snapshot.clone();
+    corrupted[snapshot.len() / 2] ^= 0x01;
+    assert_matches!(
+        st.restore_snapshot(&corrupted),
+        Err(SnapshotError::IntegrityCheckFailed)
+    );
+
+    // The restored wallet resumes scanning where the original wallet left off.
+    st.restore_snapshot(&snapshot).unwrap();
+    assert_eq!(st.wallet().suggest_scan_ranges().unwrap(), scan_ranges);
+    assert_eq!(
+        st.wallet()
+            .block_fully_scanned()
+            .unwrap()
+            .map(|m| m.block_height()),
+        fully_scanned.map(|m| m.block_height())
+    );
+    assert_eq!(st.get_total_balance(account.account_id()), value);
+
+    st.scan_cached_blocks(h2, 1);
+    assert_eq!(
+        st.get_spendable_balance(account.account_id(), 1),
+        (value * 2).unwrap()
+    );
+
+    // The note received before the snapshot was taken can be spent.
+    let to = T::random_address(&mut st.rng);
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account.account_id(),
+            StandardFeeRule::Zip317,
             NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(70000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
         )
-        .unwrap()[0];
+        .unwrap();
+    assert_matches!(
+        st.create_proposed_transactions::<Infallible, _>(
+            account.usk(),
+            OvkPolicy::Sender,
+            &proposal,
+        ),
+        Ok(txids) if txids.len() == 1
+    );
+}
 
-    let (h, _) = st.generate_next_block_including(txid);
+pub(crate) fn find_memos_across_accounts<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new().with_block_cache().build();
+
+    // Add two accounts to the wallet.
+    let seed = Secret::new([0u8; 32].to_vec());
+    let birthday = AccountBirthday::from_sapling_activation(&st.network(), BlockHash([0; 32]));
+    let (account_a, usk_a) = st
+        .wallet_mut()
+        .create_account(&Secret::new(vec![]), &seed, &birthday)
+        .unwrap();
+    let dfvk_a = T::sk_to_fvk(T::usk_to_sk(&usk_a));
+    let (account_b, usk_b) = st
+        .wallet_mut()
+        .create_account(&Secret::new(vec![]), &seed, &birthday)
+        .unwrap();
+    let dfvk_b = T::sk_to_fvk(T::usk_to_sk(&usk_b));
+
+    let (h, _, _) = st.generate_next_block(
+        &dfvk_a,
+        AddressType::DefaultExternal,
+        NonNegativeAmount::const_from_u64(100000),
+    );
     st.scan_cached_blocks(h, 1);
 
-    // TODO: send to an account so that we can check its balance.
-    // We sent back to the same account so the amount_sent should be included
-    // in the total balance.
+    let invoice = MemoBytes::from("Invoice №42 für Café ☕".parse::<Memo>().unwrap());
+    let receipt = MemoBytes::from("Paid Invoice №42".parse::<Memo>().unwrap());
+    // A memo that claims to be text, but is not valid UTF-8.
+    let mut binary = [0u8; 512];
+    binary[..4].copy_from_slice(&[0xf4, 0x90, 0x80, 0x80]);
+    let binary = MemoBytes::from_bytes(&binary).unwrap();
+
+    let payment = |recipient_address, amount, memo: &MemoBytes| Payment {
+        recipient_address,
+        amount: NonNegativeAmount::const_from_u64(amount),
+        memo: Some(memo.clone()),
+        label: None,
+        message: None,
+        other_params: vec![],
+    };
+    let input_selector = input_selector(StandardFeeRule::Zip317, None, T::SHIELDED_PROTOCOL);
+    // Memos are only available once the full transaction has been decrypted.
+    let send = |st: &mut TestState<BlockCache>, usk, request| {
+        let txid = st
+            .spend(
+                &input_selector,
+                usk,
+                request,
+                OvkPolicy::Sender,
+                NonZeroU32::new(1).unwrap(),
+            )
+            .unwrap()[0];
+        let (h, _) = st.generate_next_block_including(txid);
+        st.scan_cached_blocks(h, 1);
+        let tx = st.wallet().get_transaction(txid).unwrap().unwrap();
+        decrypt_and_store_transaction(&st.network(), st.wallet_mut(), &tx).unwrap();
+        txid
+    };
+
+    // Account A sends an invoice and a non-text memo to account B, then account B replies.
+    let txid1 = send(
+        &mut st,
+        &usk_a,
+        TransactionRequest::new(vec![
+            payment(T::fvk_default_address(&dfvk_b), 30000, &invoice),
+            payment(T::fvk_default_address(&dfvk_b), 10000, &binary),
+        ])
+        .unwrap(),
+    );
+    let txid2 = send(
+        &mut st,
+        &usk_b,
+        TransactionRequest::new(vec![payment(
+            T::fvk_default_address(&dfvk_a),
+            10000,
+            &receipt,
+        )])
+        .unwrap(),
+    );
+
+    let find = |account, query: MemoQuery| {
+        st.wallet()
+            .find_memos(account, &query)
+            .unwrap()
+            .into_iter()
+            .map(|(note_id, memo)| {
+                assert_eq!(note_id.protocol(), T::SHIELDED_PROTOCOL);
+                (*note_id.txid(), memo)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Text searches ignore case, including for non-ASCII characters, and results are ordered
+    // by mined height.
+    for account in [account_a, account_b] {
+        assert_eq!(
+            find(account, MemoQuery::text("INVOICE №42")),
+            vec![(txid1, invoice.clone()), (txid2, receipt.clone())]
+        );
+    }
     assert_eq!(
-        st.get_total_balance(account_id),
-        (total - NonNegativeAmount::const_from_u64(10000)).unwrap()
+        find(account_b, MemoQuery::text("CAFÉ ☕")),
+        vec![(txid1, invoice.clone())]
+    );
+    assert_eq!(find(account_a, MemoQuery::text("no such memo")), vec![]);
+
+    // Searches can be restricted to sent or received memos.
+    assert_eq!(
+        find(
+            account_a,
+            MemoQuery::text("invoice").with_direction(TransactionDirection::Sent)
+        ),
+        vec![(txid1, invoice.clone())]
+    );
+    assert_eq!(
+        find(
+            account_a,
+            MemoQuery::text("invoice").with_direction(TransactionDirection::Received)
+        ),
+        vec![(txid2, receipt.clone())]
+    );
+
+    // Memos that are not valid text can only be found by their bytes.
+    assert_eq!(
+        find(account_b, MemoQuery::bytes(binary.clone())),
+        vec![(txid1, binary.clone())]
+    );
+    assert_eq!(
+        find(
+            account_b,
+            MemoQuery::bytes(binary.clone()).with_direction(TransactionDirection::Sent)
+        ),
+        vec![]
+    );
+
+    // The empty memo on account A's change output is returned once, even though the output
+    // was both sent and received by account A.
+    assert_eq!(
+        find(account_a, MemoQuery::bytes(MemoBytes::empty())),
+        vec![(txid1, MemoBytes::empty())]
     );
 }
 
@@ -1268,6 +2787,93 @@ pub(crate) fn shield_transparent<T: ShieldedPoolTester>() {
     );
 }
 
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn shield_standalone_transparent<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Import the address of an externally generated key.
+    let standalone_key =
+        AccountPrivKey::from_transparent_key(&st.network(), &[7; 32], zip32::AccountId::ZERO)
+            .unwrap();
+    let taddr = standalone_key
+        .to_account_pubkey()
+        .derive_ext_ivk_from_legacy_key()
+        .default_legacy_address();
+    let birthday_height = st.sapling_activation_height();
+    let standalone_account = st
+        .wallet_mut()
+        .import_standalone_transparent_address(&taddr, birthday_height)
+        .unwrap();
+
+    // Ensure that the wallet has at least one block
+    let (h, _, _) = st.generate_next_block(
+        &dfvk,
+        AddressType::Internal,
+        NonNegativeAmount::const_from_u64(50000),
+    );
+    st.scan_cached_blocks(h, 1);
+
+    // A UTXO received at the imported address is detected, and attributed to its account.
+    let value = NonNegativeAmount::const_from_u64(10000);
+    let utxo = WalletTransparentOutput::from_parts(
+        OutPoint::new([1u8; 32], 1),
+        TxOut {
+            value,
+            script_pubkey: taddr.script(),
+        },
+        h,
+    )
+    .unwrap();
+    assert_matches!(st.wallet_mut().put_received_transparent_utxo(&utxo), Ok(_));
+    assert_eq!(
+        st.wallet()
+            .get_transparent_balances(standalone_account, h)
+            .unwrap()
+            .get(&taddr),
+        Some(&value)
+    );
+
+    // TODO: This test was originally written to use the pre-zip-313 fee rule
+    // and has not yet been updated.
+    #[allow(deprecated)]
+    let fee_rule = StandardFeeRule::PreZip313;
+
+    let input_selector = GreedyInputSelector::new(
+        standard::SingleOutputChangeStrategy::new(fee_rule, None, T::SHIELDED_PROTOCOL),
+        DustOutputPolicy::default(),
+    );
+
+    // Without the key for the imported address, its UTXO cannot be shielded.
+    assert_matches!(
+        st.shield_transparent_funds(&input_selector, value, account.usk(), &[taddr], 1),
+        Err(Error::AddressNotRecognized(addr)) if addr == taddr
+    );
+
+    // With the key, the UTXO is swept into the account of the spending key.
+    assert_matches!(
+        st.shield_standalone_transparent_funds(
+            &input_selector,
+            value,
+            account.usk(),
+            &[standalone_key],
+            &[taddr],
+            1
+        ),
+        Ok(_)
+    );
+    assert!(st
+        .wallet()
+        .get_transparent_balances(standalone_account, h)
+        .unwrap()
+        .is_empty());
+}
+
 // FIXME: This requires fixes to the test framework.
 #[allow(dead_code)]
 pub(crate) fn birthday_in_anchor_shard<T: ShieldedPoolTester>() {
@@ -1415,45 +3021,138 @@ pub(crate) fn checkpoint_gaps<T: ShieldedPoolTester>() {
         false,
     );
 
-    // Scan the block
-    st.scan_cached_blocks(account.birthday().height() + 10, 1);
+    // Scan the block
+    st.scan_cached_blocks(account.birthday().height() + 10, 1);
+
+    // Fake that everything has been scanned
+    st.wallet()
+        .conn
+        .execute_batch("UPDATE scan_queue SET priority = 10")
+        .unwrap();
+
+    // Verify that our note is considered spendable
+    let spendable = T::select_spendable_notes(
+        &st,
+        account.account_id(),
+        NonNegativeAmount::const_from_u64(300000),
+        account.birthday().height() + 5,
+        &[],
+    )
+    .unwrap();
+    assert_eq!(spendable.len(), 1);
+
+    // Attempt to spend the note with 5 confirmations
+    let to = T::fvk_default_address(&not_our_key);
+    assert_matches!(
+        st.create_spend_to_address(
+            account.usk(),
+            &to,
+            NonNegativeAmount::const_from_u64(10000),
+            None,
+            OvkPolicy::Sender,
+            NonZeroU32::new(5).unwrap(),
+            None,
+            T::SHIELDED_PROTOCOL,
+        ),
+        Ok(_)
+    );
+}
+
+#[cfg(feature = "orchard")]
+pub(crate) fn pool_crossing_required<P0: ShieldedPoolTester, P1: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32])) // TODO: Allow for Orchard
+        // activation after Sapling
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+
+    let p0_fvk = P0::test_account_fvk(&st);
+
+    let p1_fvk = P1::test_account_fvk(&st);
+    let p1_to = P1::fvk_default_address(&p1_fvk);
+
+    let note_value = NonNegativeAmount::const_from_u64(350000);
+    st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, note_value);
+    st.scan_cached_blocks(account.birthday().height(), 2);
+
+    let initial_balance = note_value;
+    assert_eq!(st.get_total_balance(account.account_id()), initial_balance);
+    assert_eq!(
+        st.get_spendable_balance(account.account_id(), 1),
+        initial_balance
+    );
+
+    let transfer_amount = NonNegativeAmount::const_from_u64(200000);
+    let p0_to_p1 = zip321::TransactionRequest::new(vec![Payment {
+        recipient_address: p1_to,
+        amount: transfer_amount,
+        memo: None,
+        label: None,
+        message: None,
+        other_params: vec![],
+    }])
+    .unwrap();
+
+    let fee_rule = StandardFeeRule::Zip317;
+    let input_selector = GreedyInputSelector::new(
+        standard::SingleOutputChangeStrategy::new(fee_rule, None, P1::SHIELDED_PROTOCOL),
+        DustOutputPolicy::default(),
+    );
+    let proposal0 = st
+        .propose_transfer(
+            account.account_id(),
+            &input_selector,
+            p0_to_p1,
+            NonZeroU32::new(1).unwrap(),
+        )
+        .unwrap();
+
+    let _min_target_height = proposal0.min_target_height();
+    assert_eq!(proposal0.steps().len(), 1);
+    let step0 = &proposal0.steps().head;
+
+    // We expect 4 logical actions, two per pool (due to padding).
+    let expected_fee = NonNegativeAmount::const_from_u64(20000);
+    assert_eq!(step0.balance().fee_required(), expected_fee);
 
-    // Fake that everything has been scanned
-    st.wallet()
-        .conn
-        .execute_batch("UPDATE scan_queue SET priority = 10")
-        .unwrap();
+    let expected_change = (note_value - transfer_amount - expected_fee).unwrap();
+    let proposed_change = step0.balance().proposed_change();
+    assert_eq!(proposed_change.len(), 1);
+    let change_output = proposed_change.get(0).unwrap();
+    // Since this is a cross-pool transfer, change will be sent to the preferred pool.
+    assert_eq!(
+        change_output.output_pool(),
+        PoolType::Shielded(std::cmp::max(
+            ShieldedProtocol::Sapling,
+            ShieldedProtocol::Orchard
+        ))
+    );
+    assert_eq!(change_output.value(), expected_change);
 
-    // Verify that our note is considered spendable
-    let spendable = T::select_spendable_notes(
-        &st,
-        account.account_id(),
-        NonNegativeAmount::const_from_u64(300000),
-        account.birthday().height() + 5,
-        &[],
-    )
-    .unwrap();
-    assert_eq!(spendable.len(), 1);
+    let create_proposed_result = st.create_proposed_transactions::<Infallible, _>(
+        account.usk(),
+        OvkPolicy::Sender,
+        &proposal0,
+    );
+    assert_matches!(&create_proposed_result, Ok(txids) if txids.len() == 1);
 
-    // Attempt to spend the note with 5 confirmations
-    let to = T::fvk_default_address(&not_our_key);
-    assert_matches!(
-        st.create_spend_to_address(
-            account.usk(),
-            &to,
-            NonNegativeAmount::const_from_u64(10000),
-            None,
-            OvkPolicy::Sender,
-            NonZeroU32::new(5).unwrap(),
-            None,
-            T::SHIELDED_PROTOCOL,
-        ),
-        Ok(_)
+    let (h, _) = st.generate_next_block_including(create_proposed_result.unwrap()[0]);
+    st.scan_cached_blocks(h, 1);
+
+    assert_eq!(
+        st.get_total_balance(account.account_id()),
+        (initial_balance - expected_fee).unwrap()
+    );
+    assert_eq!(
+        st.get_spendable_balance(account.account_id(), 1),
+        (initial_balance - expected_fee).unwrap()
     );
 }
 
 #[cfg(feature = "orchard")]
-pub(crate) fn pool_crossing_required<P0: ShieldedPoolTester, P1: ShieldedPoolTester>() {
+pub(crate) fn fully_funded_fully_private<P0: ShieldedPoolTester, P1: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32])) // TODO: Allow for Orchard
@@ -1469,9 +3168,10 @@ pub(crate) fn pool_crossing_required<P0: ShieldedPoolTester, P1: ShieldedPoolTes
 
     let note_value = NonNegativeAmount::const_from_u64(350000);
     st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, note_value);
+    st.generate_next_block(&p1_fvk, AddressType::DefaultExternal, note_value);
     st.scan_cached_blocks(account.birthday().height(), 2);
 
-    let initial_balance = note_value;
+    let initial_balance = (note_value * 2).unwrap();
     assert_eq!(st.get_total_balance(account.account_id()), initial_balance);
     assert_eq!(
         st.get_spendable_balance(account.account_id(), 1),
@@ -1491,7 +3191,9 @@ pub(crate) fn pool_crossing_required<P0: ShieldedPoolTester, P1: ShieldedPoolTes
 
     let fee_rule = StandardFeeRule::Zip317;
     let input_selector = GreedyInputSelector::new(
-        standard::SingleOutputChangeStrategy::new(fee_rule, None, P1::SHIELDED_PROTOCOL),
+        // We set the default change output pool to P0, because we want to verify later that
+        // change is actually sent to P1 (as the transaction is fully fundable from P1).
+        standard::SingleOutputChangeStrategy::new(fee_rule, None, P0::SHIELDED_PROTOCOL),
         DustOutputPolicy::default(),
     );
     let proposal0 = st
@@ -1507,18 +3209,20 @@ pub(crate) fn pool_crossing_required<P0: ShieldedPoolTester, P1: ShieldedPoolTes
     assert_eq!(proposal0.steps().len(), 1);
     let step0 = &proposal0.steps().head;
 
-    // We expect 4 logical actions, two per pool (due to padding).
-    let expected_fee = NonNegativeAmount::const_from_u64(20000);
+    // We expect 2 logical actions, since either pool can pay the full balance required
+    // and note selection should choose the fully-private path.
+    let expected_fee = NonNegativeAmount::const_from_u64(10000);
     assert_eq!(step0.balance().fee_required(), expected_fee);
 
     let expected_change = (note_value - transfer_amount - expected_fee).unwrap();
     let proposed_change = step0.balance().proposed_change();
     assert_eq!(proposed_change.len(), 1);
     let change_output = proposed_change.get(0).unwrap();
-    // Since this is a cross-pool transfer, change will be sent to the preferred pool.
+    // Since there are sufficient funds in either pool, change is kept in the same pool as
+    // the source note (the target pool), and does not necessarily follow preference order.
     assert_eq!(
         change_output.output_pool(),
-        std::cmp::max(ShieldedProtocol::Sapling, ShieldedProtocol::Orchard)
+        PoolType::Shielded(P1::SHIELDED_PROTOCOL)
     );
     assert_eq!(change_output.value(), expected_change);
 
@@ -1543,7 +3247,7 @@ pub(crate) fn pool_crossing_required<P0: ShieldedPoolTester, P1: ShieldedPoolTes
 }
 
 #[cfg(feature = "orchard")]
-pub(crate) fn fully_funded_fully_private<P0: ShieldedPoolTester, P1: ShieldedPoolTester>() {
+pub(crate) fn fully_funded_send_to_t<P0: ShieldedPoolTester, P1: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32])) // TODO: Allow for Orchard
@@ -1553,9 +3257,8 @@ pub(crate) fn fully_funded_fully_private<P0: ShieldedPoolTester, P1: ShieldedPoo
     let account = st.test_account().cloned().unwrap();
 
     let p0_fvk = P0::test_account_fvk(&st);
-
     let p1_fvk = P1::test_account_fvk(&st);
-    let p1_to = P1::fvk_default_address(&p1_fvk);
+    let p1_to = account.usk().default_transparent_address();
 
     let note_value = NonNegativeAmount::const_from_u64(350000);
     st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, note_value);
@@ -1571,7 +3274,7 @@ pub(crate) fn fully_funded_fully_private<P0: ShieldedPoolTester, P1: ShieldedPoo
 
     let transfer_amount = NonNegativeAmount::const_from_u64(200000);
     let p0_to_p1 = zip321::TransactionRequest::new(vec![Payment {
-        recipient_address: p1_to,
+        recipient_address: Address::Transparent(p1_to),
         amount: transfer_amount,
         memo: None,
         label: None,
@@ -1600,9 +3303,8 @@ pub(crate) fn fully_funded_fully_private<P0: ShieldedPoolTester, P1: ShieldedPoo
     assert_eq!(proposal0.steps().len(), 1);
     let step0 = &proposal0.steps().head;
 
-    // We expect 2 logical actions, since either pool can pay the full balance required
-    // and note selection should choose the fully-private path.
-    let expected_fee = NonNegativeAmount::const_from_u64(10000);
+    // We expect 3 logical actions, one for the transparent output and two for the source pool.
+    let expected_fee = NonNegativeAmount::const_from_u64(15000);
     assert_eq!(step0.balance().fee_required(), expected_fee);
 
     let expected_change = (note_value - transfer_amount - expected_fee).unwrap();
@@ -1611,7 +3313,11 @@ pub(crate) fn fully_funded_fully_private<P0: ShieldedPoolTester, P1: ShieldedPoo
     let change_output = proposed_change.get(0).unwrap();
     // Since there are sufficient funds in either pool, change is kept in the same pool as
     // the source note (the target pool), and does not necessarily follow preference order.
-    assert_eq!(change_output.output_pool(), P1::SHIELDED_PROTOCOL);
+    // The source note will always be sapling, as we spend Sapling funds preferentially.
+    assert_eq!(
+        change_output.output_pool(),
+        PoolType::Shielded(ShieldedProtocol::Sapling)
+    );
     assert_eq!(change_output.value(), expected_change);
 
     let create_proposed_result = st.create_proposed_transactions::<Infallible, _>(
@@ -1621,21 +3327,198 @@ pub(crate) fn fully_funded_fully_private<P0: ShieldedPoolTester, P1: ShieldedPoo
     );
     assert_matches!(&create_proposed_result, Ok(txids) if txids.len() == 1);
 
-    let (h, _) = st.generate_next_block_including(create_proposed_result.unwrap()[0]);
-    st.scan_cached_blocks(h, 1);
+    let (h, _) = st.generate_next_block_including(create_proposed_result.unwrap()[0]);
+    st.scan_cached_blocks(h, 1);
+
+    assert_eq!(
+        st.get_total_balance(account.account_id()),
+        (initial_balance - transfer_amount - expected_fee).unwrap()
+    );
+    assert_eq!(
+        st.get_spendable_balance(account.account_id(), 1),
+        (initial_balance - transfer_amount - expected_fee).unwrap()
+    );
+}
+
+#[cfg(feature = "orchard")]
+pub(crate) fn multi_pool_checkpoint<P0: ShieldedPoolTester, P1: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32])) // TODO: Allow for Orchard
+        // activation after Sapling
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let acct_id = account.account_id();
+
+    let p0_fvk = P0::test_account_fvk(&st);
+    let p1_fvk = P1::test_account_fvk(&st);
+
+    // Add some funds to the wallet; we add two notes to allow successive spends. Also,
+    // we will generate a note in the P1 pool to ensure that we have some tree state.
+    let note_value = NonNegativeAmount::const_from_u64(500000);
+    let (start_height, _, _) =
+        st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, note_value);
+    st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, note_value);
+    st.generate_next_block(&p1_fvk, AddressType::DefaultExternal, note_value);
+    let scanned = st.scan_cached_blocks(start_height, 3);
+
+    let next_to_scan = scanned.scanned_range().end;
+
+    let initial_balance = (note_value * 3).unwrap();
+    assert_eq!(st.get_total_balance(acct_id), initial_balance);
+    assert_eq!(st.get_spendable_balance(acct_id, 1), initial_balance);
+
+    // Generate several empty blocks
+    for _ in 0..10 {
+        st.generate_empty_block();
+    }
+
+    // Scan into the middle of the empty range
+    let scanned = st.scan_cached_blocks(next_to_scan, 5);
+    let next_to_scan = scanned.scanned_range().end;
+
+    // The initial balance should be unchanged.
+    assert_eq!(st.get_total_balance(acct_id), initial_balance);
+    assert_eq!(st.get_spendable_balance(acct_id, 1), initial_balance);
+
+    // Set up the fee rule and input selector we'll use for all the transfers.
+    let fee_rule = StandardFeeRule::Zip317;
+    let input_selector = GreedyInputSelector::new(
+        standard::SingleOutputChangeStrategy::new(fee_rule, None, P1::SHIELDED_PROTOCOL),
+        DustOutputPolicy::default(),
+    );
+
+    // First, send funds just to P0
+    let transfer_amount = NonNegativeAmount::const_from_u64(200000);
+    let p0_transfer = zip321::TransactionRequest::new(vec![Payment::without_memo(
+        P0::random_address(&mut st.rng),
+        transfer_amount,
+    )])
+    .unwrap();
+    let res = st
+        .spend(
+            &input_selector,
+            account.usk(),
+            p0_transfer,
+            OvkPolicy::Sender,
+            NonZeroU32::new(1).unwrap(),
+        )
+        .unwrap();
+    st.generate_next_block_including(*res.first());
+
+    let expected_fee = NonNegativeAmount::const_from_u64(10000);
+    let expected_change = (note_value - transfer_amount - expected_fee).unwrap();
+    assert_eq!(
+        st.get_total_balance(acct_id),
+        ((note_value * 2).unwrap() + expected_change).unwrap()
+    );
+    assert_eq!(st.get_pending_change(acct_id, 1), expected_change);
+
+    // In the next block, send funds to both P0 and P1
+    let both_transfer = zip321::TransactionRequest::new(vec![
+        Payment::without_memo(P0::random_address(&mut st.rng), transfer_amount),
+        Payment::without_memo(P1::random_address(&mut st.rng), transfer_amount),
+    ])
+    .unwrap();
+    let res = st
+        .spend(
+            &input_selector,
+            account.usk(),
+            both_transfer,
+            OvkPolicy::Sender,
+            NonZeroU32::new(1).unwrap(),
+        )
+        .unwrap();
+    st.generate_next_block_including(*res.first());
+
+    // Generate a few more empty blocks
+    for _ in 0..5 {
+        st.generate_empty_block();
+    }
+
+    // Generate another block with funds for us
+    let (max_height, _, _) =
+        st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, note_value);
+
+    // Scan everything.
+    st.scan_cached_blocks(
+        next_to_scan,
+        usize::try_from(u32::from(max_height) - u32::from(next_to_scan) + 1).unwrap(),
+    );
+
+    let expected_final = (initial_balance + note_value
+        - (transfer_amount * 3).unwrap()
+        - (expected_fee * 3).unwrap())
+    .unwrap();
+    assert_eq!(st.get_total_balance(acct_id), expected_final);
+
+    use incrementalmerkletree::Position;
+    let expected_checkpoints_p0: Vec<(BlockHeight, ShieldedProtocol, Option<Position>)> = [
+        (99999, None),
+        (100000, Some(0)),
+        (100001, Some(1)),
+        (100002, Some(1)),
+        (100007, Some(1)), // synthetic checkpoint in empty span from scan start
+        (100013, Some(3)),
+        (100014, Some(5)),
+        (100020, Some(6)),
+    ]
+    .into_iter()
+    .map(|(h, pos)| {
+        (
+            BlockHeight::from(h),
+            P0::SHIELDED_PROTOCOL,
+            pos.map(Position::from),
+        )
+    })
+    .collect();
+
+    let expected_checkpoints_p1: Vec<(BlockHeight, ShieldedProtocol, Option<Position>)> = [
+        (99999, None),
+        (100000, None),
+        (100001, None),
+        (100002, Some(0)),
+        (100007, Some(0)), // synthetic checkpoint in empty span from scan start
+        (100013, Some(0)),
+        (100014, Some(2)),
+        (100020, Some(2)),
+    ]
+    .into_iter()
+    .map(|(h, pos)| {
+        (
+            BlockHeight::from(h),
+            P1::SHIELDED_PROTOCOL,
+            pos.map(Position::from),
+        )
+    })
+    .collect();
+
+    let actual_checkpoints = st.get_checkpoint_history().unwrap();
 
     assert_eq!(
-        st.get_total_balance(account.account_id()),
-        (initial_balance - expected_fee).unwrap()
+        actual_checkpoints
+            .iter()
+            .filter(|(_, p, _)| p == &P0::SHIELDED_PROTOCOL)
+            .cloned()
+            .collect::<Vec<_>>(),
+        expected_checkpoints_p0
     );
     assert_eq!(
-        st.get_spendable_balance(account.account_id(), 1),
-        (initial_balance - expected_fee).unwrap()
+        actual_checkpoints
+            .iter()
+            .filter(|(_, p, _)| p == &P1::SHIELDED_PROTOCOL)
+            .cloned()
+            .collect::<Vec<_>>(),
+        expected_checkpoints_p1
     );
 }
 
 #[cfg(feature = "orchard")]
-pub(crate) fn fully_funded_send_to_t<P0: ShieldedPoolTester, P1: ShieldedPoolTester>() {
+pub(crate) fn multi_pool_checkpoints_with_pruning<
+    P0: ShieldedPoolTester,
+    P1: ShieldedPoolTester,
+>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32])) // TODO: Allow for Orchard
@@ -1644,382 +3527,489 @@ pub(crate) fn fully_funded_send_to_t<P0: ShieldedPoolTester, P1: ShieldedPoolTes
 
     let account = st.test_account().cloned().unwrap();
 
-    let p0_fvk = P0::test_account_fvk(&st);
-    let p1_fvk = P1::test_account_fvk(&st);
-    let (p1_to, _) = account.usk().default_transparent_address();
+    let p0_fvk = P0::random_fvk(&mut st.rng);
+    let p1_fvk = P1::random_fvk(&mut st.rng);
 
-    let note_value = NonNegativeAmount::const_from_u64(350000);
-    st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, note_value);
-    st.generate_next_block(&p1_fvk, AddressType::DefaultExternal, note_value);
-    st.scan_cached_blocks(account.birthday().height(), 2);
+    let note_value = NonNegativeAmount::const_from_u64(10000);
+    // Generate 100 P0 blocks, then 100 P1 blocks, then another 100 P0 blocks.
+    for _ in 0..10 {
+        for _ in 0..10 {
+            st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, note_value);
+        }
+        for _ in 0..10 {
+            st.generate_next_block(&p1_fvk, AddressType::DefaultExternal, note_value);
+        }
+    }
+    st.scan_cached_blocks(account.birthday().height(), 200);
+    for _ in 0..100 {
+        st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, note_value);
+        st.generate_next_block(&p1_fvk, AddressType::DefaultExternal, note_value);
+    }
+    st.scan_cached_blocks(account.birthday().height() + 200, 200);
+}
 
-    let initial_balance = (note_value * 2).unwrap();
-    assert_eq!(st.get_total_balance(account.account_id()), initial_balance);
-    assert_eq!(
-        st.get_spendable_balance(account.account_id(), 1),
-        initial_balance
+pub(crate) fn valid_chain_states<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let dfvk = T::test_account_fvk(&st);
+
+    // Empty chain should return None
+    assert_matches!(st.wallet().chain_height(), Ok(None));
+
+    // Create a fake CompactBlock sending value to the address
+    let (h1, _, _) = st.generate_next_block(
+        &dfvk,
+        AddressType::DefaultExternal,
+        NonNegativeAmount::const_from_u64(5),
     );
 
-    let transfer_amount = NonNegativeAmount::const_from_u64(200000);
-    let p0_to_p1 = zip321::TransactionRequest::new(vec![Payment {
-        recipient_address: Address::Transparent(p1_to),
-        amount: transfer_amount,
-        memo: None,
-        label: None,
-        message: None,
-        other_params: vec![],
-    }])
-    .unwrap();
+    // Scan the cache
+    st.scan_cached_blocks(h1, 1);
 
-    let fee_rule = StandardFeeRule::Zip317;
-    let input_selector = GreedyInputSelector::new(
-        // We set the default change output pool to P0, because we want to verify later that
-        // change is actually sent to P1 (as the transaction is fully fundable from P1).
-        standard::SingleOutputChangeStrategy::new(fee_rule, None, P0::SHIELDED_PROTOCOL),
-        DustOutputPolicy::default(),
+    // Create a second fake CompactBlock sending more value to the address
+    let (h2, _, _) = st.generate_next_block(
+        &dfvk,
+        AddressType::DefaultExternal,
+        NonNegativeAmount::const_from_u64(7),
     );
-    let proposal0 = st
-        .propose_transfer(
-            account.account_id(),
-            &input_selector,
-            p0_to_p1,
-            NonZeroU32::new(1).unwrap(),
-        )
-        .unwrap();
 
-    let _min_target_height = proposal0.min_target_height();
-    assert_eq!(proposal0.steps().len(), 1);
-    let step0 = &proposal0.steps().head;
+    // Scanning should detect no inconsistencies
+    st.scan_cached_blocks(h2, 1);
+}
 
-    // We expect 3 logical actions, one for the transparent output and two for the source pool.
-    let expected_fee = NonNegativeAmount::const_from_u64(15000);
-    assert_eq!(step0.balance().fee_required(), expected_fee);
+// FIXME: This requires fixes to the test framework.
+#[allow(dead_code)]
+pub(crate) fn invalid_chain_cache_disconnected<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
 
-    let expected_change = (note_value - transfer_amount - expected_fee).unwrap();
-    let proposed_change = step0.balance().proposed_change();
-    assert_eq!(proposed_change.len(), 1);
-    let change_output = proposed_change.get(0).unwrap();
-    // Since there are sufficient funds in either pool, change is kept in the same pool as
-    // the source note (the target pool), and does not necessarily follow preference order.
-    // The source note will always be sapling, as we spend Sapling funds preferentially.
-    assert_eq!(change_output.output_pool(), ShieldedProtocol::Sapling);
-    assert_eq!(change_output.value(), expected_change);
+    let dfvk = T::test_account_fvk(&st);
 
-    let create_proposed_result = st.create_proposed_transactions::<Infallible, _>(
-        account.usk(),
-        OvkPolicy::Sender,
-        &proposal0,
+    // Create some fake CompactBlocks
+    let (h, _, _) = st.generate_next_block(
+        &dfvk,
+        AddressType::DefaultExternal,
+        NonNegativeAmount::const_from_u64(5),
+    );
+    let (last_contiguous_height, _, _) = st.generate_next_block(
+        &dfvk,
+        AddressType::DefaultExternal,
+        NonNegativeAmount::const_from_u64(7),
     );
-    assert_matches!(&create_proposed_result, Ok(txids) if txids.len() == 1);
 
-    let (h, _) = st.generate_next_block_including(create_proposed_result.unwrap()[0]);
-    st.scan_cached_blocks(h, 1);
+    // Scanning the cache should find no inconsistencies
+    st.scan_cached_blocks(h, 2);
 
-    assert_eq!(
-        st.get_total_balance(account.account_id()),
-        (initial_balance - transfer_amount - expected_fee).unwrap()
+    // Create more fake CompactBlocks that don't connect to the scanned ones
+    let disconnect_height = last_contiguous_height + 1;
+    st.generate_block_at(
+        disconnect_height,
+        BlockHash([1; 32]),
+        &dfvk,
+        AddressType::DefaultExternal,
+        NonNegativeAmount::const_from_u64(8),
+        2,
+        2,
+        true,
     );
-    assert_eq!(
-        st.get_spendable_balance(account.account_id(), 1),
-        (initial_balance - transfer_amount - expected_fee).unwrap()
+    st.generate_next_block(
+        &dfvk,
+        AddressType::DefaultExternal,
+        NonNegativeAmount::const_from_u64(3),
+    );
+
+    // Data+cache chain should be invalid at the data/cache boundary
+    assert_matches!(
+        st.try_scan_cached_blocks(
+            disconnect_height,
+            2
+        ),
+        Err(chain::error::Error::Scan(ScanError::PrevHashMismatch { at_height }))
+            if at_height == disconnect_height
     );
 }
 
-#[cfg(feature = "orchard")]
-pub(crate) fn multi_pool_checkpoint<P0: ShieldedPoolTester, P1: ShieldedPoolTester>() {
+pub(crate) fn data_db_truncation<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
-        .with_account_from_sapling_activation(BlockHash([0; 32])) // TODO: Allow for Orchard
-        // activation after Sapling
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
         .build();
 
     let account = st.test_account().cloned().unwrap();
-    let acct_id = account.account_id();
-
-    let p0_fvk = P0::test_account_fvk(&st);
-    let p1_fvk = P1::test_account_fvk(&st);
-
-    // Add some funds to the wallet; we add two notes to allow successive spends. Also,
-    // we will generate a note in the P1 pool to ensure that we have some tree state.
-    let note_value = NonNegativeAmount::const_from_u64(500000);
-    let (start_height, _, _) =
-        st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, note_value);
-    st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, note_value);
-    st.generate_next_block(&p1_fvk, AddressType::DefaultExternal, note_value);
-    let scanned = st.scan_cached_blocks(start_height, 3);
+    let dfvk = T::test_account_fvk(&st);
 
-    let next_to_scan = scanned.scanned_range().end;
+    // Wallet summary is not yet available
+    assert_eq!(st.get_wallet_summary(0), None);
 
-    let initial_balance = (note_value * 3).unwrap();
-    assert_eq!(st.get_total_balance(acct_id), initial_balance);
-    assert_eq!(st.get_spendable_balance(acct_id, 1), initial_balance);
+    // Create fake CompactBlocks sending value to the address
+    let value = NonNegativeAmount::const_from_u64(5);
+    let value2 = NonNegativeAmount::const_from_u64(7);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.generate_next_block(&dfvk, AddressType::DefaultExternal, value2);
 
-    // Generate several empty blocks
-    for _ in 0..10 {
-        st.generate_empty_block();
-    }
+    // Scan the cache
+    st.scan_cached_blocks(h, 2);
 
-    // Scan into the middle of the empty range
-    let scanned = st.scan_cached_blocks(next_to_scan, 5);
-    let next_to_scan = scanned.scanned_range().end;
+    // Spendable balance should reflect both received notes
+    assert_eq!(
+        st.get_spendable_balance(account.account_id(), 1),
+        (value + value2).unwrap()
+    );
 
-    // The initial balance should be unchanged.
-    assert_eq!(st.get_total_balance(acct_id), initial_balance);
-    assert_eq!(st.get_spendable_balance(acct_id, 1), initial_balance);
+    // "Rewind" to height of last scanned block (this is a no-op)
+    st.wallet_mut()
+        .transactionally(|wdb| truncate_to_height(wdb.conn.0, &wdb.params, h + 1))
+        .unwrap();
 
-    // Set up the fee rule and input selector we'll use for all the transfers.
-    let fee_rule = StandardFeeRule::Zip317;
-    let input_selector = GreedyInputSelector::new(
-        standard::SingleOutputChangeStrategy::new(fee_rule, None, P1::SHIELDED_PROTOCOL),
-        DustOutputPolicy::default(),
+    // Spendable balance should be unaltered
+    assert_eq!(
+        st.get_spendable_balance(account.account_id(), 1),
+        (value + value2).unwrap()
     );
-
-    // First, send funds just to P0
-    let transfer_amount = NonNegativeAmount::const_from_u64(200000);
-    let p0_transfer = zip321::TransactionRequest::new(vec![Payment::without_memo(
-        P0::random_address(&mut st.rng),
-        transfer_amount,
-    )])
-    .unwrap();
-    let res = st
-        .spend(
-            &input_selector,
-            account.usk(),
-            p0_transfer,
-            OvkPolicy::Sender,
-            NonZeroU32::new(1).unwrap(),
-        )
+
+    // Rewind so that one block is dropped
+    st.wallet_mut()
+        .transactionally(|wdb| truncate_to_height(wdb.conn.0, &wdb.params, h))
         .unwrap();
-    st.generate_next_block_including(*res.first());
 
-    let expected_fee = NonNegativeAmount::const_from_u64(10000);
-    let expected_change = (note_value - transfer_amount - expected_fee).unwrap();
+    // Spendable balance should only contain the first received note;
+    // the rest should be pending.
+    assert_eq!(st.get_spendable_balance(account.account_id(), 1), value);
     assert_eq!(
-        st.get_total_balance(acct_id),
-        ((note_value * 2).unwrap() + expected_change).unwrap()
+        st.get_pending_shielded_balance(account.account_id(), 1),
+        value2
     );
-    assert_eq!(st.get_pending_change(acct_id, 1), expected_change);
 
-    // In the next block, send funds to both P0 and P1
-    let both_transfer = zip321::TransactionRequest::new(vec![
-        Payment::without_memo(P0::random_address(&mut st.rng), transfer_amount),
-        Payment::without_memo(P1::random_address(&mut st.rng), transfer_amount),
-    ])
-    .unwrap();
-    let res = st
-        .spend(
-            &input_selector,
-            account.usk(),
-            both_transfer,
-            OvkPolicy::Sender,
-            NonZeroU32::new(1).unwrap(),
-        )
-        .unwrap();
-    st.generate_next_block_including(*res.first());
+    // Scan the cache again
+    st.scan_cached_blocks(h, 2);
 
-    // Generate a few more empty blocks
-    for _ in 0..5 {
-        st.generate_empty_block();
-    }
+    // Account balance should again reflect both received notes
+    assert_eq!(
+        st.get_spendable_balance(account.account_id(), 1),
+        (value + value2).unwrap()
+    );
+}
 
-    // Generate another block with funds for us
-    let (max_height, _, _) =
-        st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, note_value);
+pub(crate) fn data_db_truncation_to_prior_checkpoint<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
 
-    // Scan everything.
-    st.scan_cached_blocks(
-        next_to_scan,
-        usize::try_from(u32::from(max_height) - u32::from(next_to_scan) + 1).unwrap(),
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Receive notes in the blocks on either side of an empty block.
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let value2 = NonNegativeAmount::const_from_u64(70000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.generate_empty_block();
+    st.generate_next_block(&dfvk, AddressType::DefaultExternal, value2);
+    st.scan_cached_blocks(h, 3);
+    assert_eq!(
+        st.get_spendable_balance(account.account_id(), 1),
+        (value + value2).unwrap()
     );
 
-    let expected_final = (initial_balance + note_value
-        - (transfer_amount * 3).unwrap()
-        - (expected_fee * 3).unwrap())
-    .unwrap();
-    assert_eq!(st.get_total_balance(acct_id), expected_final);
+    // The empty block added no note commitments, and so has no checkpoint; truncating to it
+    // rewinds to the block before it instead.
+    assert_eq!(st.wallet().get_truncation_height(h + 1).unwrap(), Some(h));
+    assert_eq!(st.wallet_mut().truncate_to_height(h + 1).unwrap(), h);
+    assert_eq!(
+        block_max_scanned(&st.wallet().conn, &st.wallet().params)
+            .unwrap()
+            .map(|meta| meta.block_height()),
+        Some(h)
+    );
+    assert_eq!(st.get_spendable_balance(account.account_id(), 1), value);
+    assert_eq!(
+        st.get_pending_shielded_balance(account.account_id(), 1),
+        value2
+    );
 
-    use incrementalmerkletree::Position;
-    let expected_checkpoints_p0: Vec<(BlockHeight, ShieldedProtocol, Option<Position>)> = [
-        (99999, None),
-        (100000, Some(0)),
-        (100001, Some(1)),
-        (100002, Some(1)),
-        (100007, Some(1)), // synthetic checkpoint in empty span from scan start
-        (100013, Some(3)),
-        (100014, Some(5)),
-        (100020, Some(6)),
-    ]
-    .into_iter()
-    .map(|(h, pos)| {
-        (
-            BlockHeight::from(h),
-            P0::SHIELDED_PROTOCOL,
-            pos.map(Position::from),
-        )
-    })
-    .collect();
+    // Scanning from the returned height restores the second note.
+    st.scan_cached_blocks(h + 1, 2);
+    assert_eq!(
+        st.get_spendable_balance(account.account_id(), 1),
+        (value + value2).unwrap()
+    );
+}
 
-    let expected_checkpoints_p1: Vec<(BlockHeight, ShieldedProtocol, Option<Position>)> = [
-        (99999, None),
-        (100000, None),
-        (100001, None),
-        (100002, Some(0)),
-        (100007, Some(0)), // synthetic checkpoint in empty span from scan start
-        (100013, Some(0)),
-        (100014, Some(2)),
-        (100020, Some(2)),
-    ]
-    .into_iter()
-    .map(|(h, pos)| {
-        (
-            BlockHeight::from(h),
-            P1::SHIELDED_PROTOCOL,
-            pos.map(Position::from),
-        )
-    })
-    .collect();
+pub(crate) fn data_db_truncation_across_birthday<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
 
-    let actual_checkpoints = st.get_checkpoint_history().unwrap();
+    let account = st.test_account().cloned().unwrap();
+    let birthday = account.birthday().height();
+    let dfvk = T::test_account_fvk(&st);
 
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let value2 = NonNegativeAmount::const_from_u64(70000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    assert_eq!(h, birthday);
+    st.generate_next_block(&dfvk, AddressType::DefaultExternal, value2);
+    st.scan_cached_blocks(h, 2);
+
+    // The oldest tree state the wallet has is the one as of its birthday, so it cannot be
+    // truncated any further back than the block before the birthday.
     assert_eq!(
-        actual_checkpoints
-            .iter()
-            .filter(|(_, p, _)| p == &P0::SHIELDED_PROTOCOL)
-            .cloned()
-            .collect::<Vec<_>>(),
-        expected_checkpoints_p0
+        st.wallet().get_truncation_height(birthday - 2).unwrap(),
+        None
+    );
+    assert_matches!(
+        st.wallet_mut().truncate_to_height(birthday - 2),
+        Err(SqliteClientError::RequestedRewindInvalid(Some(safe_height), requested))
+            if safe_height == birthday - 1 && requested == birthday - 2
     );
     assert_eq!(
-        actual_checkpoints
-            .iter()
-            .filter(|(_, p, _)| p == &P1::SHIELDED_PROTOCOL)
-            .cloned()
-            .collect::<Vec<_>>(),
-        expected_checkpoints_p1
+        st.get_total_balance(account.account_id()),
+        (value + value2).unwrap()
+    );
+
+    // Truncating to just below the birthday removes all scanned blocks.
+    assert_eq!(
+        st.wallet_mut().truncate_to_height(birthday - 1).unwrap(),
+        birthday - 1
+    );
+    assert!(block_max_scanned(&st.wallet().conn, &st.wallet().params)
+        .unwrap()
+        .is_none());
+
+    // Scanning again from the birthday restores the wallet's balance, without double-counting
+    // the notes retained across the truncation.
+    st.scan_cached_blocks(birthday, 2);
+    assert_eq!(
+        st.get_total_balance(account.account_id()),
+        (value + value2).unwrap()
     );
 }
 
-#[cfg(feature = "orchard")]
-pub(crate) fn multi_pool_checkpoints_with_pruning<
-    P0: ShieldedPoolTester,
-    P1: ShieldedPoolTester,
->() {
+pub(crate) fn data_db_truncation_below_oldest_checkpoint<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
-        .with_account_from_sapling_activation(BlockHash([0; 32])) // TODO: Allow for Orchard
-        // activation after Sapling
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
         .build();
 
     let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
 
-    let p0_fvk = P0::random_fvk(&mut st.rng);
-    let p1_fvk = P1::random_fvk(&mut st.rng);
-
-    let note_value = NonNegativeAmount::const_from_u64(10000);
-    // Generate 100 P0 blocks, then 100 P1 blocks, then another 100 P0 blocks.
-    for _ in 0..10 {
-        for _ in 0..10 {
-            st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, note_value);
-        }
-        for _ in 0..10 {
-            st.generate_next_block(&p1_fvk, AddressType::DefaultExternal, note_value);
-        }
-    }
-    st.scan_cached_blocks(account.birthday().height(), 200);
-    for _ in 0..100 {
-        st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, note_value);
-        st.generate_next_block(&p1_fvk, AddressType::DefaultExternal, note_value);
+    // Receive a note, followed by more blocks than the wallet retains checkpoints for.
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    let not_our_key = T::random_fvk(st.rng_mut());
+    let block_count = PRUNING_DEPTH + 10;
+    for _ in 0..block_count {
+        st.generate_next_block(&not_our_key, AddressType::DefaultExternal, value);
     }
-    st.scan_cached_blocks(account.birthday().height() + 200, 200);
+    st.scan_cached_blocks(h, usize::try_from(block_count + 1).unwrap());
+    let tip = h + block_count;
+
+    // The checkpoint for the block in which the note was received has been pruned, so it is
+    // not possible to truncate to that height.
+    assert_eq!(st.wallet().get_truncation_height(h).unwrap(), None);
+    let safe_height = match st.wallet_mut().truncate_to_height(h) {
+        Err(SqliteClientError::RequestedRewindInvalid(Some(safe_height), requested)) => {
+            assert_eq!(requested, h);
+            safe_height
+        }
+        other => panic!("Unexpected truncation result: {:?}", other),
+    };
+    assert!(safe_height > h && safe_height <= tip);
+
+    // The failed truncation left the wallet unchanged.
+    assert_eq!(
+        block_max_scanned(&st.wallet().conn, &st.wallet().params)
+            .unwrap()
+            .map(|meta| meta.block_height()),
+        Some(tip)
+    );
+    assert_eq!(st.get_total_balance(account.account_id()), value);
+
+    // Truncating to the reported height succeeds.
+    assert_eq!(
+        st.wallet().get_truncation_height(safe_height).unwrap(),
+        Some(safe_height)
+    );
+    assert_eq!(
+        st.wallet_mut().truncate_to_height(safe_height).unwrap(),
+        safe_height
+    );
+    assert_eq!(st.get_total_balance(account.account_id()), value);
 }
 
-pub(crate) fn valid_chain_states<T: ShieldedPoolTester>() {
+/// Returns the cached blocks from `from_height` onwards.
+fn cached_blocks_from(st: &TestState<BlockCache>, from_height: BlockHeight) -> Vec<CompactBlock> {
+    let mut blocks = vec![];
+    st.cache()
+        .with_blocks::<_, Infallible>(Some(from_height), None, |block| {
+            blocks.push(block);
+            Ok(())
+        })
+        .unwrap();
+    blocks
+}
+
+pub(crate) fn reorg_orphans_sent_transaction<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32]))
         .build();
 
+    let account = st.test_account().cloned().unwrap();
     let dfvk = T::test_account_fvk(&st);
 
-    // Empty chain should return None
-    assert_matches!(st.wallet().chain_height(), Ok(None));
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
 
-    // Create a fake CompactBlock sending value to the address
-    let (h1, _, _) = st.generate_next_block(
-        &dfvk,
-        AddressType::DefaultExternal,
-        NonNegativeAmount::const_from_u64(5),
-    );
+    // Send funds to an external address, and mine the transaction two blocks below the tip.
+    let to = T::fvk_default_address(&T::random_fvk(st.rng_mut()));
+    let request = zip321::TransactionRequest::new(vec![Payment {
+        recipient_address: to,
+        amount: NonNegativeAmount::const_from_u64(10000),
+        memo: None,
+        label: None,
+        message: None,
+        other_params: vec![],
+    }])
+    .unwrap();
+    let proposal = st
+        .propose_transfer(
+            account.account_id(),
+            &input_selector(StandardFeeRule::Zip317, None, T::SHIELDED_PROTOCOL),
+            request,
+            NonZeroU32::new(1).unwrap(),
+        )
+        .unwrap();
+    let txid = st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()[0];
+    let (mined_height, _) = st.generate_next_block_including(txid);
+    st.generate_empty_block();
+    let (tip, _) = st.generate_empty_block();
+    st.scan_cached_blocks(mined_height, 3);
+    assert_eq!(st.wallet().get_tx_height(txid).unwrap(), Some(mined_height));
+
+    // A competing chain replaces the last three blocks without including the transaction.
+    st.truncate_cache_to_height(h);
+    for _ in 0..4 {
+        st.generate_empty_block();
+    }
+    let new_blocks = cached_blocks_from(&st, h);
 
-    // Scan the cache
-    st.scan_cached_blocks(h1, 1);
+    // The block following the wallet's tip does not connect to it.
+    assert_matches!(
+        st.try_scan_cached_blocks(tip + 1, 1),
+        Err(chain::error::Error::Scan(ScanError::PrevHashMismatch { at_height }))
+            if at_height == tip + 1
+    );
+    assert_eq!(
+        check_chain_continuity(st.wallet(), &new_blocks[4]).unwrap(),
+        Some(tip)
+    );
 
-    // Create a second fake CompactBlock sending more value to the address
-    let (h2, _, _) = st.generate_next_block(
-        &dfvk,
-        AddressType::DefaultExternal,
-        NonNegativeAmount::const_from_u64(7),
+    // Checking the blocks of the competing chain in height order locates the fork.
+    assert_eq!(
+        check_chain_continuity(st.wallet(), &new_blocks[0]).unwrap(),
+        None
+    );
+    assert_eq!(
+        check_chain_continuity(st.wallet(), &new_blocks[1]).unwrap(),
+        Some(mined_height)
     );
 
-    // Scanning should detect no inconsistencies
-    st.scan_cached_blocks(h2, 1);
+    // Rewinding to the fork reports the transaction as no longer mined.
+    let reorg_info = truncate_to_chain_fork(st.wallet_mut(), mined_height - 1).unwrap();
+    assert_eq!(reorg_info.fork_height(), h);
+    assert_eq!(reorg_info.orphaned_txids(), &[txid]);
+    assert_eq!(reorg_info.unmined_wallet_txids(), &[txid]);
+    assert_eq!(st.wallet().get_tx_height(txid).unwrap(), None);
+
+    // After scanning the competing chain, the transaction remains unmined but has not expired,
+    // so it may still be mined.
+    st.scan_cached_blocks(mined_height, 4);
+    let tx = st
+        .get_tx_history()
+        .unwrap()
+        .into_iter()
+        .find(|tx| tx.txid() == txid)
+        .unwrap();
+    assert_eq!(tx.mined_height(), None);
+    assert!(!tx.expired_unmined());
+
+    let (h, _) = st.generate_next_block_including(txid);
+    st.scan_cached_blocks(h, 1);
+    assert_eq!(st.wallet().get_tx_height(txid).unwrap(), Some(h));
 }
 
-// FIXME: This requires fixes to the test framework.
-#[allow(dead_code)]
-pub(crate) fn invalid_chain_cache_disconnected<T: ShieldedPoolTester>() {
+pub(crate) fn reorg_orphans_received_note<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32]))
         .build();
 
+    let account = st.test_account().cloned().unwrap();
     let dfvk = T::test_account_fvk(&st);
 
-    // Create some fake CompactBlocks
-    let (h, _, _) = st.generate_next_block(
-        &dfvk,
-        AddressType::DefaultExternal,
-        NonNegativeAmount::const_from_u64(5),
-    );
-    let (last_contiguous_height, _, _) = st.generate_next_block(
-        &dfvk,
-        AddressType::DefaultExternal,
-        NonNegativeAmount::const_from_u64(7),
+    // Receive a note, and then another note three blocks below the tip.
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let value2 = NonNegativeAmount::const_from_u64(70000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    let (received_height, _, _) =
+        st.generate_next_block(&dfvk, AddressType::DefaultExternal, value2);
+    st.generate_empty_block();
+    st.generate_empty_block();
+    st.scan_cached_blocks(h, 4);
+    assert_eq!(
+        st.get_total_balance(account.account_id()),
+        (value + value2).unwrap()
     );
 
-    // Scanning the cache should find no inconsistencies
-    st.scan_cached_blocks(h, 2);
+    let received_txid = st
+        .wallet()
+        .get_transactions(
+            account.account_id(),
+            &TransactionFilter::new().with_mined_heights(received_height..received_height + 1),
+            0,
+            10,
+        )
+        .unwrap()[0]
+        .txid();
 
-    // Create more fake CompactBlocks that don't connect to the scanned ones
-    let disconnect_height = last_contiguous_height + 1;
-    st.generate_block_at(
-        disconnect_height,
-        BlockHash([1; 32]),
-        &dfvk,
-        AddressType::DefaultExternal,
-        NonNegativeAmount::const_from_u64(8),
-        2,
-        2,
-        true,
-    );
-    st.generate_next_block(
-        &dfvk,
-        AddressType::DefaultExternal,
-        NonNegativeAmount::const_from_u64(3),
+    // A competing chain replaces the last three blocks without including the second note.
+    st.truncate_cache_to_height(h);
+    for _ in 0..3 {
+        st.generate_empty_block();
+    }
+    let new_blocks = cached_blocks_from(&st, received_height);
+    assert_eq!(
+        check_chain_continuity(st.wallet(), &new_blocks[0]).unwrap(),
+        Some(received_height)
     );
 
-    // Data+cache chain should be invalid at the data/cache boundary
-    assert_matches!(
-        st.try_scan_cached_blocks(
-            disconnect_height,
-            2
-        ),
-        Err(chain::error::Error::Scan(ScanError::PrevHashMismatch { at_height }))
-            if at_height == disconnect_height
-    );
+    // Rewinding to the fork reports the transaction as orphaned, but not as one that spent the
+    // wallet's funds.
+    let reorg_info = truncate_to_chain_fork(st.wallet_mut(), received_height - 1).unwrap();
+    assert_eq!(reorg_info.fork_height(), h);
+    assert_eq!(reorg_info.orphaned_txids(), &[received_txid]);
+    assert!(reorg_info.unmined_wallet_txids().is_empty());
+    assert_eq!(st.wallet().get_tx_height(received_txid).unwrap(), None);
+
+    // The note is no longer part of the wallet's balance after scanning the competing chain.
+    st.scan_cached_blocks(received_height, 3);
+    assert_eq!(st.get_total_balance(account.account_id()), value);
+    assert_eq!(st.get_spendable_balance(account.account_id(), 1), value);
 }
 
-pub(crate) fn data_db_truncation<T: ShieldedPoolTester>() {
+pub(crate) fn reorg_orphans_spend_detected_by_nullifier<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32]))
@@ -2028,55 +4018,50 @@ pub(crate) fn data_db_truncation<T: ShieldedPoolTester>() {
     let account = st.test_account().cloned().unwrap();
     let dfvk = T::test_account_fvk(&st);
 
-    // Wallet summary is not yet available
-    assert_eq!(st.get_wallet_summary(0), None);
-
-    // Create fake CompactBlocks sending value to the address
-    let value = NonNegativeAmount::const_from_u64(5);
-    let value2 = NonNegativeAmount::const_from_u64(7);
-    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
-    st.generate_next_block(&dfvk, AddressType::DefaultExternal, value2);
-
-    // Scan the cache
+    // Receive a note, and then spend it in a transaction that the wallet only observes through
+    // the note's nullifier in a compact block.
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h, _, nf) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    let not_our_key = T::sk_to_fvk(&T::sk(&[0xf5; 32]));
+    let to = T::fvk_default_address(&not_our_key);
+    let value2 = NonNegativeAmount::const_from_u64(20000);
+    let (spent_height, _) = st.generate_next_block_spending(&dfvk, (nf, value), to, value2);
     st.scan_cached_blocks(h, 2);
-
-    // Spendable balance should reflect both received notes
     assert_eq!(
-        st.get_spendable_balance(account.account_id(), 1),
-        (value + value2).unwrap()
+        st.get_total_balance(account.account_id()),
+        (value - value2).unwrap()
     );
 
-    // "Rewind" to height of last scanned block (this is a no-op)
-    st.wallet_mut()
-        .transactionally(|wdb| truncate_to_height(wdb.conn.0, &wdb.params, h + 1))
-        .unwrap();
-
-    // Spendable balance should be unaltered
+    // A competing chain replaces the block containing the spend.
+    st.truncate_cache_to_height(h);
+    for _ in 0..3 {
+        st.generate_empty_block();
+    }
+    let reorg_info = truncate_to_chain_fork(st.wallet_mut(), h).unwrap();
+    assert_eq!(reorg_info.fork_height(), h);
+    assert_eq!(reorg_info.orphaned_txids().len(), 1);
     assert_eq!(
-        st.get_spendable_balance(account.account_id(), 1),
-        (value + value2).unwrap()
+        reorg_info.unmined_wallet_txids(),
+        reorg_info.orphaned_txids()
     );
 
-    // Rewind so that one block is dropped
-    st.wallet_mut()
-        .transactionally(|wdb| truncate_to_height(wdb.conn.0, &wdb.params, h))
-        .unwrap();
-
-    // Spendable balance should only contain the first received note;
-    // the rest should be pending.
-    assert_eq!(st.get_spendable_balance(account.account_id(), 1), value);
+    // The expiry height of the spending transaction is unknown, so it may still be mined: the
+    // spent note must not become spendable again as the chain advances, and the change that
+    // was observed in the orphaned block no longer counts towards the balance.
+    st.scan_cached_blocks(spent_height, 3);
     assert_eq!(
-        st.get_pending_shielded_balance(account.account_id(), 1),
-        value2
+        st.wallet()
+            .get_tx_height(reorg_info.orphaned_txids()[0])
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        st.get_total_balance(account.account_id()),
+        NonNegativeAmount::ZERO
     );
-
-    // Scan the cache again
-    st.scan_cached_blocks(h, 2);
-
-    // Account balance should again reflect both received notes
     assert_eq!(
         st.get_spendable_balance(account.account_id(), 1),
-        (value + value2).unwrap()
+        NonNegativeAmount::ZERO
     );
 }
 
@@ -2263,3 +4248,272 @@ pub(crate) fn scan_cached_blocks_detects_spends_out_of_order<T: ShieldedPoolTest
         (value - value2).unwrap()
     );
 }
+
+pub(crate) fn detect_spends_then_full_scan<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Receive a note and scan it.
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (received_height, _, nf) =
+        st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(received_height, 1);
+    assert_eq!(st.get_total_balance(account.account_id()), value);
+    let received =
+        T::select_spendable_notes(&st, account.account_id(), value, received_height, &[]).unwrap();
+    assert_eq!(received.len(), 1);
+    let received_txid = *received[0].txid();
+
+    // Spend the note, sending change back to the wallet, and then receive another note.
+    let not_our_key = T::sk_to_fvk(&T::sk(&[0xf5; 32]));
+    let to2 = T::fvk_default_address(&not_our_key);
+    let value2 = NonNegativeAmount::const_from_u64(20000);
+    let (spent_height, _) = st.generate_next_block_spending(&dfvk, (nf, value), to2, value2);
+    let value3 = NonNegativeAmount::const_from_u64(70000);
+    let (last_height, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value3);
+
+    // Detecting spends finds the spend, but not the change or the newly received note.
+    let summary = st.detect_spends_in_cached_blocks(spent_height, 2);
+    assert_eq!(summary.scanned_range(), spent_height..last_height + 1);
+    assert_eq!(T::received_note_count(&summary), 0);
+    assert_eq!(
+        st.get_total_balance(account.account_id()),
+        NonNegativeAmount::ZERO
+    );
+    // The spend-scanned blocks have not been trial-decrypted, so they do not count as scanned.
+    assert_eq!(
+        st.wallet()
+            .block_max_scanned()
+            .unwrap()
+            .unwrap()
+            .block_height(),
+        received_height
+    );
+    assert!(
+        T::select_spendable_notes(&st, account.account_id(), value, last_height, &[])
+            .unwrap()
+            .is_empty()
+    );
+
+    // The spend-scanned range is still suggested for a full scan.
+    assert!(st
+        .wallet()
+        .suggest_scan_ranges()
+        .unwrap()
+        .iter()
+        .any(|range| range.block_range().contains(&spent_height)
+            && range.block_range().contains(&last_height)));
+
+    // The full scan finds the received notes without counting the spend again.
+    let summary = st.scan_cached_blocks(spent_height, 2);
+    assert_eq!(T::received_note_count(&summary), 2);
+    assert_eq!(
+        st.get_total_balance(account.account_id()),
+        ((value - value2).unwrap() + value3).unwrap()
+    );
+    assert!(st.wallet().suggest_scan_ranges().unwrap().is_empty());
+    assert_eq!(
+        st.wallet()
+            .block_max_scanned()
+            .unwrap()
+            .unwrap()
+            .block_height(),
+        last_height
+    );
+
+    let spendable =
+        T::select_spendable_notes(&st, account.account_id(), value3, last_height, &[]).unwrap();
+    assert!(!spendable.is_empty());
+    assert!(spendable.iter().all(|note| note.txid() != &received_txid));
+}
+
+pub(crate) fn scan_cached_blocks_reports_progress<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Create five blocks, each sending value to the account.
+    let value = NonNegativeAmount::const_from_u64(10000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    for _ in 1..5 {
+        st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    }
+
+    // Scan in batches of two blocks.
+    let mut reports: Vec<BatchScanProgress> = vec![];
+    let summary = st.scan_cached_blocks_with_progress(
+        h,
+        5,
+        2,
+        &ScanParallelism::default(),
+        &mut |progress: &BatchScanProgress| reports.push(progress.clone()),
+        &ScanCancellation::new(),
+    );
+    assert_eq!(summary.scanned_range(), h..h + 5);
+    assert_eq!(T::received_note_count(&summary), 5);
+
+    assert_eq!(
+        reports.iter().map(|p| p.batch_range()).collect::<Vec<_>>(),
+        vec![h..h + 2, h + 2..h + 4, h + 4..h + 5]
+    );
+    assert_eq!(
+        reports
+            .iter()
+            .map(|p| p.blocks_remaining())
+            .collect::<Vec<_>>(),
+        vec![3, 1, 0]
+    );
+    assert_eq!(
+        reports
+            .iter()
+            .map(|p| T::received_note_count(p.summary()))
+            .collect::<Vec<_>>(),
+        vec![2, 4, 5]
+    );
+    assert!(reports[0].trial_decryptions() > 0);
+    assert!(reports
+        .windows(2)
+        .all(|w| w[0].batch_range().end == w[1].batch_range().start
+            && w[0].trial_decryptions() < w[1].trial_decryptions()));
+
+    assert_eq!(
+        st.get_total_balance(account.account_id()),
+        (value * 5).unwrap()
+    );
+
+    // The note commitment trees built across batches match the chain.
+    let chain_state = st.latest_cached_block().unwrap().chain_state().clone();
+    let sapling_root = st
+        .wallet_mut()
+        .with_sapling_tree_mut::<_, _, ShardTreeError<commitment_tree::Error>>(|tree| {
+            tree.root_at_checkpoint_id(&(h + 4))
+        })
+        .unwrap();
+    assert_eq!(sapling_root, chain_state.final_sapling_tree().root());
+    #[cfg(feature = "orchard")]
+    {
+        let orchard_root = st
+            .wallet_mut()
+            .with_orchard_tree_mut::<_, _, ShardTreeError<commitment_tree::Error>>(|tree| {
+                tree.root_at_checkpoint_id(&(h + 4))
+            })
+            .unwrap();
+        assert_eq!(orchard_root, chain_state.final_orchard_tree().root());
+    }
+}
+
+pub(crate) fn scan_cached_blocks_with_parallelism<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(10000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    for _ in 1..4 {
+        st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    }
+
+    // The parallelism setting may differ between scans of the same wallet.
+    let settings = [
+        ScanParallelism::single_threaded()
+            .with_decryption_batch_size(NonZeroUsize::new(1).unwrap()),
+        ScanParallelism::with_threads(NonZeroUsize::new(2).unwrap()).unwrap(),
+    ];
+    for (i, parallelism) in settings.iter().enumerate() {
+        let from_height = h + 2 * u32::try_from(i).unwrap();
+        let summary = st.scan_cached_blocks_with_progress(
+            from_height,
+            2,
+            2,
+            parallelism,
+            &mut |_: &BatchScanProgress| {},
+            &ScanCancellation::new(),
+        );
+        assert_eq!(summary.scanned_range(), from_height..from_height + 2);
+        assert_eq!(T::received_note_count(&summary), 2);
+    }
+
+    assert_eq!(
+        st.get_total_balance(account.account_id()),
+        (value * 4).unwrap()
+    );
+}
+
+pub(crate) fn scan_cached_blocks_cancellation_is_resumable<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(10000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    for _ in 1..6 {
+        st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    }
+
+    // Cancel the scan from within the observer after the first batch.
+    let cancellation = ScanCancellation::new();
+    let handle = cancellation.clone();
+    let mut batches = 0;
+    let summary = st.scan_cached_blocks_with_progress(
+        h,
+        6,
+        2,
+        &ScanParallelism::default(),
+        &mut |_: &BatchScanProgress| {
+            batches += 1;
+            handle.cancel();
+        },
+        &cancellation,
+    );
+    assert_eq!(batches, 1);
+    assert_eq!(summary.scanned_range(), h..h + 2);
+
+    // The completed batch has been committed.
+    assert_eq!(
+        block_max_scanned(&st.wallet().conn, &st.wallet().params)
+            .unwrap()
+            .unwrap()
+            .block_height(),
+        h + 1
+    );
+    assert_eq!(
+        st.get_total_balance(account.account_id()),
+        (value * 2).unwrap()
+    );
+
+    // A cancelled handle prevents any further scanning.
+    let summary = st.scan_cached_blocks_with_progress(
+        h + 2,
+        4,
+        2,
+        &ScanParallelism::default(),
+        &mut |_: &BatchScanProgress| panic!("no batch should be scanned"),
+        &cancellation,
+    );
+    assert_eq!(summary.scanned_range(), h + 2..h + 2);
+
+    // Scanning resumes from where it stopped.
+    let summary = st.scan_cached_blocks(h + 2, 4);
+    assert_eq!(summary.scanned_range(), h + 2..h + 6);
+    assert_eq!(
+        st.get_total_balance(account.account_id()),
+        (value * 6).unwrap()
+    );
+}
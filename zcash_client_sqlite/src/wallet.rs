@@ -64,18 +64,25 @@
 //!   wallet.
 //! - `memo` the shielded memo associated with the output, if any.
 
-use incrementalmerkletree::Retention;
+use incrementalmerkletree::{Hashable, Position, Retention};
+use iso_currency::Currency;
+use maybe_rayon::{
+    prelude::{IndexedParallelIterator, ParallelIterator},
+    slice::ParallelSliceMut,
+};
 use rusqlite::{self, named_params, OptionalExtension};
+use rust_decimal::Decimal;
 use secrecy::{ExposeSecret, SecretVec};
-use shardtree::{error::ShardTreeError, store::ShardStore, ShardTree};
+use shardtree::{error::ShardTreeError, store::ShardStore, LocatedPrunableTree, ShardTree};
 use zip32::fingerprint::SeedFingerprint;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryFrom;
 use std::io::{self, Cursor};
 use std::num::NonZeroU32;
-use std::ops::RangeInclusive;
-use tracing::debug;
+use std::ops::{Range, RangeInclusive};
+use std::str::FromStr;
+use tracing::{debug, trace, warn};
 use zcash_keys::keys::{
     AddressGenerationError, UnifiedAddressRequest, UnifiedIncomingViewingKey, UnifiedSpendingKey,
 };
@@ -83,13 +90,18 @@ use zcash_keys::keys::{
 use zcash_client_backend::{
     address::{Address, UnifiedAddress},
     data_api::{
+        chain::ChainState,
         scanning::{ScanPriority, ScanRange},
-        AccountBalance, AccountBirthday, AccountSource, BlockMetadata, Ratio,
-        SentTransactionOutput, WalletSummary, SAPLING_SHARD_HEIGHT,
+        AccountBalance, AccountBirthday, AccountDetails, AccountMetadataValue, AccountPoolBalances,
+        AccountSource, BlockMetadata, DraftId, DraftPayment, ExchangeRateObservation, MemoPattern,
+        MemoQuery, Ratio, ScannedBlock, SentTransactionOutput, TransactionDirection,
+        TransactionFilter, TransactionSummary, WalletRead, WalletSummary, SAPLING_SHARD_HEIGHT,
     },
     encoding::AddressCodec,
     keys::UnifiedFullViewingKey,
+    scanning::ScanParallelism,
     wallet::{Note, NoteId, Recipient, WalletTx},
+    zip321::TransactionRequest,
     PoolType, ShieldedProtocol,
 };
 use zcash_primitives::{
@@ -114,18 +126,20 @@ use crate::{
 use self::scanning::{parse_priority_code, priority_code, replace_queue_entries};
 
 #[cfg(feature = "orchard")]
-use {crate::ORCHARD_TABLES_PREFIX, zcash_client_backend::data_api::ORCHARD_SHARD_HEIGHT};
+use {
+    crate::ORCHARD_TABLES_PREFIX, incrementalmerkletree::frontier::Frontier,
+    shardtree::store::Checkpoint, zcash_client_backend::data_api::ORCHARD_SHARD_HEIGHT,
+};
 
 #[cfg(feature = "transparent-inputs")]
 use {
     crate::UtxoId,
     rusqlite::Row,
     std::collections::BTreeSet,
-    zcash_address::unified::{Encoding, Ivk, Uivk},
     zcash_client_backend::wallet::{TransparentAddressMetadata, WalletTransparentOutput},
     zcash_primitives::{
         legacy::{
-            keys::{IncomingViewingKey, NonHardenedChildIndex},
+            keys::{IncomingViewingKey, NonHardenedChildIndex, TransparentKeyScope},
             Script, TransparentAddress,
         },
         transaction::components::{OutPoint, TxOut},
@@ -139,6 +153,7 @@ pub mod init;
 pub(crate) mod orchard;
 pub(crate) mod sapling;
 pub(crate) mod scanning;
+pub(crate) mod snapshot;
 #[cfg(feature = "transparent-inputs")]
 pub(crate) mod transparent;
 
@@ -159,9 +174,12 @@ fn parse_account_source(
             })?,
         }),
         (1, None, None) => Ok(AccountSource::Imported),
-        (0, None, None) | (1, Some(_), Some(_)) => Err(SqliteClientError::CorruptedData(
-            "Wallet DB account_kind constraint violated".to_string(),
-        )),
+        (2, None, None) => Ok(AccountSource::StandaloneTransparent),
+        (0, None, None) | (1, Some(_), Some(_)) | (2, Some(_), Some(_)) => {
+            Err(SqliteClientError::CorruptedData(
+                "Wallet DB account_kind constraint violated".to_string(),
+            ))
+        }
         (_, _, _) => Err(SqliteClientError::CorruptedData(
             "Unrecognized account_kind".to_string(),
         )),
@@ -172,6 +190,7 @@ fn account_kind_code(value: AccountSource) -> u32 {
     match value {
         AccountSource::Derived { .. } => 0,
         AccountSource::Imported => 1,
+        AccountSource::StandaloneTransparent => 2,
     }
 }
 
@@ -189,6 +208,12 @@ pub(crate) enum ViewingKey {
     /// Accounts that have this kind of viewing key cannot be used in wallet contexts,
     /// because they are unable to maintain an accurate balance.
     Incoming(Box<UnifiedIncomingViewingKey>),
+
+    /// No viewing key.
+    ///
+    /// This is the case for standalone transparent accounts, which track only the single
+    /// transparent address that was imported for them.
+    StandaloneTransparent,
 }
 
 /// An account stored in a `zcash_client_sqlite` database.
@@ -212,6 +237,9 @@ impl Account {
         match &self.viewing_key {
             ViewingKey::Full(ufvk) => ufvk.default_address(request),
             ViewingKey::Incoming(uivk) => uivk.default_address(request),
+            ViewingKey::StandaloneTransparent => {
+                Err(AddressGenerationError::ShieldedReceiverRequired)
+            }
         }
     }
 }
@@ -229,7 +257,7 @@ impl zcash_client_backend::data_api::Account<AccountId> for Account {
         self.viewing_key.ufvk()
     }
 
-    fn uivk(&self) -> UnifiedIncomingViewingKey {
+    fn uivk(&self) -> Option<UnifiedIncomingViewingKey> {
         self.viewing_key.uivk()
     }
 }
@@ -238,14 +266,15 @@ impl ViewingKey {
     fn ufvk(&self) -> Option<&UnifiedFullViewingKey> {
         match self {
             ViewingKey::Full(ufvk) => Some(ufvk),
-            ViewingKey::Incoming(_) => None,
+            ViewingKey::Incoming(_) | ViewingKey::StandaloneTransparent => None,
         }
     }
 
-    fn uivk(&self) -> UnifiedIncomingViewingKey {
+    fn uivk(&self) -> Option<UnifiedIncomingViewingKey> {
         match self {
-            ViewingKey::Full(ufvk) => ufvk.as_ref().to_unified_incoming_viewing_key(),
-            ViewingKey::Incoming(uivk) => uivk.as_ref().clone(),
+            ViewingKey::Full(ufvk) => Some(ufvk.as_ref().to_unified_incoming_viewing_key()),
+            ViewingKey::Incoming(uivk) => Some(uivk.as_ref().clone()),
+            ViewingKey::StandaloneTransparent => None,
         }
     }
 }
@@ -331,6 +360,16 @@ pub(crate) fn memo_repr(memo: Option<&MemoBytes>) -> Option<&[u8]> {
     })
 }
 
+/// Returns the searchable representation of a memo, as stored in the `memo_text` columns.
+///
+/// Only text memos are searchable. Their text is lowercased so that searches ignore case.
+pub(crate) fn memo_search_text(memo: Option<&MemoBytes>) -> Option<String> {
+    match memo.map(Memo::try_from) {
+        Some(Ok(Memo::Text(text))) => Some(text.to_lowercase()),
+        _ => None,
+    }
+}
+
 // Returns the highest used account index for a given seed.
 pub(crate) fn max_zip32_account_index(
     conn: &rusqlite::Connection,
@@ -361,7 +400,7 @@ pub(crate) fn add_account<P: consensus::Parameters>(
             seed_fingerprint,
             account_index,
         } => (Some(seed_fingerprint), Some(account_index)),
-        AccountSource::Imported => (None, None),
+        AccountSource::Imported | AccountSource::StandaloneTransparent => (None, None),
     };
 
     #[cfg(feature = "orchard")]
@@ -410,7 +449,7 @@ pub(crate) fn add_account<P: consensus::Parameters>(
             ":hd_seed_fingerprint": hd_seed_fingerprint.as_ref().map(|fp| fp.to_bytes()),
             ":hd_account_index": hd_account_index.map(u32::from),
             ":ufvk": viewing_key.ufvk().map(|ufvk| ufvk.encode(params)),
-            ":uivk": viewing_key.uivk().encode(params),
+            ":uivk": viewing_key.uivk().map(|uivk| uivk.encode(params)),
             ":orchard_fvk_item_cache": orchard_item,
             ":sapling_fvk_item_cache": sapling_item,
             ":p2pkh_fvk_item_cache": transparent_item,
@@ -426,7 +465,7 @@ pub(crate) fn add_account<P: consensus::Parameters>(
             ":hd_seed_fingerprint": hd_seed_fingerprint.as_ref().map(|fp| fp.to_bytes()),
             ":hd_account_index": hd_account_index.map(u32::from),
             ":ufvk": viewing_key.ufvk().map(|ufvk| ufvk.encode(params)),
-            ":uivk": viewing_key.uivk().encode(params),
+            ":uivk": viewing_key.uivk().map(|uivk| uivk.encode(params)),
             ":sapling_fvk_item_cache": sapling_item,
             ":p2pkh_fvk_item_cache": transparent_item,
             ":birthday_height": u32::from(birthday.height()),
@@ -691,6 +730,16 @@ pub(crate) fn get_transparent_receivers<P: consensus::Parameters>(
         );
     }
 
+    // Include the external and ephemeral addresses derived under the gap limit.
+    for key_scope in [
+        TransparentKeyScope::EXTERNAL,
+        TransparentKeyScope::EPHEMERAL,
+    ] {
+        for usage in transparent::get_address_usage(conn, params, account, key_scope)? {
+            ret.insert(*usage.address(), Some(usage.metadata().clone()));
+        }
+    }
+
     Ok(ret)
 }
 
@@ -700,37 +749,8 @@ pub(crate) fn get_legacy_transparent_address<P: consensus::Parameters>(
     conn: &rusqlite::Connection,
     account_id: AccountId,
 ) -> Result<Option<(TransparentAddress, NonHardenedChildIndex)>, SqliteClientError> {
-    use zcash_address::unified::Container;
-    use zcash_primitives::legacy::keys::ExternalIvk;
-
-    // Get the UIVK for the account.
-    let uivk_str: Option<String> = conn
-        .query_row(
-            "SELECT uivk FROM accounts WHERE id = :account",
-            [account_id.0],
-            |row| row.get(0),
-        )
-        .optional()?;
-
-    if let Some(uivk_str) = uivk_str {
-        let (network, uivk) = Uivk::decode(&uivk_str)
-            .map_err(|e| SqliteClientError::CorruptedData(format!("Unable to parse UIVK: {e}")))?;
-        if params.network_type() != network {
-            return Err(SqliteClientError::CorruptedData(
-                "Network type mismatch".to_owned(),
-            ));
-        }
-
-        // Derive the default transparent address (if it wasn't already part of a derived UA).
-        for item in uivk.items() {
-            if let Ivk::P2pkh(tivk_bytes) = item {
-                let tivk = ExternalIvk::deserialize(&tivk_bytes)?;
-                return Ok(Some(tivk.default_address()));
-            }
-        }
-    }
-
-    Ok(None)
+    // Derive the default transparent address (if it wasn't already part of a derived UA).
+    Ok(transparent::get_external_ivk(conn, params, account_id)?.map(|tivk| tivk.default_address()))
 }
 
 /// Returns the [`UnifiedFullViewingKey`]s for the wallet.
@@ -1155,11 +1175,17 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
         .or(sapling_scan_progress)
         .or(orchard_scan_progress);
 
-    let mut stmt_accounts = tx.prepare_cached("SELECT id FROM accounts")?;
+    let mut stmt_accounts = tx.prepare_cached("SELECT id, ufvk IS NULL FROM accounts")?;
     let mut account_balances = stmt_accounts
         .query([])?
         .and_then(|row| {
-            Ok::<_, SqliteClientError>((AccountId(row.get::<_, u32>(0)?), AccountBalance::ZERO))
+            let mut balance = AccountBalance::ZERO;
+            // Accounts for which only an incoming viewing key is known cannot detect spends,
+            // and so can never report a spendable balance.
+            if row.get::<_, bool>(1)? {
+                balance.mark_received_only();
+            }
+            Ok::<_, SqliteClientError>((AccountId(row.get::<_, u32>(0)?), balance))
         })
         .collect::<Result<HashMap<AccountId, AccountBalance>, _>>()?;
 
@@ -1176,6 +1202,7 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
             NonNegativeAmount,
             NonNegativeAmount,
             NonNegativeAmount,
+            NonNegativeAmount,
         ) -> Result<(), SqliteClientError>,
     {
         // If the shard containing the summary height contains any unscanned ranges that start below or
@@ -1204,7 +1231,8 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
 
         let any_spendable = is_any_spendable(tx, summary_height, table_prefix)?;
         let mut stmt_select_notes = tx.prepare_cached(&format!(
-            "SELECT n.account_id, n.value, n.is_change, scan_state.max_priority, t.block
+            "SELECT n.account_id, n.value, n.is_change, scan_state.max_priority, t.block,
+                    n.is_locked
              FROM {table_prefix}_received_notes n
              JOIN transactions t ON t.id_tx = n.tx
              LEFT OUTER JOIN v_{table_prefix}_shards_scan_state scan_state
@@ -1212,8 +1240,14 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
                 AND n.commitment_tree_position < scan_state.end_position_exclusive
              WHERE (
                 t.block IS NOT NULL -- the receiving tx is mined
-                OR t.expiry_height IS NULL -- the receiving tx will not expire
                 OR t.expiry_height >= :summary_height -- the receiving tx is unexpired
+                OR (
+                    -- the receiving tx was only observed in a compact block, and if it has
+                    -- been orphaned by a chain reorg, the chain has not yet advanced past the
+                    -- height at which it had been mined
+                    t.expiry_height IS NULL
+                    AND IFNULL(t.orphaned_height >= :summary_height, TRUE)
+                )
              )
              -- and the received note is unspent
              AND n.id NOT IN (
@@ -1259,21 +1293,35 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
 
             let received_height = row.get::<_, Option<u32>>(4)?.map(BlockHeight::from);
 
+            let received_only = account_balances
+                .get(&account)
+                .map_or(false, |b| b.is_received_only());
+
             let is_spendable = any_spendable
+                && !received_only
                 && received_height.iter().any(|h| h <= &summary_height)
                 && max_priority <= ScanPriority::Scanned;
 
             let is_pending_change =
                 is_change && received_height.iter().all(|h| h > &summary_height);
 
-            let (spendable_value, change_pending_confirmation, value_pending_spendability) = {
+            let is_locked = row.get::<_, bool>(5)?;
+
+            let (
+                spendable_value,
+                change_pending_confirmation,
+                value_pending_spendability,
+                locked_value,
+            ) = {
                 let zero = NonNegativeAmount::ZERO;
-                if is_spendable {
-                    (value, zero, zero)
+                if is_locked {
+                    (zero, zero, zero, value)
+                } else if is_spendable {
+                    (value, zero, zero, zero)
                 } else if is_pending_change {
-                    (zero, value, zero)
+                    (zero, value, zero, zero)
                 } else {
-                    (zero, zero, value)
+                    (zero, zero, value, zero)
                 }
             };
 
@@ -1283,6 +1331,7 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
                     spendable_value,
                     change_pending_confirmation,
                     value_pending_spendability,
+                    locked_value,
                 )?;
             }
         }
@@ -1297,11 +1346,16 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
             summary_height,
             &mut account_balances,
             ORCHARD_TABLES_PREFIX,
-            |balances, spendable_value, change_pending_confirmation, value_pending_spendability| {
+            |balances,
+             spendable_value,
+             change_pending_confirmation,
+             value_pending_spendability,
+             locked_value| {
                 balances.with_orchard_balance_mut::<_, SqliteClientError>(|bal| {
                     bal.add_spendable_value(spendable_value)?;
                     bal.add_pending_change_value(change_pending_confirmation)?;
                     bal.add_pending_spendable_value(value_pending_spendability)?;
+                    bal.add_locked_value(locked_value)?;
                     Ok(())
                 })
             },
@@ -1315,11 +1369,16 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
         summary_height,
         &mut account_balances,
         SAPLING_TABLES_PREFIX,
-        |balances, spendable_value, change_pending_confirmation, value_pending_spendability| {
+        |balances,
+         spendable_value,
+         change_pending_confirmation,
+         value_pending_spendability,
+         locked_value| {
             balances.with_sapling_balance_mut::<_, SqliteClientError>(|bal| {
                 bal.add_spendable_value(spendable_value)?;
                 bal.add_pending_change_value(change_pending_confirmation)?;
                 bal.add_pending_spendable_value(value_pending_spendability)?;
+                bal.add_locked_value(locked_value)?;
                 Ok(())
             })
         },
@@ -1333,7 +1392,9 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
         let stable_height = chain_tip_height.saturating_sub(PRUNING_DEPTH);
 
         let mut stmt_transparent_balances = tx.prepare(
-            "SELECT u.received_by_account_id, SUM(u.value_zat)
+            "SELECT u.received_by_account_id,
+                    SUM(CASE WHEN u.is_locked THEN 0 ELSE u.value_zat END),
+                    SUM(CASE WHEN u.is_locked THEN u.value_zat ELSE 0 END)
              FROM utxos u
              WHERE u.height <= :max_height
              -- and the received txo is unspent
@@ -1355,13 +1416,17 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
 
         while let Some(row) = rows.next()? {
             let account = AccountId(row.get(0)?);
-            let raw_value = row.get(1)?;
-            let value = NonNegativeAmount::from_nonnegative_i64(raw_value).map_err(|_| {
-                SqliteClientError::CorruptedData(format!("Negative UTXO value {:?}", raw_value))
-            })?;
+            let to_value = |raw_value: i64| {
+                NonNegativeAmount::from_nonnegative_i64(raw_value).map_err(|_| {
+                    SqliteClientError::CorruptedData(format!("Negative UTXO value {:?}", raw_value))
+                })
+            };
+            let value = to_value(row.get(1)?)?;
+            let locked_value = to_value(row.get(2)?)?;
 
             if let Some(balances) = account_balances.get_mut(&account) {
                 balances.add_unshielded_value(value)?;
+                balances.add_unshielded_locked_value(locked_value)?;
             }
         }
         drop(transparent_trace);
@@ -1422,6 +1487,186 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
     Ok(Some(summary))
 }
 
+/// The classes into which [`get_account_pool_balances`] sorts unspent outputs.
+const BALANCE_SPENDABLE: i64 = 0;
+const BALANCE_PENDING_CHANGE: i64 = 1;
+const BALANCE_PENDING_CONFIRMATION: i64 = 2;
+const BALANCE_UNCONFIRMED: i64 = 3;
+const BALANCE_LOCKED: i64 = 4;
+
+/// Returns the balances of every account in the wallet, broken down by pool.
+///
+/// Unspent outputs are classified and summed per account in SQL, using one query per pool, so the
+/// cost of this does not grow with the number of accounts beyond the size of the result. All
+/// queries are evaluated within `tx`, and so observe the same chain tip.
+pub(crate) fn get_account_pool_balances(
+    tx: &rusqlite::Transaction,
+    min_confirmations: u32,
+) -> Result<HashMap<AccountId, AccountPoolBalances>, SqliteClientError> {
+    let chain_tip_height = scan_queue_extrema(tx)?.map(|range| *range.end());
+    // If the chain tip is unknown, no output can be considered confirmed.
+    let summary_height = chain_tip_height.map_or(BlockHeight::from(0), |h| {
+        (h + 1).saturating_sub(std::cmp::max(min_confirmations, 1))
+    });
+
+    let mut stmt_accounts = tx.prepare_cached("SELECT id FROM accounts")?;
+    let mut account_balances = stmt_accounts
+        .query_map([], |row| {
+            Ok((AccountId(row.get(0)?), AccountPoolBalances::ZERO))
+        })?
+        .collect::<Result<HashMap<_, _>, _>>()?;
+
+    fn add_balances(
+        rows: &mut rusqlite::Rows<'_>,
+        pool: PoolType,
+        account_balances: &mut HashMap<AccountId, AccountPoolBalances>,
+    ) -> Result<(), SqliteClientError> {
+        while let Some(row) = rows.next()? {
+            let account = AccountId(row.get(0)?);
+            let category: i64 = row.get(1)?;
+            let value_raw: i64 = row.get(2)?;
+            let value = NonNegativeAmount::from_nonnegative_i64(value_raw).map_err(|_| {
+                SqliteClientError::CorruptedData(format!(
+                    "Negative unspent output value: {}",
+                    value_raw
+                ))
+            })?;
+
+            if let Some(balances) = account_balances.get_mut(&account) {
+                let balance = balances.pool_mut(pool);
+                match category {
+                    BALANCE_SPENDABLE => balance.add_spendable_value(value)?,
+                    BALANCE_PENDING_CHANGE => balance.add_pending_change_value(value)?,
+                    BALANCE_PENDING_CONFIRMATION => {
+                        balance.add_pending_confirmation_value(value)?
+                    }
+                    BALANCE_UNCONFIRMED => balance.add_unconfirmed_value(value)?,
+                    BALANCE_LOCKED => balance.add_locked_value(value)?,
+                    _ => unreachable!("all balance categories are handled"),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // This applies the same rules as `get_wallet_summary`: a note is spendable only if the
+    // account has a full viewing key, the note has sufficient confirmations, and witnesses can
+    // be computed for both the note and the summary height.
+    let count_notes = |table_prefix: &'static str,
+                       protocol: ShieldedProtocol,
+                       account_balances: &mut HashMap<AccountId, AccountPoolBalances>|
+     -> Result<(), SqliteClientError> {
+        let mut stmt_notes = tx.prepare_cached(&format!(
+            "SELECT n.account_id,
+                    CASE
+                      WHEN n.is_locked THEN {BALANCE_LOCKED}
+                      WHEN a.ufvk IS NOT NULL
+                        AND t.block <= :summary_height
+                        AND IFNULL(scan_state.max_priority, :chain_tip_priority)
+                            <= :scanned_priority
+                        AND NOT EXISTS(
+                          SELECT 1 FROM v_{table_prefix}_shard_unscanned_ranges
+                          WHERE :summary_height
+                            BETWEEN subtree_start_height
+                            AND IFNULL(subtree_end_height, :summary_height)
+                          AND block_range_start <= :summary_height
+                        )
+                        THEN {BALANCE_SPENDABLE}
+                      WHEN n.is_change
+                        AND (t.block IS NULL OR t.block > :summary_height)
+                        THEN {BALANCE_PENDING_CHANGE}
+                      WHEN t.block IS NULL THEN {BALANCE_UNCONFIRMED}
+                      ELSE {BALANCE_PENDING_CONFIRMATION}
+                    END AS category,
+                    SUM(n.value)
+             FROM {table_prefix}_received_notes n
+             JOIN accounts a ON a.id = n.account_id
+             JOIN transactions t ON t.id_tx = n.tx
+             LEFT OUTER JOIN v_{table_prefix}_shards_scan_state scan_state
+                ON n.commitment_tree_position >= scan_state.start_position
+                AND n.commitment_tree_position < scan_state.end_position_exclusive
+             WHERE (
+                t.block IS NOT NULL -- the receiving tx is mined
+                OR t.expiry_height >= :summary_height -- the receiving tx is unexpired
+                OR (
+                    -- the receiving tx was only observed in a compact block, and if it has
+                    -- been orphaned by a chain reorg, the chain has not yet advanced past the
+                    -- height at which it had been mined
+                    t.expiry_height IS NULL
+                    AND IFNULL(t.orphaned_height >= :summary_height, TRUE)
+                )
+             )
+             -- and the received note is unspent
+             AND n.id NOT IN (
+               SELECT {table_prefix}_received_note_id
+               FROM {table_prefix}_received_note_spends
+               JOIN transactions t ON t.id_tx = transaction_id
+               WHERE t.block IS NOT NULL -- the spending transaction is mined
+               OR t.expiry_height IS NULL -- the spending tx will not expire
+               OR t.expiry_height > :summary_height -- the spending tx is unexpired
+             )
+             GROUP BY n.account_id, category"
+        ))?;
+
+        let mut rows = stmt_notes.query(named_params![
+            ":summary_height": u32::from(summary_height),
+            ":chain_tip_priority": priority_code(&ScanPriority::ChainTip),
+            ":scanned_priority": priority_code(&ScanPriority::Scanned),
+        ])?;
+        add_balances(&mut rows, PoolType::Shielded(protocol), account_balances)
+    };
+
+    count_notes(
+        SAPLING_TABLES_PREFIX,
+        ShieldedProtocol::Sapling,
+        &mut account_balances,
+    )?;
+    #[cfg(feature = "orchard")]
+    count_notes(
+        ORCHARD_TABLES_PREFIX,
+        ShieldedProtocol::Orchard,
+        &mut account_balances,
+    )?;
+
+    #[cfg(feature = "transparent-inputs")]
+    {
+        let zero_conf_height = chain_tip_height.map_or(BlockHeight::from(0), |h| {
+            (h + 1).saturating_sub(min_confirmations)
+        });
+        let stable_height =
+            chain_tip_height.map_or(BlockHeight::from(0), |h| h.saturating_sub(PRUNING_DEPTH));
+
+        let mut stmt_transparent = tx.prepare_cached(&format!(
+            "SELECT u.received_by_account_id,
+                    CASE
+                      WHEN u.is_locked THEN {BALANCE_LOCKED}
+                      WHEN u.height <= :max_height THEN {BALANCE_SPENDABLE}
+                      ELSE {BALANCE_PENDING_CONFIRMATION}
+                    END AS category,
+                    SUM(u.value_zat)
+             FROM utxos u
+             -- the received txo is unspent
+             WHERE u.id NOT IN (
+               SELECT transparent_received_output_id
+               FROM transparent_received_output_spends txo_spends
+               JOIN transactions tx
+                 ON tx.id_tx = txo_spends.transaction_id
+               WHERE tx.block IS NOT NULL -- the spending tx is mined
+               OR tx.expiry_height IS NULL -- the spending tx will not expire
+               OR tx.expiry_height > :stable_height -- the spending tx is unexpired
+             )
+             GROUP BY u.received_by_account_id, category"
+        ))?;
+        let mut rows = stmt_transparent.query(named_params![
+            ":max_height": u32::from(zero_conf_height),
+            ":stable_height": u32::from(stable_height),
+        ])?;
+        add_balances(&mut rows, PoolType::Transparent, &mut account_balances)?;
+    }
+
+    Ok(account_balances)
+}
+
 /// Returns the memo for a received note, if the note is known to the wallet.
 pub(crate) fn get_received_memo(
     conn: &rusqlite::Connection,
@@ -1676,7 +1921,9 @@ pub(crate) fn get_account<P: Parameters>(
             )?;
 
             let ufvk_str: Option<String> = row.get("ufvk")?;
-            let viewing_key = if let Some(ufvk_str) = ufvk_str {
+            let viewing_key = if kind == AccountSource::StandaloneTransparent {
+                ViewingKey::StandaloneTransparent
+            } else if let Some(ufvk_str) = ufvk_str {
                 ViewingKey::Full(Box::new(
                     UnifiedFullViewingKey::decode(params, &ufvk_str[..])
                         .map_err(SqliteClientError::BadAccountData)?,
@@ -1699,6 +1946,153 @@ pub(crate) fn get_account<P: Parameters>(
     }
 }
 
+/// Returns the details of the given account, including its name, if the account exists.
+pub(crate) fn get_account_details<P: Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    account_id: AccountId,
+) -> Result<Option<AccountDetails<AccountId>>, SqliteClientError> {
+    get_account(conn, params, account_id)?
+        .map(|account| {
+            Ok(AccountDetails::from_parts(
+                account_id,
+                account.kind,
+                account.viewing_key.ufvk().cloned(),
+                account.viewing_key.uivk(),
+                account_birthday(conn, account_id)?,
+                get_account_name(conn, account_id)?,
+            ))
+        })
+        .transpose()
+}
+
+/// Returns an error if the given account does not exist.
+fn check_account_exists(
+    conn: &rusqlite::Connection,
+    account_id: AccountId,
+) -> Result<(), SqliteClientError> {
+    conn.query_row(
+        "SELECT 1 FROM accounts WHERE id = :account_id",
+        named_params![":account_id": account_id.0],
+        |_| Ok(()),
+    )
+    .optional()?
+    .ok_or(SqliteClientError::AccountUnknown)
+}
+
+/// Returns the name of the given account, if one has been set.
+pub(crate) fn get_account_name(
+    conn: &rusqlite::Connection,
+    account_id: AccountId,
+) -> Result<Option<String>, SqliteClientError> {
+    conn.query_row(
+        "SELECT account_names.name
+        FROM accounts
+        LEFT OUTER JOIN account_names ON account_names.account_id = accounts.id
+        WHERE accounts.id = :account_id",
+        named_params![":account_id": account_id.0],
+        |row| row.get(0),
+    )
+    .optional()?
+    .ok_or(SqliteClientError::AccountUnknown)
+}
+
+/// Sets or removes the name of the given account.
+///
+/// Names are compared exactly when checking that they are unique; no Unicode normalization or
+/// case folding is performed.
+pub(crate) fn set_account_name(
+    conn: &rusqlite::Connection,
+    account_id: AccountId,
+    name: Option<&str>,
+) -> Result<(), SqliteClientError> {
+    check_account_exists(conn, account_id)?;
+    match name {
+        Some(name) => conn
+            .execute(
+                "INSERT INTO account_names (account_id, name)
+                VALUES (:account_id, :name)
+                ON CONFLICT (account_id) DO UPDATE SET name = :name",
+                named_params![":account_id": account_id.0, ":name": name],
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::SqliteFailure(f, _)
+                    if f.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE =>
+                {
+                    SqliteClientError::AccountNameConflict(name.to_owned())
+                }
+                e => e.into(),
+            })?,
+        None => conn.execute(
+            "DELETE FROM account_names WHERE account_id = :account_id",
+            named_params![":account_id": account_id.0],
+        )?,
+    };
+
+    Ok(())
+}
+
+/// Returns the application-defined metadata stored for the given account.
+pub(crate) fn get_account_metadata(
+    conn: &rusqlite::Connection,
+    account_id: AccountId,
+) -> Result<BTreeMap<String, AccountMetadataValue>, SqliteClientError> {
+    check_account_exists(conn, account_id)?;
+
+    let mut stmt = conn
+        .prepare_cached("SELECT key, value FROM account_metadata WHERE account_id = :account_id")?;
+    let mut rows = stmt.query(named_params![":account_id": account_id.0])?;
+    let mut metadata = BTreeMap::new();
+    while let Some(row) = rows.next()? {
+        let key: String = row.get(0)?;
+        let value = match row.get_ref(1)? {
+            rusqlite::types::ValueRef::Text(_) => AccountMetadataValue::Text(row.get(1)?),
+            rusqlite::types::ValueRef::Integer(i) => AccountMetadataValue::Integer(i),
+            rusqlite::types::ValueRef::Blob(b) => AccountMetadataValue::Bytes(b.to_vec()),
+            _ => {
+                return Err(SqliteClientError::CorruptedData(format!(
+                    "Invalid value for metadata key {} of account {:?}",
+                    key, account_id
+                )))
+            }
+        };
+        metadata.insert(key, value);
+    }
+
+    Ok(metadata)
+}
+
+/// Sets or removes a value in the application-defined metadata of the given account.
+pub(crate) fn set_account_metadata(
+    conn: &rusqlite::Connection,
+    account_id: AccountId,
+    key: &str,
+    value: Option<AccountMetadataValue>,
+) -> Result<(), SqliteClientError> {
+    check_account_exists(conn, account_id)?;
+    match value {
+        Some(value) => {
+            let value = match value {
+                AccountMetadataValue::Text(s) => rusqlite::types::Value::Text(s),
+                AccountMetadataValue::Integer(i) => rusqlite::types::Value::Integer(i),
+                AccountMetadataValue::Bytes(b) => rusqlite::types::Value::Blob(b),
+            };
+            conn.execute(
+                "INSERT INTO account_metadata (account_id, key, value)
+                VALUES (:account_id, :key, :value)
+                ON CONFLICT (account_id, key) DO UPDATE SET value = :value",
+                named_params![":account_id": account_id.0, ":key": key, ":value": value],
+            )?
+        }
+        None => conn.execute(
+            "DELETE FROM account_metadata WHERE account_id = :account_id AND key = :key",
+            named_params![":account_id": account_id.0, ":key": key],
+        )?,
+    };
+
+    Ok(())
+}
+
 /// Returns the minimum and maximum heights of blocks in the chain which may be scanned.
 pub(crate) fn scan_queue_extrema(
     conn: &rusqlite::Connection,
@@ -1837,17 +2231,20 @@ pub(crate) fn block_fully_scanned<P: consensus::Parameters>(
     params: &P,
 ) -> Result<Option<BlockMetadata>, SqliteClientError> {
     if let Some(birthday_height) = wallet_birthday(conn)? {
-        // We assume that the only way we get a contiguous range of block heights in the `blocks` table
-        // starting with the birthday block, is if all scanning operations have been performed on those
-        // blocks. This holds because the `blocks` table is only altered by `WalletDb::put_blocks` via
-        // `put_block`, and the effective combination of intra-range linear scanning and the nullifier
-        // map ensures that we discover all wallet-related information within the contiguous range.
+        // Blocks are stored in the `blocks` table via `put_block` both by `WalletDb::put_blocks` and
+        // by `WalletDb::put_spend_scanned_blocks`, so a contiguous range of block heights in the
+        // `blocks` table does not imply that all scanning operations have been performed on those
+        // blocks. We instead rely on the scan queue: `put_blocks` marks the range of the blocks that
+        // it stores as "Scanned", whereas `put_spend_scanned_blocks` leaves its range with at least
+        // "Historic" priority. The effective combination of intra-range linear scanning and the
+        // nullifier map ensures that we discover all wallet-related information within a range that
+        // has been marked as "Scanned".
         //
-        // We also assume that every contiguous range of block heights in the `blocks` table has a
-        // single matching entry in the `scan_queue` table with priority "Scanned". This requires no
-        // bugs in the scan queue update logic, which we have had before. However, a bug here would
-        // mean that we return a more conservative fully-scanned height, which likely just causes a
-        // performance regression.
+        // We also assume that every contiguous range of fully-scanned blocks has a single matching
+        // entry in the `scan_queue` table with priority "Scanned". This requires no bugs in the scan
+        // queue update logic, which we have had before. However, a bug here would mean that we
+        // return a more conservative fully-scanned height, which likely just causes a performance
+        // regression.
         //
         // The fully-scanned height is therefore the last height that falls within the first range in
         // the scan queue with priority "Scanned".
@@ -1888,6 +2285,11 @@ pub(crate) fn block_fully_scanned<P: consensus::Parameters>(
     }
 }
 
+/// Returns the metadata for the greatest height among the blocks that have been stored by
+/// `WalletDb::put_blocks`.
+///
+/// Blocks stored by `WalletDb::put_spend_scanned_blocks` have not been trial-decrypted, and so
+/// are only considered once their range of the scan queue has been scanned.
 pub(crate) fn block_max_scanned<P: consensus::Parameters>(
     conn: &rusqlite::Connection,
     params: &P,
@@ -1895,9 +2297,15 @@ pub(crate) fn block_max_scanned<P: consensus::Parameters>(
     conn.query_row(
         "SELECT blocks.height, hash, sapling_commitment_tree_size, sapling_tree, orchard_commitment_tree_size
          FROM blocks
-         JOIN (SELECT MAX(height) AS height FROM blocks) blocks_max
+         JOIN (
+            SELECT MAX(height) AS height
+            FROM blocks
+            JOIN scan_queue
+            ON height >= block_range_start AND height < block_range_end
+            WHERE priority = :scanned_priority
+         ) blocks_max
          ON blocks.height = blocks_max.height",
-        [],
+        named_params![":scanned_priority": priority_code(&ScanPriority::Scanned)],
         |row| {
             let height: u32 = row.get(0)?;
             let block_hash: Vec<u8> = row.get(1)?;
@@ -2013,17 +2421,67 @@ pub(crate) fn get_min_unspent_height(
         .or(min_orchard))
 }
 
-/// Truncates the database to the given height.
+/// Returns the greatest height at or below `max_height` to which the wallet can be truncated.
+///
+/// If no blocks above `max_height` have been scanned, this is `max_height` itself. Otherwise the
+/// note commitment trees must be rewound, which is only possible to a height for which every
+/// tree retains a checkpoint.
+pub(crate) fn get_truncation_height(
+    conn: &rusqlite::Connection,
+    max_height: BlockHeight,
+) -> Result<Option<BlockHeight>, SqliteClientError> {
+    match block_height_extrema(conn)? {
+        Some(scanned) if max_height < *scanned.end() => {}
+        _ => return Ok(Some(max_height)),
+    }
+
+    #[cfg(not(feature = "orchard"))]
+    let truncation_height_query = "SELECT MAX(checkpoint_id)
+        FROM sapling_tree_checkpoints
+        WHERE checkpoint_id <= :max_height";
+    #[cfg(feature = "orchard")]
+    let truncation_height_query = "SELECT MAX(sc.checkpoint_id)
+        FROM sapling_tree_checkpoints sc
+        JOIN orchard_tree_checkpoints oc ON oc.checkpoint_id = sc.checkpoint_id
+        WHERE sc.checkpoint_id <= :max_height";
+
+    Ok(conn
+        .query_row(
+            truncation_height_query,
+            named_params![":max_height": u32::from(max_height)],
+            |row| row.get::<_, Option<u32>>(0),
+        )?
+        .map(BlockHeight::from))
+}
+
+/// Returns the lowest height to which the wallet's scanned blocks can be truncated, if any.
+fn min_truncation_height(
+    conn: &rusqlite::Connection,
+) -> Result<Option<BlockHeight>, SqliteClientError> {
+    #[cfg(not(feature = "orchard"))]
+    let min_checkpoint_query = "SELECT MIN(checkpoint_id) FROM sapling_tree_checkpoints";
+    #[cfg(feature = "orchard")]
+    let min_checkpoint_query = "SELECT MIN(sc.checkpoint_id)
+        FROM sapling_tree_checkpoints sc
+        JOIN orchard_tree_checkpoints oc ON oc.checkpoint_id = sc.checkpoint_id";
+
+    Ok(conn
+        .query_row(min_checkpoint_query, [], |row| row.get::<_, Option<u32>>(0))?
+        .map(BlockHeight::from))
+}
+
+/// Truncates the database to the greatest height at or below `max_height` to which it can be
+/// truncated, as determined by [`get_truncation_height`], and returns that height.
 ///
 /// If the requested height is greater than or equal to the height of the last scanned
-/// block, this function does nothing.
+/// block, only the scan queue is truncated.
 ///
 /// This should only be executed inside a transactional context.
 pub(crate) fn truncate_to_height<P: consensus::Parameters>(
     conn: &rusqlite::Transaction,
     params: &P,
-    block_height: BlockHeight,
-) -> Result<(), SqliteClientError> {
+    max_height: BlockHeight,
+) -> Result<BlockHeight, SqliteClientError> {
     let sapling_activation_height = params
         .activation_height(NetworkUpgrade::Sapling)
         .expect("Sapling activation height must be available.");
@@ -2034,13 +2492,15 @@ pub(crate) fn truncate_to_height<P: consensus::Parameters>(
             .map(|opt| opt.map_or_else(|| sapling_activation_height - 1, BlockHeight::from))
     })?;
 
-    if block_height < last_scanned_height - PRUNING_DEPTH {
-        if let Some(h) = get_min_unspent_height(conn)? {
-            if block_height > h {
-                return Err(SqliteClientError::RequestedRewindInvalid(h, block_height));
-            }
+    let block_height = match get_truncation_height(conn, max_height)? {
+        Some(h) => h,
+        None => {
+            return Err(SqliteClientError::RequestedRewindInvalid(
+                min_truncation_height(conn)?,
+                max_height,
+            ))
         }
-    }
+    };
 
     // Delete from the scanning queue any range with a start height greater than the
     // truncation height, and then truncate any remaining range by setting the end
@@ -2066,6 +2526,9 @@ pub(crate) fn truncate_to_height<P: consensus::Parameters>(
         let mut wdb = WalletDb {
             conn: SqlTransaction(conn),
             params: params.clone(),
+            #[cfg(feature = "transparent-inputs")]
+            transparent_gap_limit: zcash_client_backend::data_api::DEFAULT_TRANSPARENT_GAP_LIMIT,
+            scan_parallelism: ScanParallelism::default(),
         };
         wdb.with_sapling_tree_mut(|tree| {
             tree.truncate_removing_checkpoint(&block_height).map(|_| ())
@@ -2089,9 +2552,12 @@ pub(crate) fn truncate_to_height<P: consensus::Parameters>(
             [u32::from(block_height)],
         )?;
 
-        // Un-mine transactions.
+        // Un-mine transactions, recording the height at which each had been mined. The expiry
+        // height of a transaction that was only observed in a compact block remains unknown:
+        // such a transaction may be mined again at any height, so notes that it spent must not
+        // become spendable.
         conn.execute(
-            "UPDATE transactions SET block = NULL, tx_index = NULL
+            "UPDATE transactions SET block = NULL, tx_index = NULL, orphaned_height = block
             WHERE block IS NOT NULL AND block > ?",
             [u32::from(block_height)],
         )?;
@@ -2111,7 +2577,7 @@ pub(crate) fn truncate_to_height<P: consensus::Parameters>(
         )?;
     }
 
-    Ok(())
+    Ok(block_height)
 }
 
 #[cfg(feature = "transparent-inputs")]
@@ -2154,6 +2620,7 @@ pub(crate) fn get_unspent_transparent_output(
          FROM utxos u
          WHERE u.prevout_txid = :txid
          AND u.prevout_idx = :output_index
+         AND NOT u.is_locked
          AND u.id NOT IN (
             SELECT txo_spends.transparent_received_output_id
             FROM transparent_received_output_spends txo_spends
@@ -2179,7 +2646,8 @@ pub(crate) fn get_unspent_transparent_output(
 
 /// Returns unspent transparent outputs that have been received by this wallet at the given
 /// transparent address, such that the block that included the transaction was mined at a
-/// height less than or equal to the provided `max_height`.
+/// height less than or equal to the provided `max_height`. Outputs that have been locked
+/// against spending are not returned.
 #[cfg(feature = "transparent-inputs")]
 pub(crate) fn get_unspent_transparent_outputs<P: consensus::Parameters>(
     conn: &rusqlite::Connection,
@@ -2199,6 +2667,7 @@ pub(crate) fn get_unspent_transparent_outputs<P: consensus::Parameters>(
          FROM utxos u
          WHERE u.address = :address
          AND u.height <= :max_height
+         AND NOT u.is_locked
          AND u.id NOT IN (
             SELECT txo_spends.transparent_received_output_id
             FROM transparent_received_output_spends txo_spends
@@ -2233,7 +2702,7 @@ pub(crate) fn get_unspent_transparent_outputs<P: consensus::Parameters>(
 
 /// Returns the unspent balance for each transparent address associated with the specified account,
 /// such that the block that included the transaction was mined at a height less than or equal to
-/// the provided `max_height`.
+/// the provided `max_height`. Outputs that have been locked against spending are not included.
 #[cfg(feature = "transparent-inputs")]
 pub(crate) fn get_transparent_balances<P: consensus::Parameters>(
     conn: &rusqlite::Connection,
@@ -2251,6 +2720,7 @@ pub(crate) fn get_transparent_balances<P: consensus::Parameters>(
          FROM utxos u
          WHERE u.received_by_account_id = :account_id
          AND u.height <= :max_height
+         AND NOT u.is_locked
          AND u.id NOT IN (
             SELECT txo_spends.transparent_received_output_id
             FROM transparent_received_output_spends txo_spends
@@ -2294,50 +2764,466 @@ pub(crate) fn get_account_ids(
     Ok(result)
 }
 
-/// Inserts information about a scanned block into the database.
-#[allow(clippy::too_many_arguments)]
-pub(crate) fn put_block(
-    conn: &rusqlite::Transaction<'_>,
-    block_height: BlockHeight,
-    block_hash: BlockHash,
-    block_time: u32,
-    sapling_commitment_tree_size: u32,
-    sapling_output_count: u32,
-    #[cfg(feature = "orchard")] orchard_commitment_tree_size: u32,
-    #[cfg(feature = "orchard")] orchard_action_count: u32,
+/// Inserts the provided scanned blocks into the database, and updates the wallet's note
+/// commitment trees and scan queue accordingly.
+///
+/// If `spends_only` is set, the blocks were scanned only for spends of the wallet's notes, and
+/// their range is not marked as scanned in the scan queue.
+#[allow(clippy::type_complexity)]
+pub(crate) fn put_blocks<P: consensus::Parameters>(
+    wdb: &mut WalletDb<SqlTransaction<'_>, P>,
+    from_state: &ChainState,
+    blocks: Vec<ScannedBlock<AccountId>>,
+    spends_only: bool,
 ) -> Result<(), SqliteClientError> {
-    let block_hash_data = conn
-        .query_row(
-            "SELECT hash FROM blocks WHERE height = ?",
-            [u32::from(block_height)],
-            |row| row.get::<_, Vec<u8>>(0),
-        )
-        .optional()?;
+    struct BlockPositions {
+        height: BlockHeight,
+        sapling_start_position: Position,
+        #[cfg(feature = "orchard")]
+        orchard_start_position: Position,
+    }
 
-    // Ensure that in the case of an upsert, we don't overwrite block data
-    // with information for a block with a different hash.
-    if let Some(bytes) = block_hash_data {
-        let expected_hash = BlockHash::try_from_slice(&bytes).ok_or_else(|| {
-            SqliteClientError::CorruptedData(format!(
-                "Invalid block hash at height {}",
-                u32::from(block_height)
-            ))
-        })?;
-        if expected_hash != block_hash {
-            return Err(SqliteClientError::BlockConflict(block_height));
+    let start_positions = blocks.first().map(|block| BlockPositions {
+        height: block.height(),
+        sapling_start_position: Position::from(
+            u64::from(block.sapling().final_tree_size())
+                - u64::try_from(block.sapling().commitments().len()).unwrap(),
+        ),
+        #[cfg(feature = "orchard")]
+        orchard_start_position: Position::from(
+            u64::from(block.orchard().final_tree_size())
+                - u64::try_from(block.orchard().commitments().len()).unwrap(),
+        ),
+    });
+    let mut sapling_commitments = vec![];
+    #[cfg(feature = "orchard")]
+    let mut orchard_commitments = vec![];
+    let mut last_scanned_height = None;
+    let mut note_positions = vec![];
+    for block in blocks.into_iter() {
+        if last_scanned_height
+            .iter()
+            .any(|prev| block.height() != *prev + 1)
+        {
+            return Err(SqliteClientError::NonSequentialBlocks);
         }
-    }
 
-    let mut stmt_upsert_block = conn.prepare_cached(
-        "INSERT INTO blocks (
-            height,
-            hash,
-            time,
-            sapling_commitment_tree_size,
-            sapling_output_count,
-            sapling_tree,
-            orchard_commitment_tree_size,
-            orchard_action_count
+        // Insert the block into the database.
+        put_block(
+            wdb.conn.0,
+            block.height(),
+            block.block_hash(),
+            block.block_time(),
+            block.sapling().final_tree_size(),
+            block.sapling().commitments().len().try_into().unwrap(),
+            #[cfg(feature = "orchard")]
+            block.orchard().final_tree_size(),
+            #[cfg(feature = "orchard")]
+            block.orchard().commitments().len().try_into().unwrap(),
+        )?;
+
+        for tx in block.transactions() {
+            let tx_row = put_tx_meta(wdb.conn.0, tx, block.height())?;
+
+            // Mark notes as spent and remove them from the scanning cache
+            for spend in tx.sapling_spends() {
+                sapling::mark_sapling_note_spent(wdb.conn.0, tx_row, spend.nf())?;
+            }
+            #[cfg(feature = "orchard")]
+            for spend in tx.orchard_spends() {
+                orchard::mark_orchard_note_spent(wdb.conn.0, tx_row, spend.nf())?;
+            }
+
+            for output in tx.sapling_outputs() {
+                // Check whether this note was spent in a later block range that
+                // we previously scanned.
+                let spent_in = output
+                    .nf()
+                    .map(|nf| {
+                        query_nullifier_map::<_, Scope>(wdb.conn.0, ShieldedProtocol::Sapling, nf)
+                    })
+                    .transpose()?
+                    .flatten();
+
+                sapling::put_received_note(wdb.conn.0, output, tx_row, spent_in)?;
+            }
+            #[cfg(feature = "orchard")]
+            for output in tx.orchard_outputs() {
+                // Check whether this note was spent in a later block range that
+                // we previously scanned.
+                let spent_in = output
+                    .nf()
+                    .map(|nf| {
+                        query_nullifier_map::<_, Scope>(
+                            wdb.conn.0,
+                            ShieldedProtocol::Orchard,
+                            &nf.to_bytes(),
+                        )
+                    })
+                    .transpose()?
+                    .flatten();
+
+                orchard::put_received_note(wdb.conn.0, output, tx_row, spent_in)?;
+            }
+        }
+
+        // Insert the new nullifiers from this block into the nullifier map.
+        insert_nullifier_map(
+            wdb.conn.0,
+            block.height(),
+            ShieldedProtocol::Sapling,
+            block.sapling().nullifier_map(),
+        )?;
+        #[cfg(feature = "orchard")]
+        insert_nullifier_map(
+            wdb.conn.0,
+            block.height(),
+            ShieldedProtocol::Orchard,
+            &block
+                .orchard()
+                .nullifier_map()
+                .iter()
+                .map(|(txid, idx, nfs)| (*txid, *idx, nfs.iter().map(|nf| nf.to_bytes()).collect()))
+                .collect::<Vec<_>>(),
+        )?;
+
+        note_positions.extend(block.transactions().iter().flat_map(|wtx| {
+            let iter = wtx.sapling_outputs().iter().map(|out| {
+                (
+                    ShieldedProtocol::Sapling,
+                    out.note_commitment_tree_position(),
+                )
+            });
+            #[cfg(feature = "orchard")]
+            let iter = iter.chain(wtx.orchard_outputs().iter().map(|out| {
+                (
+                    ShieldedProtocol::Orchard,
+                    out.note_commitment_tree_position(),
+                )
+            }));
+
+            iter
+        }));
+
+        last_scanned_height = Some(block.height());
+        let block_commitments = block.into_commitments();
+        trace!(
+            "Sapling commitments for {:?}: {:?}",
+            last_scanned_height,
+            block_commitments
+                .sapling
+                .iter()
+                .map(|(_, r)| *r)
+                .collect::<Vec<_>>()
+        );
+        #[cfg(feature = "orchard")]
+        trace!(
+            "Orchard commitments for {:?}: {:?}",
+            last_scanned_height,
+            block_commitments
+                .orchard
+                .iter()
+                .map(|(_, r)| *r)
+                .collect::<Vec<_>>()
+        );
+
+        sapling_commitments.extend(block_commitments.sapling.into_iter().map(Some));
+        #[cfg(feature = "orchard")]
+        orchard_commitments.extend(block_commitments.orchard.into_iter().map(Some));
+    }
+
+    // Prune the nullifier map of entries we no longer need.
+    if let Some(meta) = wdb.block_fully_scanned()? {
+        prune_nullifier_map(
+            wdb.conn.0,
+            meta.block_height().saturating_sub(PRUNING_DEPTH),
+        )?;
+    }
+
+    // We will have a start position and a last scanned height in all cases where
+    // `blocks` is non-empty.
+    if let Some((start_positions, last_scanned_height)) = start_positions.zip(last_scanned_height) {
+        // Create subtrees from the note commitments, in parallel unless the wallet is
+        // configured to be single-threaded.
+        fn build_subtrees<H: Hashable + Clone + PartialEq + Send + Sync>(
+            parallelism: &ScanParallelism,
+            commitments: &mut [Option<(H, Retention<BlockHeight>)>],
+            start_position: Position,
+            shard_height: u8,
+        ) -> Vec<(LocatedPrunableTree<H>, BTreeMap<BlockHeight, Position>)> {
+            const CHUNK_SIZE: usize = 1024;
+            let build_chunk = |(i, chunk): (usize, &mut [Option<(H, Retention<BlockHeight>)>])| {
+                let start = start_position + (i * CHUNK_SIZE) as u64;
+                let end = start + chunk.len() as u64;
+
+                shardtree::LocatedTree::from_iter(
+                    start..end,
+                    shard_height.into(),
+                    chunk.iter_mut().map(|n| n.take().expect("always Some")),
+                )
+                .map(|res| (res.subtree, res.checkpoints))
+            };
+
+            if parallelism.is_single_threaded() {
+                commitments
+                    .chunks_mut(CHUNK_SIZE)
+                    .enumerate()
+                    .filter_map(build_chunk)
+                    .collect()
+            } else {
+                parallelism.install(|| {
+                    commitments
+                        .par_chunks_mut(CHUNK_SIZE)
+                        .enumerate()
+                        .filter_map(build_chunk)
+                        .collect()
+                })
+            }
+        }
+
+        let sapling_subtrees = build_subtrees(
+            &wdb.scan_parallelism,
+            &mut sapling_commitments,
+            start_positions.sapling_start_position,
+            SAPLING_SHARD_HEIGHT,
+        );
+
+        #[cfg(feature = "orchard")]
+        let orchard_subtrees = build_subtrees(
+            &wdb.scan_parallelism,
+            &mut orchard_commitments,
+            start_positions.orchard_start_position,
+            ORCHARD_SHARD_HEIGHT,
+        );
+
+        // Collect the complete set of Sapling checkpoints
+        // TODO: check if this sapling-based code should actually be behind this
+        // conditional compilation cfg macro
+        #[cfg(feature = "orchard")]
+        let sapling_checkpoint_positions: BTreeMap<BlockHeight, Position> = sapling_subtrees
+            .iter()
+            .flat_map(|(_, checkpoints)| checkpoints.iter())
+            .map(|(k, v)| (*k, *v))
+            .collect();
+
+        #[cfg(feature = "orchard")]
+        let orchard_checkpoint_positions: BTreeMap<BlockHeight, Position> = orchard_subtrees
+            .iter()
+            .flat_map(|(_, checkpoints)| checkpoints.iter())
+            .map(|(k, v)| (*k, *v))
+            .collect();
+
+        #[cfg(feature = "orchard")]
+        fn ensure_checkpoints<'a, H, I: Iterator<Item = &'a BlockHeight>, const DEPTH: u8>(
+            // An iterator of checkpoints heights for which we wish to ensure that
+            // checkpoints exists.
+            ensure_heights: I,
+            // The map of checkpoint positions from which we will draw note commitment tree
+            // position information for the newly created checkpoints.
+            existing_checkpoint_positions: &BTreeMap<BlockHeight, Position>,
+            // The frontier whose position will be used for an inserted checkpoint when
+            // there is no preceding checkpoint in existing_checkpoint_positions.
+            state_final_tree: &Frontier<H, DEPTH>,
+        ) -> Vec<(BlockHeight, Checkpoint)> {
+            ensure_heights
+                .flat_map(|ensure_height| {
+                    existing_checkpoint_positions
+                        .range::<BlockHeight, _>(..=*ensure_height)
+                        .last()
+                        .map_or_else(
+                            || {
+                                Some((
+                                    *ensure_height,
+                                    state_final_tree
+                                        .value()
+                                        .map_or_else(Checkpoint::tree_empty, |t| {
+                                            Checkpoint::at_position(t.position())
+                                        }),
+                                ))
+                            },
+                            |(existing_checkpoint_height, position)| {
+                                if *existing_checkpoint_height < *ensure_height {
+                                    Some((*ensure_height, Checkpoint::at_position(*position)))
+                                } else {
+                                    // The checkpoint already exists, so we don't need to
+                                    // do anything.
+                                    None
+                                }
+                            },
+                        )
+                        .into_iter()
+                })
+                .collect::<Vec<_>>()
+        }
+
+        #[cfg(feature = "orchard")]
+        let (missing_sapling_checkpoints, missing_orchard_checkpoints) = (
+            ensure_checkpoints(
+                orchard_checkpoint_positions.keys(),
+                &sapling_checkpoint_positions,
+                from_state.final_sapling_tree(),
+            ),
+            ensure_checkpoints(
+                sapling_checkpoint_positions.keys(),
+                &orchard_checkpoint_positions,
+                from_state.final_orchard_tree(),
+            ),
+        );
+
+        // Update the Sapling note commitment tree with all newly read note commitments
+        {
+            let mut sapling_subtrees_iter = sapling_subtrees.into_iter();
+            wdb.with_sapling_tree_mut::<_, _, SqliteClientError>(|sapling_tree| {
+                debug!(
+                    "Sapling initial tree size at {:?}: {:?}",
+                    from_state.block_height(),
+                    from_state.final_sapling_tree().tree_size()
+                );
+                sapling_tree.insert_frontier(
+                    from_state.final_sapling_tree().clone(),
+                    Retention::Checkpoint {
+                        id: from_state.block_height(),
+                        is_marked: false,
+                    },
+                )?;
+
+                for (tree, checkpoints) in &mut sapling_subtrees_iter {
+                    sapling_tree.insert_tree(tree, checkpoints)?;
+                }
+
+                // Ensure we have a Sapling checkpoint for each checkpointed Orchard block height.
+                // We skip all checkpoints below the minimum retained checkpoint in the
+                // Sapling tree, because branches below this height may be pruned.
+                #[cfg(feature = "orchard")]
+                {
+                    let min_checkpoint_height = sapling_tree
+                        .store()
+                        .min_checkpoint_id()
+                        .map_err(ShardTreeError::Storage)?
+                        .expect("At least one checkpoint was inserted (by insert_frontier)");
+
+                    for (height, checkpoint) in &missing_sapling_checkpoints {
+                        if *height > min_checkpoint_height {
+                            sapling_tree
+                                .store_mut()
+                                .add_checkpoint(*height, checkpoint.clone())
+                                .map_err(ShardTreeError::Storage)?;
+                        }
+                    }
+                }
+
+                Ok(())
+            })?;
+        }
+
+        // Update the Orchard note commitment tree with all newly read note commitments
+        #[cfg(feature = "orchard")]
+        {
+            let mut orchard_subtrees = orchard_subtrees.into_iter();
+            wdb.with_orchard_tree_mut::<_, _, SqliteClientError>(|orchard_tree| {
+                debug!(
+                    "Orchard initial tree size at {:?}: {:?}",
+                    from_state.block_height(),
+                    from_state.final_orchard_tree().tree_size()
+                );
+                orchard_tree.insert_frontier(
+                    from_state.final_orchard_tree().clone(),
+                    Retention::Checkpoint {
+                        id: from_state.block_height(),
+                        is_marked: false,
+                    },
+                )?;
+
+                for (tree, checkpoints) in &mut orchard_subtrees {
+                    orchard_tree.insert_tree(tree, checkpoints)?;
+                }
+
+                // Ensure we have an Orchard checkpoint for each checkpointed Sapling block height.
+                // We skip all checkpoints below the minimum retained checkpoint in the
+                // Orchard tree, because branches below this height may be pruned.
+                {
+                    let min_checkpoint_height = orchard_tree
+                        .store()
+                        .min_checkpoint_id()
+                        .map_err(ShardTreeError::Storage)?
+                        .expect("At least one checkpoint was inserted (by insert_frontier)");
+
+                    for (height, checkpoint) in &missing_orchard_checkpoints {
+                        if *height > min_checkpoint_height {
+                            debug!(
+                                "Adding missing Orchard checkpoint for height: {:?}: {:?}",
+                                height,
+                                checkpoint.position()
+                            );
+                            orchard_tree
+                                .store_mut()
+                                .add_checkpoint(*height, checkpoint.clone())
+                                .map_err(ShardTreeError::Storage)?;
+                        }
+                    }
+                }
+                Ok(())
+            })?;
+        }
+
+        let scanned_range = Range {
+            start: start_positions.height,
+            end: last_scanned_height + 1,
+        };
+        if spends_only {
+            scanning::spend_scan_complete(wdb.conn.0, scanned_range)?;
+        } else {
+            scanning::scan_complete(wdb.conn.0, &wdb.params, scanned_range, &note_positions)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Inserts information about a scanned block into the database.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn put_block(
+    conn: &rusqlite::Transaction<'_>,
+    block_height: BlockHeight,
+    block_hash: BlockHash,
+    block_time: u32,
+    sapling_commitment_tree_size: u32,
+    sapling_output_count: u32,
+    #[cfg(feature = "orchard")] orchard_commitment_tree_size: u32,
+    #[cfg(feature = "orchard")] orchard_action_count: u32,
+) -> Result<(), SqliteClientError> {
+    let block_hash_data = conn
+        .query_row(
+            "SELECT hash FROM blocks WHERE height = ?",
+            [u32::from(block_height)],
+            |row| row.get::<_, Vec<u8>>(0),
+        )
+        .optional()?;
+
+    // Ensure that in the case of an upsert, we don't overwrite block data
+    // with information for a block with a different hash.
+    if let Some(bytes) = block_hash_data {
+        let expected_hash = BlockHash::try_from_slice(&bytes).ok_or_else(|| {
+            SqliteClientError::CorruptedData(format!(
+                "Invalid block hash at height {}",
+                u32::from(block_height)
+            ))
+        })?;
+        if expected_hash != block_hash {
+            return Err(SqliteClientError::BlockConflict(block_height));
+        }
+    }
+
+    let mut stmt_upsert_block = conn.prepare_cached(
+        "INSERT INTO blocks (
+            height,
+            hash,
+            time,
+            sapling_commitment_tree_size,
+            sapling_output_count,
+            sapling_tree,
+            orchard_commitment_tree_size,
+            orchard_action_count
         )
         VALUES (
             :height,
@@ -2471,17 +3357,20 @@ pub(crate) fn put_received_transparent_utxo<P: consensus::Parameters>(
     conn: &rusqlite::Connection,
     params: &P,
     output: &WalletTransparentOutput,
+    gap_limit: u32,
 ) -> Result<UtxoId, SqliteClientError> {
     let address_str = output.recipient_address().encode(params);
     let account_id = conn
         .query_row(
-            "SELECT account_id FROM addresses WHERE cached_transparent_receiver_address = :address",
+            "SELECT account_id FROM addresses WHERE cached_transparent_receiver_address = :address
+            UNION
+            SELECT account_id FROM transparent_addresses WHERE address = :address",
             named_params![":address": &address_str],
             |row| Ok(AccountId(row.get(0)?)),
         )
         .optional()?;
 
-    if let Some(account) = account_id {
+    let utxo_id = if let Some(account) = account_id {
         Ok(put_legacy_transparent_utxo(conn, params, output, account)?)
     } else {
         // If the UTXO is received at the legacy transparent address (at BIP 44 address
@@ -2510,7 +3399,17 @@ pub(crate) fn put_received_transparent_utxo<P: consensus::Parameters>(
                     *output.recipient_address(),
                 ))
             })
-    }
+    }?;
+
+    transparent::mark_address_used(
+        conn,
+        params,
+        output.recipient_address(),
+        output.height(),
+        gap_limit,
+    )?;
+
+    Ok(utxo_id)
 }
 
 #[cfg(feature = "transparent-inputs")]
@@ -2589,10 +3488,10 @@ pub(crate) fn insert_sent_output<P: consensus::Parameters>(
     let mut stmt_insert_sent_output = conn.prepare_cached(
         "INSERT INTO sent_notes (
             tx, output_pool, output_index, from_account_id,
-            to_address, to_account_id, value, memo)
+            to_address, to_account_id, value, memo, memo_text)
         VALUES (
             :tx, :output_pool, :output_index, :from_account_id,
-            :to_address, :to_account_id, :value, :memo)",
+            :to_address, :to_account_id, :value, :memo, :memo_text)",
     )?;
 
     let (to_address, to_account_id, pool_type) = recipient_params(params, output.recipient());
@@ -2604,7 +3503,8 @@ pub(crate) fn insert_sent_output<P: consensus::Parameters>(
         ":to_address": &to_address,
         ":to_account_id": to_account_id.map(|a| a.0),
         ":value": &i64::from(Amount::from(output.value())),
-        ":memo": memo_repr(output.memo())
+        ":memo": memo_repr(output.memo()),
+        ":memo_text": memo_search_text(output.memo())
     ];
 
     stmt_insert_sent_output.execute(sql_args)?;
@@ -2637,16 +3537,17 @@ pub(crate) fn put_sent_output<P: consensus::Parameters>(
     let mut stmt_upsert_sent_output = conn.prepare_cached(
         "INSERT INTO sent_notes (
             tx, output_pool, output_index, from_account_id,
-            to_address, to_account_id, value, memo)
+            to_address, to_account_id, value, memo, memo_text)
         VALUES (
             :tx, :output_pool, :output_index, :from_account_id,
-            :to_address, :to_account_id, :value, :memo)
+            :to_address, :to_account_id, :value, :memo, :memo_text)
         ON CONFLICT (tx, output_pool, output_index) DO UPDATE
         SET from_account_id = :from_account_id,
             to_address = :to_address,
             to_account_id = IFNULL(to_account_id, :to_account_id),
             value = :value,
-            memo = IFNULL(:memo, memo)",
+            memo = IFNULL(:memo, memo),
+            memo_text = CASE WHEN :memo IS NULL THEN memo_text ELSE :memo_text END",
     )?;
 
     let (to_address, to_account_id, pool_type) = recipient_params(params, recipient);
@@ -2658,7 +3559,8 @@ pub(crate) fn put_sent_output<P: consensus::Parameters>(
         ":to_address": &to_address,
         ":to_account_id": &to_account_id.map(|a| a.0),
         ":value": &i64::from(Amount::from(value)),
-        ":memo": memo_repr(memo)
+        ":memo": memo_repr(memo),
+        ":memo_text": memo_search_text(memo)
     ];
 
     stmt_upsert_sent_output.execute(sql_args)?;
@@ -2830,78 +3732,507 @@ pub(crate) fn prune_nullifier_map(
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use std::num::NonZeroU32;
+/// Inserts the given exchange rate observation, replacing any existing observation for the
+/// same currency and fetch time.
+pub(crate) fn insert_exchange_rate(
+    conn: &rusqlite::Connection,
+    observation: &ExchangeRateObservation,
+) -> Result<(), SqliteClientError> {
+    let mut stmt_upsert_rate = conn.prepare_cached(
+        "INSERT INTO exchange_rates (currency, fetched_at, rate)
+        VALUES (:currency, :fetched_at, :rate)
+        ON CONFLICT (currency, fetched_at) DO UPDATE
+        SET rate = :rate",
+    )?;
 
-    use sapling::zip32::ExtendedSpendingKey;
-    use secrecy::{ExposeSecret, SecretVec};
-    use zcash_client_backend::data_api::{AccountSource, WalletRead};
-    use zcash_primitives::{block::BlockHash, transaction::components::amount::NonNegativeAmount};
+    stmt_upsert_rate.execute(named_params![
+        ":currency": observation.currency().code(),
+        ":fetched_at": observation.fetched_at().unix_timestamp(),
+        ":rate": observation.rate().to_string(),
+    ])?;
 
-    use crate::{
-        testing::{AddressType, BlockCache, TestBuilder, TestState},
-        AccountId,
-    };
+    Ok(())
+}
 
-    use super::account_birthday;
+/// Returns the stored exchange rate observation for the given currency whose fetch time is
+/// closest to `time`, provided that it lies within `max_distance` of `time`. Ties are broken
+/// in favour of the earlier observation.
+pub(crate) fn get_exchange_rate_nearest(
+    conn: &rusqlite::Connection,
+    currency: Currency,
+    time: time::OffsetDateTime,
+    max_distance: time::Duration,
+) -> Result<Option<ExchangeRateObservation>, SqliteClientError> {
+    let target = time.unix_timestamp();
+    let distance = max_distance.whole_seconds().abs();
 
-    #[cfg(feature = "transparent-inputs")]
-    use {
-        crate::PRUNING_DEPTH,
-        zcash_client_backend::{
-            data_api::{wallet::input_selection::GreedyInputSelector, InputSource, WalletWrite},
-            encoding::AddressCodec,
-            fees::{fixed, DustOutputPolicy},
-            wallet::WalletTransparentOutput,
-        },
-        zcash_primitives::{
-            consensus::BlockHeight,
-            transaction::{
-                components::{OutPoint, TxOut},
-                fees::fixed::FeeRule as FixedFeeRule,
-            },
-        },
-    };
+    conn.query_row(
+        "SELECT fetched_at, rate
+        FROM exchange_rates
+        WHERE currency = :currency
+        AND fetched_at BETWEEN :min_fetched_at AND :max_fetched_at
+        ORDER BY ABS(fetched_at - :target), fetched_at
+        LIMIT 1",
+        named_params![
+            ":currency": currency.code(),
+            ":target": target,
+            ":min_fetched_at": target.saturating_sub(distance),
+            ":max_fetched_at": target.saturating_add(distance),
+        ],
+        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+    )
+    .optional()?
+    .map(|(fetched_at, rate)| {
+        let fetched_at = time::OffsetDateTime::from_unix_timestamp(fetched_at).map_err(|e| {
+            SqliteClientError::CorruptedData(format!("Invalid exchange rate fetch time: {}", e))
+        })?;
+        let rate = Decimal::from_str(&rate).map_err(|e| {
+            SqliteClientError::CorruptedData(format!("Invalid exchange rate {}: {}", rate, e))
+        })?;
+        Ok(ExchangeRateObservation::from_parts(
+            currency, rate, fetched_at,
+        ))
+    })
+    .transpose()
+}
 
-    #[test]
-    fn empty_database_has_no_balance() {
-        let st = TestBuilder::new()
-            .with_account_from_sapling_activation(BlockHash([0; 32]))
-            .build();
-        let account = st.test_account().unwrap();
+fn parse_draft<P: consensus::Parameters>(
+    params: &P,
+    request: &str,
+    min_confirmations: u32,
+    note: String,
+) -> Result<DraftPayment, SqliteClientError> {
+    let request = TransactionRequest::from_uri(params, request).map_err(|e| {
+        SqliteClientError::CorruptedData(format!("Invalid draft payment request: {}", e))
+    })?;
+    let min_confirmations = NonZeroU32::new(min_confirmations).ok_or_else(|| {
+        SqliteClientError::CorruptedData(
+            "Draft payment minimum confirmations must be nonzero.".to_string(),
+        )
+    })?;
 
-        // The account should have no summary information
-        assert_eq!(st.get_wallet_summary(0), None);
+    Ok(DraftPayment::new(request, min_confirmations, note))
+}
 
-        // We can't get an anchor height, as we have not scanned any blocks.
-        assert_eq!(
-            st.wallet()
-                .get_target_and_anchor_heights(NonZeroU32::new(10).unwrap())
-                .unwrap(),
-            None
-        );
+/// Saves the given draft payment for the specified account.
+pub(crate) fn save_draft<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    account: AccountId,
+    draft: &DraftPayment,
+) -> Result<DraftId, SqliteClientError> {
+    conn.query_row(
+        "INSERT INTO payment_drafts (account_id, request, min_confirmations, note)
+        SELECT id, :request, :min_confirmations, :note
+        FROM accounts
+        WHERE id = :account_id
+        RETURNING id",
+        named_params![
+            ":account_id": account.0,
+            ":request": draft.request().to_uri(params),
+            ":min_confirmations": u32::from(draft.min_confirmations()),
+            ":note": draft.note(),
+        ],
+        |row| row.get::<_, u64>(0).map(DraftId::from_u64),
+    )
+    .optional()?
+    .ok_or(SqliteClientError::AccountUnknown)
+}
 
-        // The default address is set for the test account
-        assert_matches!(
-            st.wallet().get_current_address(account.account_id()),
-            Ok(Some(_))
-        );
+/// Returns the draft payments saved for the specified account, in the order in which they
+/// were saved.
+pub(crate) fn get_drafts<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    account: AccountId,
+) -> Result<Vec<(DraftId, DraftPayment)>, SqliteClientError> {
+    let mut stmt_drafts = conn.prepare(
+        "SELECT id, request, min_confirmations, note
+        FROM payment_drafts
+        WHERE account_id = :account_id
+        ORDER BY id",
+    )?;
 
-        // No default address is set for an un-initialized account
-        assert_matches!(
-            st.wallet()
-                .get_current_address(AccountId(account.account_id().0 + 1)),
-            Ok(None)
-        );
+    let mut rows = stmt_drafts.query(named_params![":account_id": account.0])?;
+    let mut drafts = vec![];
+    while let Some(row) = rows.next()? {
+        let draft_id = DraftId::from_u64(row.get(0)?);
+        let request: String = row.get(1)?;
+        let draft = parse_draft(params, &request, row.get(2)?, row.get(3)?)?;
+        drafts.push((draft_id, draft));
     }
 
-    #[test]
-    #[cfg(feature = "transparent-inputs")]
-    fn put_received_transparent_utxo() {
-        use crate::testing::TestBuilder;
+    Ok(drafts)
+}
 
-        let mut st = TestBuilder::new()
+/// Returns the draft payment with the given identifier, along with the account for which it
+/// was saved.
+pub(crate) fn get_draft<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    draft_id: DraftId,
+) -> Result<Option<(AccountId, DraftPayment)>, SqliteClientError> {
+    conn.query_row(
+        "SELECT account_id, request, min_confirmations, note
+        FROM payment_drafts
+        WHERE id = :draft_id",
+        named_params![":draft_id": draft_id.as_u64()],
+        |row| {
+            Ok((
+                AccountId(row.get(0)?),
+                row.get::<_, String>(1)?,
+                row.get::<_, u32>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        },
+    )
+    .optional()?
+    .map(|(account, request, min_confirmations, note)| {
+        parse_draft(params, &request, min_confirmations, note).map(|draft| (account, draft))
+    })
+    .transpose()
+}
+
+/// Deletes the draft payment with the given identifier, if it exists.
+pub(crate) fn delete_draft(
+    conn: &rusqlite::Connection,
+    draft_id: DraftId,
+) -> Result<(), SqliteClientError> {
+    conn.execute(
+        "DELETE FROM payment_drafts WHERE id = :draft_id",
+        named_params![":draft_id": draft_id.as_u64()],
+    )?;
+
+    Ok(())
+}
+
+/// Sets whether the received note with the given identifier is locked against spending.
+pub(crate) fn set_note_spend_policy(
+    conn: &rusqlite::Connection,
+    note_id: NoteId,
+    locked: bool,
+) -> Result<(), SqliteClientError> {
+    let set_locked = |table_prefix: &'static str, output_col: &'static str| {
+        conn.execute(
+            &format!(
+                "UPDATE {table_prefix}_received_notes
+                SET is_locked = :locked
+                WHERE tx = (SELECT id_tx FROM transactions WHERE txid = :txid)
+                AND {output_col} = :output_index"
+            ),
+            named_params![
+                ":locked": locked,
+                ":txid": note_id.txid().as_ref(),
+                ":output_index": note_id.output_index(),
+            ],
+        )
+    };
+
+    let updated = match note_id.protocol() {
+        ShieldedProtocol::Sapling => set_locked(SAPLING_TABLES_PREFIX, "output_index")?,
+        #[cfg(feature = "orchard")]
+        ShieldedProtocol::Orchard => set_locked(ORCHARD_TABLES_PREFIX, "action_index")?,
+        #[cfg(not(feature = "orchard"))]
+        ShieldedProtocol::Orchard => {
+            return Err(SqliteClientError::UnsupportedPoolType(PoolType::Shielded(
+                ShieldedProtocol::Orchard,
+            )))
+        }
+    };
+
+    if updated == 0 {
+        Err(SqliteClientError::NoteNotFound(note_id))
+    } else {
+        Ok(())
+    }
+}
+
+/// Sets whether the transparent UTXO at the given outpoint is locked against spending.
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn set_utxo_spend_policy(
+    conn: &rusqlite::Connection,
+    outpoint: &OutPoint,
+    locked: bool,
+) -> Result<(), SqliteClientError> {
+    let updated = conn.execute(
+        "UPDATE utxos
+        SET is_locked = :locked
+        WHERE prevout_txid = :prevout_txid
+        AND prevout_idx = :prevout_idx",
+        named_params![
+            ":locked": locked,
+            ":prevout_txid": outpoint.hash(),
+            ":prevout_idx": outpoint.n(),
+        ],
+    )?;
+
+    if updated == 0 {
+        Err(SqliteClientError::UtxoNotFound(outpoint.clone()))
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn get_transactions(
+    conn: &rusqlite::Connection,
+    account: AccountId,
+    filter: &TransactionFilter,
+    offset: u32,
+    limit: u32,
+) -> Result<Vec<TransactionSummary>, SqliteClientError> {
+    // Filtering and ordering are applied to `v_transactions` first, so that the per-pool
+    // output counts only need to be computed for the rows in the requested page.
+    let mut stmt = conn.prepare_cached(&format!(
+        "WITH page AS (
+             SELECT txid, mined_height, tx_index, fee_paid, account_balance_delta, memo_count
+             FROM v_transactions
+             WHERE account_id = :account_id
+             AND (
+                 :direction IS NULL
+                 OR (:direction = 0 AND account_balance_delta < 0)
+                 OR (:direction = 1 AND account_balance_delta > 0)
+             )
+             AND (:min_height IS NULL OR mined_height >= :min_height)
+             AND (:max_height IS NULL OR mined_height < :max_height)
+             AND (:min_time IS NULL OR block_time >= :min_time)
+             AND (:max_time IS NULL OR block_time < :max_time)
+             AND (:min_value IS NULL OR ABS(account_balance_delta) >= :min_value)
+             ORDER BY mined_height IS NULL DESC, mined_height DESC, tx_index DESC, txid
+             LIMIT :limit OFFSET :offset
+         )
+         SELECT page.txid, page.mined_height, page.fee_paid, page.account_balance_delta,
+                page.memo_count,
+                COUNT(CASE WHEN o.output_pool = {transparent} THEN 1 END),
+                COUNT(CASE WHEN o.output_pool = {sapling} THEN 1 END),
+                COUNT(CASE WHEN o.output_pool = {orchard} THEN 1 END)
+         FROM page
+         LEFT JOIN v_tx_outputs o
+            ON o.txid = page.txid
+            AND (o.from_account_id = :account_id OR o.to_account_id = :account_id)
+         GROUP BY page.txid
+         ORDER BY page.mined_height IS NULL DESC, page.mined_height DESC, page.tx_index DESC,
+                  page.txid",
+        transparent = pool_code(PoolType::Transparent),
+        sapling = pool_code(PoolType::Shielded(ShieldedProtocol::Sapling)),
+        orchard = pool_code(PoolType::Shielded(ShieldedProtocol::Orchard)),
+    ))?;
+
+    let direction = filter.direction().map(|d| match d {
+        TransactionDirection::Sent => 0,
+        TransactionDirection::Received => 1,
+    });
+
+    let mut rows = stmt.query(named_params![
+        ":account_id": account.0,
+        ":direction": direction,
+        ":min_height": filter.mined_heights().map(|r| u32::from(r.start)),
+        ":max_height": filter.mined_heights().map(|r| u32::from(r.end)),
+        ":min_time": filter.block_times().map(|r| r.start),
+        ":max_time": filter.block_times().map(|r| r.end),
+        ":min_value": filter.min_value().map(u64::from),
+        ":limit": limit,
+        ":offset": offset,
+    ])?;
+
+    let mut result = vec![];
+    while let Some(row) = rows.next()? {
+        let txid = TxId::from_bytes(row.get(0)?);
+        let mined_height = row.get::<_, Option<u32>>(1)?.map(BlockHeight::from);
+        let fee_paid = row
+            .get::<_, Option<i64>>(2)?
+            .map(NonNegativeAmount::from_nonnegative_i64)
+            .transpose()?;
+        let account_value_delta = Amount::from_i64(row.get(3)?)?;
+
+        result.push(TransactionSummary::from_parts(
+            txid,
+            mined_height,
+            fee_paid,
+            account_value_delta,
+            row.get::<_, u32>(4)? > 0,
+            row.get(5)?,
+            row.get(6)?,
+            row.get(7)?,
+        ));
+    }
+
+    Ok(result)
+}
+
+/// Returns the memos on outputs sent or received by the given account that match `query`.
+pub(crate) fn find_memos(
+    conn: &rusqlite::Connection,
+    account: AccountId,
+    query: &MemoQuery,
+) -> Result<Vec<(NoteId, MemoBytes)>, SqliteClientError> {
+    let pattern = match query.pattern() {
+        MemoPattern::Text(text) => rusqlite::types::Value::Text(text.to_lowercase()),
+        MemoPattern::Bytes(memo) => {
+            rusqlite::types::Value::Blob(memo_repr(Some(memo)).expect("memo is present").to_vec())
+        }
+    };
+    let text_search = matches!(query.pattern(), MemoPattern::Text(_));
+    let matches = |t: &str| {
+        if text_search {
+            format!("{t}.memo_text IS NOT NULL AND instr({t}.memo_text, :pattern) > 0")
+        } else {
+            format!("{t}.memo = :pattern")
+        }
+    };
+
+    let received_notes = |table_prefix: &'static str, output_col: &'static str, pool| {
+        format!(
+            "SELECT t.txid AS txid, t.block AS block, {pool_code} AS output_pool,
+                    rn.{output_col} AS output_index, rn.memo AS memo
+             FROM {table_prefix}_received_notes rn
+             JOIN transactions t ON t.id_tx = rn.tx
+             WHERE rn.account_id = :account_id
+             AND {matches}",
+            pool_code = pool_code(PoolType::Shielded(pool)),
+            matches = matches("rn"),
+        )
+    };
+
+    let mut selects = vec![];
+    if query.direction() != Some(TransactionDirection::Sent) {
+        selects.push(received_notes(
+            SAPLING_TABLES_PREFIX,
+            "output_index",
+            ShieldedProtocol::Sapling,
+        ));
+        #[cfg(feature = "orchard")]
+        selects.push(received_notes(
+            ORCHARD_TABLES_PREFIX,
+            "action_index",
+            ShieldedProtocol::Orchard,
+        ));
+    }
+    if query.direction() != Some(TransactionDirection::Received) {
+        selects.push(format!(
+            "SELECT t.txid AS txid, t.block AS block, sn.output_pool AS output_pool,
+                    sn.output_index AS output_index, sn.memo AS memo
+             FROM sent_notes sn
+             JOIN transactions t ON t.id_tx = sn.tx
+             WHERE sn.from_account_id = :account_id
+             AND sn.output_pool != {transparent}
+             AND {matches}",
+            transparent = pool_code(PoolType::Transparent),
+            matches = matches("sn"),
+        ));
+    }
+
+    // `UNION` removes the duplicate rows for outputs that the account sent to itself.
+    let mut stmt = conn.prepare(&format!(
+        "SELECT txid, block, output_pool, output_index, memo
+         FROM ({})
+         ORDER BY block IS NULL, block, txid, output_pool, output_index",
+        selects.join(" UNION ")
+    ))?;
+
+    let mut rows = stmt.query(named_params![
+        ":account_id": account.0,
+        ":pattern": pattern,
+    ])?;
+
+    let mut result = vec![];
+    while let Some(row) = rows.next()? {
+        let txid = TxId::from_bytes(row.get(0)?);
+        let protocol = match row.get::<_, i64>(2)? {
+            2 => ShieldedProtocol::Sapling,
+            3 => ShieldedProtocol::Orchard,
+            other => {
+                return Err(SqliteClientError::CorruptedData(format!(
+                    "Memo found on output in unexpected pool {}",
+                    other
+                )))
+            }
+        };
+        let memo = MemoBytes::from_bytes(&row.get::<_, Vec<u8>>(4)?)?;
+        result.push((NoteId::new(txid, protocol, row.get(3)?), memo));
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+    use std::str::FromStr;
+
+    use iso_currency::Currency;
+    use rust_decimal::Decimal;
+    use sapling::zip32::ExtendedSpendingKey;
+    use secrecy::{ExposeSecret, SecretVec};
+    use zcash_client_backend::data_api::{
+        Account as _, AccountMetadataValue, AccountSource, ExchangeRateObservation, WalletRead,
+        WalletWrite,
+    };
+    use zcash_primitives::{block::BlockHash, transaction::components::amount::NonNegativeAmount};
+
+    use crate::{
+        error::SqliteClientError,
+        testing::{AddressType, BlockCache, TestBuilder, TestState},
+        AccountId,
+    };
+
+    use super::account_birthday;
+
+    #[cfg(feature = "transparent-inputs")]
+    use {
+        crate::PRUNING_DEPTH,
+        zcash_client_backend::{
+            data_api::{wallet::input_selection::GreedyInputSelector, InputSource},
+            encoding::AddressCodec,
+            fees::{fixed, DustOutputPolicy},
+            wallet::WalletTransparentOutput,
+        },
+        zcash_primitives::{
+            consensus::BlockHeight,
+            transaction::{
+                components::{OutPoint, TxOut},
+                fees::fixed::FeeRule as FixedFeeRule,
+            },
+        },
+    };
+
+    #[test]
+    fn empty_database_has_no_balance() {
+        let st = TestBuilder::new()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let account = st.test_account().unwrap();
+
+        // The account should have no summary information
+        assert_eq!(st.get_wallet_summary(0), None);
+
+        // We can't get an anchor height, as we have not scanned any blocks.
+        assert_eq!(
+            st.wallet()
+                .get_target_and_anchor_heights(NonZeroU32::new(10).unwrap())
+                .unwrap(),
+            None
+        );
+
+        // The default address is set for the test account
+        assert_matches!(
+            st.wallet().get_current_address(account.account_id()),
+            Ok(Some(_))
+        );
+
+        // No default address is set for an un-initialized account
+        assert_matches!(
+            st.wallet()
+                .get_current_address(AccountId(account.account_id().0 + 1)),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn put_received_transparent_utxo() {
+        use crate::testing::TestBuilder;
+
+        let mut st = TestBuilder::new()
             .with_account_from_sapling_activation(BlockHash([0; 32]))
             .build();
 
@@ -2948,52 +4279,488 @@ mod tests {
             Ok(Some(ret)) if (ret.outpoint(), ret.txout(), ret.height()) == (utxo.outpoint(), utxo.txout(), height_1)
         );
 
-        // Change the mined height of the UTXO and upsert; we should get back
-        // the same `UtxoId`.
-        let height_2 = BlockHeight::from_u32(34567);
-        let utxo2 = WalletTransparentOutput::from_parts(outpoint, txout, height_2).unwrap();
-        let res1 = st.wallet_mut().put_received_transparent_utxo(&utxo2);
-        assert_matches!(res1, Ok(id) if id == res0.unwrap());
+        // Change the mined height of the UTXO and upsert; we should get back
+        // the same `UtxoId`.
+        let height_2 = BlockHeight::from_u32(34567);
+        let utxo2 = WalletTransparentOutput::from_parts(outpoint, txout, height_2).unwrap();
+        let res1 = st.wallet_mut().put_received_transparent_utxo(&utxo2);
+        assert_matches!(res1, Ok(id) if id == res0.unwrap());
+
+        // Confirm that we no longer see any unspent outputs as of `height_1`.
+        assert_matches!(
+            st.wallet()
+                .get_unspent_transparent_outputs(taddr, height_1, &[])
+                .as_deref(),
+            Ok(&[])
+        );
+
+        // We can still look up the specific output, and it has the expected height.
+        assert_matches!(
+            st.wallet().get_unspent_transparent_output(utxo2.outpoint()),
+            Ok(Some(ret)) if (ret.outpoint(), ret.txout(), ret.height()) == (utxo2.outpoint(), utxo2.txout(), height_2)
+        );
+
+        // If we include `height_2` then the output is returned.
+        assert_matches!(
+            st.wallet()
+                .get_unspent_transparent_outputs(taddr, height_2, &[])
+                .as_deref(),
+            Ok(&[ref ret]) if (ret.outpoint(), ret.txout(), ret.height()) == (utxo.outpoint(), utxo.txout(), height_2)
+        );
+
+        assert_matches!(
+            st.wallet().get_transparent_balances(account_id, height_2),
+            Ok(h) if h.get(taddr) == Some(&value)
+        );
+
+        // Artificially delete the address from the addresses table and from the addresses
+        // derived under the gap limit, so that we can ensure the update fails if the join
+        // doesn't work.
+        st.wallet()
+            .conn
+            .execute(
+                "DELETE FROM addresses WHERE cached_transparent_receiver_address = ?",
+                [Some(taddr.encode(&st.wallet().params))],
+            )
+            .unwrap();
+        st.wallet()
+            .conn
+            .execute(
+                "DELETE FROM transparent_addresses WHERE address = ?",
+                [taddr.encode(&st.wallet().params)],
+            )
+            .unwrap();
+
+        let res2 = st.wallet_mut().put_received_transparent_utxo(&utxo2);
+        assert_matches!(res2, Err(_));
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn import_standalone_transparent_address() {
+        use zcash_keys::keys::{AddressGenerationError, UnifiedAddressRequest};
+        use zcash_primitives::legacy::keys::{AccountPrivKey, AccountPubKey, IncomingViewingKey};
+
+        let mut st = TestBuilder::new()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let hd_account_id = st.test_account().unwrap().account_id();
+
+        // Import the address of a bare transparent pubkey.
+        let pubkey: [u8; 33] =
+            AccountPrivKey::from_transparent_key(&st.network(), &[7; 32], zip32::AccountId::ZERO)
+                .unwrap()
+                .to_account_pubkey()
+                .serialize()[32..]
+                .try_into()
+                .unwrap();
+        let taddr = AccountPubKey::deserialize_and_pad(&pubkey)
+            .unwrap()
+            .derive_ext_ivk_from_legacy_key()
+            .default_legacy_address();
+        let birthday_height = st.sapling_activation_height();
+        let account_id = st
+            .wallet_mut()
+            .import_standalone_transparent_address(&taddr, birthday_height)
+            .unwrap();
+
+        // The account is marked as a standalone transparent account, and has no viewing keys.
+        let account = st.wallet().get_account(account_id).unwrap().unwrap();
+        assert_eq!(account.source(), AccountSource::StandaloneTransparent);
+        assert!(account.ufvk().is_none());
+        assert!(account.uivk().is_none());
+        let details = st
+            .wallet()
+            .get_account_details(account_id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(details.source(), AccountSource::StandaloneTransparent);
+        assert_eq!(details.birthday_height(), birthday_height);
+        assert!(!st
+            .wallet()
+            .get_unified_full_viewing_keys()
+            .unwrap()
+            .contains_key(&account_id));
+
+        // The address is tracked, and cannot be imported again.
+        assert!(st
+            .wallet()
+            .get_transparent_receivers(account_id)
+            .unwrap()
+            .contains_key(&taddr));
+        assert_matches!(
+            st.wallet_mut()
+                .import_standalone_transparent_address(&taddr, birthday_height),
+            Err(SqliteClientError::BadAccountData(_))
+        );
+
+        // An address of an HD account cannot be imported either.
+        let hd_taddr = *st
+            .wallet()
+            .get_current_address(hd_account_id)
+            .unwrap()
+            .unwrap()
+            .transparent()
+            .unwrap();
+        assert_matches!(
+            st.wallet_mut()
+                .import_standalone_transparent_address(&hd_taddr, birthday_height),
+            Err(SqliteClientError::BadAccountData(_))
+        );
+
+        // A UTXO received at the address is attributed to the standalone account.
+        let height = birthday_height + 10;
+        let value = NonNegativeAmount::const_from_u64(100000);
+        let utxo = WalletTransparentOutput::from_parts(
+            OutPoint::new([1u8; 32], 1),
+            TxOut {
+                value,
+                script_pubkey: taddr.script(),
+            },
+            height,
+        )
+        .unwrap();
+        assert_matches!(st.wallet_mut().put_received_transparent_utxo(&utxo), Ok(_));
+        assert_eq!(
+            st.wallet()
+                .get_transparent_balances(account_id, height)
+                .unwrap()
+                .get(&taddr),
+            Some(&value)
+        );
+        assert!(st
+            .wallet()
+            .get_transparent_balances(hd_account_id, height)
+            .unwrap()
+            .is_empty());
+
+        // Operations that require shielded keys are rejected.
+        assert_matches!(
+            st.wallet_mut()
+                .get_next_available_address(account_id, UnifiedAddressRequest::all().unwrap()),
+            Err(SqliteClientError::AddressGeneration(
+                AddressGenerationError::ShieldedReceiverRequired
+            ))
+        );
+        assert_matches!(st.wallet().get_current_address(account_id), Ok(None));
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn transparent_address_recovery_with_gaps() {
+        use std::collections::HashSet;
+
+        use zcash_client_backend::data_api::DEFAULT_TRANSPARENT_GAP_LIMIT;
+        use zcash_primitives::legacy::keys::{IncomingViewingKey, NonHardenedChildIndex};
+
+        use crate::error::SqliteClientError;
+
+        let mut st = TestBuilder::new()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+
+        let account = st.test_account().cloned().unwrap();
+        let external_ivk = account
+            .usk()
+            .transparent()
+            .to_account_pubkey()
+            .derive_external_ivk()
+            .unwrap();
+        let address_at = |index| {
+            external_ivk
+                .derive_address(NonHardenedChildIndex::from_index(index).unwrap())
+                .unwrap()
+        };
+
+        // Funds were received at addresses separated by gaps of up to the gap limit, and at
+        // one address beyond the gap limit of the last of these.
+        assert_eq!(DEFAULT_TRANSPARENT_GAP_LIMIT, 20);
+        let funded_indices = [3u32, 22, 42, 70];
+        let chain_utxos = funded_indices
+            .iter()
+            .enumerate()
+            .map(|(i, index)| {
+                WalletTransparentOutput::from_parts(
+                    OutPoint::new([i as u8 + 1; 32], 0),
+                    TxOut {
+                        value: NonNegativeAmount::const_from_u64(10000),
+                        script_pubkey: address_at(*index).script(),
+                    },
+                    BlockHeight::from_u32(12345 + i as u32),
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        // Simulate recovery from seed: repeatedly look up the UTXOs received by the wallet's
+        // transparent receivers, until no more are found.
+        let mut found = HashSet::new();
+        loop {
+            let receivers = st
+                .wallet()
+                .get_transparent_receivers(account.account_id())
+                .unwrap();
+            let new_utxos = chain_utxos
+                .iter()
+                .filter(|utxo| {
+                    receivers.contains_key(utxo.recipient_address())
+                        && !found.contains(utxo.recipient_address())
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            if new_utxos.is_empty() {
+                break;
+            }
+            for utxo in new_utxos {
+                st.wallet_mut()
+                    .put_received_transparent_utxo(&utxo)
+                    .unwrap();
+                found.insert(*utxo.recipient_address());
+            }
+        }
+
+        // Every UTXO within the gap limit of the previous one was found, each only after the
+        // window had advanced past the previously found address.
+        let balances = st
+            .wallet()
+            .get_transparent_balances(account.account_id(), BlockHeight::from_u32(20000))
+            .unwrap();
+        assert_eq!(
+            balances.keys().copied().collect::<HashSet<_>>(),
+            [3, 22, 42]
+                .into_iter()
+                .map(address_at)
+                .collect::<HashSet<_>>()
+        );
+
+        let usage = st
+            .wallet()
+            .get_transparent_address_usage(account.account_id())
+            .unwrap();
+        assert_eq!(usage.len(), 42 + 1 + 20);
+        for (index, address_usage) in usage.iter().enumerate() {
+            assert_eq!(address_usage.address(), &address_at(index as u32));
+            assert_eq!(
+                address_usage.metadata().address_index().index(),
+                index as u32
+            );
+            let expected_height = funded_indices
+                .iter()
+                .position(|i| *i as usize == index)
+                .map(|i| BlockHeight::from_u32(12345 + i as u32));
+            assert_eq!(address_usage.first_use_height(), expected_height);
+        }
+
+        // Observing funds at the address beyond the gap limit by other means brings it into
+        // the window, which advances accordingly.
+        assert_matches!(
+            st.wallet_mut()
+                .mark_transparent_address_used(&address_at(70), BlockHeight::from_u32(20000)),
+            Err(SqliteClientError::AddressNotRecognized(_))
+        );
+        st.wallet_mut()
+            .mark_transparent_address_used(&address_at(60), BlockHeight::from_u32(20000))
+            .unwrap();
+        let receivers = st
+            .wallet()
+            .get_transparent_receivers(account.account_id())
+            .unwrap();
+        assert!(receivers.contains_key(&address_at(70)));
+        assert!(receivers.contains_key(&address_at(80)));
+        assert!(!receivers.contains_key(&address_at(81)));
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn transparent_address_reservation_persists() {
+        use tempfile::NamedTempFile;
+        use zcash_client_backend::data_api::AccountBirthday;
+        use zcash_primitives::consensus::Network;
+
+        use crate::{error::SqliteClientError, wallet::init::init_wallet_db, WalletDb};
+
+        let data_file = NamedTempFile::new().unwrap();
+        let mut db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork)
+            .unwrap()
+            .with_transparent_gap_limit(5);
+        init_wallet_db(&mut db_data, None, None).unwrap();
+
+        let seed = SecretVec::new(vec![0u8; 32]);
+        let birthday =
+            AccountBirthday::from_sapling_activation(&Network::TestNetwork, BlockHash([0; 32]));
+        let (account_id, _) = db_data
+            .create_account(&SecretVec::new(vec![]), &seed, &birthday)
+            .unwrap();
+        let ua_receiver = *db_data
+            .get_current_address(account_id)
+            .unwrap()
+            .unwrap()
+            .transparent()
+            .unwrap();
+
+        let first = db_data
+            .reserve_next_transparent_addresses(account_id, 2)
+            .unwrap();
+        assert_eq!(first.len(), 2);
+        assert!(first.iter().all(|(address, _)| address != &ua_receiver));
+
+        // Reopen the wallet; previously reserved addresses are not handed out again.
+        drop(db_data);
+        let mut db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork)
+            .unwrap()
+            .with_transparent_gap_limit(5);
+        let second = db_data
+            .reserve_next_transparent_addresses(account_id, 2)
+            .unwrap();
+        assert_eq!(second.len(), 2);
+        assert!(second.iter().all(
+            |(address, _)| address != &ua_receiver && !first.iter().any(|(a, _)| a == address)
+        ));
+
+        let reserved = db_data
+            .get_transparent_address_usage(account_id)
+            .unwrap()
+            .into_iter()
+            .filter(|usage| usage.is_reserved())
+            .map(|usage| *usage.address())
+            .collect::<Vec<_>>();
+        assert_eq!(reserved.len(), 4);
 
-        // Confirm that we no longer see any unspent outputs as of `height_1`.
+        // At most one unused address remains within the gap limit of five.
         assert_matches!(
-            st.wallet()
-                .get_unspent_transparent_outputs(taddr, height_1, &[])
-                .as_deref(),
-            Ok(&[])
+            db_data.reserve_next_transparent_addresses(account_id, 2),
+            Err(SqliteClientError::ReachedGapLimit(id, 5)) if id == account_id
+        );
+        assert_eq!(
+            db_data
+                .get_transparent_address_usage(account_id)
+                .unwrap()
+                .iter()
+                .filter(|usage| usage.is_reserved())
+                .count(),
+            4
         );
 
-        // We can still look up the specific output, and it has the expected height.
-        assert_matches!(
-            st.wallet().get_unspent_transparent_output(utxo2.outpoint()),
-            Ok(Some(ret)) if (ret.outpoint(), ret.txout(), ret.height()) == (utxo2.outpoint(), utxo2.txout(), height_2)
+        // Once a reserved address receives funds, the window advances.
+        db_data
+            .mark_transparent_address_used(&second[1].0, BlockHeight::from_u32(20000))
+            .unwrap();
+        let third = db_data
+            .reserve_next_transparent_addresses(account_id, 2)
+            .unwrap();
+        assert_eq!(third.len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn ephemeral_addresses_have_their_own_window() {
+        use tempfile::NamedTempFile;
+        use zcash_client_backend::{data_api::AccountBirthday, wallet::TransparentAddressMetadata};
+        use zcash_primitives::{
+            consensus::Network,
+            legacy::keys::{IncomingViewingKey, NonHardenedChildIndex, TransparentKeyScope},
+        };
+
+        use crate::{error::SqliteClientError, wallet::init::init_wallet_db, WalletDb};
+
+        let seed = SecretVec::new(vec![0u8; 32]);
+        let birthday =
+            AccountBirthday::from_sapling_activation(&Network::TestNetwork, BlockHash([0; 32]));
+        let open_wallet = || {
+            let data_file = NamedTempFile::new().unwrap();
+            let mut db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork)
+                .unwrap()
+                .with_transparent_gap_limit(5);
+            init_wallet_db(&mut db_data, None, None).unwrap();
+            let (account_id, usk) = db_data
+                .create_account(&SecretVec::new(vec![]), &seed, &birthday)
+                .unwrap();
+            (data_file, db_data, account_id, usk)
+        };
+
+        let (_file, mut db_data, account_id, usk) = open_wallet();
+        let ephemeral_ivk = usk
+            .transparent()
+            .to_account_pubkey()
+            .derive_ephemeral_ivk()
+            .unwrap();
+        let ephemeral_at = |i| {
+            ephemeral_ivk
+                .derive_address(NonHardenedChildIndex::from_index(i).unwrap())
+                .unwrap()
+        };
+
+        // Ephemeral addresses are derived under their own scope, starting at index zero.
+        let reserved = db_data
+            .reserve_next_ephemeral_addresses(account_id, 2)
+            .unwrap();
+        assert_eq!(
+            reserved
+                .iter()
+                .map(|(address, meta)| {
+                    assert_eq!(meta.scope(), TransparentKeyScope::EPHEMERAL);
+                    (*address, meta.address_index().index())
+                })
+                .collect::<Vec<_>>(),
+            vec![(ephemeral_at(0), 0), (ephemeral_at(1), 1)]
         );
 
-        // If we include `height_2` then the output is returned.
-        assert_matches!(
-            st.wallet()
-                .get_unspent_transparent_outputs(taddr, height_2, &[])
-                .as_deref(),
-            Ok(&[ref ret]) if (ret.outpoint(), ret.txout(), ret.height()) == (utxo.outpoint(), utxo.txout(), height_2)
+        // Reserving them does not affect the external addresses, but they are watched for
+        // UTXOs along with the account's other transparent receivers.
+        let external = db_data.get_transparent_address_usage(account_id).unwrap();
+        assert!(external.iter().all(|usage| !usage.is_reserved()));
+        assert!(external
+            .iter()
+            .all(|usage| !reserved.iter().any(|(a, _)| a == usage.address())));
+        let receivers = db_data.get_transparent_receivers(account_id).unwrap();
+        assert_eq!(
+            receivers.get(&ephemeral_at(4)),
+            Some(&Some(TransparentAddressMetadata::new(
+                TransparentKeyScope::EPHEMERAL,
+                NonHardenedChildIndex::from_index(4).unwrap()
+            )))
         );
 
+        // The ephemeral window is limited by the gap limit, independently of the external one.
+        db_data
+            .reserve_next_ephemeral_addresses(account_id, 3)
+            .unwrap();
         assert_matches!(
-            st.wallet().get_transparent_balances(account_id, height_2),
-            Ok(h) if h.get(taddr) == Some(&value)
+            db_data.reserve_next_ephemeral_addresses(account_id, 1),
+            Err(SqliteClientError::ReachedGapLimit(id, 5)) if id == account_id
+        );
+        assert_eq!(
+            db_data
+                .reserve_next_transparent_addresses(account_id, 1)
+                .unwrap()
+                .len(),
+            1
         );
 
-        // Artificially delete the address from the addresses table so that
-        // we can ensure the update fails if the join doesn't work.
-        st.wallet()
-            .conn
-            .execute(
-                "DELETE FROM addresses WHERE cached_transparent_receiver_address = ?",
-                [Some(taddr.encode(&st.wallet().params))],
-            )
+        // A wallet recovered from the same seed derives the same ephemeral addresses, and
+        // advances its window as their use is discovered.
+        let (_file, mut recovered, account_id, _) = open_wallet();
+        let usage = recovered.get_ephemeral_address_usage(account_id).unwrap();
+        assert_eq!(
+            usage.iter().map(|u| *u.address()).collect::<Vec<_>>(),
+            (0..5).map(ephemeral_at).collect::<Vec<_>>()
+        );
+        recovered
+            .mark_transparent_address_used(&ephemeral_at(4), BlockHeight::from_u32(20000))
             .unwrap();
-
-        let res2 = st.wallet_mut().put_received_transparent_utxo(&utxo2);
-        assert_matches!(res2, Err(_));
+        let usage = recovered.get_ephemeral_address_usage(account_id).unwrap();
+        assert_eq!(usage.len(), 10);
+        assert_eq!(
+            usage[4].first_use_height(),
+            Some(BlockHeight::from_u32(20000))
+        );
+        assert_eq!(
+            recovered
+                .get_transparent_address_usage(account_id)
+                .unwrap()
+                .len(),
+            5
+        );
     }
 
     #[test]
@@ -3022,7 +4789,7 @@ mod tests {
             .with_account_from_sapling_activation(BlockHash([0; 32]))
             .build();
 
-        let transparentkey = SecretVec::new(st.test_transparentkey().unwrap().expose_secret().clone());
+        let transparentkey = SecretVec::new(vec![]);
         let seed = SecretVec::new(st.test_seed().unwrap().expose_secret().clone());
         let birthday = st.test_account().unwrap().birthday().clone();
 
@@ -3149,11 +4916,17 @@ mod tests {
         check_balance(&st, 2, NonNegativeAmount::ZERO);
 
         // Unmine the shielding transaction via a reorg.
-        st.wallet_mut()
-            .truncate_to_height(mined_height - 1)
-            .unwrap();
+        assert_eq!(
+            st.wallet_mut()
+                .truncate_to_height(mined_height - 1)
+                .unwrap(),
+            mined_height - 1
+        );
         assert_eq!(st.wallet().chain_height().unwrap(), Some(mined_height - 1));
 
+        // The shielding transaction is retained, but is no longer mined.
+        assert_eq!(st.wallet().get_tx_height(txid).unwrap(), None);
+
         // The wallet should still have zero transparent balance.
         check_balance(&st, 0, NonNegativeAmount::ZERO);
         check_balance(&st, 1, NonNegativeAmount::ZERO);
@@ -3187,6 +4960,101 @@ mod tests {
         check_balance(&st, 2, value);
     }
 
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn locked_utxo_is_not_spendable() {
+        use zcash_client_backend::{data_api::error::Error, ShieldedProtocol};
+
+        let mut st = TestBuilder::new()
+            .with_block_cache()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+
+        let account_id = st.test_account().unwrap().account_id();
+        let uaddr = st
+            .wallet()
+            .get_current_address(account_id)
+            .unwrap()
+            .unwrap();
+        let taddr = uaddr.transparent().unwrap();
+
+        let not_our_key = ExtendedSpendingKey::master(&[]).to_diversifiable_full_viewing_key();
+        let not_our_value = NonNegativeAmount::const_from_u64(10000);
+        let (start_height, _, _) =
+            st.generate_next_block(&not_our_key, AddressType::DefaultExternal, not_our_value);
+        st.scan_cached_blocks(start_height, 1);
+
+        // Receive a transparent output in the chain tip, and lock it.
+        let value = NonNegativeAmount::const_from_u64(100000);
+        let outpoint = OutPoint::new([1u8; 32], 1);
+        let txout = TxOut {
+            value,
+            script_pubkey: taddr.script(),
+        };
+        let utxo =
+            WalletTransparentOutput::from_parts(outpoint.clone(), txout, start_height).unwrap();
+        st.wallet_mut()
+            .put_received_transparent_utxo(&utxo)
+            .unwrap();
+        st.wallet_mut()
+            .set_utxo_spend_policy(&outpoint, true)
+            .unwrap();
+
+        // The locked output counts towards the total balance, but is not spendable.
+        let check_balance = |st: &TestState<_>, unlocked, locked| {
+            let summary = st.wallet().get_wallet_summary(1).unwrap().unwrap();
+            let balance = summary.account_balances().get(&account_id).unwrap();
+            assert_eq!(balance.unshielded(), unlocked);
+            assert_eq!(balance.unshielded_locked(), locked);
+            assert_eq!(balance.total(), value);
+            assert_eq!(
+                st.wallet()
+                    .get_transparent_balances(account_id, start_height)
+                    .unwrap()
+                    .get(taddr)
+                    .cloned()
+                    .unwrap_or(NonNegativeAmount::ZERO),
+                unlocked,
+            );
+        };
+        check_balance(&st, NonNegativeAmount::ZERO, value);
+        assert_matches!(
+            st.wallet()
+                .get_unspent_transparent_outputs(taddr, start_height, &[])
+                .as_deref(),
+            Ok(&[])
+        );
+        assert_matches!(
+            st.wallet().get_unspent_transparent_output(&outpoint),
+            Ok(None)
+        );
+
+        // Shielding reports that the funds are locked, rather than absent.
+        let input_selector = GreedyInputSelector::new(
+            fixed::SingleOutputChangeStrategy::new(
+                FixedFeeRule::non_standard(NonNegativeAmount::ZERO),
+                None,
+                ShieldedProtocol::Sapling,
+            ),
+            DustOutputPolicy::default(),
+        );
+        assert_matches!(
+            st.propose_shielding(&input_selector, value, &[*taddr], 1),
+            Err(Error::InsufficientUnlockedFunds { available, required, locked })
+                if available == NonNegativeAmount::ZERO && required == value && locked == value
+        );
+
+        // Unlocking the output makes it spendable again.
+        st.wallet_mut()
+            .set_utxo_spend_policy(&outpoint, false)
+            .unwrap();
+        check_balance(&st, value, NonNegativeAmount::ZERO);
+        assert_matches!(
+            st.propose_shielding(&input_selector, value, &[*taddr], 1),
+            Ok(_)
+        );
+    }
+
     #[test]
     fn block_fully_scanned() {
         let mut st = TestBuilder::new()
@@ -3257,4 +5125,231 @@ mod tests {
             Ok(birthday) if birthday == st.sapling_activation_height()
         )
     }
+
+    #[test]
+    fn account_names() {
+        let mut st = TestBuilder::new()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let account = st.test_account().cloned().unwrap();
+        let account_id = account.account_id();
+
+        let transparentkey = SecretVec::new(vec![]);
+        let seed = SecretVec::new(st.test_seed().unwrap().expose_secret().clone());
+        let (second_id, _) = st
+            .wallet_mut()
+            .create_account(&transparentkey, &seed, account.birthday())
+            .unwrap();
+
+        assert_eq!(st.wallet().get_account_name(account_id).unwrap(), None);
+
+        // Non-ASCII names are stored and returned unchanged.
+        let name = "Épargne 💰 貯金";
+        st.wallet_mut()
+            .set_account_name(account_id, Some(name))
+            .unwrap();
+        assert_eq!(
+            st.wallet().get_account_name(account_id).unwrap().as_deref(),
+            Some(name)
+        );
+
+        let details = st
+            .wallet()
+            .get_account_details(account_id)
+            .unwrap()
+            .unwrap();
+        let stored = st.wallet().get_account(account_id).unwrap().unwrap();
+        assert_eq!(details.account_id(), account_id);
+        assert_eq!(details.source(), stored.source());
+        assert_eq!(
+            details.ufvk().map(|ufvk| ufvk.encode(&st.network())),
+            stored.ufvk().map(|ufvk| ufvk.encode(&st.network()))
+        );
+        assert_eq!(details.birthday_height(), st.sapling_activation_height());
+        assert_eq!(details.name(), Some(name));
+
+        // Names are unique within the wallet, but an account may be renamed to its own name.
+        assert_matches!(
+            st.wallet_mut().set_account_name(second_id, Some(name)),
+            Err(SqliteClientError::AccountNameConflict(n)) if n == name
+        );
+        st.wallet_mut()
+            .set_account_name(account_id, Some(name))
+            .unwrap();
+        st.wallet_mut()
+            .set_account_name(second_id, Some("Spending"))
+            .unwrap();
+
+        st.wallet_mut().set_account_name(account_id, None).unwrap();
+        assert_eq!(st.wallet().get_account_name(account_id).unwrap(), None);
+        assert_eq!(
+            st.wallet().get_account_name(second_id).unwrap().as_deref(),
+            Some("Spending")
+        );
+
+        let unknown = AccountId(42);
+        assert_matches!(st.wallet().get_account_details(unknown), Ok(None));
+        assert_matches!(
+            st.wallet().get_account_name(unknown),
+            Err(SqliteClientError::AccountUnknown)
+        );
+        assert_matches!(
+            st.wallet_mut().set_account_name(unknown, Some("Unknown")),
+            Err(SqliteClientError::AccountUnknown)
+        );
+        assert_matches!(
+            st.wallet_mut().set_account_name(unknown, None),
+            Err(SqliteClientError::AccountUnknown)
+        );
+    }
+
+    #[test]
+    fn account_metadata() {
+        let mut st = TestBuilder::new()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let account_id = st.test_account().unwrap().account_id();
+
+        assert!(st
+            .wallet()
+            .get_account_metadata(account_id)
+            .unwrap()
+            .is_empty());
+
+        let entries = [
+            (
+                "couleur",
+                AccountMetadataValue::Text("bleu ciel ☁".to_owned()),
+            ),
+            ("sort_order", AccountMetadataValue::Integer(-3)),
+            ("icon", AccountMetadataValue::Bytes(vec![0, 159, 146, 150])),
+        ];
+        for (key, value) in &entries {
+            st.wallet_mut()
+                .set_account_metadata(account_id, key, Some(value.clone()))
+                .unwrap();
+        }
+        // Setting an existing key replaces its value, including with a value of another type.
+        st.wallet_mut()
+            .set_account_metadata(
+                account_id,
+                "sort_order",
+                Some(AccountMetadataValue::Integer(7)),
+            )
+            .unwrap();
+        st.wallet_mut()
+            .set_account_metadata(
+                account_id,
+                "icon",
+                Some(AccountMetadataValue::Text("🐷".to_owned())),
+            )
+            .unwrap();
+
+        let metadata = st.wallet().get_account_metadata(account_id).unwrap();
+        assert_eq!(metadata.len(), 3);
+        assert_eq!(metadata["couleur"], entries[0].1);
+        assert_eq!(metadata["sort_order"], AccountMetadataValue::Integer(7));
+        assert_eq!(
+            metadata["icon"],
+            AccountMetadataValue::Text("🐷".to_owned())
+        );
+
+        st.wallet_mut()
+            .set_account_metadata(account_id, "couleur", None)
+            .unwrap();
+        // Removing a key that is not present is not an error.
+        st.wallet_mut()
+            .set_account_metadata(account_id, "couleur", None)
+            .unwrap();
+        assert_eq!(
+            st.wallet()
+                .get_account_metadata(account_id)
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            vec!["icon", "sort_order"]
+        );
+
+        let unknown = AccountId(42);
+        assert_matches!(
+            st.wallet().get_account_metadata(unknown),
+            Err(SqliteClientError::AccountUnknown)
+        );
+        assert_matches!(
+            st.wallet_mut().set_account_metadata(
+                unknown,
+                "couleur",
+                Some(AccountMetadataValue::Integer(1))
+            ),
+            Err(SqliteClientError::AccountUnknown)
+        );
+        assert_matches!(
+            st.wallet_mut()
+                .set_account_metadata(unknown, "couleur", None),
+            Err(SqliteClientError::AccountUnknown)
+        );
+    }
+
+    #[test]
+    fn exchange_rate_nearest() {
+        let mut st = TestBuilder::new().build();
+
+        let at = |secs: i64| time::OffsetDateTime::from_unix_timestamp(secs).unwrap();
+        let insert = |st: &mut TestState<()>, currency, rate: &str, secs| {
+            st.wallet_mut()
+                .insert_exchange_rate(&ExchangeRateObservation::from_parts(
+                    currency,
+                    Decimal::from_str(rate).unwrap(),
+                    at(secs),
+                ))
+                .unwrap();
+        };
+        let nearest = |st: &TestState<()>, currency, secs, max_secs| {
+            st.wallet()
+                .get_exchange_rate_nearest(currency, at(secs), time::Duration::seconds(max_secs))
+                .unwrap()
+                .map(|obs| (obs.rate().to_string(), obs.fetched_at().unix_timestamp()))
+        };
+
+        // A fresh wallet has no exchange rates.
+        assert_eq!(nearest(&st, Currency::USD, 1_700_000_000, 3600), None);
+
+        insert(&mut st, Currency::USD, "30.10", 1_700_000_000);
+        insert(&mut st, Currency::USD, "30.50", 1_700_000_600);
+        insert(&mut st, Currency::EUR, "27.00", 1_700_000_300);
+
+        // Re-inserting an observation with the same currency and fetch time replaces it.
+        insert(&mut st, Currency::USD, "30.20", 1_700_000_000);
+        assert_eq!(
+            nearest(&st, Currency::USD, 1_700_000_000, 0),
+            Some(("30.20".to_string(), 1_700_000_000))
+        );
+
+        // The closest observation for the requested currency is returned.
+        assert_eq!(
+            nearest(&st, Currency::USD, 1_700_000_450, 3600),
+            Some(("30.50".to_string(), 1_700_000_600))
+        );
+
+        // Ties are broken in favour of the earlier observation.
+        assert_eq!(
+            nearest(&st, Currency::USD, 1_700_000_300, 3600),
+            Some(("30.20".to_string(), 1_700_000_000))
+        );
+
+        // Observations exactly `max_distance` away are included, and those further away
+        // are not.
+        assert_eq!(
+            nearest(&st, Currency::USD, 1_700_000_700, 100),
+            Some(("30.50".to_string(), 1_700_000_600))
+        );
+        assert_eq!(nearest(&st, Currency::USD, 1_700_000_701, 100), None);
+
+        // Other currencies are not considered.
+        assert_eq!(nearest(&st, Currency::GBP, 1_700_000_300, 3600), None);
+        assert_eq!(
+            nearest(&st, Currency::EUR, 1_700_000_000, 3600),
+            Some(("27.00".to_string(), 1_700_000_300))
+        );
+    }
 }
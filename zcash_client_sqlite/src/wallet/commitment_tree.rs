@@ -1104,7 +1104,7 @@ mod tests {
         let mut db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork).unwrap();
         data_file.keep().unwrap();
 
-        init_wallet_db(&mut db_data, None).unwrap();
+        init_wallet_db(&mut db_data, None, None).unwrap();
         let store =
             SqliteShardStore::<_, String, 3>::from_connection(db_data.conn, T::TABLES_PREFIX)
                 .unwrap();
@@ -1202,7 +1202,7 @@ mod tests {
         let mut db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork).unwrap();
         data_file.keep().unwrap();
 
-        init_wallet_db(&mut db_data, None).unwrap();
+        init_wallet_db(&mut db_data, None, None).unwrap();
         let tx = db_data.conn.transaction().unwrap();
         let store =
             SqliteShardStore::<_, String, 3>::from_connection(&tx, T::TABLES_PREFIX).unwrap();
@@ -78,6 +78,7 @@ where
              AND recipient_key_scope IS NOT NULL
              AND nf IS NOT NULL
              AND commitment_tree_position IS NOT NULL
+             AND NOT rn.is_locked
              AND rn.id NOT IN (
                SELECT {table_prefix}_received_note_id
                FROM {table_prefix}_received_note_spends
@@ -163,6 +164,7 @@ where
                  AND recipient_key_scope IS NOT NULL
                  AND nf IS NOT NULL
                  AND commitment_tree_position IS NOT NULL
+                 AND NOT {table_prefix}_received_notes.is_locked
                  AND transactions.block <= :anchor_height
                  AND {table_prefix}_received_notes.id NOT IN rarray(:exclude)
                  AND {table_prefix}_received_notes.id NOT IN (
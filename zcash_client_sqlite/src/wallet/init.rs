@@ -1,5 +1,6 @@
 //! Functions for initializing the various databases.
 
+use std::collections::BTreeSet;
 use std::fmt;
 use std::rc::Rc;
 
@@ -24,6 +25,15 @@ use zcash_primitives::{consensus, transaction::components::amount::BalanceError}
 use super::commitment_tree;
 use crate::{error::SqliteClientError, WalletDb};
 
+#[cfg(feature = "sqlcipher")]
+use {
+    rusqlite::named_params,
+    secrecy::{ExposeSecret, SecretString},
+    std::fs,
+    std::io,
+    std::path::{Path, PathBuf},
+};
+
 mod migrations;
 
 #[derive(Debug)]
@@ -165,13 +175,23 @@ fn sqlite_client_error_to_wallet_migration_error(e: SqliteClientError) -> Wallet
         | SqliteClientError::KeyDerivationError(_)
         | SqliteClientError::AccountIdDiscontinuity
         | SqliteClientError::AccountIdOutOfRange
-        | SqliteClientError::CacheMiss(_) => {
+        | SqliteClientError::AccountNameConflict(_)
+        | SqliteClientError::CacheMiss(_)
+        | SqliteClientError::NoteNotFound(_)
+        | SqliteClientError::SnapshotIncompatible(_)
+        | SqliteClientError::SchemaOutdated(_) => {
             unreachable!("we only call WalletRead methods; mutations can't occur")
         }
         #[cfg(feature = "transparent-inputs")]
-        SqliteClientError::AddressNotRecognized(_) => {
+        SqliteClientError::AddressNotRecognized(_)
+        | SqliteClientError::UtxoNotFound(_)
+        | SqliteClientError::ReachedGapLimit(_, _) => {
             unreachable!("we only call WalletRead methods; mutations can't occur")
         }
+        #[cfg(feature = "sqlcipher")]
+        SqliteClientError::IncorrectKey => {
+            unreachable!("the database has already been opened when migrations are run")
+        }
         SqliteClientError::AccountUnknown => {
             unreachable!("all accounts are known in migration context")
         }
@@ -184,6 +204,102 @@ fn sqlite_client_error_to_wallet_migration_error(e: SqliteClientError) -> Wallet
     }
 }
 
+/// Returns the identifiers of the migrations required by this version of the library that have
+/// not been applied to the wallet database, in lexicographic order.
+pub(crate) fn missing_migrations<P: consensus::Parameters + 'static>(
+    conn: &rusqlite::Connection,
+    params: &P,
+) -> Result<Vec<Uuid>, rusqlite::Error> {
+    let mut missing = migrations::all_migrations(params, None, None)
+        .iter()
+        .map(|m| m.id())
+        .collect::<BTreeSet<_>>();
+
+    let initialized: bool = conn.query_row(
+        "SELECT EXISTS (
+            SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schemer_migrations'
+        )",
+        [],
+        |row| row.get(0),
+    )?;
+    if initialized {
+        let mut stmt = conn.prepare("SELECT id FROM schemer_migrations")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let id: Vec<u8> = row.get(0)?;
+            if let Ok(id) = Uuid::from_slice(&id) {
+                missing.remove(&id);
+            }
+        }
+    }
+
+    Ok(missing.into_iter().collect())
+}
+
+/// Applies the given passphrase to a newly opened connection to an encrypted database, and checks
+/// that it decrypts the database.
+#[cfg(feature = "sqlcipher")]
+pub(crate) fn apply_key(
+    conn: &rusqlite::Connection,
+    key: &SecretString,
+) -> Result<(), SqliteClientError> {
+    conn.pragma_update(None, "key", key.expose_secret())?;
+
+    // SQLCipher only attempts to decrypt the database once it is first read.
+    match conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| {
+        row.get::<_, i64>(0)
+    }) {
+        Ok(_) => Ok(()),
+        Err(rusqlite::Error::SqliteFailure(e, _))
+            if e.code == rusqlite::ErrorCode::NotADatabase =>
+        {
+            Err(SqliteClientError::IncorrectKey)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Encrypts the unencrypted wallet database at the given path in place, using the given
+/// passphrase.
+///
+/// The contents of the database are exported to a new encrypted database alongside it, which then
+/// replaces the original file. No other connection to the database may be open while this is in
+/// progress. Afterwards, the database must be opened with [`WalletDb::for_path_with_key`].
+#[cfg(feature = "sqlcipher")]
+pub fn encrypt_wallet_db<F: AsRef<Path>>(
+    path: F,
+    key: &SecretString,
+) -> Result<(), SqliteClientError> {
+    let path = path.as_ref();
+    let mut encrypted_path = path.as_os_str().to_owned();
+    encrypted_path.push("-encrypted");
+    let encrypted_path = PathBuf::from(encrypted_path);
+
+    // Remove any partial export left behind by an earlier, interrupted call.
+    if encrypted_path.exists() {
+        fs::remove_file(&encrypted_path)?;
+    }
+
+    {
+        let conn = rusqlite::Connection::open(path)?;
+        conn.execute(
+            "ATTACH DATABASE :path AS encrypted KEY :key",
+            named_params![
+                ":path": encrypted_path.to_str().ok_or_else(|| io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the wallet database path is not valid UTF-8",
+                ))?,
+                ":key": key.expose_secret(),
+            ],
+        )?;
+        conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+        conn.execute("DETACH DATABASE encrypted", [])?;
+    }
+
+    fs::rename(&encrypted_path, path)?;
+    Ok(())
+}
+
 /// Sets up the internal structure of the data database.
 ///
 /// This procedure will automatically perform migration operations to update the wallet database to
@@ -239,7 +355,7 @@ fn sqlite_client_error_to_wallet_migration_error(e: SqliteClientError) -> Wallet
 /// # let get_data_db_path = || data_file.path();
 /// # let load_seed = || -> Result<_, String> { Ok(SecretVec::new(vec![])) };
 /// let mut db = WalletDb::for_path(get_data_db_path(), Network::TestNetwork)?;
-/// match init_wallet_db(&mut db, None) {
+/// match init_wallet_db(&mut db, None, None) {
 ///     Err(e)
 ///         if matches!(
 ///             e.source().and_then(|e| e.downcast_ref()),
@@ -247,7 +363,7 @@ fn sqlite_client_error_to_wallet_migration_error(e: SqliteClientError) -> Wallet
 ///         ) =>
 ///     {
 ///         let seed = load_seed()?;
-///         init_wallet_db(&mut db, Some(seed))
+///         init_wallet_db(&mut db, None, Some(seed))
 ///     }
 ///     res => res,
 /// }?;
@@ -383,13 +499,25 @@ mod tests {
         let re = Regex::new(r"\s+").unwrap();
 
         let expected_tables = vec![
+            "CREATE TABLE account_metadata (
+                account_id INTEGER NOT NULL,
+                key TEXT NOT NULL,
+                value NOT NULL,
+                FOREIGN KEY (account_id) REFERENCES accounts(id),
+                CONSTRAINT account_metadata_key UNIQUE (account_id, key)
+            )",
+            "CREATE TABLE account_names (
+                account_id INTEGER NOT NULL PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                FOREIGN KEY (account_id) REFERENCES accounts(id)
+            )",
             r#"CREATE TABLE "accounts" (
                 id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
                 account_kind INTEGER NOT NULL DEFAULT 0,
                 hd_seed_fingerprint BLOB,
                 hd_account_index INTEGER,
                 ufvk TEXT,
-                uivk TEXT NOT NULL,
+                uivk TEXT,
                 orchard_fvk_item_cache BLOB,
                 sapling_fvk_item_cache BLOB,
                 p2pkh_fvk_item_cache BLOB,
@@ -403,12 +531,22 @@ mod tests {
                     AND hd_seed_fingerprint IS NOT NULL
                     AND hd_account_index IS NOT NULL
                     AND ufvk IS NOT NULL
+                    AND uivk IS NOT NULL
                   )
                   OR
                   (
                     account_kind = 1
                     AND hd_seed_fingerprint IS NULL
                     AND hd_account_index IS NULL
+                    AND uivk IS NOT NULL
+                  )
+                  OR
+                  (
+                    account_kind = 2
+                    AND hd_seed_fingerprint IS NULL
+                    AND hd_account_index IS NULL
+                    AND ufvk IS NULL
+                    AND uivk IS NULL
                   )
                 )
             )"#,
@@ -429,6 +567,12 @@ mod tests {
                 orchard_commitment_tree_size INTEGER,
                 sapling_output_count INTEGER,
                 orchard_action_count INTEGER)",
+            "CREATE TABLE exchange_rates (
+                currency TEXT NOT NULL,
+                fetched_at INTEGER NOT NULL,
+                rate TEXT NOT NULL,
+                PRIMARY KEY (currency, fetched_at)
+            )",
             "CREATE TABLE nullifier_map (
                 spend_pool INTEGER NOT NULL,
                 nf BLOB NOT NULL,
@@ -466,6 +610,8 @@ mod tests {
                 memo BLOB,
                 commitment_tree_position INTEGER,
                 recipient_key_scope INTEGER,
+                is_locked INTEGER NOT NULL DEFAULT 0,
+                memo_text TEXT,
                 FOREIGN KEY (tx) REFERENCES transactions(id_tx),
                 FOREIGN KEY (account_id) REFERENCES accounts(id),
                 CONSTRAINT tx_output UNIQUE (tx, action_index)
@@ -495,6 +641,14 @@ mod tests {
                 contains_marked INTEGER,
                 CONSTRAINT root_unique UNIQUE (root_hash)
             )",
+            "CREATE TABLE payment_drafts (
+                id INTEGER PRIMARY KEY,
+                account_id INTEGER NOT NULL,
+                request TEXT NOT NULL,
+                min_confirmations INTEGER NOT NULL,
+                note TEXT NOT NULL,
+                FOREIGN KEY (account_id) REFERENCES accounts(id)
+            )",
             "CREATE TABLE sapling_received_note_spends (
                 sapling_received_note_id INTEGER NOT NULL,
                 transaction_id INTEGER NOT NULL,
@@ -519,6 +673,8 @@ mod tests {
                 memo BLOB,
                 commitment_tree_position INTEGER,
                 recipient_key_scope INTEGER,
+                is_locked INTEGER NOT NULL DEFAULT 0,
+                memo_text TEXT,
                 FOREIGN KEY (tx) REFERENCES transactions(id_tx),
                 FOREIGN KEY (account_id) REFERENCES accounts(id),
                 CONSTRAINT tx_output UNIQUE (tx, output_index)
@@ -571,6 +727,7 @@ mod tests {
                 to_account_id INTEGER,
                 value INTEGER NOT NULL,
                 memo BLOB,
+                memo_text TEXT,
                 FOREIGN KEY (tx) REFERENCES transactions(id_tx),
                 FOREIGN KEY (from_account_id) REFERENCES accounts(id),
                 FOREIGN KEY (to_account_id) REFERENCES accounts(id),
@@ -589,9 +746,20 @@ mod tests {
                 tx_index INTEGER,
                 expiry_height INTEGER,
                 raw BLOB,
-                fee INTEGER,
+                fee INTEGER, orphaned_height INTEGER,
                 FOREIGN KEY (block) REFERENCES blocks(height)
             )",
+            r#"CREATE TABLE "transparent_addresses" (
+                account_id INTEGER NOT NULL,
+                key_scope INTEGER NOT NULL DEFAULT 0,
+                address_index INTEGER NOT NULL,
+                address TEXT NOT NULL,
+                is_reserved INTEGER NOT NULL DEFAULT 0,
+                first_use_height INTEGER,
+                FOREIGN KEY (account_id) REFERENCES accounts(id),
+                CONSTRAINT transparent_address_index UNIQUE (account_id, key_scope, address_index),
+                CONSTRAINT transparent_address UNIQUE (address)
+            )"#,
             "CREATE TABLE transparent_received_output_spends (
                 transparent_received_output_id INTEGER NOT NULL,
                 transaction_id INTEGER NOT NULL,
@@ -618,6 +786,7 @@ mod tests {
                 script BLOB NOT NULL,
                 value_zat INTEGER NOT NULL,
                 height INTEGER NOT NULL,
+                is_locked INTEGER NOT NULL DEFAULT 0,
                 FOREIGN KEY (received_by_account_id) REFERENCES accounts(id),
                 CONSTRAINT tx_outpoint UNIQUE (prevout_txid, prevout_idx)
             )"#,
@@ -1155,7 +1324,7 @@ mod tests {
 
         init_0_3_0(&mut db_data, &extfvk, account).unwrap();
         assert_matches!(
-            init_wallet_db(&mut db_data, Some(Secret::new(transparentkey.tovec())), Some(Secret::new(seed.to_vec()))),
+            init_wallet_db(&mut db_data, Some(Secret::new(transparentkey.to_vec())), Some(Secret::new(seed.to_vec()))),
             Ok(_)
         );
     }
@@ -1327,7 +1496,7 @@ mod tests {
 
         init_autoshielding(&mut db_data, &extfvk, account).unwrap();
         assert_matches!(
-            init_wallet_db(&mut db_data, Some(Secret::new(transparentkey.to_vect())), Some(Secret::new(seed.to_vec()))),
+            init_wallet_db(&mut db_data, Some(Secret::new(transparentkey.to_vec())), Some(Secret::new(seed.to_vec()))),
             Ok(_)
         );
     }
@@ -1513,23 +1682,23 @@ mod tests {
         let network = Network::MainNetwork;
         let data_file = NamedTempFile::new().unwrap();
         let mut db_data = WalletDb::for_path(data_file.path(), network).unwrap();
-        assert_matches!(init_wallet_db(&mut db_data, None), Ok(_));
+        assert_matches!(init_wallet_db(&mut db_data, None, None), Ok(_));
 
         // Prior to adding any accounts, every seed phrase is relevant to the wallet.
         let seed = test_vectors::UNIFIED[0].root_seed;
         let other_seed = [7; 32];
         assert_matches!(
-            init_wallet_db(&mut db_data, Some(Secret::new(seed.to_vec()))),
+            init_wallet_db(&mut db_data, None, Some(Secret::new(seed.to_vec()))),
             Ok(())
         );
         assert_matches!(
-            init_wallet_db(&mut db_data, Some(Secret::new(other_seed.to_vec()))),
+            init_wallet_db(&mut db_data, None, Some(Secret::new(other_seed.to_vec()))),
             Ok(())
         );
 
         let birthday = AccountBirthday::from_sapling_activation(&network, BlockHash([0; 32]));
         let (account_id, _usk) = db_data
-            .create_account(&Secret::new(seed.to_vec()), &birthday)
+            .create_account(&secrecy::SecretVec::new(vec![]), &Secret::new(seed.to_vec()), &birthday)
             .unwrap();
         assert_matches!(
             db_data.get_account(account_id),
@@ -1541,11 +1710,11 @@ mod tests {
 
         // After adding an account, only the real seed phrase is relevant to the wallet.
         assert_matches!(
-            init_wallet_db(&mut db_data, Some(Secret::new(seed.to_vec()))),
+            init_wallet_db(&mut db_data, None, Some(Secret::new(seed.to_vec()))),
             Ok(())
         );
         assert_matches!(
-            init_wallet_db(&mut db_data, Some(Secret::new(other_seed.to_vec()))),
+            init_wallet_db(&mut db_data, None, Some(Secret::new(other_seed.to_vec()))),
             Err(schemer::MigratorError::Adapter(
                 WalletMigrationError::SeedNotRelevant
             ))
@@ -1579,4 +1748,122 @@ mod tests {
             }
         }
     }
+
+    #[cfg(feature = "sqlcipher")]
+    mod sqlcipher {
+        use secrecy::{SecretString, SecretVec};
+        use tempfile::NamedTempFile;
+
+        use zcash_client_backend::data_api::{AccountBirthday, WalletRead, WalletWrite};
+        use zcash_primitives::{block::BlockHash, consensus::Network};
+
+        use crate::{error::SqliteClientError, wallet::init::encrypt_wallet_db, WalletDb};
+
+        use super::init_wallet_db;
+
+        fn key(passphrase: &str) -> SecretString {
+            SecretString::new(passphrase.to_owned())
+        }
+
+        /// Creates an account in the given wallet database, returning its identifier.
+        fn add_account(db_data: &mut WalletDb<rusqlite::Connection, Network>) -> crate::AccountId {
+            init_wallet_db(db_data, None, None).unwrap();
+            let birthday =
+                AccountBirthday::from_sapling_activation(&Network::TestNetwork, BlockHash([0; 32]));
+            let (account_id, _) = db_data
+                .create_account(
+                    &SecretVec::new(vec![]),
+                    &SecretVec::new(vec![0u8; 32]),
+                    &birthday,
+                )
+                .unwrap();
+            account_id
+        }
+
+        #[test]
+        fn open_with_wrong_key() {
+            let data_file = NamedTempFile::new().unwrap();
+            let account_id = {
+                let mut db_data =
+                    WalletDb::for_path_with_key(data_file.path(), Network::TestNetwork, &key("a"))
+                        .unwrap();
+                add_account(&mut db_data)
+            };
+
+            assert_matches!(
+                WalletDb::for_path_with_key(data_file.path(), Network::TestNetwork, &key("b"))
+                    .map(|_| ()),
+                Err(SqliteClientError::IncorrectKey)
+            );
+
+            // The database cannot be read without a key.
+            let db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork).unwrap();
+            assert_matches!(
+                db_data.get_account_ids(),
+                Err(SqliteClientError::DbError(_))
+            );
+
+            let db_data =
+                WalletDb::for_path_with_key(data_file.path(), Network::TestNetwork, &key("a"))
+                    .unwrap();
+            assert_eq!(db_data.get_account_ids().unwrap(), vec![account_id]);
+        }
+
+        #[test]
+        fn rekey() {
+            let data_file = NamedTempFile::new().unwrap();
+            let account_id = {
+                let mut db_data =
+                    WalletDb::for_path_with_key(data_file.path(), Network::TestNetwork, &key("a"))
+                        .unwrap();
+                let account_id = add_account(&mut db_data);
+                db_data.rekey(&key("b")).unwrap();
+                account_id
+            };
+
+            assert_matches!(
+                WalletDb::for_path_with_key(data_file.path(), Network::TestNetwork, &key("a"))
+                    .map(|_| ()),
+                Err(SqliteClientError::IncorrectKey)
+            );
+            let db_data =
+                WalletDb::for_path_with_key(data_file.path(), Network::TestNetwork, &key("b"))
+                    .unwrap();
+            assert_eq!(db_data.get_account_ids().unwrap(), vec![account_id]);
+        }
+
+        #[test]
+        fn encrypt_plaintext_wallet() {
+            let data_file = NamedTempFile::new().unwrap();
+            let account_id = {
+                let mut db_data =
+                    WalletDb::for_path(data_file.path(), Network::TestNetwork).unwrap();
+                add_account(&mut db_data)
+            };
+
+            // A plaintext database cannot be opened with a key.
+            assert_matches!(
+                WalletDb::for_path_with_key(data_file.path(), Network::TestNetwork, &key("a"))
+                    .map(|_| ()),
+                Err(SqliteClientError::IncorrectKey)
+            );
+
+            encrypt_wallet_db(data_file.path(), &key("a")).unwrap();
+
+            let db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork).unwrap();
+            assert_matches!(
+                db_data.get_account_ids(),
+                Err(SqliteClientError::DbError(_))
+            );
+
+            let mut db_data =
+                WalletDb::for_path_with_key(data_file.path(), Network::TestNetwork, &key("a"))
+                    .unwrap();
+            assert_eq!(db_data.get_account_ids().unwrap(), vec![account_id]);
+
+            // The migrations that were applied to the plaintext database are retained.
+            init_wallet_db(&mut db_data, None, None).unwrap();
+            assert_eq!(db_data.get_account_ids().unwrap(), vec![account_id]);
+        }
+    }
 }
@@ -1,18 +1,27 @@
+mod account_metadata;
 mod add_account_birthdays;
 mod add_transaction_views;
 mod add_utxo_account;
 mod addresses_table;
 mod ensure_orchard_ua_receiver;
+mod ephemeral_address_window;
+mod exchange_rates;
 mod full_account_ids;
 mod initial_setup;
+mod memo_search;
 mod nullifier_map;
 mod orchard_received_notes;
 mod orchard_shardtree;
+mod orphaned_transactions;
+mod payment_drafts;
 mod received_notes_nullable_nf;
 mod receiving_key_scopes;
 mod sapling_memo_consistency;
 mod sent_notes_to_internal;
 mod shardtree_support;
+mod spend_locks;
+mod standalone_transparent_accounts;
+mod transparent_address_window;
 mod ufvk_support;
 mod utxos_table;
 mod v_sapling_shard_unscanned_ranges;
@@ -66,6 +75,24 @@ pub(super) fn all_migrations<P: consensus::Parameters + 'static>(
     //                                             orchard_received_notes
     //                                                       |
     //                                           ensure_orchard_ua_receiver
+    //                                                       |
+    //                                                exchange_rates
+    //                                                       |
+    //                                                payment_drafts
+    //                                                       |
+    //                                                 spend_locks
+    //                                                       |
+    //                                                 memo_search
+    //                                                       |
+    //                                           transparent_address_window
+    //                                                       |
+    //                                               account_metadata
+    //                                                       |
+    //                                        standalone_transparent_accounts
+    //                                                       |
+    //                                            ephemeral_address_window
+    //                                                       |
+    //                                             orphaned_transactions
     vec![
         Box::new(initial_setup::Migration {}),
         Box::new(utxos_table::Migration {}),
@@ -117,5 +144,18 @@ pub(super) fn all_migrations<P: consensus::Parameters + 'static>(
         Box::new(ensure_orchard_ua_receiver::Migration {
             params: params.clone(),
         }),
+        Box::new(exchange_rates::Migration),
+        Box::new(payment_drafts::Migration),
+        Box::new(spend_locks::Migration),
+        Box::new(memo_search::Migration),
+        Box::new(transparent_address_window::Migration {
+            params: params.clone(),
+        }),
+        Box::new(account_metadata::Migration),
+        Box::new(standalone_transparent_accounts::Migration),
+        Box::new(ephemeral_address_window::Migration {
+            params: params.clone(),
+        }),
+        Box::new(orphaned_transactions::Migration),
     ]
 }
@@ -0,0 +1,134 @@
+//! This migration adds tables for storing a human-readable name and application-defined
+//! metadata for each account.
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+
+use crate::wallet::init::WalletMigrationError;
+
+use super::transparent_address_window;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0x5c6f0a3e_84d1_4b2f_9e37_a1c2d8f4e690);
+
+pub(super) struct Migration;
+
+impl schemer::Migration for Migration {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [transparent_address_window::MIGRATION_ID]
+            .into_iter()
+            .collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Adds tables for storing account names and metadata."
+    }
+}
+
+impl RusqliteMigration for Migration {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        // The metadata `value` column is declared without a type, so that text, integer and
+        // blob values are each stored and returned with their own storage class.
+        transaction.execute_batch(
+            "CREATE TABLE account_names (
+                account_id INTEGER NOT NULL PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                FOREIGN KEY (account_id) REFERENCES accounts(id)
+            );
+            CREATE TABLE account_metadata (
+                account_id INTEGER NOT NULL,
+                key TEXT NOT NULL,
+                value NOT NULL,
+                FOREIGN KEY (account_id) REFERENCES accounts(id),
+                CONSTRAINT account_metadata_key UNIQUE (account_id, key)
+            );",
+        )?;
+
+        Ok(())
+    }
+
+    fn down(&self, _transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        Err(WalletMigrationError::CannotRevert(MIGRATION_ID))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::named_params;
+    use tempfile::NamedTempFile;
+    use zcash_primitives::consensus::Network;
+
+    use crate::{
+        wallet::init::{
+            init_wallet_db, init_wallet_db_internal, migrations::transparent_address_window,
+        },
+        WalletDb,
+    };
+
+    #[test]
+    fn migrate_with_existing_account() {
+        let data_file = NamedTempFile::new().unwrap();
+        let mut db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork).unwrap();
+        init_wallet_db_internal(
+            &mut db_data,
+            None,
+            None,
+            &[transparent_address_window::MIGRATION_ID],
+            false,
+        )
+        .unwrap();
+
+        // Only the accounts' identifiers are of interest here, so we store imported accounts
+        // with placeholder viewing keys.
+        db_data
+            .conn
+            .execute_batch(
+                "INSERT INTO accounts (account_kind, uivk, birthday_height)
+                VALUES (1, 'uivk0', 0), (1, 'uivk1', 0);",
+            )
+            .unwrap();
+
+        init_wallet_db(&mut db_data, None, None).unwrap();
+        db_data
+            .conn
+            .execute("PRAGMA foreign_keys = ON", [])
+            .unwrap();
+
+        let insert_name = |account_id: u32, name: &str| {
+            db_data.conn.execute(
+                "INSERT INTO account_names (account_id, name) VALUES (:account_id, :name)",
+                named_params![":account_id": account_id, ":name": name],
+            )
+        };
+        insert_name(1, "Savings").unwrap();
+        // Each account has at most one name.
+        assert!(insert_name(1, "Spending").is_err());
+        // Names are unique across accounts.
+        assert!(insert_name(2, "Savings").is_err());
+        // Names may only be given to accounts that exist.
+        assert!(insert_name(3, "Spending").is_err());
+        insert_name(2, "Spending").unwrap();
+
+        db_data
+            .conn
+            .execute(
+                "INSERT INTO account_metadata (account_id, key, value) VALUES (1, 'color', 'blue')",
+                [],
+            )
+            .unwrap();
+        assert!(db_data
+            .conn
+            .execute(
+                "INSERT INTO account_metadata (account_id, key, value) VALUES (1, 'color', 'red')",
+                [],
+            )
+            .is_err());
+    }
+}
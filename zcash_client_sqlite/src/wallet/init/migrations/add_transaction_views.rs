@@ -305,9 +305,9 @@ mod tests {
         let network = Network::TestNetwork;
         let data_file = NamedTempFile::new().unwrap();
         let mut db_data = WalletDb::for_path(data_file.path(), network).unwrap();
-        init_wallet_db_internal(&mut db_data, None, &[addresses_table::MIGRATION_ID], false)
+        init_wallet_db_internal(&mut db_data, None, None, &[addresses_table::MIGRATION_ID], false)
             .unwrap();
-        let usk = UnifiedSpendingKey::from_seed(&network, &[0u8; 32][..], AccountId::ZERO).unwrap();
+        let usk = UnifiedSpendingKey::from_seed(&network, &[], &[0u8; 32][..], AccountId::ZERO).unwrap();
         let ufvk = usk.to_unified_full_viewing_key();
 
         db_data
@@ -337,7 +337,7 @@ mod tests {
             VALUES (0, 4, 0, '', 7, '', 'c', true, X'63');",
         ).unwrap();
 
-        init_wallet_db_internal(&mut db_data, None, &[super::MIGRATION_ID], false).unwrap();
+        init_wallet_db_internal(&mut db_data, None, None, &[super::MIGRATION_ID], false).unwrap();
 
         let mut q = db_data
             .conn
@@ -405,7 +405,7 @@ mod tests {
         let data_file = NamedTempFile::new().unwrap();
         let mut db_data = WalletDb::for_path(data_file.path(), network).unwrap();
         init_wallet_db_internal(
-            &mut db_data,
+            &mut db_data, None,
             None,
             &[utxos_table::MIGRATION_ID, ufvk_support::MIGRATION_ID],
             false,
@@ -440,7 +440,7 @@ mod tests {
         let mut tx_bytes = vec![];
         tx.write(&mut tx_bytes).unwrap();
 
-        let usk = UnifiedSpendingKey::from_seed(&network, &[0u8; 32][..], AccountId::ZERO).unwrap();
+        let usk = UnifiedSpendingKey::from_seed(&network, &[], &[0u8; 32][..], AccountId::ZERO).unwrap();
         let ufvk = usk.to_unified_full_viewing_key();
         let (ua, _) = ufvk
             .default_address(UnifiedAddressRequest::unsafe_new(
@@ -481,7 +481,7 @@ mod tests {
             )
             .unwrap();
 
-        init_wallet_db_internal(&mut db_data, None, &[super::MIGRATION_ID], false).unwrap();
+        init_wallet_db_internal(&mut db_data, None, None, &[super::MIGRATION_ID], false).unwrap();
 
         let fee = db_data
             .conn
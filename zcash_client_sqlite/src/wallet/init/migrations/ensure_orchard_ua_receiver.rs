@@ -113,7 +113,7 @@ mod tests {
         let seed = vec![0x10; 32];
         let account_id = 0u32;
         let ufvk = UnifiedSpendingKey::from_seed(
-            &db_data.params,
+            &db_data.params, &[],
             &seed,
             zip32::AccountId::try_from(account_id).unwrap(),
         )
@@ -122,7 +122,7 @@ mod tests {
 
         assert_matches!(
             init_wallet_db_internal(
-                &mut db_data,
+                &mut db_data, None,
                 Some(SecretVec::new(seed.clone())),
                 &[addresses_table::MIGRATION_ID],
                 false
@@ -180,7 +180,7 @@ mod tests {
         }
 
         assert_matches!(
-            init_wallet_db(&mut db_data, Some(SecretVec::new(seed))),
+            init_wallet_db(&mut db_data, None, Some(SecretVec::new(seed))),
             Ok(_)
         );
 
@@ -0,0 +1,265 @@
+//! This migration adds a key scope to the transparent addresses derived under the gap limit, so
+//! that the ZIP 320 ephemeral addresses of each account can be tracked with an address window of
+//! their own.
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+use zcash_primitives::consensus;
+
+use crate::wallet::init::WalletMigrationError;
+
+#[cfg(feature = "transparent-inputs")]
+use {
+    rusqlite::named_params,
+    zcash_client_backend::{encoding::AddressCodec, keys::UnifiedFullViewingKey},
+    zcash_primitives::legacy::keys::{EphemeralIvk, IncomingViewingKey, NonHardenedChildIndex},
+};
+
+use super::standalone_transparent_accounts;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0x6f446c0b_879a_42d5_bea0_553d014155e4);
+
+/// The BIP 44 change level of ZIP 320 ephemeral addresses.
+#[cfg(feature = "transparent-inputs")]
+const EPHEMERAL_KEY_SCOPE: u32 = 2;
+
+/// The number of unused addresses derived beyond the last used address of each account.
+///
+/// This is the default transparent gap limit at the time this migration was written.
+#[cfg(feature = "transparent-inputs")]
+const GAP_LIMIT: u32 = 20;
+
+pub(super) struct Migration<P> {
+    #[cfg_attr(not(feature = "transparent-inputs"), allow(dead_code))]
+    pub(super) params: P,
+}
+
+impl<P> schemer::Migration for Migration<P> {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [standalone_transparent_accounts::MIGRATION_ID]
+            .into_iter()
+            .collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Tracks ZIP 320 ephemeral addresses separately from external transparent addresses."
+    }
+}
+
+impl<P: consensus::Parameters> RusqliteMigration for Migration<P> {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        // The key scope of an address is its BIP 44 change level. All of the addresses derived
+        // so far are external addresses, which have key scope 0.
+        transaction.execute_batch(
+            "CREATE TABLE transparent_addresses_new (
+                account_id INTEGER NOT NULL,
+                key_scope INTEGER NOT NULL DEFAULT 0,
+                address_index INTEGER NOT NULL,
+                address TEXT NOT NULL,
+                is_reserved INTEGER NOT NULL DEFAULT 0,
+                first_use_height INTEGER,
+                FOREIGN KEY (account_id) REFERENCES accounts(id),
+                CONSTRAINT transparent_address_index UNIQUE (account_id, key_scope, address_index),
+                CONSTRAINT transparent_address UNIQUE (address)
+            );
+            INSERT INTO transparent_addresses_new
+                (account_id, key_scope, address_index, address, is_reserved, first_use_height)
+            SELECT account_id, 0, address_index, address, is_reserved, first_use_height
+            FROM transparent_addresses;
+
+            DROP TABLE transparent_addresses;
+            ALTER TABLE transparent_addresses_new RENAME TO transparent_addresses;",
+        )?;
+
+        // Derive the ephemeral address window for each existing account that has a full viewing
+        // key with an HD transparent component. The external windows were derived by an earlier
+        // migration.
+        #[cfg(feature = "transparent-inputs")]
+        {
+            let mut stmt_accounts =
+                transaction.prepare("SELECT id, ufvk FROM accounts WHERE ufvk IS NOT NULL")?;
+            let mut rows = stmt_accounts.query([])?;
+            while let Some(row) = rows.next()? {
+                let account_id: u32 = row.get(0)?;
+                let ufvk_str: String = row.get(1)?;
+                let ufvk = UnifiedFullViewingKey::decode(&self.params, &ufvk_str).map_err(|e| {
+                    WalletMigrationError::CorruptedData(format!("Unable to decode UFVK: {}", e))
+                })?;
+                if let Some(pubkey) = ufvk.transparent().filter(|pubkey| pubkey.is_bip44()) {
+                    let ivk = pubkey.derive_ephemeral_ivk().map_err(|e| {
+                        WalletMigrationError::CorruptedData(format!(
+                            "Unable to derive ephemeral IVK: {:?}",
+                            e
+                        ))
+                    })?;
+                    derive_address_window(transaction, &self.params, account_id, &ivk)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn down(&self, _transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        Err(WalletMigrationError::CannotRevert(MIGRATION_ID))
+    }
+}
+
+/// Derives the ephemeral addresses of the given account up to the gap limit beyond its last
+/// used ephemeral address, treating an address at which the wallet has received a UTXO as used.
+#[cfg(feature = "transparent-inputs")]
+fn derive_address_window<P: consensus::Parameters>(
+    transaction: &rusqlite::Transaction,
+    params: &P,
+    account_id: u32,
+    ivk: &EphemeralIvk,
+) -> Result<(), WalletMigrationError> {
+    let mut stmt_insert_address = transaction.prepare(
+        "INSERT INTO transparent_addresses
+            (account_id, key_scope, address_index, address, first_use_height)
+        SELECT :account_id, :key_scope, :address_index, :address, MIN(height)
+        FROM utxos
+        WHERE address = :address",
+    )?;
+
+    let mut next_index = 0u32;
+    loop {
+        let window_start: u32 = transaction.query_row(
+            "SELECT IFNULL(MAX(address_index) + 1, 0)
+            FROM transparent_addresses
+            WHERE account_id = :account_id
+            AND key_scope = :key_scope
+            AND first_use_height IS NOT NULL",
+            named_params![
+                ":account_id": account_id,
+                ":key_scope": EPHEMERAL_KEY_SCOPE,
+            ],
+            |row| row.get(0),
+        )?;
+        let window_end = window_start.saturating_add(GAP_LIMIT);
+        if next_index >= window_end {
+            return Ok(());
+        }
+
+        for index in next_index..window_end {
+            let child_index = match NonHardenedChildIndex::from_index(index) {
+                Some(child_index) => child_index,
+                // The space of non-hardened child indices has been exhausted.
+                None => return Ok(()),
+            };
+            // Per BIP 32, an index at which derivation fails is skipped.
+            if let Ok(address) = ivk.derive_address(child_index) {
+                stmt_insert_address.execute(named_params![
+                    ":account_id": account_id,
+                    ":key_scope": EPHEMERAL_KEY_SCOPE,
+                    ":address_index": index,
+                    ":address": address.encode(params),
+                ])?;
+            }
+        }
+        next_index = window_end;
+    }
+}
+
+#[cfg(all(test, feature = "transparent-inputs"))]
+mod tests {
+    use rusqlite::named_params;
+    use tempfile::NamedTempFile;
+    use zcash_client_backend::{encoding::AddressCodec, keys::UnifiedSpendingKey};
+    use zcash_primitives::{
+        consensus::Network,
+        legacy::keys::{IncomingViewingKey, NonHardenedChildIndex},
+    };
+
+    use crate::{
+        wallet::init::{init_wallet_db, init_wallet_db_internal, migrations::memo_search},
+        WalletDb,
+    };
+
+    #[test]
+    fn migrate_with_existing_account() {
+        let data_file = NamedTempFile::new().unwrap();
+        let mut db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork).unwrap();
+
+        // Start from the schema that precedes the tracking of transparent addresses.
+        init_wallet_db_internal(
+            &mut db_data,
+            None,
+            None,
+            &[memo_search::MIGRATION_ID],
+            false,
+        )
+        .unwrap();
+
+        // Store an imported account whose full viewing key has an HD transparent component.
+        let ufvk = UnifiedSpendingKey::from_seed(
+            &db_data.params,
+            &[],
+            &[0x10; 32],
+            zip32::AccountId::ZERO,
+        )
+        .unwrap()
+        .to_unified_full_viewing_key();
+        db_data
+            .conn
+            .execute(
+                "INSERT INTO accounts (account_kind, ufvk, uivk, birthday_height)
+                VALUES (1, :ufvk, :uivk, 0)",
+                named_params![
+                    ":ufvk": ufvk.encode(&db_data.params),
+                    ":uivk": ufvk.to_unified_incoming_viewing_key().encode(&db_data.params),
+                ],
+            )
+            .unwrap();
+
+        init_wallet_db(&mut db_data, None, None).unwrap();
+
+        // Both the external and the ephemeral windows of the account have been derived.
+        let addresses_in_scope = |key_scope: u32| {
+            db_data
+                .conn
+                .prepare(
+                    "SELECT address
+                    FROM transparent_addresses
+                    WHERE account_id = 1
+                    AND key_scope = :key_scope
+                    ORDER BY address_index",
+                )
+                .unwrap()
+                .query_map(named_params![":key_scope": key_scope], |row| row.get(0))
+                .unwrap()
+                .collect::<Result<Vec<String>, _>>()
+                .unwrap()
+        };
+        let pubkey = ufvk.transparent().unwrap();
+        let external_ivk = pubkey.derive_external_ivk().unwrap();
+        let ephemeral_ivk = pubkey.derive_ephemeral_ivk().unwrap();
+        let derive = |ivk: &dyn Fn(NonHardenedChildIndex) -> String| {
+            (0..20)
+                .map(|i| ivk(NonHardenedChildIndex::from_index(i).unwrap()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            addresses_in_scope(0),
+            derive(&|i| external_ivk
+                .derive_address(i)
+                .unwrap()
+                .encode(&db_data.params))
+        );
+        assert_eq!(
+            addresses_in_scope(2),
+            derive(&|i| ephemeral_ivk
+                .derive_address(i)
+                .unwrap()
+                .encode(&db_data.params))
+        );
+    }
+}
@@ -0,0 +1,105 @@
+//! This migration adds a table for storing exchange rate observations fetched by the wallet.
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+
+use crate::wallet::init::WalletMigrationError;
+
+use super::ensure_orchard_ua_receiver;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0xceb6b247_e400_4afb_850c_e1e6e67208d5);
+
+pub(super) struct Migration;
+
+impl schemer::Migration for Migration {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [ensure_orchard_ua_receiver::MIGRATION_ID]
+            .into_iter()
+            .collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Adds a table for storing fetched exchange rates."
+    }
+}
+
+impl RusqliteMigration for Migration {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        // `fetched_at` is stored as seconds since the Unix epoch, and `rate` as the
+        // canonical string encoding of a decimal so that no precision is lost.
+        transaction.execute_batch(
+            "CREATE TABLE exchange_rates (
+                currency TEXT NOT NULL,
+                fetched_at INTEGER NOT NULL,
+                rate TEXT NOT NULL,
+                PRIMARY KEY (currency, fetched_at)
+            );",
+        )?;
+
+        Ok(())
+    }
+
+    fn down(&self, _transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        Err(WalletMigrationError::CannotRevert(MIGRATION_ID))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::named_params;
+    use tempfile::NamedTempFile;
+    use zcash_primitives::consensus::Network;
+
+    use crate::{
+        wallet::init::{init_wallet_db_internal, migrations::ensure_orchard_ua_receiver},
+        WalletDb,
+    };
+
+    #[test]
+    fn migrate_and_store_rates() {
+        let data_file = NamedTempFile::new().unwrap();
+        let mut db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork).unwrap();
+        init_wallet_db_internal(
+            &mut db_data,
+            None,
+            None,
+            &[ensure_orchard_ua_receiver::MIGRATION_ID],
+            false,
+        )
+        .unwrap();
+        init_wallet_db_internal(&mut db_data, None, None, &[super::MIGRATION_ID], false).unwrap();
+
+        let insert_rate = |currency: &str, fetched_at: i64, rate: &str| {
+            db_data.conn.execute(
+                "INSERT INTO exchange_rates (currency, fetched_at, rate)
+                VALUES (:currency, :fetched_at, :rate)",
+                named_params![":currency": currency, ":fetched_at": fetched_at, ":rate": rate],
+            )
+        };
+        insert_rate("USD", 1_700_000_000, "27.123456789012345678").unwrap();
+        insert_rate("USD", 1_700_000_060, "27.2").unwrap();
+        insert_rate("EUR", 1_700_000_000, "25.1").unwrap();
+        // At most one observation is stored per currency and time.
+        assert!(insert_rate("USD", 1_700_000_000, "28").is_err());
+
+        // Rates are stored without loss of precision.
+        let rate: String = db_data
+            .conn
+            .query_row(
+                "SELECT rate FROM exchange_rates
+                WHERE currency = 'USD' AND fetched_at = 1700000000",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(rate, "27.123456789012345678");
+    }
+}
@@ -0,0 +1,193 @@
+//! This migration adds a `memo_text` column to each table that stores memos, holding a
+//! searchable representation of text memos, and populates it for existing rows.
+
+use std::collections::HashSet;
+
+use rusqlite::named_params;
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+use zcash_primitives::memo::{Memo, MemoBytes};
+
+use crate::wallet::init::WalletMigrationError;
+
+use super::spend_locks;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0x79d59ab2_d965_42e7_8de5_8cdc074cc227);
+
+const MEMO_TABLES: [&str; 3] = [
+    "sapling_received_notes",
+    "orchard_received_notes",
+    "sent_notes",
+];
+
+pub(super) struct Migration;
+
+impl schemer::Migration for Migration {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [spend_locks::MIGRATION_ID].into_iter().collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Adds a searchable text column for memos, and populates it for existing notes."
+    }
+}
+
+impl RusqliteMigration for Migration {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        for table in MEMO_TABLES {
+            transaction
+                .execute_batch(&format!("ALTER TABLE {table} ADD COLUMN memo_text TEXT;"))?;
+
+            let mut stmt_memos = transaction.prepare(&format!(
+                "SELECT id, memo FROM {table} WHERE memo IS NOT NULL"
+            ))?;
+            let mut stmt_set_text = transaction.prepare(&format!(
+                "UPDATE {table} SET memo_text = :memo_text WHERE id = :id"
+            ))?;
+
+            let mut rows = stmt_memos.query([])?;
+            while let Some(row) = rows.next()? {
+                let id: i64 = row.get(0)?;
+                let memo_bytes: Vec<u8> = row.get(1)?;
+                let memo = MemoBytes::from_bytes(&memo_bytes).map_err(|e| {
+                    WalletMigrationError::CorruptedData(format!(
+                        "Invalid memo data in {} row {}: {:?}",
+                        table, id, e
+                    ))
+                })?;
+
+                if let Some(memo_text) = memo_search_text(&memo) {
+                    stmt_set_text.execute(named_params![
+                        ":memo_text": memo_text,
+                        ":id": id,
+                    ])?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn down(&self, _transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        Err(WalletMigrationError::CannotRevert(MIGRATION_ID))
+    }
+}
+
+/// Returns the searchable representation of a memo, as defined at the time of this migration:
+/// the lowercased text of a text memo, or `None` for any other memo.
+fn memo_search_text(memo: &MemoBytes) -> Option<String> {
+    match Memo::try_from(memo) {
+        Ok(Memo::Text(text)) => Some(text.to_lowercase()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rusqlite::named_params;
+    use tempfile::NamedTempFile;
+    use zcash_primitives::{
+        consensus::Network,
+        memo::{Memo, MemoBytes},
+    };
+
+    use crate::{
+        wallet::{
+            init::{init_wallet_db, init_wallet_db_internal, migrations::spend_locks},
+            memo_repr,
+        },
+        WalletDb,
+    };
+
+    #[test]
+    fn memo_text_backfilled() {
+        let data_file = NamedTempFile::new().unwrap();
+        let mut db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork).unwrap();
+        init_wallet_db_internal(
+            &mut db_data,
+            None,
+            None,
+            &[spend_locks::MIGRATION_ID],
+            false,
+        )
+        .unwrap();
+
+        let text_memo = MemoBytes::from(Memo::from_str("Rechnung Nr. 42 für das Café").unwrap());
+        // A memo with a leading byte in the text range that is not valid UTF-8.
+        let mut invalid_bytes = [0u8; 512];
+        invalid_bytes[..3].copy_from_slice(&[0xf4, 0x90, 0x80]);
+        let invalid_memo = MemoBytes::from_bytes(&invalid_bytes).unwrap();
+        let arbitrary_memo = MemoBytes::from_bytes(&[0xff, 1, 2, 3]).unwrap();
+        let empty_memo = MemoBytes::empty();
+
+        // Only the memo columns are of interest here, so we skip creating the accounts and
+        // transactions that the notes would otherwise refer to.
+        db_data
+            .conn
+            .execute("PRAGMA foreign_keys = OFF", [])
+            .unwrap();
+        let memos = [
+            Some(&text_memo),
+            Some(&invalid_memo),
+            Some(&arbitrary_memo),
+            Some(&empty_memo),
+            None,
+        ];
+        for (i, memo) in memos.iter().enumerate() {
+            db_data
+                .conn
+                .execute(
+                    "INSERT INTO sapling_received_notes
+                    (tx, output_index, account_id, diversifier, value, rcm, is_change, memo)
+                    VALUES (0, :output_index, 0, x'', 1, x'', 0, :memo)",
+                    named_params![":output_index": i, ":memo": memo_repr(*memo)],
+                )
+                .unwrap();
+        }
+        db_data
+            .conn
+            .execute(
+                "INSERT INTO sent_notes
+                (tx, output_pool, output_index, from_account_id, to_address, value, memo)
+                VALUES (0, 2, 0, 0, 'address', 1, :memo)",
+                named_params![":memo": memo_repr(Some(&text_memo))],
+            )
+            .unwrap();
+
+        init_wallet_db(&mut db_data, None, None).unwrap();
+
+        let mut stmt = db_data
+            .conn
+            .prepare("SELECT memo_text FROM sapling_received_notes ORDER BY output_index")
+            .unwrap();
+        let received_texts = stmt
+            .query_map([], |row| row.get::<_, Option<String>>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            received_texts,
+            vec![
+                Some("rechnung nr. 42 für das café".to_owned()),
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+
+        let sent_text: Option<String> = db_data
+            .conn
+            .query_row("SELECT memo_text FROM sent_notes", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(sent_text.as_deref(), Some("rechnung nr. 42 für das café"));
+    }
+}
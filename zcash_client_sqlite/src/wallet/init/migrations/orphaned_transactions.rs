@@ -0,0 +1,47 @@
+//! This migration adds a column to the transactions table that records the height at which a
+//! transaction had been mined, for transactions that were un-mined by a truncation of the
+//! wallet.
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+
+use crate::wallet::init::WalletMigrationError;
+
+use super::ephemeral_address_window;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0xef462213_bc9e_4481_884b_f0f304a64a35);
+
+pub(super) struct Migration;
+
+impl schemer::Migration for Migration {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [ephemeral_address_window::MIGRATION_ID]
+            .into_iter()
+            .collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Records the height at which transactions orphaned by a chain reorg had been mined."
+    }
+}
+
+impl RusqliteMigration for Migration {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        transaction
+            .execute_batch("ALTER TABLE transactions ADD COLUMN orphaned_height INTEGER;")?;
+
+        Ok(())
+    }
+
+    fn down(&self, _transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        Err(WalletMigrationError::CannotRevert(MIGRATION_ID))
+    }
+}
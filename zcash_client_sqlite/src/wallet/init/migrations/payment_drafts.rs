@@ -0,0 +1,107 @@
+//! This migration adds a table for storing draft payments that the user has composed but not
+//! yet confirmed.
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+
+use crate::wallet::init::WalletMigrationError;
+
+use super::exchange_rates;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0x48d7f990_65db_49f3_8507_f5b0c88a75b2);
+
+pub(super) struct Migration;
+
+impl schemer::Migration for Migration {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [exchange_rates::MIGRATION_ID].into_iter().collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Adds a table for storing draft payments."
+    }
+}
+
+impl RusqliteMigration for Migration {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        // The payment request is stored as a ZIP 321 URI. No proposal is stored, because
+        // inputs are always selected afresh when a draft is promoted to a proposal.
+        transaction.execute_batch(
+            "CREATE TABLE payment_drafts (
+                id INTEGER PRIMARY KEY,
+                account_id INTEGER NOT NULL,
+                request TEXT NOT NULL,
+                min_confirmations INTEGER NOT NULL,
+                note TEXT NOT NULL,
+                FOREIGN KEY (account_id) REFERENCES accounts(id)
+            );",
+        )?;
+
+        Ok(())
+    }
+
+    fn down(&self, _transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        Err(WalletMigrationError::CannotRevert(MIGRATION_ID))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::named_params;
+    use tempfile::NamedTempFile;
+    use zcash_primitives::consensus::Network;
+
+    use crate::{
+        wallet::init::{init_wallet_db_internal, migrations::exchange_rates},
+        WalletDb,
+    };
+
+    #[test]
+    fn migrate_with_existing_account() {
+        let data_file = NamedTempFile::new().unwrap();
+        let mut db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork).unwrap();
+        init_wallet_db_internal(
+            &mut db_data,
+            None,
+            None,
+            &[exchange_rates::MIGRATION_ID],
+            false,
+        )
+        .unwrap();
+
+        // Only the account's identifier is of interest here, so we store an imported account
+        // with a placeholder viewing key.
+        db_data
+            .conn
+            .execute_batch(
+                "INSERT INTO accounts (account_kind, uivk, birthday_height)
+                VALUES (1, 'uivk0', 0);",
+            )
+            .unwrap();
+
+        init_wallet_db_internal(&mut db_data, None, None, &[super::MIGRATION_ID], false).unwrap();
+        db_data
+            .conn
+            .execute("PRAGMA foreign_keys = ON", [])
+            .unwrap();
+
+        let insert_draft = |account_id: u32| {
+            db_data.conn.execute(
+                "INSERT INTO payment_drafts (account_id, request, min_confirmations, note)
+                VALUES (:account_id, 'zcash:?amount=1', 10, 'rent')",
+                named_params![":account_id": account_id],
+            )
+        };
+        insert_draft(1).unwrap();
+        // Drafts may only be stored for accounts that exist.
+        assert!(insert_draft(2).is_err());
+    }
+}
@@ -239,7 +239,7 @@ mod tests {
         let data_file = NamedTempFile::new().unwrap();
         let mut db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork).unwrap();
         init_wallet_db_internal(
-            &mut db_data,
+            &mut db_data, None,
             None,
             &[v_transactions_net::MIGRATION_ID],
             false,
@@ -247,7 +247,7 @@ mod tests {
         .unwrap();
 
         // Create an account in the wallet
-        let usk0 = UnifiedSpendingKey::from_seed(&db_data.params, &[0u8; 32][..], AccountId::ZERO)
+        let usk0 = UnifiedSpendingKey::from_seed(&db_data.params, &[], &[0u8; 32][..], AccountId::ZERO)
             .unwrap();
         let ufvk0 = usk0.to_unified_full_viewing_key();
         db_data
@@ -269,7 +269,7 @@ mod tests {
             VALUES (0, 3, 0, '', 5, '', 'nf_b', false);").unwrap();
 
         // Apply the current migration
-        init_wallet_db_internal(&mut db_data, None, &[super::MIGRATION_ID], false).unwrap();
+        init_wallet_db_internal(&mut db_data, None, None, &[super::MIGRATION_ID], false).unwrap();
 
         {
             let mut q = db_data
@@ -332,7 +332,7 @@ mod tests {
     ) -> (UnifiedFullViewingKey, BlockHeight, BuildResult) {
         // Create an account in the wallet
         let usk0 =
-            UnifiedSpendingKey::from_seed(&db_data.params, &[0u8; 32][..], zip32::AccountId::ZERO)
+            UnifiedSpendingKey::from_seed(&db_data.params, &[], &[0u8; 32][..], zip32::AccountId::ZERO)
                 .unwrap();
         let ufvk0 = usk0.to_unified_full_viewing_key();
         let height = db_data
@@ -477,7 +477,7 @@ mod tests {
         let data_file = NamedTempFile::new().unwrap();
         let mut db_data = WalletDb::for_path(data_file.path(), params).unwrap();
         init_wallet_db_internal(
-            &mut db_data,
+            &mut db_data, None,
             None,
             &[
                 add_account_birthdays::MIGRATION_ID,
@@ -538,7 +538,7 @@ mod tests {
             .unwrap();
 
         // Apply the current migration
-        init_wallet_db_internal(&mut db_data, None, &[super::MIGRATION_ID], false).unwrap();
+        init_wallet_db_internal(&mut db_data, None, None, &[super::MIGRATION_ID], false).unwrap();
 
         // There should be two rows in the `sapling_received_notes` table with correct scopes.
         let mut q = db_data
@@ -576,7 +576,7 @@ mod tests {
         let data_file = NamedTempFile::new().unwrap();
         let mut db_data = WalletDb::for_path(data_file.path(), params).unwrap();
         init_wallet_db_internal(
-            &mut db_data,
+            &mut db_data, None,
             None,
             &[
                 wallet_summaries::MIGRATION_ID,
@@ -716,7 +716,7 @@ mod tests {
             .unwrap();
 
         // Apply the current migration
-        init_wallet_db_internal(&mut db_data, None, &[super::MIGRATION_ID], false).unwrap();
+        init_wallet_db_internal(&mut db_data, None, None, &[super::MIGRATION_ID], false).unwrap();
 
         // There should be two rows in the `sapling_received_notes` table with correct scopes.
         let mut q = db_data
@@ -0,0 +1,47 @@
+//! This migration adds a flag to each received note and UTXO table recording whether the output
+//! has been locked against spending.
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+
+use crate::wallet::init::WalletMigrationError;
+
+use super::payment_drafts;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0x527a6f66_4c08_458f_a7b1_1f29075af80c);
+
+pub(super) struct Migration;
+
+impl schemer::Migration for Migration {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [payment_drafts::MIGRATION_ID].into_iter().collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Adds support for locking received notes and UTXOs against spending."
+    }
+}
+
+impl RusqliteMigration for Migration {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        transaction.execute_batch(
+            "ALTER TABLE sapling_received_notes ADD COLUMN is_locked INTEGER NOT NULL DEFAULT 0;
+             ALTER TABLE orchard_received_notes ADD COLUMN is_locked INTEGER NOT NULL DEFAULT 0;
+             ALTER TABLE utxos ADD COLUMN is_locked INTEGER NOT NULL DEFAULT 0;",
+        )?;
+
+        Ok(())
+    }
+
+    fn down(&self, _transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        Err(WalletMigrationError::CannotRevert(MIGRATION_ID))
+    }
+}
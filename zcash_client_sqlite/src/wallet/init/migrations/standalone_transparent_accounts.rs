@@ -0,0 +1,187 @@
+//! This migration rebuilds the `accounts` table to support standalone transparent accounts,
+//! which track a single imported transparent address and have no viewing keys.
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+use zcash_client_backend::data_api::AccountSource;
+use zip32::fingerprint::SeedFingerprint;
+
+use crate::wallet::{account_kind_code, init::WalletMigrationError};
+
+use super::account_metadata;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0x8e1d4b7a_3c52_4f09_b6a1_5d2e9c7f0b34);
+
+pub(super) struct Migration;
+
+impl schemer::Migration for Migration {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [account_metadata::MIGRATION_ID].into_iter().collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Allows accounts without viewing keys that track a standalone transparent address."
+    }
+}
+
+impl RusqliteMigration for Migration {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        let account_kind_derived = account_kind_code(AccountSource::Derived {
+            seed_fingerprint: SeedFingerprint::from_bytes([0; 32]),
+            account_index: zip32::AccountId::ZERO,
+        });
+        let account_kind_imported = account_kind_code(AccountSource::Imported);
+        let account_kind_standalone = account_kind_code(AccountSource::StandaloneTransparent);
+        // `PRAGMA foreign_keys` has no effect within a transaction, so replacing the `accounts`
+        // table relies on `init_wallet_db_internal` having disabled foreign key enforcement
+        // before running the migrations.
+        transaction.execute_batch(&format!(
+            r#"
+            PRAGMA legacy_alter_table = ON;
+
+            CREATE TABLE accounts_new (
+                id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+                account_kind INTEGER NOT NULL DEFAULT {account_kind_derived},
+                hd_seed_fingerprint BLOB,
+                hd_account_index INTEGER,
+                ufvk TEXT,
+                uivk TEXT,
+                orchard_fvk_item_cache BLOB,
+                sapling_fvk_item_cache BLOB,
+                p2pkh_fvk_item_cache BLOB,
+                birthday_height INTEGER NOT NULL,
+                birthday_sapling_tree_size INTEGER,
+                birthday_orchard_tree_size INTEGER,
+                recover_until_height INTEGER,
+                CHECK (
+                  (
+                    account_kind = {account_kind_derived}
+                    AND hd_seed_fingerprint IS NOT NULL
+                    AND hd_account_index IS NOT NULL
+                    AND ufvk IS NOT NULL
+                    AND uivk IS NOT NULL
+                  )
+                  OR
+                  (
+                    account_kind = {account_kind_imported}
+                    AND hd_seed_fingerprint IS NULL
+                    AND hd_account_index IS NULL
+                    AND uivk IS NOT NULL
+                  )
+                  OR
+                  (
+                    account_kind = {account_kind_standalone}
+                    AND hd_seed_fingerprint IS NULL
+                    AND hd_account_index IS NULL
+                    AND ufvk IS NULL
+                    AND uivk IS NULL
+                  )
+                )
+            );
+            INSERT INTO accounts_new (
+                id, account_kind, hd_seed_fingerprint, hd_account_index,
+                ufvk, uivk,
+                orchard_fvk_item_cache, sapling_fvk_item_cache, p2pkh_fvk_item_cache,
+                birthday_height, birthday_sapling_tree_size, birthday_orchard_tree_size,
+                recover_until_height
+            )
+            SELECT
+                id, account_kind, hd_seed_fingerprint, hd_account_index,
+                ufvk, uivk,
+                orchard_fvk_item_cache, sapling_fvk_item_cache, p2pkh_fvk_item_cache,
+                birthday_height, birthday_sapling_tree_size, birthday_orchard_tree_size,
+                recover_until_height
+            FROM accounts;
+
+            DROP TABLE accounts;
+            CREATE UNIQUE INDEX hd_account ON accounts_new (hd_seed_fingerprint, hd_account_index);
+            CREATE UNIQUE INDEX accounts_uivk ON accounts_new (uivk);
+            CREATE UNIQUE INDEX accounts_ufvk ON accounts_new (ufvk);
+            ALTER TABLE accounts_new RENAME TO accounts;
+
+            PRAGMA legacy_alter_table = OFF;
+            "#
+        ))?;
+
+        Ok(())
+    }
+
+    fn down(&self, _transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        Err(WalletMigrationError::CannotRevert(MIGRATION_ID))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+    use zcash_primitives::consensus::Network;
+
+    use crate::{
+        wallet::init::{init_wallet_db, init_wallet_db_internal, migrations::account_metadata},
+        WalletDb,
+    };
+
+    #[test]
+    fn migrate_with_existing_account() {
+        let data_file = NamedTempFile::new().unwrap();
+        let mut db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork).unwrap();
+        init_wallet_db_internal(
+            &mut db_data,
+            None,
+            None,
+            &[account_metadata::MIGRATION_ID],
+            false,
+        )
+        .unwrap();
+
+        // Only the account rows are of interest here, so we store an imported account with a
+        // placeholder viewing key.
+        db_data
+            .conn
+            .execute(
+                "INSERT INTO accounts (account_kind, uivk, birthday_height)
+                VALUES (1, 'uivk', 0)",
+                [],
+            )
+            .unwrap();
+        db_data
+            .conn
+            .execute(
+                "INSERT INTO account_names (account_id, name) VALUES (1, 'Savings')",
+                [],
+            )
+            .unwrap();
+
+        init_wallet_db(&mut db_data, None, None).unwrap();
+
+        // The existing account is preserved.
+        let (id, uivk): (u32, String) = db_data
+            .conn
+            .query_row("SELECT id, uivk FROM accounts", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((id, uivk.as_str()), (1, "uivk"));
+
+        // Standalone transparent accounts have no viewing keys, and other accounts must have
+        // at least a UIVK.
+        let insert = |account_kind: u32, uivk: Option<&str>| {
+            db_data.conn.execute(
+                "INSERT INTO accounts (account_kind, uivk, birthday_height)
+                VALUES (?, ?, 0)",
+                rusqlite::params![account_kind, uivk],
+            )
+        };
+        assert!(insert(2, None).is_ok());
+        assert!(insert(2, Some("uivk2")).is_err());
+        assert!(insert(1, None).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+//! This migration adds a table that tracks the external transparent addresses derived for each
+//! account under the wallet's gap limit, along with the height at which each address was first
+//! observed to have received funds.
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+use zcash_primitives::consensus;
+
+use crate::wallet::init::WalletMigrationError;
+
+#[cfg(feature = "transparent-inputs")]
+use {
+    rusqlite::named_params,
+    zcash_address::unified::{Container, Encoding, Ivk, Uivk},
+    zcash_client_backend::encoding::AddressCodec,
+    zcash_primitives::legacy::keys::{ExternalIvk, IncomingViewingKey, NonHardenedChildIndex},
+};
+
+use super::memo_search;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0x2b3c81cf_9e2a_4d6b_a3f0_6d1e57c0b8a4);
+
+/// The number of unused addresses derived beyond the last used address of each account.
+///
+/// This is the default transparent gap limit at the time this migration was written.
+#[cfg(feature = "transparent-inputs")]
+const GAP_LIMIT: u32 = 20;
+
+pub(super) struct Migration<P> {
+    #[cfg_attr(not(feature = "transparent-inputs"), allow(dead_code))]
+    pub(super) params: P,
+}
+
+impl<P> schemer::Migration for Migration<P> {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [memo_search::MIGRATION_ID].into_iter().collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Adds a table tracking the usage of transparent addresses derived under the gap limit."
+    }
+}
+
+impl<P: consensus::Parameters> RusqliteMigration for Migration<P> {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        transaction.execute_batch(
+            "CREATE TABLE transparent_addresses (
+                account_id INTEGER NOT NULL,
+                address_index INTEGER NOT NULL,
+                address TEXT NOT NULL,
+                is_reserved INTEGER NOT NULL DEFAULT 0,
+                first_use_height INTEGER,
+                FOREIGN KEY (account_id) REFERENCES accounts(id),
+                CONSTRAINT transparent_address_index UNIQUE (account_id, address_index),
+                CONSTRAINT transparent_address UNIQUE (address)
+            );",
+        )?;
+
+        // Derive the address window for each existing account, taking into account the UTXOs
+        // that the wallet has already received.
+        #[cfg(feature = "transparent-inputs")]
+        {
+            let mut stmt_accounts = transaction.prepare("SELECT id, uivk FROM accounts")?;
+            let mut rows = stmt_accounts.query([])?;
+            while let Some(row) = rows.next()? {
+                let account_id: u32 = row.get(0)?;
+                let uivk_str: String = row.get(1)?;
+                if let Some(ivk) = external_ivk(&self.params, &uivk_str)? {
+                    derive_address_window(transaction, &self.params, account_id, &ivk)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn down(&self, _transaction: &rusqlite::Transaction) -> Result<(), Self::Error> {
+        Err(WalletMigrationError::CannotRevert(MIGRATION_ID))
+    }
+}
+
+/// Returns the external transparent incoming viewing key in the given encoded UIVK, unless it
+/// has none or only a single legacy (non-HD) transparent key.
+#[cfg(feature = "transparent-inputs")]
+fn external_ivk<P: consensus::Parameters>(
+    params: &P,
+    uivk_str: &str,
+) -> Result<Option<ExternalIvk>, WalletMigrationError> {
+    let (network, uivk) = Uivk::decode(uivk_str)
+        .map_err(|e| WalletMigrationError::CorruptedData(format!("Unable to parse UIVK: {e}")))?;
+    if params.network_type() != network {
+        return Err(WalletMigrationError::CorruptedData(
+            "Network type mismatch".to_owned(),
+        ));
+    }
+
+    for item in uivk.items() {
+        if let Ivk::P2pkh(tivk_bytes) = item {
+            // Legacy keys are stored with an all-zero chain code, and have only the one address.
+            if tivk_bytes[..32].iter().all(|b| *b == 0) {
+                return Ok(None);
+            }
+            return ExternalIvk::deserialize(&tivk_bytes)
+                .map(Some)
+                .map_err(|e| {
+                    WalletMigrationError::CorruptedData(format!(
+                        "Unable to parse transparent IVK: {:?}",
+                        e
+                    ))
+                });
+        }
+    }
+
+    Ok(None)
+}
+
+/// Derives the external transparent addresses of the given account up to the gap limit beyond
+/// its last used address, treating an address at which the wallet has received a UTXO as used.
+#[cfg(feature = "transparent-inputs")]
+fn derive_address_window<P: consensus::Parameters>(
+    transaction: &rusqlite::Transaction,
+    params: &P,
+    account_id: u32,
+    ivk: &ExternalIvk,
+) -> Result<(), WalletMigrationError> {
+    let mut stmt_insert_address = transaction.prepare(
+        "INSERT INTO transparent_addresses
+            (account_id, address_index, address, first_use_height)
+        SELECT :account_id, :address_index, :address, MIN(height)
+        FROM utxos
+        WHERE address = :address",
+    )?;
+
+    let mut next_index = 0u32;
+    loop {
+        let window_start: u32 = transaction.query_row(
+            "SELECT IFNULL(MAX(address_index) + 1, 0)
+            FROM transparent_addresses
+            WHERE account_id = :account_id
+            AND first_use_height IS NOT NULL",
+            named_params![":account_id": account_id],
+            |row| row.get(0),
+        )?;
+        let window_end = window_start.saturating_add(GAP_LIMIT);
+        if next_index >= window_end {
+            return Ok(());
+        }
+
+        for index in next_index..window_end {
+            let child_index = match NonHardenedChildIndex::from_index(index) {
+                Some(child_index) => child_index,
+                // The space of non-hardened child indices has been exhausted.
+                None => return Ok(()),
+            };
+            // Per BIP 32, an index at which derivation fails is skipped.
+            if let Ok(address) = ivk.derive_address(child_index) {
+                stmt_insert_address.execute(named_params![
+                    ":account_id": account_id,
+                    ":address_index": index,
+                    ":address": address.encode(params),
+                ])?;
+            }
+        }
+        next_index = window_end;
+    }
+}
+
+#[cfg(all(test, feature = "transparent-inputs"))]
+mod tests {
+    use rusqlite::named_params;
+    use tempfile::NamedTempFile;
+    use zcash_client_backend::{encoding::AddressCodec, keys::UnifiedSpendingKey};
+    use zcash_primitives::{
+        consensus::Network,
+        legacy::keys::{IncomingViewingKey, NonHardenedChildIndex},
+    };
+
+    use crate::{
+        wallet::init::{init_wallet_db_internal, migrations::memo_search},
+        WalletDb,
+    };
+
+    #[test]
+    fn migrate_with_existing_account() {
+        let data_file = NamedTempFile::new().unwrap();
+        let mut db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork).unwrap();
+        init_wallet_db_internal(
+            &mut db_data,
+            None,
+            None,
+            &[memo_search::MIGRATION_ID],
+            false,
+        )
+        .unwrap();
+
+        // Store an imported account whose viewing key has an HD transparent component.
+        let ufvk = UnifiedSpendingKey::from_seed(
+            &db_data.params,
+            &[],
+            &[0x10; 32],
+            zip32::AccountId::ZERO,
+        )
+        .unwrap()
+        .to_unified_full_viewing_key();
+        db_data
+            .conn
+            .execute(
+                "INSERT INTO accounts (account_kind, ufvk, uivk, birthday_height)
+                VALUES (1, :ufvk, :uivk, 0)",
+                named_params![
+                    ":ufvk": ufvk.encode(&db_data.params),
+                    ":uivk": ufvk.to_unified_incoming_viewing_key().encode(&db_data.params),
+                ],
+            )
+            .unwrap();
+
+        // The account has received a UTXO at its address at index 3.
+        let ivk = ufvk.transparent().unwrap().derive_external_ivk().unwrap();
+        let address_at = |index| {
+            ivk.derive_address(NonHardenedChildIndex::from_index(index).unwrap())
+                .unwrap()
+        };
+        db_data
+            .conn
+            .execute(
+                "INSERT INTO utxos (
+                    received_by_account_id, address, prevout_txid, prevout_idx, script,
+                    value_zat, height
+                )
+                VALUES (1, :address, :txid, 0, :script, 10000, 7)",
+                named_params![
+                    ":address": address_at(3).encode(&db_data.params),
+                    ":txid": &[1u8; 32][..],
+                    ":script": address_at(3).script().0,
+                ],
+            )
+            .unwrap();
+
+        init_wallet_db_internal(&mut db_data, None, None, &[super::MIGRATION_ID], false).unwrap();
+
+        // The window extends 20 addresses beyond the used address.
+        let addresses = db_data
+            .conn
+            .prepare(
+                "SELECT address_index, address, first_use_height
+                FROM transparent_addresses
+                WHERE account_id = 1
+                ORDER BY address_index",
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<Vec<(u32, String, Option<u32>)>, _>>()
+            .unwrap();
+        assert_eq!(
+            addresses,
+            (0..24)
+                .map(|index| (
+                    index,
+                    address_at(index).encode(&db_data.params),
+                    (index == 3).then_some(7),
+                ))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -137,6 +137,10 @@ impl<P: consensus::Parameters> RusqliteMigration for Migration<P> {
                           return Err(WalletMigrationError::CorruptedData(
                               "Address field value decoded to a transparent address; should have been Sapling or unified.".to_string()));
                       }
+                      Address::Tex(_) => {
+                          return Err(WalletMigrationError::CorruptedData(
+                              "Address field value decoded to a TEX address; should have been Sapling or unified.".to_string()));
+                      }
                       Address::Unified(decoded_address) => {
                           let (expected_address, idx) = ufvk.default_address(ua_request)?;
                           if decoded_address != expected_address {
@@ -268,7 +272,9 @@ impl<P: consensus::Parameters> RusqliteMigration for Migration<P> {
                     Address::Sapling(_) => {
                         Ok(pool_code(PoolType::Shielded(ShieldedProtocol::Sapling)))
                     }
-                    Address::Transparent(_) => Ok(pool_code(PoolType::Transparent)),
+                    Address::Transparent(_) | Address::Tex(_) => {
+                        Ok(pool_code(PoolType::Transparent))
+                    }
                     Address::Unified(_) => Err(WalletMigrationError::CorruptedData(
                         "Unified addresses should not yet appear in the sent_notes table."
                             .to_string(),
@@ -218,7 +218,7 @@ mod tests {
         let data_file = NamedTempFile::new().unwrap();
         let mut db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork).unwrap();
         init_wallet_db_internal(
-            &mut db_data,
+            &mut db_data, None,
             None,
             &[add_transaction_views::MIGRATION_ID],
             false,
@@ -226,7 +226,7 @@ mod tests {
         .unwrap();
 
         // Create two accounts in the wallet.
-        let usk0 = UnifiedSpendingKey::from_seed(&db_data.params, &[0u8; 32][..], AccountId::ZERO)
+        let usk0 = UnifiedSpendingKey::from_seed(&db_data.params, &[], &[0u8; 32][..], AccountId::ZERO)
             .unwrap();
         let ufvk0 = usk0.to_unified_full_viewing_key();
         db_data
@@ -238,7 +238,7 @@ mod tests {
             .unwrap();
 
         let usk1 = UnifiedSpendingKey::from_seed(
-            &db_data.params,
+            &db_data.params, &[],
             &[1u8; 32][..],
             AccountId::try_from(1).unwrap(),
         )
@@ -388,7 +388,7 @@ mod tests {
         }
 
         // Run this migration
-        init_wallet_db_internal(&mut db_data, None, &[super::MIGRATION_ID], false).unwrap();
+        init_wallet_db_internal(&mut db_data, None, None, &[super::MIGRATION_ID], false).unwrap();
 
         // Corrected behavior after v_transactions has been updated
         {
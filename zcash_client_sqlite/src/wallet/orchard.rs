@@ -23,7 +23,7 @@ use zip32::Scope;
 
 use crate::{error::SqliteClientError, AccountId, ReceivedNoteId};
 
-use super::{memo_repr, parse_scope, scope_code};
+use super::{memo_repr, memo_search_text, parse_scope, scope_code};
 
 /// This trait provides a generalization over shielded output representations.
 pub(crate) trait ReceivedOrchardOutput {
@@ -234,13 +234,13 @@ pub(crate) fn put_received_note<T: ReceivedOrchardOutput>(
         "INSERT INTO orchard_received_notes
         (
             tx, action_index, account_id,
-            diversifier, value, rho, rseed, memo, nf,
+            diversifier, value, rho, rseed, memo, memo_text, nf,
             is_change, commitment_tree_position,
             recipient_key_scope
         )
         VALUES (
             :tx, :action_index, :account_id,
-            :diversifier, :value, :rho, :rseed, :memo, :nf,
+            :diversifier, :value, :rho, :rseed, :memo, :memo_text, :nf,
             :is_change, :commitment_tree_position,
             :recipient_key_scope
         )
@@ -252,6 +252,7 @@ pub(crate) fn put_received_note<T: ReceivedOrchardOutput>(
             rseed = :rseed,
             nf = IFNULL(:nf, nf),
             memo = IFNULL(:memo, memo),
+            memo_text = CASE WHEN :memo IS NULL THEN memo_text ELSE :memo_text END,
             is_change = IFNULL(:is_change, is_change),
             commitment_tree_position = IFNULL(:commitment_tree_position, commitment_tree_position),
             recipient_key_scope = :recipient_key_scope
@@ -272,6 +273,7 @@ pub(crate) fn put_received_note<T: ReceivedOrchardOutput>(
         ":rseed": &rseed.as_bytes(),
         ":nf": output.nullifier().map(|nf| nf.to_bytes()),
         ":memo": memo_repr(output.memo()),
+        ":memo_text": memo_search_text(output.memo()),
         ":is_change": output.is_change(),
         ":commitment_tree_position": output.note_commitment_tree_position().map(u64::from),
         ":recipient_key_scope": output.recipient_key_scope().map(scope_code),
@@ -569,12 +571,49 @@ pub(crate) mod tests {
         testing::pool::send_single_step_proposed_transfer::<OrchardPoolTester>()
     }
 
+    #[test]
+    fn send_with_split_change() {
+        testing::pool::send_with_split_change::<OrchardPoolTester>()
+    }
+
     #[test]
     #[cfg(feature = "transparent-inputs")]
     fn send_multi_step_proposed_transfer() {
         testing::pool::send_multi_step_proposed_transfer::<OrchardPoolTester>()
     }
 
+    #[test]
+    fn estimate_transfer_fee_range() {
+        testing::pool::estimate_transfer_fee_range::<OrchardPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn send_to_tex_address() {
+        testing::pool::send_to_tex_address::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn proposal_roundtrip_multi_output_transfer() {
+        testing::pool::proposal_roundtrip_multi_output_transfer::<OrchardPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn proposal_roundtrip_shielding() {
+        testing::pool::proposal_roundtrip_shielding::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn execute_stale_proposal() {
+        testing::pool::execute_stale_proposal::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn decode_proposal_checks_wallet_state() {
+        testing::pool::decode_proposal_checks_wallet_state::<OrchardPoolTester>()
+    }
+
     #[test]
     #[allow(deprecated)]
     fn create_to_address_fails_on_incorrect_usk() {
@@ -587,6 +626,27 @@ pub(crate) mod tests {
         testing::pool::proposal_fails_with_no_blocks::<OrchardPoolTester>()
     }
 
+    #[test]
+    fn promote_draft_after_chain_advance() {
+        testing::pool::promote_draft_after_chain_advance::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn uivk_account_receives_but_cannot_spend() {
+        testing::pool::uivk_account_receives_but_cannot_spend::<OrchardPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn uivk_account_cannot_shield() {
+        testing::pool::uivk_account_cannot_shield::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn transaction_history_filtering_and_paging() {
+        testing::pool::transaction_history_filtering_and_paging::<OrchardPoolTester>()
+    }
+
     #[test]
     fn spend_fails_on_unverified_notes() {
         testing::pool::spend_fails_on_unverified_notes::<OrchardPoolTester>()
@@ -626,12 +686,43 @@ pub(crate) mod tests {
         testing::pool::zip317_spend::<OrchardPoolTester>()
     }
 
+    #[test]
+    fn note_selection_strategies() {
+        testing::pool::note_selection_strategies::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn locked_notes_are_not_spendable() {
+        testing::pool::locked_notes_are_not_spendable::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn account_pool_balances_match_summary() {
+        testing::pool::account_pool_balances_match_summary::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn wallet_snapshot_round_trip() {
+        testing::pool::wallet_snapshot_round_trip::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn find_memos_across_accounts() {
+        testing::pool::find_memos_across_accounts::<OrchardPoolTester>()
+    }
+
     #[test]
     #[cfg(feature = "transparent-inputs")]
     fn shield_transparent() {
         testing::pool::shield_transparent::<OrchardPoolTester>()
     }
 
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn shield_standalone_transparent() {
+        testing::pool::shield_standalone_transparent::<OrchardPoolTester>()
+    }
+
     #[test]
     fn birthday_in_anchor_shard() {
         testing::pool::birthday_in_anchor_shard::<OrchardPoolTester>()
@@ -22,7 +22,7 @@ use zip32::Scope;
 
 use crate::{error::SqliteClientError, AccountId, ReceivedNoteId};
 
-use super::{memo_repr, parse_scope, scope_code};
+use super::{memo_repr, memo_search_text, parse_scope, scope_code};
 
 /// This trait provides a generalization over shielded output representations.
 pub(crate) trait ReceivedSaplingOutput {
@@ -332,7 +332,7 @@ pub(crate) fn put_received_note<T: ReceivedSaplingOutput>(
 ) -> Result<(), SqliteClientError> {
     let mut stmt_upsert_received_note = conn.prepare_cached(
         "INSERT INTO sapling_received_notes
-        (tx, output_index, account_id, diversifier, value, rcm, memo, nf,
+        (tx, output_index, account_id, diversifier, value, rcm, memo, memo_text, nf,
          is_change, commitment_tree_position,
          recipient_key_scope)
         VALUES (
@@ -343,6 +343,7 @@ pub(crate) fn put_received_note<T: ReceivedSaplingOutput>(
             :value,
             :rcm,
             :memo,
+            :memo_text,
             :nf,
             :is_change,
             :commitment_tree_position,
@@ -355,6 +356,7 @@ pub(crate) fn put_received_note<T: ReceivedSaplingOutput>(
             rcm = :rcm,
             nf = IFNULL(:nf, nf),
             memo = IFNULL(:memo, memo),
+            memo_text = CASE WHEN :memo IS NULL THEN memo_text ELSE :memo_text END,
             is_change = IFNULL(:is_change, is_change),
             commitment_tree_position = IFNULL(:commitment_tree_position, commitment_tree_position),
             recipient_key_scope = :recipient_key_scope
@@ -374,6 +376,7 @@ pub(crate) fn put_received_note<T: ReceivedSaplingOutput>(
         ":rcm": &rcm.as_ref(),
         ":nf": output.nullifier().map(|nf| nf.0.as_ref()),
         ":memo": memo_repr(output.memo()),
+        ":memo_text": memo_search_text(output.memo()),
         ":is_change": output.is_change(),
         ":commitment_tree_position": output.note_commitment_tree_position().map(u64::from),
         ":recipient_key_scope": output.recipient_key_scope().map(scope_code)
@@ -575,12 +578,49 @@ pub(crate) mod tests {
         testing::pool::send_single_step_proposed_transfer::<SaplingPoolTester>()
     }
 
+    #[test]
+    fn send_with_split_change() {
+        testing::pool::send_with_split_change::<SaplingPoolTester>()
+    }
+
     #[test]
     #[cfg(feature = "transparent-inputs")]
     fn send_multi_step_proposed_transfer() {
         testing::pool::send_multi_step_proposed_transfer::<SaplingPoolTester>()
     }
 
+    #[test]
+    fn estimate_transfer_fee_range() {
+        testing::pool::estimate_transfer_fee_range::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn send_to_tex_address() {
+        testing::pool::send_to_tex_address::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn proposal_roundtrip_multi_output_transfer() {
+        testing::pool::proposal_roundtrip_multi_output_transfer::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn proposal_roundtrip_shielding() {
+        testing::pool::proposal_roundtrip_shielding::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn execute_stale_proposal() {
+        testing::pool::execute_stale_proposal::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn decode_proposal_checks_wallet_state() {
+        testing::pool::decode_proposal_checks_wallet_state::<SaplingPoolTester>()
+    }
+
     #[test]
     #[allow(deprecated)]
     fn create_to_address_fails_on_incorrect_usk() {
@@ -593,6 +633,27 @@ pub(crate) mod tests {
         testing::pool::proposal_fails_with_no_blocks::<SaplingPoolTester>()
     }
 
+    #[test]
+    fn promote_draft_after_chain_advance() {
+        testing::pool::promote_draft_after_chain_advance::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn uivk_account_receives_but_cannot_spend() {
+        testing::pool::uivk_account_receives_but_cannot_spend::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn uivk_account_cannot_shield() {
+        testing::pool::uivk_account_cannot_shield::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn transaction_history_filtering_and_paging() {
+        testing::pool::transaction_history_filtering_and_paging::<SaplingPoolTester>()
+    }
+
     #[test]
     fn spend_fails_on_unverified_notes() {
         testing::pool::spend_fails_on_unverified_notes::<SaplingPoolTester>()
@@ -632,12 +693,43 @@ pub(crate) mod tests {
         testing::pool::zip317_spend::<SaplingPoolTester>()
     }
 
+    #[test]
+    fn note_selection_strategies() {
+        testing::pool::note_selection_strategies::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn locked_notes_are_not_spendable() {
+        testing::pool::locked_notes_are_not_spendable::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn account_pool_balances_match_summary() {
+        testing::pool::account_pool_balances_match_summary::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn wallet_snapshot_round_trip() {
+        testing::pool::wallet_snapshot_round_trip::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn find_memos_across_accounts() {
+        testing::pool::find_memos_across_accounts::<SaplingPoolTester>()
+    }
+
     #[test]
     #[cfg(feature = "transparent-inputs")]
     fn shield_transparent() {
         testing::pool::shield_transparent::<SaplingPoolTester>()
     }
 
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn shield_standalone_transparent() {
+        testing::pool::shield_standalone_transparent::<SaplingPoolTester>()
+    }
+
     #[test]
     fn birthday_in_anchor_shard() {
         testing::pool::birthday_in_anchor_shard::<SaplingPoolTester>()
@@ -391,6 +391,25 @@ pub(crate) fn scan_complete<P: consensus::Parameters>(
     Ok(())
 }
 
+/// Updates the scan queue after the blocks in `range` have been scanned only for spends of the
+/// wallet's notes.
+///
+/// Notes received in these blocks have not been detected, so the range is not marked as
+/// scanned. Instead, the range is queued with at least `Historic` priority (parts of it that
+/// were already fully scanned remain `Scanned`), so that it is still suggested for a full scan
+/// even though its blocks are now present in the `blocks` table.
+pub(crate) fn spend_scan_complete(
+    conn: &rusqlite::Transaction<'_>,
+    range: Range<BlockHeight>,
+) -> Result<(), SqliteClientError> {
+    replace_queue_entries::<SqliteClientError>(
+        conn,
+        &range,
+        Some(ScanRange::from_parts(range.clone(), ScanPriority::Historic)).into_iter(),
+        false,
+    )
+}
+
 fn tip_shard_end_height(
     conn: &rusqlite::Transaction<'_>,
     table_prefix: &'static str,
@@ -876,7 +895,7 @@ pub(crate) mod tests {
         // Now add an account.
         let wallet_birthday = sap_active + 500;
         st.wallet_mut()
-            .create_account(
+            .create_account(&SecretVec::new(vec![]), 
                 &SecretVec::new(vec![0; 32]),
                 &AccountBirthday::from_parts(
                     ChainState::empty(wallet_birthday - 1, BlockHash([0; 32])),
@@ -0,0 +1,236 @@
+//! Functions for producing and restoring wallet snapshots.
+//!
+//! A snapshot of a SQLite wallet contains the rows of every table in the wallet database other
+//! than the migration bookkeeping tables. The wallet database never stores spending keys, so
+//! neither do its snapshots. The set of applied migrations is recorded as the snapshot's schema,
+//! and a snapshot can only be restored into a wallet that has the same set of migrations applied.
+
+use std::collections::BTreeSet;
+
+use rusqlite::types::{Value, ValueRef};
+use uuid::Uuid;
+use zcash_client_backend::data_api::snapshot::{SnapshotTable, SnapshotValue, WalletSnapshot};
+use zcash_primitives::consensus;
+
+use crate::error::SqliteClientError;
+
+/// Quotes an SQL identifier for inclusion in a statement.
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Returns whether the given table is populated when the wallet database is initialized, rather
+/// than only once the wallet has data.
+///
+/// The note commitment tree caps are created with an empty cap, which is replaced by the cap from
+/// the snapshot when it is restored. The Orchard tables exist whether or not the `orchard`
+/// feature is enabled.
+fn is_initialized_table(name: &str) -> bool {
+    matches!(name, "sapling_tree_cap" | "orchard_tree_cap")
+}
+
+/// Returns the names of the wallet tables that are included in snapshots, in lexicographic order.
+fn snapshot_table_names(conn: &rusqlite::Connection) -> Result<Vec<String>, SqliteClientError> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master
+         WHERE type = 'table'
+         AND name NOT LIKE 'sqlite_%'
+         AND name != 'schemer_migrations'
+         ORDER BY name",
+    )?;
+    let names = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+    Ok(names)
+}
+
+/// Returns the identifiers of the migrations that have been applied to the wallet database, in
+/// lexicographic order.
+fn applied_migrations(conn: &rusqlite::Connection) -> Result<Vec<String>, SqliteClientError> {
+    let mut stmt = conn.prepare("SELECT id FROM schemer_migrations")?;
+    let mut rows = stmt.query([])?;
+    let mut migrations = BTreeSet::new();
+    while let Some(row) = rows.next()? {
+        let id: Vec<u8> = row.get(0)?;
+        let id = Uuid::from_slice(&id).map_err(|e| {
+            SqliteClientError::CorruptedData(format!("Invalid migration identifier: {}", e))
+        })?;
+        migrations.insert(id.to_string());
+    }
+    Ok(migrations.into_iter().collect())
+}
+
+fn table_columns(
+    conn: &rusqlite::Connection,
+    table: &str,
+) -> Result<Vec<String>, SqliteClientError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT name FROM pragma_table_info({})",
+        quote_identifier(table)
+    ))?;
+    let columns = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+    Ok(columns)
+}
+
+fn to_snapshot_value(value: ValueRef<'_>) -> Result<SnapshotValue, SqliteClientError> {
+    Ok(match value {
+        ValueRef::Null => SnapshotValue::Null,
+        ValueRef::Integer(i) => SnapshotValue::Integer(i),
+        ValueRef::Real(r) => SnapshotValue::Real(r),
+        ValueRef::Text(t) => SnapshotValue::Text(
+            String::from_utf8(t.to_vec())
+                .map_err(|e| SqliteClientError::CorruptedData(format!("Invalid text: {}", e)))?,
+        ),
+        ValueRef::Blob(b) => SnapshotValue::Blob(b.to_vec()),
+    })
+}
+
+fn from_snapshot_value(value: &SnapshotValue) -> Value {
+    match value {
+        SnapshotValue::Null => Value::Null,
+        SnapshotValue::Integer(i) => Value::Integer(*i),
+        SnapshotValue::Real(r) => Value::Real(*r),
+        SnapshotValue::Text(t) => Value::Text(t.clone()),
+        SnapshotValue::Blob(b) => Value::Blob(b.clone()),
+    }
+}
+
+/// Reads the contents of the wallet database into a snapshot.
+///
+/// All data is read within a single transaction, so that the snapshot reflects one consistent
+/// state of the wallet even if the database is being written to concurrently.
+pub(crate) fn read_snapshot<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+) -> Result<WalletSnapshot, SqliteClientError> {
+    let tx = conn.unchecked_transaction()?;
+
+    let schema = applied_migrations(&tx)?;
+    let mut tables = vec![];
+    for name in snapshot_table_names(&tx)? {
+        let columns = table_columns(&tx, &name)?;
+        let mut stmt = tx.prepare(&format!(
+            "SELECT {} FROM {}",
+            columns
+                .iter()
+                .map(|c| quote_identifier(c))
+                .collect::<Vec<_>>()
+                .join(", "),
+            quote_identifier(&name)
+        ))?;
+
+        let mut rows = vec![];
+        let mut result = stmt.query([])?;
+        while let Some(row) = result.next()? {
+            rows.push(
+                (0..columns.len())
+                    .map(|i| to_snapshot_value(row.get_ref(i)?))
+                    .collect::<Result<Vec<_>, _>>()?,
+            );
+        }
+
+        tables.push(
+            SnapshotTable::from_parts(name, columns, rows)
+                .expect("each row has one value per column"),
+        );
+    }
+
+    tx.commit()?;
+    Ok(WalletSnapshot::from_parts(
+        params.network_type(),
+        schema,
+        tables,
+    ))
+}
+
+/// Populates an empty wallet database from the given snapshot.
+///
+/// This must be called within a transaction; if it returns an error, the transaction must not
+/// be committed.
+pub(crate) fn restore_snapshot<P: consensus::Parameters>(
+    conn: &rusqlite::Transaction,
+    params: &P,
+    snapshot: WalletSnapshot,
+) -> Result<(), SqliteClientError> {
+    if snapshot.network() != params.network_type() {
+        return Err(SqliteClientError::SnapshotIncompatible(format!(
+            "the snapshot is for network {:?}, but the wallet is for network {:?}",
+            snapshot.network(),
+            params.network_type()
+        )));
+    }
+    if snapshot.schema() != applied_migrations(conn)? {
+        return Err(SqliteClientError::SnapshotIncompatible(
+            "the snapshot was produced with a different set of wallet database migrations"
+                .to_owned(),
+        ));
+    }
+
+    let table_names = snapshot_table_names(conn)?;
+    let snapshot_names = snapshot
+        .tables()
+        .iter()
+        .map(|t| t.name().to_owned())
+        .collect::<BTreeSet<_>>();
+    if snapshot_names.len() != snapshot.tables().len()
+        || table_names.iter().cloned().collect::<BTreeSet<_>>() != snapshot_names
+    {
+        return Err(SqliteClientError::SnapshotIncompatible(
+            "the snapshot does not contain the wallet's set of tables".to_owned(),
+        ));
+    }
+
+    for name in table_names
+        .iter()
+        .filter(|name| !is_initialized_table(name))
+    {
+        let has_rows: bool = conn.query_row(
+            &format!("SELECT EXISTS (SELECT 1 FROM {})", quote_identifier(name)),
+            [],
+            |row| row.get(0),
+        )?;
+        if has_rows {
+            return Err(SqliteClientError::TableNotEmpty);
+        }
+    }
+
+    // Tables are restored in name order, so foreign key constraints can only be checked once all
+    // rows are present.
+    conn.execute_batch("PRAGMA defer_foreign_keys = ON;")?;
+    for table in snapshot.into_tables() {
+        let columns = table_columns(conn, table.name())?;
+        if table.columns().iter().collect::<BTreeSet<_>>() != columns.iter().collect() {
+            return Err(SqliteClientError::SnapshotIncompatible(format!(
+                "the snapshot columns for table {} do not match the wallet schema",
+                table.name()
+            )));
+        }
+        if is_initialized_table(table.name()) {
+            conn.execute(
+                &format!("DELETE FROM {}", quote_identifier(table.name())),
+                [],
+            )?;
+        }
+
+        let mut stmt = conn.prepare(&format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            quote_identifier(table.name()),
+            table
+                .columns()
+                .iter()
+                .map(|c| quote_identifier(c))
+                .collect::<Vec<_>>()
+                .join(", "),
+            vec!["?"; table.columns().len()].join(", ")
+        ))?;
+        for row in table.rows() {
+            stmt.execute(rusqlite::params_from_iter(
+                row.iter().map(from_snapshot_value),
+            ))?;
+        }
+    }
+
+    Ok(())
+}
@@ -1,10 +1,28 @@
 //! Functions for transparent input support in the wallet.
 use std::collections::HashSet;
 
-use rusqlite::{named_params, Connection};
-use zcash_primitives::transaction::components::OutPoint;
+use rusqlite::{named_params, Connection, OptionalExtension};
+use zcash_address::unified::{self, Container, Encoding, Ivk, Uivk};
+use zcash_client_backend::{
+    data_api::{AccountSource, TransparentAddressUsage},
+    encoding::AddressCodec,
+    wallet::TransparentAddressMetadata,
+};
+use zcash_keys::keys::{AddressGenerationError, UnifiedFullViewingKey};
+use zcash_primitives::{
+    consensus::{self, BlockHeight},
+    legacy::{
+        keys::{
+            EphemeralIvk, ExternalIvk, IncomingViewingKey, NonHardenedChildIndex,
+            TransparentKeyScope,
+        },
+        TransparentAddress,
+    },
+    transaction::components::OutPoint,
+};
 
-use crate::AccountId;
+use super::{account_kind_code, get_account_ids, get_legacy_transparent_address};
+use crate::{error::SqliteClientError, AccountId};
 
 pub(crate) fn detect_spending_accounts<'a>(
     conn: &Connection,
@@ -32,3 +50,437 @@ pub(crate) fn detect_spending_accounts<'a>(
 
     Ok(acc)
 }
+
+/// Returns the external transparent incoming viewing key of the given account, if the account
+/// exists and has a transparent key.
+pub(crate) fn get_external_ivk<P: consensus::Parameters>(
+    conn: &Connection,
+    params: &P,
+    account_id: AccountId,
+) -> Result<Option<ExternalIvk>, SqliteClientError> {
+    // Standalone transparent accounts have no UIVK.
+    let uivk_str: Option<String> = conn
+        .query_row(
+            "SELECT uivk FROM accounts WHERE id = :account",
+            [account_id.0],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+
+    if let Some(uivk_str) = uivk_str {
+        let (network, uivk) = Uivk::decode(&uivk_str)
+            .map_err(|e| SqliteClientError::CorruptedData(format!("Unable to parse UIVK: {e}")))?;
+        if params.network_type() != network {
+            return Err(SqliteClientError::CorruptedData(
+                "Network type mismatch".to_owned(),
+            ));
+        }
+
+        for item in uivk.items() {
+            if let Ivk::P2pkh(tivk_bytes) = item {
+                return Ok(Some(ExternalIvk::deserialize(&tivk_bytes)?));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Returns whether the given key was imported from a single legacy (non-HD) transparent key.
+///
+/// Such keys are stored with an all-zero chain code, and have only the one address.
+fn is_legacy_key(ivk: &ExternalIvk) -> bool {
+    ivk.serialize()[..32].iter().all(|b| *b == 0)
+}
+
+/// Returns the ZIP 320 ephemeral incoming viewing key of the given account, if the account
+/// exists and has a full viewing key with an HD transparent component.
+///
+/// Ephemeral addresses are only used to fund payments that the account makes, so accounts
+/// without spending capability have none.
+fn get_ephemeral_ivk<P: consensus::Parameters>(
+    conn: &Connection,
+    params: &P,
+    account_id: AccountId,
+) -> Result<Option<EphemeralIvk>, SqliteClientError> {
+    let ufvk_str: Option<String> = conn
+        .query_row(
+            "SELECT ufvk FROM accounts WHERE id = :account",
+            [account_id.0],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+
+    let ufvk = match ufvk_str {
+        Some(ufvk_str) => UnifiedFullViewingKey::decode(params, &ufvk_str).map_err(|e| {
+            SqliteClientError::CorruptedData(format!(
+                "Could not decode unified full viewing key for account {:?}: {}",
+                account_id, e
+            ))
+        })?,
+        None => return Ok(None),
+    };
+
+    match ufvk.transparent() {
+        Some(pubkey) if pubkey.is_bip44() => Ok(Some(pubkey.derive_ephemeral_ivk()?)),
+        _ => Ok(None),
+    }
+}
+
+/// The incoming viewing key from which the addresses of an account in a particular key scope
+/// are derived.
+enum ScopeIvk {
+    External(ExternalIvk),
+    Ephemeral(EphemeralIvk),
+}
+
+impl ScopeIvk {
+    fn derive_address(&self, child_index: NonHardenedChildIndex) -> Option<TransparentAddress> {
+        match self {
+            ScopeIvk::External(ivk) => ivk.derive_address(child_index).ok(),
+            ScopeIvk::Ephemeral(ivk) => ivk.derive_address(child_index).ok(),
+        }
+    }
+}
+
+/// Derives any external and ephemeral transparent addresses of the given account that are
+/// within `gap_limit` addresses of the last used address in their scope, and that have not yet
+/// been derived.
+///
+/// Returns `false` if the account has no external address window, because it does not exist,
+/// has no transparent key, or has only a legacy transparent key.
+pub(crate) fn extend_address_window<P: consensus::Parameters>(
+    conn: &Connection,
+    params: &P,
+    account_id: AccountId,
+    gap_limit: u32,
+) -> Result<bool, SqliteClientError> {
+    let has_external = extend_scope_window(
+        conn,
+        params,
+        account_id,
+        TransparentKeyScope::EXTERNAL,
+        gap_limit,
+    )?;
+    extend_scope_window(
+        conn,
+        params,
+        account_id,
+        TransparentKeyScope::EPHEMERAL,
+        gap_limit,
+    )?;
+    Ok(has_external)
+}
+
+/// Derives any transparent addresses of the given account in the given key scope that are
+/// within `gap_limit` addresses of the last used address in that scope, and that have not yet
+/// been derived.
+///
+/// A newly derived address at which the wallet has already received a UTXO is marked as used,
+/// which advances the window; derivation continues until the window is complete.
+///
+/// Returns `false` if the account has no address window in the scope. Only the external and
+/// ephemeral scopes have address windows.
+fn extend_scope_window<P: consensus::Parameters>(
+    conn: &Connection,
+    params: &P,
+    account_id: AccountId,
+    key_scope: TransparentKeyScope,
+    gap_limit: u32,
+) -> Result<bool, SqliteClientError> {
+    let ivk = if key_scope == TransparentKeyScope::EXTERNAL {
+        get_external_ivk(conn, params, account_id)?
+            .filter(|ivk| !is_legacy_key(ivk))
+            .map(ScopeIvk::External)
+    } else if key_scope == TransparentKeyScope::EPHEMERAL {
+        get_ephemeral_ivk(conn, params, account_id)?.map(ScopeIvk::Ephemeral)
+    } else {
+        None
+    };
+    let ivk = match ivk {
+        Some(ivk) => ivk,
+        None => return Ok(false),
+    };
+
+    let mut stmt_insert_address = conn.prepare_cached(
+        "INSERT INTO transparent_addresses
+            (account_id, key_scope, address_index, address, first_use_height)
+        SELECT :account_id, :key_scope, :address_index, :address, MIN(height)
+        FROM utxos
+        WHERE address = :address",
+    )?;
+
+    let mut next_index: u32 = conn.query_row(
+        "SELECT IFNULL(MAX(address_index) + 1, 0)
+        FROM transparent_addresses
+        WHERE account_id = :account_id
+        AND key_scope = :key_scope",
+        named_params![
+            ":account_id": account_id.0,
+            ":key_scope": u32::from(key_scope),
+        ],
+        |row| row.get(0),
+    )?;
+    loop {
+        let window_start: u32 = conn.query_row(
+            "SELECT IFNULL(MAX(address_index) + 1, 0)
+            FROM transparent_addresses
+            WHERE account_id = :account_id
+            AND key_scope = :key_scope
+            AND first_use_height IS NOT NULL",
+            named_params![
+                ":account_id": account_id.0,
+                ":key_scope": u32::from(key_scope),
+            ],
+            |row| row.get(0),
+        )?;
+        let window_end = window_start.saturating_add(gap_limit);
+        if next_index >= window_end {
+            break;
+        }
+
+        for index in next_index..window_end {
+            let child_index = match NonHardenedChildIndex::from_index(index) {
+                Some(child_index) => child_index,
+                // The space of non-hardened child indices has been exhausted.
+                None => return Ok(true),
+            };
+            // Per BIP 32, an index at which derivation fails is skipped.
+            if let Some(address) = ivk.derive_address(child_index) {
+                stmt_insert_address.execute(named_params![
+                    ":account_id": account_id.0,
+                    ":key_scope": u32::from(key_scope),
+                    ":address_index": index,
+                    ":address": address.encode(params),
+                ])?;
+            }
+        }
+        next_index = window_end;
+    }
+
+    Ok(true)
+}
+
+/// Adds a standalone transparent account that tracks the given address, and returns its
+/// identifier.
+///
+/// The address is recorded as the account's only transparent address, at index zero, so that
+/// the UTXOs received at it are attributed to the account. Returns an error if the address is
+/// already tracked by the wallet.
+pub(crate) fn add_standalone_account<P: consensus::Parameters>(
+    conn: &Connection,
+    params: &P,
+    address: &TransparentAddress,
+    birthday_height: BlockHeight,
+) -> Result<AccountId, SqliteClientError> {
+    let address_str = address.encode(params);
+    let mut is_tracked: bool = conn.query_row(
+        "SELECT EXISTS (
+            SELECT 1 FROM addresses WHERE cached_transparent_receiver_address = :address
+            UNION
+            SELECT 1 FROM transparent_addresses WHERE address = :address
+        )",
+        named_params![":address": &address_str],
+        |row| row.get(0),
+    )?;
+    for account_id in get_account_ids(conn)? {
+        if let Some((legacy_address, _)) = get_legacy_transparent_address(params, conn, account_id)?
+        {
+            is_tracked |= &legacy_address == address;
+        }
+    }
+    if is_tracked {
+        return Err(SqliteClientError::BadAccountData(format!(
+            "The transparent address {} is already tracked by the wallet.",
+            address_str
+        )));
+    }
+
+    let account_id = conn.query_row(
+        "INSERT INTO accounts (account_kind, birthday_height)
+        VALUES (:account_kind, :birthday_height)
+        RETURNING id",
+        named_params![
+            ":account_kind": account_kind_code(AccountSource::StandaloneTransparent),
+            ":birthday_height": u32::from(birthday_height),
+        ],
+        |row| row.get(0).map(AccountId),
+    )?;
+
+    conn.execute(
+        "INSERT INTO transparent_addresses
+            (account_id, address_index, address, first_use_height)
+        SELECT :account_id, 0, :address, MIN(height)
+        FROM utxos
+        WHERE address = :address",
+        named_params![
+            ":account_id": account_id.0,
+            ":address": &address_str,
+        ],
+    )?;
+
+    Ok(account_id)
+}
+
+/// Records that the given external or ephemeral transparent address received funds at the given
+/// height, and derives any addresses that this brings within the gap limit of its account.
+///
+/// Returns the account that the address belongs to, or `None` if the address was not derived
+/// under the gap limit.
+pub(crate) fn mark_address_used<P: consensus::Parameters>(
+    conn: &Connection,
+    params: &P,
+    address: &TransparentAddress,
+    height: BlockHeight,
+    gap_limit: u32,
+) -> Result<Option<AccountId>, SqliteClientError> {
+    let used = conn
+        .query_row(
+            "UPDATE transparent_addresses
+            SET first_use_height = MIN(IFNULL(first_use_height, :height), :height)
+            WHERE address = :address
+            RETURNING account_id, key_scope",
+            named_params![
+                ":address": address.encode(params),
+                ":height": u32::from(height),
+            ],
+            |row| Ok((AccountId(row.get(0)?), row.get::<_, u32>(1)?)),
+        )
+        .optional()?;
+
+    if let Some((account_id, key_scope)) = used {
+        let key_scope = TransparentKeyScope::custom(key_scope).ok_or_else(|| {
+            SqliteClientError::CorruptedData(format!("Invalid transparent key scope {}", key_scope))
+        })?;
+        extend_scope_window(conn, params, account_id, key_scope, gap_limit)?;
+    }
+
+    Ok(used.map(|(account_id, _)| account_id))
+}
+
+/// Returns the usage of each transparent address in the given key scope derived for the given
+/// account under the gap limit, in order of address index.
+pub(crate) fn get_address_usage<P: consensus::Parameters>(
+    conn: &Connection,
+    params: &P,
+    account_id: AccountId,
+    key_scope: TransparentKeyScope,
+) -> Result<Vec<TransparentAddressUsage>, SqliteClientError> {
+    let mut stmt_addresses = conn.prepare_cached(
+        "SELECT address_index, address, is_reserved, first_use_height
+        FROM transparent_addresses
+        WHERE account_id = :account_id
+        AND key_scope = :key_scope
+        ORDER BY address_index",
+    )?;
+
+    let usage = stmt_addresses
+        .query_and_then(
+            named_params![
+                ":account_id": account_id.0,
+                ":key_scope": u32::from(key_scope),
+            ],
+            |row| -> Result<_, SqliteClientError> {
+                let index: u32 = row.get(0)?;
+                let address_str: String = row.get(1)?;
+                let first_use_height: Option<u32> = row.get(3)?;
+                Ok(TransparentAddressUsage::from_parts(
+                    TransparentAddress::decode(params, &address_str)?,
+                    TransparentAddressMetadata::new(
+                        key_scope,
+                        NonHardenedChildIndex::from_index(index).ok_or_else(|| {
+                            SqliteClientError::CorruptedData(format!(
+                                "Invalid transparent address index {}",
+                                index
+                            ))
+                        })?,
+                    ),
+                    row.get(2)?,
+                    first_use_height.map(BlockHeight::from),
+                ))
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(usage)
+}
+
+/// Reserves the `n` lowest-indexed transparent addresses in the given key scope of the given
+/// account that have not been used, reserved, or exposed as the transparent receiver of a
+/// unified address, and returns them.
+pub(crate) fn reserve_next_addresses<P: consensus::Parameters>(
+    conn: &Connection,
+    params: &P,
+    account_id: AccountId,
+    key_scope: TransparentKeyScope,
+    n: u32,
+    gap_limit: u32,
+) -> Result<Vec<(TransparentAddress, TransparentAddressMetadata)>, SqliteClientError> {
+    if super::get_account(conn, params, account_id)?.is_none() {
+        return Err(SqliteClientError::AccountUnknown);
+    }
+    if !extend_scope_window(conn, params, account_id, key_scope, gap_limit)? {
+        return Err(AddressGenerationError::KeyNotAvailable(unified::Typecode::P2pkh).into());
+    }
+
+    let mut stmt_available = conn.prepare_cached(
+        "SELECT address_index, address
+        FROM transparent_addresses
+        WHERE account_id = :account_id
+        AND key_scope = :key_scope
+        AND first_use_height IS NULL
+        AND is_reserved = 0
+        AND address NOT IN (
+            SELECT cached_transparent_receiver_address
+            FROM addresses
+            WHERE cached_transparent_receiver_address IS NOT NULL
+        )
+        ORDER BY address_index
+        LIMIT :n",
+    )?;
+    let available = stmt_available
+        .query_and_then(
+            named_params![
+                ":account_id": account_id.0,
+                ":key_scope": u32::from(key_scope),
+                ":n": n,
+            ],
+            |row| -> Result<_, SqliteClientError> {
+                let index: u32 = row.get(0)?;
+                let address_str: String = row.get(1)?;
+                Ok((index, TransparentAddress::decode(params, &address_str)?))
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+    if available.len() < n as usize {
+        return Err(SqliteClientError::ReachedGapLimit(account_id, gap_limit));
+    }
+
+    let mut stmt_reserve = conn.prepare_cached(
+        "UPDATE transparent_addresses
+        SET is_reserved = 1
+        WHERE account_id = :account_id
+        AND key_scope = :key_scope
+        AND address_index = :address_index",
+    )?;
+    available
+        .into_iter()
+        .map(|(index, address)| {
+            stmt_reserve.execute(named_params![
+                ":account_id": account_id.0,
+                ":key_scope": u32::from(key_scope),
+                ":address_index": index,
+            ])?;
+            Ok((
+                address,
+                TransparentAddressMetadata::new(
+                    key_scope,
+                    NonHardenedChildIndex::from_index(index)
+                        .expect("only non-hardened indices are derived"),
+                ),
+            ))
+        })
+        .collect()
+}
@@ -0,0 +1,98 @@
+//! Checks that a wallet configured for single-threaded scanning never uses a thread pool.
+//!
+//! This runs in its own test binary, as the global `rayon` thread pool is shared by all of the
+//! tests in a process.
+
+use std::convert::Infallible;
+
+use sapling::Nullifier;
+use secrecy::SecretVec;
+use tempfile::NamedTempFile;
+use zcash_client_backend::{
+    data_api::{
+        chain::{
+            error::Error, scan_cached_blocks_with_progress, BatchScanProgress, BlockSource,
+            ChainState, ScanCancellation,
+        },
+        AccountBirthday, WalletWrite,
+    },
+    proto::compact_formats::CompactBlock,
+    scanning::{testing::fake_compact_block, ScanParallelism},
+};
+use zcash_client_sqlite::{wallet::init::init_wallet_db, WalletDb};
+use zcash_primitives::{
+    block::BlockHash,
+    consensus::{Network, NetworkUpgrade, Parameters},
+    transaction::components::amount::NonNegativeAmount,
+};
+
+struct MemBlockSource(Vec<CompactBlock>);
+
+impl BlockSource for MemBlockSource {
+    type Error = Infallible;
+
+    fn with_blocks<F, WalletErrT>(
+        &self,
+        from_height: Option<zcash_primitives::consensus::BlockHeight>,
+        limit: Option<usize>,
+        mut with_block: F,
+    ) -> Result<(), Error<WalletErrT, Self::Error>>
+    where
+        F: FnMut(CompactBlock) -> Result<(), Error<WalletErrT, Self::Error>>,
+    {
+        self.0
+            .iter()
+            .filter(|block| from_height.map_or(true, |h| block.height() >= h))
+            .take(limit.unwrap_or(usize::MAX))
+            .try_for_each(|block| with_block(block.clone()))
+    }
+}
+
+#[test]
+fn single_threaded_scan_uses_no_thread_pool() {
+    let network = Network::TestNetwork;
+    let data_file = NamedTempFile::new().unwrap();
+    let mut db_data = WalletDb::for_path(data_file.path(), network)
+        .unwrap()
+        .with_scan_parallelism(ScanParallelism::single_threaded());
+    init_wallet_db(&mut db_data, None, None).unwrap();
+
+    let birthday = AccountBirthday::from_sapling_activation(&network, BlockHash([0; 32]));
+    let (_, usk) = db_data
+        .create_account(
+            &SecretVec::new(vec![]),
+            &SecretVec::new(vec![0x09; 32]),
+            &birthday,
+        )
+        .unwrap();
+    let dfvk = usk.sapling().to_diversifiable_full_viewing_key();
+
+    let sapling_activation = network.activation_height(NetworkUpgrade::Sapling).unwrap();
+    let block = fake_compact_block(
+        sapling_activation,
+        BlockHash([0; 32]),
+        Nullifier([0; 32]),
+        &dfvk,
+        NonNegativeAmount::const_from_u64(10000),
+        false,
+        Some((0, 0)),
+    );
+
+    let summary = scan_cached_blocks_with_progress(
+        &network,
+        &MemBlockSource(vec![block]),
+        &mut db_data,
+        sapling_activation,
+        &ChainState::empty(sapling_activation - 1, BlockHash([0; 32])),
+        1,
+        1,
+        &ScanParallelism::single_threaded(),
+        &mut |_: &BatchScanProgress| {},
+        &ScanCancellation::new(),
+    )
+    .unwrap();
+    assert_eq!(summary.received_sapling_note_count(), 1);
+
+    // The global thread pool can only be configured if it has not yet been created.
+    assert!(rayon::ThreadPoolBuilder::new().build_global().is_ok());
+}
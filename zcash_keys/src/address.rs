@@ -240,6 +240,11 @@ pub enum Address {
     Sapling(PaymentAddress),
     Transparent(TransparentAddress),
     Unified(UnifiedAddress),
+    /// A [ZIP 320] transparent-source-only address, which encodes a P2PKH receiver that may
+    /// only be paid from transparent inputs.
+    ///
+    /// [ZIP 320]: https://zips.z.cash/zip-0320
+    Tex([u8; 20]),
 }
 
 #[cfg(feature = "sapling")]
@@ -287,6 +292,10 @@ impl TryFromRawAddress for Address {
     fn try_from_raw_transparent_p2sh(data: [u8; 20]) -> Result<Self, ConversionError<Self::Error>> {
         Ok(TransparentAddress::ScriptHash(data).into())
     }
+
+    fn try_from_raw_tex(data: [u8; 20]) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(Address::Tex(data))
+    }
 }
 
 impl Address {
@@ -310,17 +319,56 @@ impl Address {
                 }
             },
             Address::Unified(ua) => ua.to_address(net),
+            Address::Tex(data) => ZcashAddress::from_tex(net, *data),
         }
         .to_string()
     }
 
+    /// Returns the TEX address that encodes the given transparent address, or `None` if the
+    /// address is not a P2PKH address.
+    pub fn tex_from_transparent(addr: &TransparentAddress) -> Option<Self> {
+        match addr {
+            TransparentAddress::PublicKeyHash(data) => Some(Address::Tex(*data)),
+            TransparentAddress::ScriptHash(_) => None,
+        }
+    }
+
+    /// Returns the transparent address that funds sent to this address are paid to, if any.
+    ///
+    /// For a unified address, this is its transparent receiver; for a TEX address, it is the
+    /// P2PKH address that it encodes.
+    pub fn to_transparent_address(&self) -> Option<TransparentAddress> {
+        match self {
+            #[cfg(feature = "sapling")]
+            Address::Sapling(_) => None,
+            Address::Transparent(addr) => Some(*addr),
+            Address::Unified(ua) => ua.transparent().copied(),
+            Address::Tex(data) => Some(TransparentAddress::PublicKeyHash(*data)),
+        }
+    }
+
+    /// Returns whether a memo may be attached to a payment to this address.
+    ///
+    /// Memos can only be delivered to shielded recipients, so this is `false` for transparent
+    /// and TEX addresses.
+    pub fn can_receive_memo(&self) -> bool {
+        match self {
+            #[cfg(feature = "sapling")]
+            Address::Sapling(_) => true,
+            Address::Unified(_) => true,
+            Address::Transparent(_) | Address::Tex(_) => false,
+        }
+    }
+
     pub fn has_receiver(&self, pool_type: PoolType) -> bool {
         match self {
             #[cfg(feature = "sapling")]
             Address::Sapling(_) => {
                 matches!(pool_type, PoolType::Shielded(ShieldedProtocol::Sapling))
             }
-            Address::Transparent(_) => matches!(pool_type, PoolType::Transparent),
+            Address::Transparent(_) | Address::Tex(_) => {
+                matches!(pool_type, PoolType::Transparent)
+            }
             Address::Unified(ua) => match pool_type {
                 PoolType::Transparent => ua.transparent().is_some(),
                 PoolType::Shielded(ShieldedProtocol::Sapling) => {
@@ -390,7 +438,11 @@ pub mod testing {
 #[cfg(test)]
 mod tests {
     use zcash_address::test_vectors;
-    use zcash_primitives::consensus::MAIN_NETWORK;
+    use zcash_primitives::{
+        consensus::{MAIN_NETWORK, TEST_NETWORK},
+        legacy::TransparentAddress,
+    };
+    use zcash_protocol::PoolType;
 
     use super::{Address, UnifiedAddress};
 
@@ -441,6 +493,27 @@ mod tests {
         assert_eq!(UnifiedAddress::from_receivers(transparent), None)
     }
 
+    #[test]
+    fn tex_round_trip() {
+        // Test vector from ZIP 320.
+        let tex_str = "tex1s2rt77ggv6q989lr49rkgzmh5slsksa9khdgte";
+        let addr = Address::decode(&MAIN_NETWORK, tex_str).unwrap();
+        assert_eq!(addr.encode(&MAIN_NETWORK), tex_str);
+        assert!(addr.has_receiver(PoolType::Transparent));
+        assert!(!addr.can_receive_memo());
+
+        let taddr = addr.to_transparent_address().unwrap();
+        assert!(matches!(taddr, TransparentAddress::PublicKeyHash(_)));
+        assert_eq!(Address::tex_from_transparent(&taddr), Some(addr));
+        assert_eq!(
+            Address::tex_from_transparent(&TransparentAddress::ScriptHash([0; 20])),
+            None
+        );
+
+        // A TEX address is only valid for the network it was encoded for.
+        assert_eq!(Address::decode(&TEST_NETWORK, tex_str), None);
+    }
+
     #[test]
     fn ua_parsing() {
         for tv in test_vectors::UNIFIED {
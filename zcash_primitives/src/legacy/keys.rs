@@ -24,6 +24,20 @@ use super::TransparentAddress;
 pub struct TransparentKeyScope(u32);
 
 impl TransparentKeyScope {
+    /// The scope used to derive keys for external transparent addresses,
+    /// intended to be used to send funds to this wallet.
+    pub const EXTERNAL: Self = TransparentKeyScope(0);
+
+    /// The scope used to derive keys for internal wallet operations, e.g.
+    /// change or UTXO management.
+    pub const INTERNAL: Self = TransparentKeyScope(1);
+
+    /// The scope used to derive keys for ephemeral transparent addresses, which are used as
+    /// the intermediate step of a payment to a [ZIP 320] TEX address.
+    ///
+    /// [ZIP 320]: https://zips.z.cash/zip-0320
+    pub const EPHEMERAL: Self = TransparentKeyScope(2);
+
     pub fn custom(i: u32) -> Option<Self> {
         if i < (1 << 31) {
             Some(TransparentKeyScope(i))
@@ -42,6 +56,12 @@ impl From<zip32::Scope> for TransparentKeyScope {
     }
 }
 
+impl From<TransparentKeyScope> for u32 {
+    fn from(value: TransparentKeyScope) -> Self {
+        value.0
+    }
+}
+
 impl From<TransparentKeyScope> for KeyIndex {
     fn from(value: TransparentKeyScope) -> Self {
         KeyIndex::Normal(value.0)
@@ -297,6 +317,14 @@ impl AccountPubKey {
             .map(InternalIvk)
     }
 
+    /// Derives the public key at the "ephemeral" path
+    /// `m/44'/<coin_type>'/<account>'/2`.
+    pub fn derive_ephemeral_ivk(&self) -> Result<EphemeralIvk, hdwallet::error::Error> {
+        self.0
+            .derive_public_key(TransparentKeyScope::EPHEMERAL.into())
+            .map(EphemeralIvk)
+    }
+
     pub fn derive_int_ivk_from_legacy_key(&self) -> InternalIvk {
             let chain_code = [0; 32].to_vec();
             let public_key = self.0.public_key;
@@ -521,6 +549,37 @@ impl private::SealedChangeLevelKey for InternalIvk {
 
 impl IncomingViewingKey for InternalIvk {}
 
+/// An incoming viewing key at the "ephemeral" path
+/// `m/44'/<coin_type>'/<account>'/2`.
+///
+/// This allows derivation of the ephemeral addresses that fund payments to [ZIP 320] TEX
+/// addresses. Each such address should receive funds only once, and should not be shared with
+/// external parties.
+///
+/// [ZIP 320]: https://zips.z.cash/zip-0320
+#[derive(Clone, Debug)]
+pub struct EphemeralIvk(ExtendedPubKey);
+
+impl private::SealedChangeLevelKey for EphemeralIvk {
+    fn from_compressed_pubkey(key: PublicKey) -> Self {
+        let chain_code = [0; 32].to_vec();
+        EphemeralIvk(ExtendedPubKey {
+            public_key: key,
+            chain_code,
+        })
+    }
+
+    fn extended_pubkey(&self) -> &ExtendedPubKey {
+        &self.0
+    }
+
+    fn from_extended_pubkey(key: ExtendedPubKey) -> Self {
+        EphemeralIvk(key)
+    }
+}
+
+impl IncomingViewingKey for EphemeralIvk {}
+
 /// Internal outgoing viewing key used for autoshielding.
 pub struct InternalOvk([u8; 32]);
 
@@ -794,6 +853,39 @@ mod tests {
         }
     }
 
+    #[test]
+    #[allow(deprecated)]
+    fn ephemeral_addresses_use_their_own_scope() {
+        use super::{pubkey_to_address, AccountPrivKey, IncomingViewingKey, TransparentKeyScope};
+        use zcash_protocol::consensus::MAIN_NETWORK;
+
+        let sk =
+            AccountPrivKey::from_seed(&MAIN_NETWORK, &[0; 32], zip32::AccountId::ZERO).unwrap();
+        let pk = sk.to_account_pubkey();
+        let index = NonHardenedChildIndex::from_index(3).unwrap();
+
+        let ephemeral = pk
+            .derive_ephemeral_ivk()
+            .unwrap()
+            .derive_address(index)
+            .unwrap();
+        let secret = sk
+            .derive_secret_key(TransparentKeyScope::EPHEMERAL, index)
+            .unwrap();
+        let expected = pubkey_to_address(&secp256k1::PublicKey::from_secret_key(
+            &secp256k1::Secp256k1::new(),
+            &secret,
+        ));
+        assert_eq!(ephemeral, expected);
+        assert_ne!(
+            ephemeral,
+            pk.derive_external_ivk()
+                .unwrap()
+                .derive_address(index)
+                .unwrap()
+        );
+    }
+
     #[test]
     fn nonhardened_indexes_accepted() {
         assert_eq!(0, NonHardenedChildIndex::from_index(0).unwrap().index());